@@ -1,8 +1,7 @@
-// Deobfuscates games processed with Schreib's GM Obfuscator.
+// Deobfuscates games processed with Schreib's GM Obfuscator, and tidies up GML code in general.
 //
-// This entire file is manually writing an AST back to GML,
-// and is really disgusting - read at your own risk.
-// You have been warned.
+// All the code is parsed and then printed back out by gml_parser's printer,
+// which asks this module what to rename each identifier to along the way.
 
 use crate::mappings;
 use gm8exe::{
@@ -10,13 +9,11 @@ use gm8exe::{
     GameAssets,
 };
 use gml_parser::{
-    ast::{self, AST},
+    ast::{self, Expr, ExprKind},
+    printer::{self, Place, Rewrite},
     token::Operator,
 };
-use std::{
-    collections::{HashMap, HashSet},
-    io::Write,
-};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Mode {
@@ -31,27 +28,71 @@ struct DeobfState {
     vars: HashSet<&'static [u8]>,
 }
 
-struct ExprWriter<'a, 'b, 'c> {
+/// Renames things in a piece of code while it's being printed.
+struct Renamer<'a> {
+    deobf: &'a mut DeobfState,
     assets: &'a GameAssets,
-    deobf: &'b mut DeobfState,
-    indent: usize,
-    indent_str: String,
-    output: &'c mut Vec<u8>,
-
-    is_gml_expr: bool,        // whether we're in a gml expr
-    group_skip_newline: bool, // overrides writing a newline after a group
 }
 
 pub fn process(assets: &mut GameAssets) {
     let constants = mappings::make_constants_map();
     let vars = mappings::make_kernel_vars_lut();
     let mut deobfuscator = DeobfState { fields: Vec::new(), constants, vars };
+    rewrite_code(assets, Some(&mut deobfuscator));
+
+    // Mass rename assets
+    for (i, sprite) in assets.sprites.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        if sprite.frames.is_empty() {
+            sprite.colliders.clear();
+            sprite.per_frame_colliders = true;
+        }
+        sprite.name = PascalString(format!("sprite{}", i).into_bytes().into());
+    }
+    for (i, sound) in assets.sounds.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        sound.name = PascalString(format!("sound{}", i).into_bytes().into());
+    }
+    for (i, background) in assets.backgrounds.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        background.name = PascalString(format!("background{}", i).into_bytes().into());
+    }
+    for (i, path) in assets.paths.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        path.name = PascalString(format!("path{}", i).into_bytes().into());
+    }
+    for (i, script) in assets.scripts.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        script.name = PascalString(format!("script{}", i).into_bytes().into());
+    }
+    for (i, font) in assets.fonts.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        font.name = PascalString(format!("font{}", i).into_bytes().into());
+    }
+    for (i, timeline) in assets.timelines.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        timeline.name = PascalString(format!("timeline{}", i).into_bytes().into());
+    }
+    for (i, object) in assets.objects.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        object.name = PascalString(format!("object{}", i).into_bytes().into());
+    }
+    for (i, room) in assets.rooms.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        room.name = PascalString(format!("room{}", i).into_bytes().into());
+    }
+    for (i, trigger) in assets.triggers.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
+        trigger.constant_name = PascalString(format!("trigger{}", i).into_bytes().into());
+    }
+    for (i, constant) in assets.constants.iter_mut().enumerate() {
+        constant.name = PascalString(format!("constant{}", i).into_bytes().into());
+    }
+}
+
+/// Pretty-prints all the GML code in a game, without deobfuscating it.
+pub fn format(assets: &mut GameAssets) {
+    rewrite_code(assets, None);
+}
+
+/// Parses and reprints all the GML code in a game, deobfuscating it along the way if there's a deobfuscator.
+fn rewrite_code(assets: &mut GameAssets, mut deobfuscator: Option<&mut DeobfState>) {
     let assets2 = unsafe { std::mem::transmute::<_, &'static mut GameAssets>(&mut *assets) };
 
     // Helper function for CodeActions
     fn process_action(
         action: &mut CodeAction,
-        deobfuscator: &mut DeobfState,
+        mut deobfuscator: Option<&mut DeobfState>,
         assets: &GameAssets,
     ) -> Result<(), ast::Error> {
         match action.action_kind {
@@ -59,31 +100,36 @@ pub fn process(assets: &mut GameAssets) {
                 // "normal"
                 if action.execution_type == 2 {
                     // "code"
-                    action.fn_code = PascalString(deobfuscator.process_gml(&action.fn_code.0, assets)?.into());
+                    action.fn_code =
+                        PascalString(rewrite_gml(&action.fn_code.0, deobfuscator.as_deref_mut(), assets)?.into());
                 }
 
                 for (expression, ty) in action.param_strings.iter_mut().zip(action.param_types.iter().copied()) {
                     if ty == 0 {
-                        *expression = PascalString(deobfuscator.process_expression(&expression.0, assets)?.into());
+                        *expression = PascalString(
+                            rewrite_expression(&expression.0, deobfuscator.as_deref_mut(), assets)?.into(),
+                        );
                     }
                 }
             },
             5 => {
                 // "repeat"
-                action.param_strings[0] =
-                    PascalString(deobfuscator.process_expression(&action.param_strings[0].0, assets)?.into());
+                action.param_strings[0] = PascalString(
+                    rewrite_expression(&action.param_strings[0].0, deobfuscator.as_deref_mut(), assets)?.into(),
+                );
             },
             6 => {
                 // "variable"
                 for i in 0..=1 {
-                    action.param_strings[i] =
-                        PascalString(deobfuscator.process_expression(&action.param_strings[i].0, assets)?.into());
+                    action.param_strings[i] = PascalString(
+                        rewrite_expression(&action.param_strings[i].0, deobfuscator.as_deref_mut(), assets)?.into(),
+                    );
                 }
             },
             7 => {
                 // "code"
                 action.param_strings[0] =
-                    PascalString(deobfuscator.process_gml(&action.param_strings[0].0, assets)?.into());
+                    PascalString(rewrite_gml(&action.param_strings[0].0, deobfuscator, assets)?.into());
             },
             _ => (),
         }
@@ -91,15 +137,15 @@ pub fn process(assets: &mut GameAssets) {
         Ok(())
     }
 
-    // Deobfuscate scripts
+    // Scripts
     for (i, script) in assets.scripts.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
-        match deobfuscator.process_gml(&script.source.0, assets2) {
+        match rewrite_gml(&script.source.0, deobfuscator.as_deref_mut(), assets2) {
             Ok(res) => {
                 script.source = PascalString(res.into());
             },
             Err(err) => {
                 eprintln!(
-                    "[Warning] Failed to deobfuscate script {} ({}): {}",
+                    "[Warning] Failed to parse script {} ({}): {}",
                     i,
                     std::str::from_utf8(&script.name.0).unwrap_or("<INVALID UTF-8>"),
                     err,
@@ -108,13 +154,13 @@ pub fn process(assets: &mut GameAssets) {
         }
     }
 
-    // Deobfuscate timelines
+    // Timelines
     for (i, timeline) in assets.timelines.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
         for (j, moment) in timeline.moments.iter_mut() {
             for (k, action) in moment.iter_mut().enumerate() {
-                if let Err(err) = process_action(action, &mut deobfuscator, assets2) {
+                if let Err(err) = process_action(action, deobfuscator.as_deref_mut(), assets2) {
                     eprintln!(
-                        "[Warning] Failed to deobfuscate timeline {} ({}) moment {} action {}: {}",
+                        "[Warning] Failed to parse timeline {} ({}) moment {} action {}: {}",
                         i,
                         std::str::from_utf8(&timeline.name.0).unwrap_or("<INVALID UTF-8>"),
                         j,
@@ -126,14 +172,14 @@ pub fn process(assets: &mut GameAssets) {
         }
     }
 
-    // Deobfuscate objects
+    // Objects
     for (i, object) in assets.objects.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
         for (e1, events) in object.events.iter_mut().enumerate() {
             for (e2, actions) in events.iter_mut() {
                 for (j, action) in actions.iter_mut().enumerate() {
-                    if let Err(err) = process_action(action, &mut deobfuscator, assets2) {
+                    if let Err(err) = process_action(action, deobfuscator.as_deref_mut(), assets2) {
                         eprintln!(
-                            "[Warning] Failed to deobfuscate object {} ({}) event {},{} action {}: {}",
+                            "[Warning] Failed to parse object {} ({}) event {},{} action {}: {}",
                             i,
                             std::str::from_utf8(&object.name.0).unwrap_or("<INVALID UTF-8>"),
                             e1,
@@ -147,15 +193,15 @@ pub fn process(assets: &mut GameAssets) {
         }
     }
 
-    // Deobfuscate rooms (creation code + instance creation code)
+    // Rooms (creation code + instance creation code)
     for (i, room) in assets.rooms.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
-        match deobfuscator.process_gml(&room.creation_code.0, assets2) {
+        match rewrite_gml(&room.creation_code.0, deobfuscator.as_deref_mut(), assets2) {
             Ok(res) => {
                 room.creation_code = PascalString(res.into());
             },
             Err(err) => {
                 eprintln!(
-                    "[Warning] Failed to deobfuscate creation code for room {} ({}): {}",
+                    "[Warning] Failed to parse creation code for room {} ({}): {}",
                     i,
                     std::str::from_utf8(&room.name.0).unwrap_or("<INVALID UTF-8>"),
                     err,
@@ -163,13 +209,13 @@ pub fn process(assets: &mut GameAssets) {
             },
         }
         for instance in room.instances.iter_mut() {
-            match deobfuscator.process_gml(&instance.creation_code.0, assets2) {
+            match rewrite_gml(&instance.creation_code.0, deobfuscator.as_deref_mut(), assets2) {
                 Ok(res) => {
                     instance.creation_code = PascalString(res.into());
                 },
                 Err(err) => {
                     eprintln!(
-                        "[Warning] Failed to deobfuscate creation code for instance {} in room {} ({}): {}",
+                        "[Warning] Failed to parse creation code for instance {} in room {} ({}): {}",
                         instance.id,
                         i,
                         std::str::from_utf8(&room.name.0).unwrap_or("<INVALID UTF-8>"),
@@ -180,15 +226,15 @@ pub fn process(assets: &mut GameAssets) {
         }
     }
 
-    // Deobfuscate triggers
+    // Triggers
     for (i, trigger) in assets.triggers.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|x| (i, x))) {
-        match deobfuscator.process_expression(&trigger.condition.0, assets2) {
+        match rewrite_expression(&trigger.condition.0, deobfuscator.as_deref_mut(), assets2) {
             Ok(res) => {
                 trigger.condition = PascalString(res.into());
             },
             Err(err) => {
                 eprintln!(
-                    "[Warning] Failed to deobfuscate condition for trigger {} ({}): {}",
+                    "[Warning] Failed to parse condition for trigger {} ({}): {}",
                     i,
                     std::str::from_utf8(&trigger.name.0).unwrap_or("<INVALID UTF-8>"),
                     err,
@@ -197,15 +243,15 @@ pub fn process(assets: &mut GameAssets) {
         }
     }
 
-    // Deobfuscate constants
+    // Constants
     for (i, constant) in assets.constants.iter_mut().enumerate() {
-        match deobfuscator.process_expression(&constant.expression.0, assets2) {
+        match rewrite_expression(&constant.expression.0, deobfuscator.as_deref_mut(), assets2) {
             Ok(res) => {
                 constant.expression = PascalString(res.into());
             },
             Err(err) => {
                 eprintln!(
-                    "[Warning] Failed to deobfuscate condition for constant {} ({}): {}",
+                    "[Warning] Failed to parse expression for constant {} ({}): {}",
                     i,
                     std::str::from_utf8(&constant.name.0).unwrap_or("<INVALID UTF-8>"),
                     err,
@@ -213,87 +259,29 @@ pub fn process(assets: &mut GameAssets) {
             },
         }
     }
-
-    // Mass rename assets
-    for (i, sprite) in assets.sprites.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        if sprite.frames.is_empty() {
-            sprite.colliders.clear();
-            sprite.per_frame_colliders = true;
-        }
-        sprite.name = PascalString(format!("sprite{}", i).into_bytes().into());
-    }
-    for (i, sound) in assets.sounds.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        sound.name = PascalString(format!("sound{}", i).into_bytes().into());
-    }
-    for (i, background) in assets.backgrounds.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        background.name = PascalString(format!("background{}", i).into_bytes().into());
-    }
-    for (i, path) in assets.paths.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        path.name = PascalString(format!("path{}", i).into_bytes().into());
-    }
-    for (i, script) in assets.scripts.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        script.name = PascalString(format!("script{}", i).into_bytes().into());
-    }
-    for (i, font) in assets.fonts.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        font.name = PascalString(format!("font{}", i).into_bytes().into());
-    }
-    for (i, timeline) in assets.timelines.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        timeline.name = PascalString(format!("timeline{}", i).into_bytes().into());
-    }
-    for (i, object) in assets.objects.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        object.name = PascalString(format!("object{}", i).into_bytes().into());
-    }
-    for (i, room) in assets.rooms.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        room.name = PascalString(format!("room{}", i).into_bytes().into());
-    }
-    for (i, trigger) in assets.triggers.iter_mut().enumerate().filter_map(|(i, o)| o.as_mut().map(|x| (i, x))) {
-        trigger.constant_name = PascalString(format!("trigger{}", i).into_bytes().into());
-    }
-    for (i, constant) in assets.constants.iter_mut().enumerate() {
-        constant.name = PascalString(format!("constant{}", i).into_bytes().into());
-    }
 }
 
-impl DeobfState {
-    pub fn process_gml(&mut self, input: &[u8], assets: &GameAssets) -> Result<Vec<u8>, ast::Error> {
-        let mut output = Vec::new();
-        let ast = AST::new(input)?;
-
-        let mut writer = ExprWriter {
-            assets,
-            deobf: self,
-            indent: 0,
-            indent_str: "    ".into(),
-            output: &mut output,
-
-            is_gml_expr: false,
-            group_skip_newline: false,
-        };
-
-        for expr in ast {
-            writer.process_expr(&expr);
-        }
-
-        Ok(output)
+/// Pretty-prints a piece of GML code, deobfuscating it too if there's a deobfuscator.
+fn rewrite_gml(input: &[u8], deobf: Option<&mut DeobfState>, assets: &GameAssets) -> Result<Vec<u8>, ast::Error> {
+    match deobf {
+        Some(deobf) => printer::format(input, &Default::default(), &mut Renamer { deobf, assets }),
+        None => printer::format(input, &Default::default(), &mut ()),
     }
+}
 
-    pub fn process_expression(&mut self, input: &[u8], assets: &GameAssets) -> Result<Vec<u8>, ast::Error> {
-        let mut output = Vec::new();
-        let expr = AST::expression(input)?;
-        let mut writer = ExprWriter {
-            assets,
-            deobf: self,
-            indent: 0,
-            indent_str: "    ".into(),
-            output: &mut output,
-
-            is_gml_expr: true,
-            group_skip_newline: false,
-        };
-        writer.process_expr(&expr);
-        Ok(output)
+/// Pretty-prints a GML expression, deobfuscating it too if there's a deobfuscator.
+fn rewrite_expression(
+    input: &[u8],
+    deobf: Option<&mut DeobfState>,
+    assets: &GameAssets,
+) -> Result<Vec<u8>, ast::Error> {
+    match deobf {
+        Some(deobf) => printer::format_expression(input, &Default::default(), &mut Renamer { deobf, assets }),
+        None => printer::format_expression(input, &Default::default(), &mut ()),
     }
+}
 
+impl DeobfState {
     pub fn register_field(&mut self, field: &[u8]) -> usize {
         match self.fields.iter().position(|x| &**x == field) {
             Some(x) => x,
@@ -305,9 +293,13 @@ impl DeobfState {
         }
     }
 
-    pub fn simplify(&mut self, expr: &ast::Expr, assets: &GameAssets) -> Option<f64> {
-        match expr {
-            ast::Expr::LiteralIdentifier(ident) => {
+    pub fn field_name(&mut self, field: &[u8]) -> Vec<u8> {
+        format!("field{}", self.register_field(field)).into_bytes()
+    }
+
+    pub fn simplify(&mut self, expr: &Expr, assets: &GameAssets) -> Option<f64> {
+        match &expr.kind {
+            ExprKind::LiteralIdentifier(ident) => {
                 if let Some(index) = self.get_asset_index(ident, assets) {
                     Some(index as f64)
                 } else if ident == b"pi" {
@@ -317,8 +309,8 @@ impl DeobfState {
                     self.constants.get(ident).copied()
                 }
             },
-            ast::Expr::LiteralReal(real) => Some(*real),
-            ast::Expr::Unary(unary) => {
+            ExprKind::LiteralReal(real) => Some(*real),
+            ExprKind::Unary(unary) => {
                 let child = self.simplify(&unary.child, assets)?;
                 match unary.op {
                     Operator::Add => Some(child),
@@ -326,7 +318,7 @@ impl DeobfState {
                     _ => None, // technically there's others. none used by obf
                 }
             },
-            ast::Expr::Binary(binary) => {
+            ExprKind::Binary(binary) => {
                 let left = self.simplify(&binary.left, assets)?;
                 let right = self.simplify(&binary.right, assets)?;
                 match binary.op {
@@ -358,438 +350,55 @@ impl DeobfState {
     }
 }
 
-impl<'a, 'b, 'c> ExprWriter<'a, 'b, 'c> {
-    pub fn process_expr(&mut self, ex: &'_ ast::Expr) {
-        macro_rules! push_str {
-            ($lit: literal) => {{
-                self.output.extend_from_slice(($lit).as_bytes());
-            }};
-        }
-
-        fn write_wrapped(writer: &mut ExprWriter, expr: &ast::Expr) {
-            writer.output.push(b'(');
-            writer.process_expr(expr);
-            writer.output.push(b')');
-        }
-
-        match ex {
-            ast::Expr::LiteralIdentifier(expr) => {
-                if let Some(simple) = self.deobf.simplify(&ast::Expr::LiteralIdentifier(expr), self.assets) {
-                    let _ = write!(self.output, "{}", simple);
-                } else if self.deobf.vars.get(expr).is_some() || expr == b"pi" {
-                    self.output.extend_from_slice(expr);
-                } else {
-                    self.write_field(expr);
-                }
-            },
-            ast::Expr::LiteralReal(real) => {
-                let _ = write!(self.output, "{}", real);
-            },
-            ast::Expr::LiteralString(string) => {
-                let quote = if string.iter().any(|&x| x == b'"') { b'\'' } else { b'"' };
-                self.output.push(quote);
-                self.output.extend_from_slice(string);
-                self.output.push(quote);
-            },
-            ast::Expr::Unary(expr) => {
-                let op = op_to_str(expr.op);
-                self.output.extend_from_slice(op);
-                let prev_state = self.is_gml_expr;
-                self.is_gml_expr = true;
-                if let Some(simple) = self.deobf.simplify(&expr.child, self.assets) {
-                    self.process_expr(&ast::Expr::LiteralReal(simple));
-                } else {
-                    match &expr.child {
-                        ast::Expr::Binary(b) if !matches!(b.op, Operator::Deref | Operator::Index) => {
-                            write_wrapped(self, &expr.child)
-                        },
-                        _ => self.process_expr(&expr.child),
-                    }
-                }
-                self.is_gml_expr = prev_state;
-            },
-            ast::Expr::Binary(expr) => {
-                let prev_state = self.is_gml_expr;
-                self.is_gml_expr = true;
-                if let Some(simple) = self.deobf.simplify(ex, self.assets) {
-                    self.process_expr(&ast::Expr::LiteralReal(simple));
-                } else if expr.op == Operator::Index {
-                    // array indexing
-                    self.process_expr(&expr.left);
-                    self.output.push(b'[');
-                    if let ast::Expr::Group(group) = &expr.right {
-                        for (i, expr) in group.iter().enumerate() {
-                            if i != 0 {
-                                push_str!(", ");
-                            }
-                            if let Some(simple) = self.deobf.simplify(expr, &self.assets) {
-                                self.process_expr(&ast::Expr::LiteralReal(simple));
-                            } else {
-                                self.process_expr(expr);
-                            }
-                        }
-                    } else {
-                        panic!("index rhs wasn't a group");
-                    }
-                    self.output.push(b']');
-                } else if expr.op == Operator::Deref {
-                    // Deref operator - lots of special cases here
-                    // If LHS can be simplified,
-                    if let Some(simple) = self.deobf.simplify(&expr.left, self.assets) {
-                        // If the simplified number is the ID of an object,
-                        let simple_int = simple as i32;
-                        if simple_int >= 0
-                            && self.assets.objects.get(simple_int as usize).is_some()
-                            && simple.fract() == 0.0
-                        {
-                            // Write eg "object123"
-                            let _ = write!(self.output, "object{}", simple_int);
-                        } else if simple.fract() == 0.0 {
-                            // Special cases for certain keywords, otherwise just write eg "(123)"
-                            match simple_int {
-                                -1 => self.output.extend_from_slice(b"self"),
-                                -2 => self.output.extend_from_slice(b"other"),
-                                -5 => self.output.extend_from_slice(b"global"),
-                                -7 => self.output.extend_from_slice(b"local"),
-                                i => {
-                                    let _ = write!(self.output, "({})", i);
-                                },
-                            }
-                        } else {
-                            // Write the whole LHS expression normally
-                            write_wrapped(self, &expr.left);
-                        }
-                    } else {
-                        // Write the LHS expression normally, wrapping it only if necessary
-                        match &expr.left {
-                            ast::Expr::LiteralIdentifier(_) => {
-                                self.process_expr(&expr.left);
-                            },
-                            ast::Expr::Binary(b) if matches!(b.op, Operator::Index | Operator::Deref) => {
-                                self.process_expr(&expr.left);
-                            },
-                            _ => {
-                                write_wrapped(self, &expr.left);
-                            },
-                        }
-                    }
-
-                    self.output.push(b'.');
-                    self.process_expr(&expr.right);
-                } else {
-                    // This is a "normal" binary expression with an operator between two things
-                    // Helper fn: write one side of the expr, deciding whether to paren-wrap it or not
-                    fn write_side(writer: &mut ExprWriter, expr: &ast::Expr, can_wrap: bool) {
-                        if let Some(simple) = writer.deobf.simplify(expr, writer.assets) {
-                            writer.process_expr(&ast::Expr::LiteralReal(simple));
-                        } else if can_wrap {
-                            match expr {
-                                ast::Expr::LiteralIdentifier(_)
-                                | ast::Expr::LiteralReal(_)
-                                | ast::Expr::LiteralString(_)
-                                | ast::Expr::Unary(_)
-                                | ast::Expr::Function(_) => {
-                                    writer.process_expr(expr);
-                                },
-                                ast::Expr::Binary(b) if matches!(b.op, Operator::Index | Operator::Deref) => {
-                                    writer.process_expr(expr);
-                                },
-                                _ => {
-                                    write_wrapped(writer, expr);
-                                },
-                            }
-                        } else {
-                            writer.process_expr(expr);
-                        }
-                    }
-
-                    let is_assign = matches!(
-                        expr.op,
-                        Operator::Assign
-                            | Operator::AssignAdd
-                            | Operator::AssignSubtract
-                            | Operator::AssignMultiply
-                            | Operator::AssignDivide
-                            | Operator::AssignBitwiseAnd
-                            | Operator::AssignBitwiseOr
-                            | Operator::AssignBitwiseXor
-                    );
-                    write_side(self, &expr.left, !is_assign);
-                    self.output.push(b' ');
-                    self.output.extend_from_slice(op_to_str(expr.op));
-                    self.output.push(b' ');
-                    write_side(self, &expr.right, !is_assign);
-                }
-                self.is_gml_expr = prev_state;
-                if !self.is_gml_expr {
-                    push_str!(";\r\n");
-                }
-            },
-            ast::Expr::DoUntil(expr) => {
-                push_str!("do ");
-                self.write_expr_grouped(&expr.body, false);
-                push_str!("until (");
-                self.is_gml_expr = true;
-                self.process_expr(&expr.cond);
-                self.is_gml_expr = false;
-                push_str!(");\r\n");
-            },
-            ast::Expr::For(expr) => {
-                fn remove_truncate(x: &mut Vec<u8>, pat: &[u8]) {
-                    if x.ends_with(pat) {
-                        x.truncate(x.len() - pat.len());
-                    }
-                }
-
-                push_str!("for (");
-                self.is_gml_expr = true;
-                self.process_expr(&expr.start);
-                remove_truncate(&mut self.output, b"\r\n");
-                push_str!("; ");
-                self.process_expr(&expr.cond);
-                push_str!("; ");
-                self.process_expr(&expr.step);
-                remove_truncate(&mut self.output, b"\r\n");
-                remove_truncate(&mut self.output, b";");
-                push_str!(") ");
-                self.is_gml_expr = false;
-                self.write_expr_grouped(&expr.body, true);
-            },
-            ast::Expr::Function(expr) => {
-                if let Some(idx) = self
-                    .assets
-                    .scripts
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(i, o)| o.as_ref().map(|x| (i, x)))
-                    .find(|(_, scr)| &*scr.name.0 == expr.name)
-                    .map(|(i, _)| i)
-                {
-                    let _ = write!(self.output, "script{}", idx);
-                } else {
-                    self.output.extend_from_slice(expr.name);
-                }
-                self.output.push(b'(');
-                let prev_expr_state = self.is_gml_expr;
-                self.is_gml_expr = true;
-                for (i, param) in expr.params.iter().enumerate() {
-                    if i != 0 {
-                        push_str!(", ");
-                    }
-                    self.process_expr(param);
-                }
-                self.is_gml_expr = prev_expr_state;
-                if self.is_gml_expr {
-                    push_str!(")");
-                } else {
-                    push_str!(");\r\n");
-                }
-            },
-            ast::Expr::Group(exprs) => {
-                let skip_newline = self.group_skip_newline;
-                self.group_skip_newline = false;
-                push_str!("{\r\n");
-                self.indent += 1;
-                let mut is_case = false;
-                for expr in exprs {
-                    if matches!(expr, ast::Expr::Case(_) | ast::Expr::Default) {
-                        if is_case {
-                            self.indent -= 1;
-                        } else {
-                            is_case = true;
-                        }
-                        self.write_indent();
-                        self.process_expr(expr);
-                        self.indent += 1;
-                    } else {
-                        self.write_indent();
-                        self.process_expr(expr);
-                    }
-                }
-                if is_case {
-                    self.indent -= 1;
-                }
-                self.indent -= 1;
-                self.write_indent();
-                if skip_newline {
-                    self.output.push(b'}');
-                } else {
-                    push_str!("}\r\n");
-                }
+impl Rewrite for Renamer<'_> {
+    fn expression(&mut self, expr: &Expr, place: Place) -> Option<Vec<u8>> {
+        match place {
+            Place::Value => match &expr.kind {
+                ExprKind::LiteralIdentifier(name) => match self.deobf.simplify(expr, self.assets) {
+                    Some(simple) => Some(simple.to_string().into_bytes()),
+                    None if self.deobf.vars.contains(name) || *name == b"pi" => None,
+                    None => Some(self.deobf.field_name(name)),
+                },
+                ExprKind::LiteralReal(_) => None,
+                _ => self.deobf.simplify(expr, self.assets).map(|x| x.to_string().into_bytes()),
             },
-            ast::Expr::If(expr) => {
-                push_str!("if (");
-                self.is_gml_expr = true;
-                self.process_expr(&expr.cond);
-                self.is_gml_expr = false;
-                push_str!(") ");
-
-                self.write_expr_grouped(&expr.body, false);
-
-                if let Some(expr_else) = &expr.else_body {
-                    push_str!(" else ");
-                    if matches!(expr_else, ast::Expr::If(_)) {
-                        self.process_expr(expr_else);
+            Place::Field => match &expr.kind {
+                ExprKind::LiteralIdentifier(name) if !self.deobf.vars.contains(name) => {
+                    Some(self.deobf.field_name(name))
+                },
+                _ => None,
+            },
+            Place::DerefTarget | Place::WithTarget => match self.deobf.simplify(expr, self.assets) {
+                Some(simple) if simple.fract() == 0.0 => {
+                    let id = simple as i32;
+                    let code = if id >= 0 && self.assets.objects.get(id as usize).is_some() {
+                        format!("object{}", id)
+                    } else if place == Place::WithTarget {
+                        id.to_string()
                     } else {
-                        self.write_expr_grouped(expr_else, true);
-                    }
-                } else {
-                    push_str!("\r\n");
-                }
-            },
-            ast::Expr::Repeat(expr) => {
-                push_str!("repeat (");
-                self.is_gml_expr = true;
-                self.process_expr(&expr.count);
-                self.is_gml_expr = false;
-                push_str!(") ");
-                self.write_expr_grouped(&expr.body, true);
-            },
-            ast::Expr::Switch(expr) => {
-                push_str!("switch (");
-                self.is_gml_expr = true;
-                self.process_expr(&expr.input);
-                self.is_gml_expr = false;
-                push_str!(") ");
-                self.write_expr_grouped(&expr.body, true);
-            },
-            ast::Expr::Var(expr) => {
-                if !expr.vars.is_empty() {
-                    push_str!("var ");
-                    for (i, name) in expr.vars.iter().enumerate() {
-                        if i != 0 {
-                            push_str!(", ");
-                        }
-                        self.write_field(name);
-                    }
-                    push_str!(";\r\n");
-                }
-            },
-            ast::Expr::GlobalVar(expr) => {
-                if !expr.vars.is_empty() {
-                    push_str!("globalvar ");
-                    for (i, name) in expr.vars.iter().enumerate() {
-                        if i != 0 {
-                            push_str!(", ");
+                        // Special cases for certain keywords, otherwise just write eg "(123)"
+                        match id {
+                            -1 => "self".into(),
+                            -2 => "other".into(),
+                            -5 => "global".into(),
+                            -7 => "local".into(),
+                            id => format!("({})", id),
                         }
-                        self.write_field(name);
-                    }
-                    push_str!(";\r\n");
-                }
-            },
-            ast::Expr::With(expr) => {
-                push_str!("with (");
-                self.is_gml_expr = true;
-                if let Some(simple) = self.deobf.simplify(&expr.target, self.assets) {
-                    let simple_int = simple as i32;
-                    if simple_int >= 0
-                        && self.assets.objects.get(simple_int as usize).is_some()
-                        && simple.fract() == 0.0
-                    {
-                        let _ = write!(self.output, "object{}", simple_int);
-                    } else if simple.fract() == 0.0 {
-                        let _ = write!(self.output, "{}", simple_int);
-                    } else {
-                        self.process_expr(&expr.target);
-                    }
-                } else {
-                    self.process_expr(&expr.target);
-                }
-                self.is_gml_expr = false;
-                push_str!(") ");
-                self.write_expr_grouped(&expr.body, true);
-            },
-            ast::Expr::While(expr) => {
-                push_str!("while (");
-                self.is_gml_expr = true;
-                self.process_expr(&expr.cond);
-                self.is_gml_expr = false;
-                push_str!(") ");
-                self.write_expr_grouped(&expr.body, true);
-            },
-            ast::Expr::Case(expr) => {
-                push_str!("case ");
-                self.is_gml_expr = true;
-                self.process_expr(expr);
-                self.is_gml_expr = false;
-                push_str!(":\r\n");
-            },
-            ast::Expr::Default => push_str!("default:\r\n"),
-            ast::Expr::Continue => push_str!("continue;\r\n"),
-            ast::Expr::Break => push_str!("break;\r\n"),
-            ast::Expr::Exit => push_str!("exit;\r\n"),
-            ast::Expr::Return(expr) => {
-                push_str!("return ");
-                self.is_gml_expr = true;
-                self.process_expr(expr);
-                self.is_gml_expr = false;
-                push_str!(";\r\n");
+                    };
+                    Some(code.into_bytes())
+                },
+                Some(simple) => Some(format!("({})", simple).into_bytes()),
+                None => self.expression(expr, Place::Value),
             },
         }
     }
 
-    pub fn write_expr_grouped(&mut self, expr: &ast::Expr, newline: bool) {
-        if matches!(expr, ast::Expr::Group(_)) {
-            if !newline {
-                self.group_skip_newline = true;
-            }
-            self.process_expr(expr);
-        } else {
-            self.output.extend_from_slice(b"{\r\n");
-            self.indent += 1;
-            self.write_indent();
-            self.process_expr(expr);
-            self.indent -= 1;
-            self.write_indent();
-            self.output.extend_from_slice(if newline { b"}\r\n" } else { b"}" });
-        }
+    fn function(&mut self, name: &[u8]) -> Option<Vec<u8>> {
+        let index = self.assets.scripts.iter().position(|x| x.as_ref().map_or(false, |x| &*x.name.0 == name))?;
+        Some(format!("script{}", index).into_bytes())
     }
 
-    pub fn write_field(&mut self, ident: &[u8]) {
-        let field_number = self.deobf.register_field(ident);
-        let _ = write!(self.output, "field{}", field_number);
-    }
-
-    pub fn write_indent(&mut self) {
-        for _ in 0..self.indent {
-            self.output.extend_from_slice(self.indent_str.as_bytes());
-        }
-    }
-}
-
-fn op_to_str(op: Operator) -> &'static [u8] {
-    match op {
-        Operator::Add => b"+",
-        Operator::Subtract => b"-",
-        Operator::Multiply => b"*",
-        Operator::Divide => b"/",
-        Operator::IntDivide => b"div",
-        Operator::BitwiseAnd => b"&",
-        Operator::BitwiseOr => b"|",
-        Operator::BitwiseXor => b"^",
-        Operator::Assign => b"=",
-        Operator::Not => b"!",
-        Operator::LessThan => b"<",
-        Operator::GreaterThan => b">",
-        Operator::AssignAdd => b"+=",
-        Operator::AssignSubtract => b"-=",
-        Operator::AssignMultiply => b"*=",
-        Operator::AssignDivide => b"/=",
-        Operator::AssignBitwiseAnd => b"&=",
-        Operator::AssignBitwiseOr => b"|=",
-        Operator::AssignBitwiseXor => b"^=",
-        Operator::Equal => b"==",
-        Operator::NotEqual => b"!=",
-        Operator::LessThanOrEqual => b"<=",
-        Operator::GreaterThanOrEqual => b">=",
-        Operator::Modulo => b"mod",
-        Operator::And => b"&&",
-        Operator::Or => b"||",
-        Operator::Xor => b"^^",
-        Operator::BinaryShiftLeft => b"<<",
-        Operator::BinaryShiftRight => b">>",
-        Operator::Complement => b"~",
-        Operator::Deref => b".",
-        Operator::Index => panic!("index op passed to op_to_str"),
+    fn declaration(&mut self, name: &[u8]) -> Option<Vec<u8>> {
+        Some(self.deobf.field_name(name))
     }
 }
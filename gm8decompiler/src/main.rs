@@ -62,6 +62,7 @@ fn main() {
         .optflag("l", "lazy", "disable various data integrity checks")
         .optflag("v", "verbose", "enable verbose logging for decompilation")
         .optopt("d", "deobfuscate", "set deobfuscation mode auto/on/off (default=auto)", "")
+        .optflag("f", "format", "pretty-print all GML code, even when not deobfuscating")
         .optflag("p", "preserve", "preserve broken events (instead of trying to fix them)")
         .optflag("s", "singlethread", "decompile gamedata synchronously (lower RAM usage)")
         .optopt("o", "output", "specify output filename", "FILE");
@@ -98,6 +99,7 @@ Options:
     -l, --lazy                disable various data integrity checks
     -v, --verbose             enable verbose logging for decompilation
     -d, --deobfuscate <mode>  set deobfuscation mode auto/on/off (defaults to auto)
+    -f, --format              pretty-print all GML code, even when not deobfuscating
     -p, --preserve            preserve broken events (instead of trying to fix them)
     -s, --singlethread        decompile gamedata synchronously (lower RAM usage)
     -o, --output <file>       specify output filename",
@@ -135,6 +137,7 @@ Options:
             process::exit(1);
         },
     };
+    let format_code = matches.opt_present("f");
     let out_path = matches.opt_str("o");
     let preserve = matches.opt_present("p");
     // no_pause extracted before help
@@ -152,6 +155,9 @@ Options:
         deobfuscate::Mode::Off => println!("Deobfuscation OFF: will ignore obfuscation"),
         _ => (),
     }
+    if format_code {
+        println!("Format mode ON: GML code will be pretty-printed");
+    }
     if singlethread {
         println!("Single-threaded mode ON: process will not start new threads (slow)");
     }
@@ -170,7 +176,8 @@ Options:
     }
 
     // allow decompile to handle the rest of main
-    if let Err(e) = decompile(input_path, out_path, !lazy, !singlethread, verbose, deobfuscate, !preserve) {
+    if let Err(e) = decompile(input_path, out_path, !lazy, !singlethread, verbose, deobfuscate, format_code, !preserve)
+    {
         eprintln!("Error parsing gamedata:\n{}", e);
         process::exit(1);
     }
@@ -180,6 +187,7 @@ Options:
     }
 }
 
+#[allow(clippy::too_many_arguments)] // one for each command line option
 fn decompile(
     in_path: &Path,
     out_path: Option<String>,
@@ -187,6 +195,7 @@ fn decompile(
     multithread: bool,
     verbose: bool,
     deobf_mode: deobfuscate::Mode,
+    format_code: bool,
     fix_events: bool,
 ) -> Result<(), String> {
     // slurp in file contents
@@ -286,6 +295,8 @@ fn decompile(
 
     if deobfuscate {
         deobfuscate::process(&mut assets);
+    } else if format_code {
+        deobfuscate::format(&mut assets);
     }
 
     let mut gmk = fs::File::create(&out_path)
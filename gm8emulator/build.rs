@@ -6,6 +6,9 @@ use std::{
     path::Path,
 };
 
+#[path = "src/gml/kernel_scan.rs"]
+mod kernel_scan;
+
 static OPENGL_EXTENSIONS: &[&str] = &[];
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -40,6 +43,14 @@ fn main() -> Result<(), Box<dyn Error>> {
         fs::write(aa_macro_path, &aa_macro)?;
     }
 
+    // how complete each kernel function and variable in mappings.rs is, for the linter and compatibility report
+    let kernel_info = kernel_scan::kernel_info(
+        &fs::read_to_string("src/gml/kernel.rs")?,
+        &fs::read_to_string("src/gml/mappings.rs")?,
+        &fs::read_to_string("src/gml/runtime.rs")?,
+    )?;
+    fs::write(Path::new(&out).join("kernel_info.rs"), kernel_info)?;
+
     // opengl bindings
     let mut bindings = File::create(&Path::new(&out).join("gl_bindings.rs"))?;
     Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, &OPENGL_EXTENSIONS)
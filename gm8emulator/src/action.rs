@@ -4,7 +4,9 @@ use crate::{
         self,
         compiler::Compiler,
         mappings,
-        runtime::{Instruction, Node},
+        bytecode::Program,
+        runtime::Node,
+        trace::Source,
         Context, Value,
     },
 };
@@ -59,7 +61,7 @@ pub struct Tree(Vec<Action>);
 pub enum Body {
     Normal {
         /// The arguments to be passed to the function or code body
-        args: Box<[Node]>,
+        args: Box<[Program]>,
 
         /// The body of this action to be executed
         body: GmlBody,
@@ -70,7 +72,7 @@ pub enum Body {
     Else,
     Repeat {
         /// The expression giving the number of times to repeat.
-        count: Node,
+        count: Program,
     },
     BlockBegin,
     BlockEnd,
@@ -84,7 +86,7 @@ pub enum GmlBody {
     StateFunction(gml::StateFunction),
     RoutineFunction(gml::RoutineFunction),
     ValueFunction(gml::ValueFunction),
-    Code(Rc<[Instruction]>),
+    Code(Rc<Program>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -121,6 +123,12 @@ impl std::fmt::Debug for GmlBody {
     }
 }
 
+impl From<Vec<Action>> for Tree {
+    fn from(actions: Vec<Action>) -> Self {
+        Self(actions)
+    }
+}
+
 impl Tree {
     /// Turn a list of gm8exe CodeActions into an Action tree.
     pub fn from_list(list: &[CodeAction], compiler: &mut Compiler) -> Result<Self, String> {
@@ -190,7 +198,9 @@ impl Tree {
                                         &action.param_types,
                                         action.param_count,
                                     )?,
-                                    body: GmlBody::Code(compiler.compile(&action.fn_code.0).map_err(|e| e.message)?),
+                                    body: GmlBody::Code(
+                                        compiler.compile(&action.fn_code.0).map_err(|e| e.to_string())?,
+                                    ),
                                     is_condition: action.is_condition,
                                 },
                             });
@@ -241,7 +251,7 @@ impl Tree {
                         relative: action.is_relative,
                         invert_condition: action.invert_condition,
                         body: Body::Repeat {
-                            count: compiler.compile_expression(&action.param_strings[0].0).map_err(|e| e.message)?,
+                            count: compiler.compile_expression(&action.param_strings[0].0).map_err(|e| e.to_string())?,
                         },
                     });
                 },
@@ -257,7 +267,7 @@ impl Tree {
                         invert_condition: action.invert_condition,
                         body: Body::Normal {
                             args: Box::new([]),
-                            body: GmlBody::Code(compiler.compile(&code).map_err(|e| e.message)?),
+                            body: GmlBody::Code(compiler.compile(&code).map_err(|e| e.to_string())?),
                             is_condition: false,
                         },
                     });
@@ -271,7 +281,9 @@ impl Tree {
                         invert_condition: action.invert_condition,
                         body: Body::Normal {
                             args: Box::new([]),
-                            body: GmlBody::Code(compiler.compile(&action.param_strings[0].0).map_err(|e| e.message)?),
+                            body: GmlBody::Code(
+                                compiler.compile(&action.param_strings[0].0).map_err(|e| e.to_string())?,
+                            ),
                             is_condition: false,
                         },
                     });
@@ -289,27 +301,27 @@ impl Tree {
         params: &[gm8exe::asset::PascalString],
         types: &[u32],
         count: usize,
-    ) -> Result<Box<[Node]>, String> {
+    ) -> Result<Box<[Program]>, String> {
         Ok(params
             .iter()
             .zip(types.iter())
             .take(count)
             .map(|(param, t)| match *t {
-                1 | 2 => Ok(Node::Literal { value: Value::Str(param.0.as_ref().into()) }),
+                1 | 2 => Ok(Program::from_expression(&Node::Literal { value: Value::Str(param.0.as_ref().into()) })),
                 _ => compiler.compile_expression(&param.0),
             })
             .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| e.message)?
+            .map_err(|e| e.to_string())?
             .into_boxed_slice())
     }
 
-    pub fn new_from_code(code: Rc<[Instruction]>) -> Rc<RefCell<Self>> {
+    pub fn new_from_code(code: Rc<Program>) -> Rc<RefCell<Self>> {
         let mut tree = Self(Vec::new());
         tree.push_code(code);
         Rc::new(RefCell::new(tree))
     }
 
-    pub fn push_code(&mut self, code: Rc<[Instruction]>) {
+    pub fn push_code(&mut self, code: Rc<Program>) {
         self.0.push(Action {
             index: self.0.len(),
             target: None,
@@ -397,59 +409,9 @@ impl Game {
                     }
                     */
 
-                    let mut returned_value = Default::default();
-                    match action.target {
-                        None | Some(gml::SELF) | Some(gml::OTHER) => {
-                            if action.target == Some(gml::OTHER) {
-                                context.this = other;
-                                context.other = this;
-                            }
-
-                            let mut arg_values: [Value; 16] = Default::default();
-                            for (dest, src) in arg_values.iter_mut().zip(args.iter()) {
-                                *dest = self.eval(src, &mut context)?;
-                            }
-
-                            returned_value = match gml_body {
-                                GmlBody::ContextFunction(f) => f.0(self, &mut context, &arg_values[..args.len()])?,
-                                GmlBody::StateFunction(f) => f.0(self, &arg_values[..args.len()])?,
-                                GmlBody::RoutineFunction(f) => f.0(self, &arg_values[..args.len()])?,
-                                GmlBody::ValueFunction(f) => f.0(&arg_values[..args.len()])?,
-                                GmlBody::Code(code) => {
-                                    context.arguments = arg_values;
-                                    context.argument_count = args.len();
-                                    self.execute(code, &mut context)?;
-                                    context.return_value
-                                },
-                            };
-                        },
-                        Some(i) if i < 0 => (),
-                        Some(i) => {
-                            context.other = this;
-                            let mut iter = self.room.instance_list.iter_by_identity(i);
-                            while let Some(instance) = iter.next(&self.room.instance_list) {
-                                context.this = instance;
-
-                                let mut arg_values: [Value; 16] = Default::default();
-                                for (dest, src) in arg_values.iter_mut().zip(args.iter()) {
-                                    *dest = self.eval(src, &mut context)?;
-                                }
-
-                                returned_value = match gml_body {
-                                    GmlBody::ContextFunction(f) => f.0(self, &mut context, &arg_values[..args.len()])?,
-                                    GmlBody::StateFunction(f) => f.0(self, &arg_values[..args.len()])?,
-                                    GmlBody::RoutineFunction(f) => f.0(self, &arg_values[..args.len()])?,
-                                    GmlBody::ValueFunction(f) => f.0(&arg_values[..args.len()])?,
-                                    GmlBody::Code(code) => {
-                                        context.arguments = arg_values;
-                                        context.argument_count = args.len();
-                                        self.execute(code, &mut context)?;
-                                        context.return_value.clone()
-                                    },
-                                };
-                            }
-                        },
-                    }
+                    let returned_value = self
+                        .exec_action(action, args, gml_body, this, other, &mut context)
+                        .map_err(|e| e.within(Source::Action(action.index)))?;
 
                     if *is_condition {
                         let do_if = returned_value.is_truthy() != action.invert_condition;
@@ -508,7 +470,9 @@ impl Game {
                             event_object: as_object,
                             ..Default::default()
                         };
-                        let mut count = i32::from(self.eval(count, &mut context)?);
+                        let mut count = i32::from(
+                            self.eval(count, &mut context).map_err(|e| e.within(Source::Action(action.index)))?,
+                        );
                         while count > 0 {
                             match self.exec_slice(body, this, other, event_type, event_number, as_object, true)? {
                                 (ReturnType::Continue, _) => (),
@@ -533,4 +497,70 @@ impl Game {
 
         Ok((ReturnType::Continue, slice.len()))
     }
+
+    /// Runs the function or code of a single action, returning its result.
+    fn exec_action(
+        &mut self,
+        action: &Action,
+        args: &[Program],
+        gml_body: &GmlBody,
+        this: usize,
+        other: usize,
+        context: &mut Context,
+    ) -> gml::Result<Value> {
+        let mut returned_value = Default::default();
+        match action.target {
+            None | Some(gml::SELF) | Some(gml::OTHER) => {
+                if action.target == Some(gml::OTHER) {
+                    context.this = other;
+                    context.other = this;
+                }
+
+                let mut arg_values: [Value; 16] = Default::default();
+                for (dest, src) in arg_values.iter_mut().zip(args.iter()) {
+                    *dest = self.eval(src, context)?;
+                }
+
+                returned_value = match gml_body {
+                    GmlBody::ContextFunction(f) => f.0(self, context, &arg_values[..args.len()])?,
+                    GmlBody::StateFunction(f) => f.0(self, &arg_values[..args.len()])?,
+                    GmlBody::RoutineFunction(f) => f.0(self, &arg_values[..args.len()])?,
+                    GmlBody::ValueFunction(f) => f.0(&arg_values[..args.len()])?,
+                    GmlBody::Code(code) => {
+                        context.arguments = arg_values;
+                        context.argument_count = args.len();
+                        self.execute(code, context)?;
+                        context.return_value.clone()
+                    },
+                };
+            },
+            Some(i) if i < 0 => (),
+            Some(i) => {
+                context.other = this;
+                let mut iter = self.room.instance_list.iter_by_identity(i);
+                while let Some(instance) = iter.next(&self.room.instance_list) {
+                    context.this = instance;
+
+                    let mut arg_values: [Value; 16] = Default::default();
+                    for (dest, src) in arg_values.iter_mut().zip(args.iter()) {
+                        *dest = self.eval(src, context)?;
+                    }
+
+                    returned_value = match gml_body {
+                        GmlBody::ContextFunction(f) => f.0(self, context, &arg_values[..args.len()])?,
+                        GmlBody::StateFunction(f) => f.0(self, &arg_values[..args.len()])?,
+                        GmlBody::RoutineFunction(f) => f.0(self, &arg_values[..args.len()])?,
+                        GmlBody::ValueFunction(f) => f.0(&arg_values[..args.len()])?,
+                        GmlBody::Code(code) => {
+                            context.arguments = arg_values;
+                            context.argument_count = args.len();
+                            self.execute(code, context)?;
+                            context.return_value.clone()
+                        },
+                    };
+                }
+            },
+        }
+        Ok(returned_value)
+    }
 }
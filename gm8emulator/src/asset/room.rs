@@ -1,6 +1,6 @@
 use crate::{
     game::{Background, View},
-    gml::{self, runtime::Instruction},
+    gml::{self, bytecode::Program},
     tile::Tile,
     types::{Colour, ID},
 };
@@ -17,7 +17,7 @@ pub struct Room {
     pub persistent: bool,
     pub bg_colour: Colour,
     pub clear_screen: bool,
-    pub creation_code: Result<Rc<[Instruction]>, String>,
+    pub creation_code: Result<Rc<Program>, String>,
 
     pub backgrounds: Vec<Background>,
     pub views_enabled: bool,
@@ -33,7 +33,7 @@ pub struct Instance {
     pub y: i32,
     pub object: i32,
     pub id: ID,
-    pub creation: Result<Rc<[Instruction]>, String>,
+    pub creation: Result<Rc<Program>, String>,
     pub xscale: f64,
     pub yscale: f64,
     pub blend: u32,
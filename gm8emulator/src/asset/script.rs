@@ -1,4 +1,4 @@
-use crate::gml::{self, runtime::Instruction};
+use crate::gml::{self, bytecode::Program};
 use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 
@@ -6,5 +6,5 @@ use std::rc::Rc;
 pub struct Script {
     pub name: gml::String,
     pub source: gml::String,
-    pub compiled: Rc<[Instruction]>,
+    pub compiled: Rc<Program>,
 }
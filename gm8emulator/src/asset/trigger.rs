@@ -1,4 +1,4 @@
-use crate::gml::{self, runtime::Instruction};
+use crate::gml::{self, bytecode::Program};
 use gm8exe::asset::trigger::TriggerKind;
 use serde::{Deserialize, Serialize};
 use std::rc::Rc;
@@ -6,7 +6,7 @@ use std::rc::Rc;
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Trigger {
     pub name: gml::String,
-    pub condition: Rc<[Instruction]>,
+    pub condition: Rc<Program>,
     pub moment: TriggerTime,
 }
 
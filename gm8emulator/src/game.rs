@@ -1,18 +1,26 @@
 pub mod audio;
 pub mod background;
+pub mod debugger;
+pub mod dialog;
+pub mod display;
 pub mod draw;
+pub mod encoding;
 pub mod events;
 pub mod external;
 pub mod gm_save;
 pub mod includedfile;
+pub mod modal;
 pub mod model;
 pub mod movement;
 pub mod particle;
 pub mod pathfinding;
 pub mod platform;
+pub mod profiler;
 pub mod recording;
 pub mod replay;
 pub mod savestate;
+pub mod search;
+pub mod shell;
 pub mod surface;
 pub mod transition;
 pub mod view;
@@ -35,7 +43,7 @@ use crate::{
     },
     game::gm_save::GMSave,
     game::replay::FrameRng,
-    gml::{self, ds, ev, file, rand::Random, runtime::Instruction, Compiler, Context},
+    gml::{self, bytecode::Program, ds, ev, file, rand::Random, trace::Source, Compiler, Context},
     handleman::{HandleArray, HandleList, HandleManager},
     input::{self, Input},
     instance::{DummyFieldHolder, Instance, InstanceState},
@@ -201,6 +209,8 @@ pub struct Game {
     pub error_last: gml::String,
 
     pub game_id: i32,
+    // CRC-32 of the game's exe, which replays are checked against. It's set after launching, if it's known.
+    pub game_hash: u32,
     pub program_directory: gml::String,
     pub temp_directory: gml::String,
     pub included_files: Vec<IncludedFile>,
@@ -215,7 +225,13 @@ pub struct Game {
     pub esc_close_game: bool,
 
     pub play_type: PlayType,
+    pub shell_policy: shell::Policy, // what execute_program() and execute_shell() are allowed to do
     pub stored_events: VecDeque<replay::Event>,
+    pub dialog_answers: VecDeque<dialog::Answer>, // answers prepared in the TAS UI for upcoming dialogs
+    pub unanswered_dialog: Option<dialog::Kind>,  // last dialog that was cancelled because nothing was prepared
+    pub clipboard: Option<gml::String>,           // emulated clipboard, used instead of the host's outside of normal play
+    pub debugger: Option<Box<debugger::Debugger>>, // attached from the TAS UI, not part of savestates
+    pub profiler: Option<Box<profiler::Profiler>>, // turned on from the command line or the TAS UI
     pub frame_limiter: bool, // whether to limit FPS of gameplay by room_speed
     pub frame_limit_at: usize, // on which frame to start limiting FPS
 
@@ -226,13 +242,19 @@ pub struct Game {
     pub window_border: bool,
     pub window_caption: String,
     pub window_cursor_gml: i32,
+    pub window_fullscreen: bool,
     pub window_icons: bool,
     pub window_inner_size: (u32, u32),
+    // Size of the window while something other than the game is shown in it, such as the TAS UI
+    pub window_ui_size: Option<(u32, u32)>,
     pub window_offset_spoof: (i32, i32),
     pub window_is_logical_dpi: bool,
     pub window_sizeable: bool,
+    pub window_stayontop: bool,
     pub window_visible: bool,
     pub close_requested: bool,
+    // Display the game thinks it's running on
+    pub display: display::Display,
     // Scaling type
     pub scaling: Scaling,
     // Width the window is supposed to have, assuming it hasn't been resized by the user
@@ -268,7 +290,7 @@ pub enum SceneChange {
 /// A function defined in an extension, which could either be a DLL external or some compiled GML
 pub enum ExtensionFunction {
     Dll(String, ID),
-    Gml(Rc<[Instruction]>),
+    Gml(Rc<Program>),
 }
 
 /// A room state originally loaded from a room asset.
@@ -580,6 +602,14 @@ impl Game {
             .enumerate()
             .for_each(|(i, x)| compiler.register_user_constant(x.name.0.clone(), i + const_index));
 
+        // Fold any constants which don't depend on the game state, in the same order they get evaluated in
+        let extension_constants = extensions.iter().flat_map(|x| x.files.iter()).flat_map(|x| x.consts.iter());
+        extension_constants
+            .map(|x| &x.value.0)
+            .chain(constants.iter().map(|x| &x.expression.0))
+            .enumerate()
+            .for_each(|(i, source)| compiler.fold_user_constant(i, source));
+
         // Set up a Renderer
         let options = RendererOptions {
             size: (room1_width, room1_height),
@@ -1314,6 +1344,7 @@ impl Game {
             health: Real::from(100.0),
             health_capt: "Health: ".to_string().into(),
             game_id: game_id as i32,
+            game_hash: 0,
             program_directory: program_directory.into(),
             temp_directory: "".into(),
             included_files,
@@ -1342,7 +1373,13 @@ impl Game {
             close_requested: false,
             scaling,
             play_type,
+            shell_policy: shell::Policy::default_for(play_type),
             stored_events: VecDeque::new(),
+            dialog_answers: VecDeque::new(),
+            unanswered_dialog: None,
+            clipboard: None,
+            debugger: None,
+            profiler: None,
 
             // load_room sets this
             unscaled_width: 0,
@@ -1351,11 +1388,15 @@ impl Game {
             // lazy state
             window_caption: room1_caption.clone(),
             window_cursor_gml: gml::mappings::constants::CR_DEFAULT as _,
+            window_fullscreen: settings.fullscreen,
             window_inner_size: (width, height),
+            window_ui_size: None,
             window_is_logical_dpi: false,
             window_offset_spoof: (0, 0),
             window_sizeable: settings.allow_resize,
+            window_stayontop: settings.window_on_top,
             window_visible: true,
+            display: display::Display::default(),
         };
 
         game.temp_directory = game.encode_str_maybe(temp_directory.to_str().unwrap()).unwrap().into_owned().into();
@@ -1364,7 +1405,7 @@ impl Game {
         for extension in extensions {
             for file in extension.files {
                 for constant in file.consts {
-                    let expr = game.compiler.compile_expression(&constant.value.0)?;
+                    let expr = game.compiler.compile_user_constant(game.constants.len(), &constant.value.0)?;
                     let dummy_instance = game
                         .room
                         .instance_list
@@ -1377,7 +1418,7 @@ impl Game {
         }
 
         for c in &constants {
-            let expr = game.compiler.compile_expression(&c.expression.0)?;
+            let expr = game.compiler.compile_user_constant(game.constants.len(), &c.expression.0)?;
             let dummy_instance = game
                 .room
                 .instance_list
@@ -1392,6 +1433,9 @@ impl Game {
         game.globals.vars.clear();
         game.globalvars.clear();
 
+        if game.window_fullscreen {
+            game.update_window_fullscreen();
+        }
         game.window.set_visible(true);
 
         Ok(game)
@@ -1496,6 +1540,15 @@ impl Game {
         }
     }
 
+    /// Applies the tracked fullscreen state to the real window.
+    /// This only happens in normal play, in record and replay mode the window is left as it is.
+    pub fn update_window_fullscreen(&mut self) {
+        if self.play_type == PlayType::Normal {
+            self.window.set_borderless(self.window_fullscreen || !self.window_border);
+            self.window.set_maximised(self.window_fullscreen);
+        }
+    }
+
     pub fn decode_str<'a>(&self, string: &'a [u8]) -> Cow<'a, str> {
         match self.gm_version {
             Version::GameMaker8_0 => self.encoding.decode_without_bom_handling(string).0,
@@ -1675,7 +1728,8 @@ impl Game {
                 // Run this instance's room creation code
                 let mut new_context = Context::with_single_instance(*handle);
                 new_context.event_object = instance.object;
-                self.execute(&instance.creation.clone()?, &mut new_context)?;
+                self.execute(&instance.creation.clone()?, &mut new_context)
+                    .map_err(|e| e.within(Source::InstanceCreation(instance.id)))?;
 
                 if !self.swap_creation_events {
                     // Run create event for this instance
@@ -1697,7 +1751,8 @@ impl Game {
                 .instance_list
                 .insert_dummy(Instance::new_dummy(self.assets.objects.get_asset(0).map(|x| x.as_ref())));
             let mut new_context = Context::with_single_instance(dummy_instance);
-            self.execute(&room.creation_code?, &mut new_context)?;
+            self.execute(&room.creation_code.clone()?, &mut new_context)
+                .map_err(|e| e.within(Source::RoomCreation(self.decode_str(room.name.as_ref()).into_owned())))?;
             self.room.instance_list.remove_dummy(dummy_instance);
         }
 
@@ -1847,8 +1902,9 @@ impl Game {
         while let Some(handle) = iter.next(&self.room.instance_list) {
             let instance = self.room.instance_list.get(handle);
             let object_index = instance.object_index.get();
+            let timeline_index = instance.timeline_index.get();
             if instance.timeline_running.get() {
-                if let Some(timeline) = self.assets.timelines.get_asset(instance.timeline_index.get()) {
+                if let Some(timeline) = self.assets.timelines.get_asset(timeline_index) {
                     let moments = timeline.moments.clone();
                     let timeline_len = Real::from(*moments.borrow().keys().max().unwrap_or(&0));
                     let old_position = instance.timeline_position.get();
@@ -1867,21 +1923,23 @@ impl Game {
 
                     if timeline_len >= Real::from(0) {
                         if speed > Real::from(0) {
-                            for (_, tree) in moments
+                            for (&moment, tree) in moments
                                 .borrow()
                                 .iter()
                                 .filter(|(&x, _)| Real::from(x) >= old_position && Real::from(x) < new_position)
                             {
-                                self.execute_tree(tree.clone(), handle, handle, 0, 0, object_index)?;
+                                self.execute_tree(tree.clone(), handle, handle, 0, 0, object_index)
+                                    .map_err(|e| e.within(self.timeline_source(timeline_index, moment)))?;
                             }
                         } else if speed < Real::from(0) {
-                            for (_, tree) in moments
+                            for (&moment, tree) in moments
                                 .borrow()
                                 .iter()
                                 .filter(|(&x, _)| Real::from(x) <= old_position && Real::from(x) > new_position)
                                 .rev()
                             {
-                                self.execute_tree(tree.clone(), handle, handle, 0, 0, object_index)?;
+                                self.execute_tree(tree.clone(), handle, handle, 0, 0, object_index)
+                                    .map_err(|e| e.within(self.timeline_source(timeline_index, moment)))?;
                             }
                         }
                     }
@@ -2202,7 +2260,11 @@ impl Game {
 
     pub fn set_input_from_frame(&mut self, frame: &crate::game::replay::Frame) {
         for ev in frame.events.iter() {
-            self.stored_events.push_back(ev.clone());
+            match ev {
+                // pasting into the emulated clipboard happens at the start of the frame, it isn't waiting on anything
+                replay::Event::PasteClipboard(text) => self.clipboard = Some(text.clone().into()),
+                _ => self.stored_events.push_back(ev.clone()),
+            }
         }
 
         if let Some(seed) = &frame.new_seed {
@@ -2229,17 +2291,106 @@ impl Game {
         }
     }
 
-    // Replays some recorded inputs to the game
-    pub fn replay(mut self, replay: Replay, output_bin: Option<PathBuf>, start_save_path: Option<&PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
-        let mut frame_count: usize = 0;
-        self.rand.set_seed(replay.start_seed);
-        self.clock = GameClock::SpoofedNanos(replay.start_time);
+    /// Runs one frame of a replay the same way as in record mode, but without drawing any UI.
+    pub fn run_replay_frame(&mut self, frame: &replay::Frame) -> Result<(), Box<dyn std::error::Error>> {
+        self.input.mouse_step();
+        self.set_input_from_frame(frame);
+        self.unanswered_dialog = None;
+
+        self.frame()?;
+        match self.scene_change {
+            Some(SceneChange::Room(id)) => self.load_room(id)?,
+            Some(SceneChange::Restart) => self.restart()?,
+            Some(SceneChange::End) => self.restart()?,
+            Some(SceneChange::Load(ref mut path)) => {
+                let path = std::mem::take(path);
+                self.load_gm_save(path)?
+            },
+            None => (),
+        }
 
+        if let GameClock::SpoofedNanos(t) = &mut self.clock {
+            *t += 1_000_000_000 / self.room.speed as u128;
+        }
+        if self.frame_counter == self.room.speed {
+            self.fps = self.room.speed;
+            self.frame_counter = 0;
+        }
+        self.frame_counter += 1;
+        Ok(())
+    }
+
+    /// Runs `f` in replay mode, which stored events are only used up in, and then switches back.
+    fn in_replay_mode<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let play_type = std::mem::replace(&mut self.play_type, PlayType::Replay);
+        let result = f(self);
+        self.play_type = play_type;
+        result
+    }
+
+    /// Plays a replay from the very start of the game without drawing anything, such as to rebuild a savestate of
+    /// its last frame. The game mustn't have run any of its startup yet.
+    pub fn play_from_start(&mut self, replay: &Replay) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.in_replay_mode(|game| -> Result<(), Box<dyn std::error::Error>> {
+            game.rand.set_seed(replay.start_seed);
+            game.clock = GameClock::SpoofedNanos(replay.start_time);
+            game.stored_events.extend(replay.startup_events.iter().cloned());
+            game.init()?;
+            match game.scene_change {
+                Some(SceneChange::Room(id)) => game.load_room(id)?,
+                Some(SceneChange::Restart) => game.restart()?,
+                Some(SceneChange::End) => return Err("the game ended during startup".into()),
+                Some(SceneChange::Load(ref mut path)) => {
+                    let path = std::mem::take(path);
+                    game.load_gm_save(path)?
+                },
+                None => (),
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => self.play_frames(replay, 0),
+            Err(e) => {
+                self.stored_events.clear();
+                Err(e)
+            },
+        }
+    }
+
+    /// Plays a replay's frames from the given one onwards without drawing anything. The game must already be at
+    /// that frame of the replay.
+    pub fn play_frames(&mut self, replay: &Replay, start: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.in_replay_mode(|game| -> Result<(), Box<dyn std::error::Error>> {
+            for i in start..replay.frame_count() {
+                if !game.stored_events.is_empty() {
+                    let count = game.stored_events.len();
+                    return Err(format!("{} stored events left over at the start of frame {}", count, i).into())
+                }
+                game.run_replay_frame(replay.get_frame(i).unwrap())?;
+            }
+            Ok(())
+        });
+        self.stored_events.clear();
+        result
+    }
+
+    /// Uploads blank sprites in place of the font and grid textures the TAS UI creates, so textures made by the game
+    /// get the same IDs as they did while recording.
+    pub fn upload_tas_ui_sprites(&mut self) {
         // the tas ui creates some sprites, so as a hotfix we need to generate them here too
         // TODO don't
         for _ in 0..2 {
             self.renderer.upload_sprite(Box::new([0, 0, 0, 0]), 1, 1, 0, 0).expect("Failed to upload blank sprite");
         }
+    }
+
+    // Replays some recorded inputs to the game
+    pub fn replay(mut self, replay: Replay, output_bin: Option<PathBuf>, start_save_path: Option<&PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut frame_count: usize = 0;
+        self.rand.set_seed(replay.start_seed);
+        self.clock = GameClock::SpoofedNanos(replay.start_time);
+
+        self.upload_tas_ui_sprites();
 
         let mut clean_state = true;
         if start_save_path.is_some() {
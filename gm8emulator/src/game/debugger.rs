@@ -0,0 +1,328 @@
+use crate::{
+    game::Game,
+    gml::{
+        bytecode::Program,
+        trace::{Frame, Source},
+        Context, Value,
+    },
+    instance::Field,
+};
+use gml_parser::lexer::Position;
+use std::collections::HashMap;
+
+/// A piece of code a breakpoint can be placed in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// A script, by name
+    Script(String),
+    /// An object's event, named the same way as in error messages, e.g. "Alarm Event for alarm 0"
+    Event { object: String, event: String },
+}
+
+pub struct Breakpoint {
+    pub location: Location,
+    /// Line to stop at, or None to stop as soon as the code is entered
+    pub line: Option<usize>,
+    pub enabled: bool,
+}
+
+/// How the game should carry on after being paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Keep going until a breakpoint is hit
+    Run,
+    /// Stop at the next line, wherever it is
+    Into,
+    /// Stop at the next line that isn't in a script or event called from this one
+    Over(usize),
+    /// Stop at the next line after this script or event returns
+    Out(usize),
+}
+
+/// State of the GML debugger. Only exists on the Game while the debugger is attached in the TAS UI,
+/// and isn't part of savestates.
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watches: Vec<String>,
+
+    /// Pause at the next line of GML that runs, no matter where it is
+    pub break_requested: bool,
+
+    /// The live call stack, outermost frame first
+    stack: Vec<Frame>,
+    step: Step,
+    last_line: Option<(usize, usize)>, // (stack depth, line) of the last line seen, so a line isn't hit twice in a row
+
+    /// Whether the user changed anything in the game while it was paused
+    modified: bool,
+
+    /// Watch expressions compiled against a copy of the game's compiler, so that fields only named in a watch don't
+    /// get registered in the real one. Thrown away whenever the game registers new fields, since those could then
+    /// have been given the same IDs as the copy gave the watch's fields.
+    watch_programs: HashMap<String, Result<Program, String>>,
+    watch_field_count: usize,
+
+    code_input: String,
+    output: Vec<String>,
+}
+
+impl Location {
+    fn matches(&self, source: &Source) -> bool {
+        match (self, source) {
+            (Self::Script(name), Source::Script(script)) => name.eq_ignore_ascii_case(script),
+            (Self::Event { object, event }, Source::Event { object: o, event: e }) => {
+                object.eq_ignore_ascii_case(o) && event.eq_ignore_ascii_case(e)
+            },
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Script(name) => write!(f, "script {}", name),
+            Self::Event { object, event } => write!(f, "{} for object {}", event, object),
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            break_requested: false,
+            stack: Vec::new(),
+            step: Step::Run,
+            last_line: None,
+            modified: false,
+            watch_programs: HashMap::new(),
+            watch_field_count: 0,
+            code_input: String::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Returns whether anything in the game was changed from the debugger since this was last called.
+    pub fn take_modified(&mut self) -> bool {
+        std::mem::take(&mut self.modified)
+    }
+
+    /// Updates the call stack with a new line and returns whether the game should pause on it.
+    fn reached_line(&mut self, pos: Position) -> bool {
+        let depth = self.stack.len();
+        let entering = match self.stack.last_mut() {
+            Some(frame) => frame.pos.replace(pos).is_none(),
+            None => false,
+        };
+        let new_line = self.last_line != Some((depth, pos.line));
+        self.last_line = Some((depth, pos.line));
+
+        let stepping = match self.step {
+            Step::Run => false,
+            Step::Into => true,
+            Step::Over(d) => depth <= d,
+            Step::Out(d) => depth < d,
+        };
+        if std::mem::take(&mut self.break_requested) || stepping {
+            return true
+        }
+        match self.stack.last() {
+            Some(frame) => self.breakpoints.iter().filter(|bp| bp.enabled).any(|bp| {
+                bp.location.matches(&frame.source)
+                    && match bp.line {
+                        Some(line) => new_line && line == pos.line,
+                        None => entering,
+                    }
+            }),
+            None => false,
+        }
+    }
+
+    /// Draws the paused view. Returns how to continue once the user picks something.
+    fn show_paused(&mut self, game: &mut Game, context: &mut Context, frame: &imgui::Ui) -> Option<Step> {
+        let depth = self.stack.len();
+        let mut step = None;
+
+        match self.stack.last() {
+            Some(top) => frame.text(format!("Paused in {}", describe(top))),
+            None => frame.text("Paused"),
+        }
+        if frame.button("Continue") {
+            step = Some(Step::Run);
+        }
+        frame.same_line();
+        if frame.button("Step into") {
+            step = Some(Step::Into);
+        }
+        frame.same_line();
+        if frame.button("Step over") {
+            step = Some(Step::Over(depth));
+        }
+        frame.same_line();
+        if frame.button("Step out") {
+            step = Some(Step::Out(depth));
+        }
+        if let Some(Frame { source, pos: Some(pos) }) = self.stack.last() {
+            let location = match source {
+                Source::Script(name) => Some(Location::Script(name.clone())),
+                Source::Event { object, event } => {
+                    Some(Location::Event { object: object.clone(), event: event.clone() })
+                },
+                _ => None,
+            };
+            if let Some(location) = location {
+                frame.same_line();
+                if frame.button("Break on this line") {
+                    self.breakpoints.push(Breakpoint { location, line: Some(pos.line), enabled: true });
+                }
+            }
+        }
+        frame.separator();
+
+        if frame.collapsing_header("Call stack", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+            for stack_frame in self.stack.iter().rev() {
+                frame.text(describe(stack_frame));
+            }
+        }
+
+        if frame.collapsing_header("Watches", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+            if game.compiler.field_count() != self.watch_field_count {
+                self.watch_programs.clear();
+                self.watch_field_count = game.compiler.field_count();
+            }
+            let mut compiler = None;
+            for watch in &self.watches {
+                let program = self.watch_programs.entry(watch.clone()).or_insert_with(|| {
+                    let compiler = compiler.get_or_insert_with(|| game.compiler.clone());
+                    compiler.compile_expression(watch.as_bytes()).map_err(|e| e.to_string())
+                });
+                frame.text(format!("{} = {}", watch, eval_watch(game, program, context)));
+            }
+        }
+
+        if frame.collapsing_header("Locals", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+            for (i, arg) in context.arguments[..context.argument_count.min(16)].iter().enumerate() {
+                frame.text(format!("argument{} = {}", i, arg));
+            }
+            for line in field_lines(game, &context.locals.fields) {
+                frame.text(line);
+            }
+        }
+
+        for (header, handle) in [("self", context.this), ("other", context.other)] {
+            if frame.collapsing_header(header, imgui::TreeNodeFlags::empty()) {
+                let instance = game.room.instance_list.get(handle);
+                frame.text(format!("id = {}", instance.id.get()));
+                for line in field_lines(game, &instance.fields.borrow()) {
+                    frame.text(line);
+                }
+            }
+        }
+
+        if frame.collapsing_header("global", imgui::TreeNodeFlags::empty()) {
+            for line in field_lines(game, &game.globals.fields) {
+                frame.text(line);
+            }
+        }
+
+        frame.separator();
+        for line in &self.output {
+            frame.text(line);
+        }
+        let run = frame.input_text("##debuggercode", &mut self.code_input).enter_returns_true(true).build();
+        frame.same_line();
+        if frame.button("Run") || run {
+            let mut args: [Value; 16] = Default::default();
+            args[0] = self.code_input.clone().into();
+            match game.execute_string(context, &args) {
+                Ok(value) => self.output.push(format!("{} = {}", self.code_input, value)),
+                Err(e) => self.output.push(format!("{}: {}", self.code_input, e)),
+            }
+            self.code_input.clear();
+            self.modified = true;
+        }
+
+        step
+    }
+}
+
+impl Game {
+    /// Tells the debugger, if one is attached, that some code is being entered.
+    pub fn debug_enter(&mut self, source: impl FnOnce(&Game) -> Source) {
+        if self.debugger.is_some() {
+            let source = source(self);
+            if let Some(debugger) = &mut self.debugger {
+                debugger.stack.push(Frame { source, pos: None });
+            }
+        }
+    }
+
+    /// Tells the debugger, if one is attached, that the code last entered has finished.
+    pub fn debug_leave(&mut self) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.stack.pop();
+        }
+    }
+
+    /// Called before each line of GML while a debugger is attached. Pauses the game there if it should.
+    pub fn debug_line(&mut self, pos: Position, context: &mut Context) {
+        let pause = match &mut self.debugger {
+            Some(debugger) => debugger.reached_line(pos),
+            None => return,
+        };
+        if pause {
+            // Detach the debugger while paused, so any code run from it doesn't end up back in here
+            if let Some(mut debugger) = self.debugger.take() {
+                let step = self
+                    .run_modal("Debugger", [720.0, 540.0], |game, frame| debugger.show_paused(game, context, frame));
+                debugger.step = step.unwrap_or(Step::Run);
+                self.debugger = Some(debugger);
+            }
+        }
+    }
+
+}
+
+/// Evaluates a compiled watch expression, as long as doing so can't change anything in the game.
+fn eval_watch(game: &mut Game, program: &Result<Program, String>, context: &mut Context) -> String {
+    match program {
+        Ok(program) if program.is_pure() => match game.eval(program, context) {
+            Ok(value) => value.to_string(),
+            Err(e) => format!("<{}>", e),
+        },
+        Ok(_) => "<not evaluated, it calls functions which could change the game>".into(),
+        Err(e) => format!("<{}>", e),
+    }
+}
+
+fn describe(frame: &Frame) -> String {
+    let source = match &frame.source {
+        Source::Script(name) => format!("script {}", name),
+        Source::Event { object, event } => format!("{} for object {}", event, object),
+        source => format!("{:?}", source),
+    };
+    match frame.pos {
+        Some(pos) => format!("{} at line {}, position {}", source, pos.line, pos.column),
+        None => source,
+    }
+}
+
+/// Lists some fields by name, sorted so they don't jump around between frames.
+fn field_lines(game: &Game, fields: &HashMap<usize, Field>) -> Vec<String> {
+    let mut lines = Vec::with_capacity(fields.len());
+    for (id, field) in fields {
+        let name = game.compiler.get_field_name(*id).unwrap_or("<???>".into());
+        match field {
+            Field::Single(value) => lines.push(format!("{} = {}", name, value)),
+            Field::Array(map) => {
+                let mut indices = map.keys().copied().collect::<Vec<_>>();
+                indices.sort_unstable();
+                lines.extend(indices.into_iter().map(|i| format!("{}[{}] = {}", name, i, map[&i])));
+            },
+        }
+    }
+    lines.sort();
+    lines
+}
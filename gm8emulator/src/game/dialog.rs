@@ -0,0 +1,274 @@
+use crate::{
+    game::{replay, Game},
+    gml::Value,
+    types::Colour,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// One of the dialogs a game can open with get_open_filename() and friends.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    OpenFile { filter: String, default: String },
+    SaveFile { filter: String, default: String },
+    Directory { caption: String, root: String },
+    Colour(i32),
+}
+
+/// The answer a dialog was closed with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Answer {
+    Cancelled,
+    Path(String),
+    Colour(i32),
+}
+
+impl Kind {
+    /// Name of the kernel function which opens this dialog.
+    pub fn function_name(&self) -> &'static str {
+        match self {
+            Self::OpenFile { .. } => "get_open_filename",
+            Self::SaveFile { .. } => "get_save_filename",
+            Self::Directory { .. } => "get_directory",
+            Self::Colour(_) => "get_color",
+        }
+    }
+
+    /// Wraps the value returned by this dialog in the replay event used to store it.
+    pub fn to_event(&self, value: Value) -> replay::Event {
+        match self {
+            Self::OpenFile { .. } => replay::Event::GetOpenFilename(value),
+            Self::SaveFile { .. } => replay::Event::GetSaveFilename(value),
+            Self::Directory { .. } => replay::Event::GetDirectory(value),
+            Self::Colour(_) => replay::Event::GetColor(value),
+        }
+    }
+
+    /// Unwraps the value stored in a replay event, if the event belongs to this kind of dialog.
+    pub fn from_event(&self, event: replay::Event) -> Option<Value> {
+        match (self, event) {
+            (Self::OpenFile { .. }, replay::Event::GetOpenFilename(value))
+            | (Self::SaveFile { .. }, replay::Event::GetSaveFilename(value))
+            | (Self::Directory { .. }, replay::Event::GetDirectory(value))
+            | (Self::Colour(_), replay::Event::GetColor(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a GM8 file filter such as "Text files|*.txt|All files|*.*" into (name, patterns) pairs.
+fn parse_filter(filter: &str) -> Vec<(String, Vec<String>)> {
+    let mut parts = filter.split('|');
+    let mut filters = Vec::new();
+    while let Some(name) = parts.next() {
+        let patterns = parts.next().unwrap_or("*.*");
+        filters.push((name.to_string(), patterns.split(';').map(|p| p.trim().to_lowercase()).collect()));
+    }
+    if filters.iter().all(|(name, _)| name.is_empty()) {
+        filters = vec![("All files".into(), vec!["*.*".into()])];
+    }
+    filters
+}
+
+/// Case-insensitive wildcard match supporting '*' and '?', the way Windows matches file patterns.
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| wildcard_match(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && wildcard_match(rest, &name[1..]),
+        Some((c, rest)) => {
+            name.first().map_or(false, |n| n.eq_ignore_ascii_case(c)) && wildcard_match(rest, &name[1..])
+        },
+    }
+}
+
+fn matches_patterns(patterns: &[String], name: &str) -> bool {
+    // "*.*" also matches files without an extension
+    patterns.iter().any(|p| p == "*.*" || wildcard_match(p.as_bytes(), name.as_bytes()))
+}
+
+/// Resolves a directory, giving it back only if it exists and is inside the root, which must already be resolved.
+fn inside(root: &Path, directory: &Path) -> Option<PathBuf> {
+    directory.canonicalize().ok().filter(|dir| dir.is_dir() && dir.starts_with(root))
+}
+
+/// State of an emulated dialog, drawn with imgui instead of using the native ones.
+pub struct Dialog {
+    kind: Kind,
+    /// The game's working directory. The file browser can't leave it, and paths are given relative to it,
+    /// so that replays don't depend on where the game is on the machine they were made on.
+    root: PathBuf,
+    directory: PathBuf,
+    entries: Vec<(String, bool)>,
+    filters: Vec<(String, Vec<String>)>,
+    filter_index: usize,
+    filename: String,
+    colour: [f32; 3],
+}
+
+impl Dialog {
+    /// Creates a dialog, with the file browser starting in the game's working directory.
+    pub fn new(kind: Kind) -> Self {
+        let root = std::env::current_dir().and_then(|dir| dir.canonicalize()).unwrap_or_default();
+        let mut directory = root.clone();
+        let (filters, filename, colour) = match &kind {
+            Kind::OpenFile { filter, default } | Kind::SaveFile { filter, default } => {
+                let default = Path::new(default);
+                if let Some(parent) = default.parent().and_then(|p| inside(&root, &root.join(p))) {
+                    directory = parent;
+                }
+                let filename = default.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+                (parse_filter(filter), filename, [0.0; 3])
+            },
+            Kind::Directory { root: start, .. } => {
+                if let Some(start) = inside(&root, &root.join(start)) {
+                    directory = start;
+                }
+                (Vec::new(), String::new(), [0.0; 3])
+            },
+            Kind::Colour(col) => {
+                let colour = Colour::from(*col as u32);
+                (Vec::new(), String::new(), [colour.r as f32, colour.g as f32, colour.b as f32])
+            },
+        };
+        let mut dialog =
+            Self { kind, root, directory, entries: Vec::new(), filters, filter_index: 0, filename, colour };
+        dialog.refresh();
+        dialog
+    }
+
+    pub fn kind(&self) -> &Kind {
+        &self.kind
+    }
+
+    /// Re-reads the current directory, listing folders first.
+    fn refresh(&mut self) {
+        let show_files = matches!(self.kind, Kind::OpenFile { .. } | Kind::SaveFile { .. });
+        let patterns = self.filters.get(self.filter_index).map(|(_, p)| p.as_slice()).unwrap_or(&[]);
+        self.entries = fs::read_dir(&self.directory)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let is_dir = entry.file_type().ok()?.is_dir();
+                (is_dir || (show_files && matches_patterns(patterns, &name))).then(|| (name, is_dir))
+            })
+            .collect();
+        self.entries
+            .sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then_with(|| a.to_lowercase().cmp(&b.to_lowercase())));
+    }
+
+    /// Moves to another directory, as long as it's inside the root.
+    fn enter(&mut self, directory: PathBuf) {
+        if let Some(directory) = inside(&self.root, &directory) {
+            self.directory = directory;
+            self.refresh();
+        }
+    }
+
+    /// Gives a path inside the root relative to it, with backslashes as GM8 games expect. The root itself is ".".
+    fn relative(&self, path: &Path) -> String {
+        let parts = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            ".".into()
+        } else {
+            parts.join("\\")
+        }
+    }
+
+    /// Draws the dialog's widgets into the current imgui window.
+    /// Returns Some once the user has either confirmed or cancelled the dialog.
+    pub fn show(&mut self, frame: &imgui::Ui) -> Option<Answer> {
+        if let Kind::Colour(_) = self.kind {
+            frame.color_picker3("##colour", &mut self.colour);
+            let colour = Colour::new(self.colour[0].into(), self.colour[1].into(), self.colour[2].into());
+            return self.buttons(frame, Some(Answer::Colour(colour.as_decimal() as i32)))
+        }
+
+        frame.text(self.relative(&self.directory));
+        if self.directory != self.root && frame.button("Up") {
+            if let Some(parent) = self.directory.parent() {
+                self.enter(parent.to_path_buf());
+            }
+        }
+
+        let mut entered = None;
+        let list_height = frame.content_region_avail()[1] - frame.frame_height_with_spacing() * 3.0;
+        frame.child_window("##entries").size([0.0, list_height.max(100.0)]).border(true).build(|| {
+            for (name, is_dir) in &self.entries {
+                let label = if *is_dir { format!("[{}]", name) } else { name.clone() };
+                let selected = !*is_dir && *name == self.filename;
+                if frame.selectable_config(&label).selected(selected).allow_double_click(true).build() {
+                    if *is_dir {
+                        if frame.is_mouse_double_clicked(imgui::MouseButton::Left) {
+                            entered = Some(self.directory.join(name));
+                        }
+                    } else {
+                        self.filename = name.clone();
+                    }
+                }
+            }
+        });
+        if let Some(directory) = entered {
+            self.enter(directory);
+        }
+
+        match &self.kind {
+            Kind::OpenFile { .. } | Kind::SaveFile { .. } => {
+                frame.input_text("File name", &mut self.filename).build();
+                let names = self.filters.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+                if frame.combo_simple_string("File type", &mut self.filter_index, &names) {
+                    self.refresh();
+                }
+                let path = self.directory.join(&self.filename);
+                // The name can't lead anywhere else, so the answer stays inside the root
+                let valid = !self.filename.is_empty()
+                    && self.filename != ".."
+                    && !self.filename.contains(|c: char| c == '/' || c == '\\')
+                    && match self.kind {
+                        Kind::OpenFile { .. } => path.is_file(),
+                        _ => !path.is_dir(),
+                    };
+                self.buttons(frame, valid.then(|| Answer::Path(self.relative(&path))))
+            },
+            Kind::Directory { caption, .. } => {
+                if !caption.is_empty() {
+                    frame.text(caption);
+                }
+                self.buttons(frame, Some(Answer::Path(self.relative(&self.directory))))
+            },
+            Kind::Colour(_) => unreachable!(),
+        }
+    }
+
+    /// Draws the OK and Cancel buttons. OK does nothing if there's no valid answer yet.
+    fn buttons(&self, frame: &imgui::Ui, answer: Option<Answer>) -> Option<Answer> {
+        if frame.button("OK") && answer.is_some() {
+            return answer
+        }
+        frame.same_line();
+        if frame.button("Cancel") {
+            Some(Answer::Cancelled)
+        } else {
+            None
+        }
+    }
+}
+
+impl Game {
+    /// Shows an emulated dialog on top of the game window until the user closes it.
+    /// Only used in normal play, the TAS UI prepares answers for dialogs ahead of time instead.
+    pub fn run_dialog(&mut self, kind: Kind) -> Answer {
+        let title = kind.function_name();
+        let mut dialog = Dialog::new(kind);
+        self.run_modal(title, [480.0, 360.0], |_, frame| dialog.show(frame)).unwrap_or(Answer::Cancelled)
+    }
+}
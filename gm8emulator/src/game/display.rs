@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Settings of the display the game is running on.
+/// In record and replay mode, this is the only display the game gets to see, so that
+/// display functions never depend on the host machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Display {
+    pub width: u32,
+    pub height: u32,
+    pub colour_depth: u32,
+    pub frequency: u32,
+}
+
+impl Display {
+    /// The display mode emulated in record and replay mode, and what display_reset() returns to.
+    pub const VIRTUAL: Self = Self { width: 1280, height: 720, colour_depth: 32, frequency: 60 };
+
+    /// Returns this display with the given settings applied, or None if GM8 would reject them.
+    /// Any argument set to -1 keeps its current value, same as in display_set_all().
+    pub fn with(&self, width: i32, height: i32, frequency: i32, colour_depth: i32) -> Option<Self> {
+        let pick = |new: i32, old: u32| match new {
+            -1 => Some(old),
+            n if n > 0 => Some(n as u32),
+            _ => None,
+        };
+        let display = Self {
+            width: pick(width, self.width)?,
+            height: pick(height, self.height)?,
+            colour_depth: pick(colour_depth, self.colour_depth)?,
+            frequency: pick(frequency, self.frequency)?,
+        };
+        matches!(display.colour_depth, 16 | 32).then(|| display)
+    }
+
+    /// Returns the position a window of the given size would need to be centered on this display.
+    pub fn center(&self, width: u32, height: u32) -> (i32, i32) {
+        ((self.width as i32 - width as i32) / 2, (self.height as i32 - height as i32) / 2)
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::VIRTUAL
+    }
+}
@@ -0,0 +1,235 @@
+//! GM8.0 stores text in whatever codepage the author's system used, and doesn't record which one that was.
+//! This guesses it from the text in the game, by decoding it with each likely codepage and seeing which gives
+//! the most believable result.
+
+use encoding_rs::Encoding;
+use gm8exe::{
+    asset::{CodeAction, PascalString},
+    GameAssets,
+};
+
+/// Rough categories of characters. Letters from different scripts rarely end up next to each other in real text,
+/// but text decoded with the wrong codepage is full of them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Ascii,
+    AsciiLetter,
+    Latin,
+    Cyrillic,
+    Kana,
+    Han,
+    Hangul,
+    CjkPunctuation,
+    Symbol,
+    Other,
+}
+
+fn classify(c: char) -> Class {
+    match c {
+        'A'..='Z' | 'a'..='z' => Class::AsciiLetter,
+        '\0'..='\x7F' => Class::Ascii,
+        '\u{D7}' | '\u{F7}' => Class::Symbol,
+        '\u{A0}'..='\u{BF}' | '\u{2010}'..='\u{203A}' | '\u{20AC}' | '\u{2122}' => Class::Symbol,
+        '\u{C0}'..='\u{24F}' => Class::Latin,
+        '\u{400}'..='\u{4FF}' => Class::Cyrillic,
+        '\u{3040}'..='\u{30FF}' => Class::Kana,
+        '\u{4E00}'..='\u{9FFF}' => Class::Han,
+        '\u{AC00}'..='\u{D7A3}' => Class::Hangul,
+        '\u{3000}'..='\u{303F}' | '\u{FF01}'..='\u{FF60}' => Class::CjkPunctuation,
+        _ => Class::Other,
+    }
+}
+
+/// Whether two adjacent characters look like they came from decoding with the wrong codepage.
+fn mismatched(a: Class, b: Class) -> bool {
+    use Class::*;
+    matches!(
+        (a, b),
+        (Cyrillic, AsciiLetter | Latin)
+            | (AsciiLetter | Latin, Cyrillic)
+            | (Hangul, Han | Kana)
+            | (Han | Kana, Hangul)
+            | (Latin, Kana | Han | Hangul)
+            | (Kana | Han | Hangul, Latin)
+    )
+}
+
+/// How believable some decoded text is. Higher is better. Characters score roughly by how many bytes they take up,
+/// so that multi-byte codepages don't lose out just because their text is shorter.
+fn plausibility(text: &str) -> i64 {
+    let mut score = 0;
+    let mut prev = (' ', Class::Ascii);
+    let mut latin_run = 0;
+    for c in text.chars() {
+        let class = classify(c);
+        score += match class {
+            Class::Ascii | Class::AsciiLetter | Class::Symbol => 0,
+            // Accented letters are sparse in Western text, long runs of them mean it's something else
+            Class::Latin if latin_run >= 2 => -2,
+            Class::Latin | Class::Cyrillic => 1,
+            Class::Han | Class::Hangul | Class::CjkPunctuation => 2,
+            Class::Kana => 3,
+            Class::Other => -3,
+        };
+        if mismatched(prev.1, class) {
+            score -= 3;
+        }
+        if c.is_uppercase() && prev.0.is_lowercase() {
+            score -= 2;
+        }
+        latin_run = if class == Class::Latin { latin_run + 1 } else { 0 };
+        prev = (c, class);
+    }
+    score
+}
+
+/// Guesses the codepage of a GM8.0 game from its asset names, code and room captions.
+/// Games with no text outside of ASCII get Shift-JIS, as that's what was always used before.
+pub fn detect(assets: &GameAssets) -> &'static Encoding {
+    guess(&samples(assets))
+}
+
+/// Picks the codepage which decodes the given text most believably.
+fn guess(samples: &[&[u8]]) -> &'static Encoding {
+    // In order of preference when they score the same
+    let candidates = [
+        encoding_rs::SHIFT_JIS,
+        encoding_rs::EUC_KR,
+        encoding_rs::GBK,
+        encoding_rs::BIG5,
+        encoding_rs::WINDOWS_1252,
+        encoding_rs::WINDOWS_1251,
+        encoding_rs::WINDOWS_1250,
+    ];
+
+    if samples.is_empty() {
+        return encoding_rs::SHIFT_JIS
+    }
+    let mut best = (encoding_rs::SHIFT_JIS, i64::MIN);
+    for encoding in candidates {
+        let score = samples
+            .iter()
+            .map(|sample| match encoding.decode_without_bom_handling_and_without_replacement(sample) {
+                Some(text) => plausibility(&text),
+                None => -4 * sample.iter().filter(|b| !b.is_ascii()).count() as i64,
+            })
+            .sum::<i64>();
+        if score > best.1 {
+            best = (encoding, score);
+        }
+    }
+    best.0
+}
+
+/// Collects every string in the game which has non-ASCII characters in it.
+fn samples(assets: &GameAssets) -> Vec<&[u8]> {
+    fn actions<'a>(out: &mut Vec<&'a [u8]>, list: &'a [CodeAction]) {
+        for action in list {
+            out.push(&action.fn_code.0);
+            out.extend(action.param_strings.iter().map(|x| x.0.as_ref()));
+        }
+    }
+    fn names<'a, T>(out: &mut Vec<&'a [u8]>, assets: &'a [Option<Box<T>>], name: fn(&T) -> &PascalString) {
+        out.extend(assets.iter().flatten().map(|x| name(x).0.as_ref()));
+    }
+
+    let mut out = Vec::new();
+    names(&mut out, &assets.sprites, |x| &x.name);
+    names(&mut out, &assets.sounds, |x| &x.name);
+    names(&mut out, &assets.backgrounds, |x| &x.name);
+    names(&mut out, &assets.paths, |x| &x.name);
+    names(&mut out, &assets.fonts, |x| &x.name);
+    names(&mut out, &assets.timelines, |x| &x.name);
+    names(&mut out, &assets.objects, |x| &x.name);
+    names(&mut out, &assets.rooms, |x| &x.name);
+    names(&mut out, &assets.triggers, |x| &x.name);
+    for script in assets.scripts.iter().flatten() {
+        out.push(&script.name.0);
+        out.push(&script.source.0);
+    }
+    for timeline in assets.timelines.iter().flatten() {
+        timeline.moments.iter().for_each(|(_, x)| actions(&mut out, x));
+    }
+    for object in assets.objects.iter().flatten() {
+        object.events.iter().flatten().for_each(|(_, x)| actions(&mut out, x));
+    }
+    for room in assets.rooms.iter().flatten() {
+        out.push(&room.caption.0);
+        out.push(&room.creation_code.0);
+        out.extend(room.instances.iter().map(|x| x.creation_code.0.as_ref()));
+    }
+    for trigger in assets.triggers.iter().flatten() {
+        out.push(&trigger.condition.0);
+    }
+    out.extend(assets.constants.iter().map(|x| x.expression.0.as_ref()));
+    out.retain(|x| !x.is_ascii());
+    out
+}
+
+/// (De)serializes an encoding by its name, for use with `#[serde(with)]`.
+pub mod by_name {
+    use encoding_rs::Encoding;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(encoding: &&'static Encoding, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(encoding.name())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<&'static Encoding, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Encoding::for_label(name.as_bytes()).ok_or_else(|| de::Error::custom(format!("unknown encoding {}", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guess_from(encoding: &'static Encoding, texts: &[&str]) -> &'static str {
+        let encoded = texts.iter().map(|text| encoding.encode(text).0.into_owned()).collect::<Vec<_>>();
+        guess(&encoded.iter().map(Vec::as_slice).collect::<Vec<_>>()).name()
+    }
+
+    #[test]
+    fn plausible_text() {
+        assert_eq!(plausibility("Press any key"), 0);
+        assert!(plausibility("Привет") > 0);
+        assert!(plausibility("ゲーム") > plausibility("Привет"));
+        // The same bytes decoded as Windows-1252
+        assert!(plausibility("Ïðèâåò") < 0);
+        // Long runs of accented letters, mixed scripts, capitals after lowercase letters and unknown characters
+        assert!(plausibility("ÃƒÂ©tÃ©") < plausibility("été"));
+        assert!(plausibility("CafПe") < plausibility("Café"));
+        assert!(plausibility("aB") < plausibility("ab"));
+        assert!(plausibility("\u{FFFD}") < 0);
+    }
+
+    #[test]
+    fn guesses() {
+        assert_eq!(guess(&[]), encoding_rs::SHIFT_JIS);
+        assert_eq!(
+            guess_from(encoding_rs::SHIFT_JIS, &["ゲームを始めるにはキーを押してください", "スコア"]),
+            "Shift_JIS",
+        );
+        assert_eq!(guess_from(encoding_rs::GBK, &["按任意键开始游戏", "分数"]), "GBK");
+        assert_eq!(guess_from(encoding_rs::BIG5, &["按任意鍵開始遊戲", "分數"]), "Big5");
+        assert_eq!(
+            guess_from(encoding_rs::WINDOWS_1251, &["Нажмите любую клавишу, чтобы начать", "Счёт"]),
+            "windows-1251",
+        );
+        assert_eq!(
+            guess_from(encoding_rs::WINDOWS_1250, &["Naciśnij dowolny klawisz, aby rozpocząć", "Łódź"]),
+            "windows-1250",
+        );
+        // Hangul decodes to just as many Chinese characters with GBK, and Western text is valid Central European
+        // text too, so these only win by coming first
+        assert_eq!(guess_from(encoding_rs::EUC_KR, &["게임을 시작하려면 아무 키나 누르세요", "점수"]), "EUC-KR");
+        assert_eq!(guess_from(encoding_rs::WINDOWS_1252, &["Größe", "Café"]), "windows-1252");
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     asset::trigger::TriggerTime,
-    game::{Game, GetAsset},
+    game::{profiler, Game, GetAsset},
     gml,
     input::MouseButton,
     instance::Instance,
@@ -77,7 +77,16 @@ impl Game {
                 }
             };
 
-            self.execute_tree(event, instance, other, event_id, event_sub as _, object_id)
+            self.debug_enter(|game| game.event_source(original_object_id, event_id, event_sub));
+            self.profile_enter(profiler::Entry::Event {
+                object: original_object_id,
+                event_type: event_id,
+                event_number: event_sub,
+            });
+            let result = self.execute_tree(event, instance, other, event_id, event_sub as _, object_id);
+            self.profile_leave();
+            self.debug_leave();
+            result.map_err(|e| e.within(self.event_source(original_object_id, event_id, event_sub)))
         } else {
             Ok(())
         }
@@ -0,0 +1,125 @@
+use crate::{
+    game::{Game, PlayType},
+    imgui_utils::{render_draw_data, EmuClipboardProvider},
+    input,
+    render::RendererState,
+    types::Colour,
+};
+use ramen::event::Event;
+
+impl Game {
+    /// Runs a UI loop on top of the game window, in the middle of whatever the game is doing.
+    /// Keeps going until `show` returns something, or returns None if the window gets closed first.
+    pub fn run_modal<T>(
+        &mut self,
+        title: &str,
+        size: [f32; 2],
+        mut show: impl FnMut(&mut Game, &imgui::Ui) -> Option<T>,
+    ) -> Option<T> {
+        let mut context = imgui::Context::create();
+        context.set_clipboard_backend(EmuClipboardProvider);
+        context.set_ini_filename(None);
+
+        let fonts = context.fonts();
+        let font_tex = fonts.build_rgba32_texture();
+        let font =
+            match self.renderer.upload_sprite(font_tex.data.into(), font_tex.width as _, font_tex.height as _, 0, 0) {
+                Ok(font) if font.0 >= 0 => font,
+                _ => return None,
+            };
+        fonts.tex_id = imgui::TextureId::new(font.0 as usize);
+
+        // Keep whatever the game has drawn so far, so it can be shown behind the UI and restored afterwards
+        let game_state = self.renderer.state();
+        let (game_width, game_height) = (self.unscaled_width, self.unscaled_height);
+        let ui_state = RendererState {
+            alpha_blending: true,
+            colour_blending: true,
+            pixel_interpolation: true,
+            texture_repeat: false,
+            using_3d: false,
+            depth_test: false,
+            write_depth: false,
+            culling: false,
+            perspective: false,
+            fog: None,
+            gouraud: false,
+            lighting_enabled: false,
+            ..game_state.clone()
+        };
+        let (mut width, mut height) = self.window_ui_size.unwrap_or(self.window_inner_size);
+        self.renderer.resize_framebuffer(width, height, true);
+        self.renderer.set_state(&ui_state);
+
+        let result = 'modal: loop {
+            let io = context.io_mut();
+            io.display_size = [width as f32, height as f32];
+            io.mouse_wheel = 0.0;
+            self.window.poll_events();
+            for event in self.window.events().into_iter().copied() {
+                match event {
+                    ev @ Event::KeyboardDown(key) | ev @ Event::KeyboardUp(key) => {
+                        if let Some(key) = input::ramen2imgui(key) {
+                            io.add_key_event(key, matches!(ev, Event::KeyboardDown(_)));
+                        }
+                    },
+                    Event::Input(chr) => io.add_input_character(chr),
+                    Event::MouseMove((x, y)) => io.mouse_pos = [x as f32, y as f32],
+                    ev @ Event::MouseDown(btn) | ev @ Event::MouseUp(btn) => usize::try_from(input::ramen2mb(btn))
+                        .ok()
+                        .and_then(|x| x.checked_sub(1))
+                        .into_iter()
+                        .for_each(|x| io.mouse_down[x] = matches!(ev, Event::MouseDown(_))),
+                    Event::ScrollUp => io.add_mouse_wheel_event([0.0, 1.0]),
+                    Event::ScrollDown => io.add_mouse_wheel_event([0.0, -1.0]),
+                    Event::Resize((w, h)) => {
+                        (width, height) = (w as _, h as _);
+                        // Outside normal play, the window isn't the game's, so its size isn't the game's either
+                        if let Some(size) = &mut self.window_ui_size {
+                            *size = (width, height);
+                        } else if self.play_type == PlayType::Normal {
+                            self.window_inner_size = (width, height);
+                        }
+                        self.renderer.resize_framebuffer(width, height, false);
+                    },
+                    Event::CloseRequest => {
+                        self.close_requested = true;
+                        break 'modal None
+                    },
+                    _ => (),
+                }
+            }
+
+            let frame = context.new_frame();
+            let mut result = None;
+            frame
+                .window(title)
+                .position([width as f32 / 2.0, height as f32 / 2.0], imgui::Condition::Always)
+                .position_pivot([0.5, 0.5])
+                .size(
+                    [(width as f32 - 20.0).min(size[0]), (height as f32 - 20.0).min(size[1])],
+                    imgui::Condition::Always,
+                )
+                .collapsible(false)
+                .resizable(false)
+                .build(|| result = show(self, frame));
+
+            self.renderer.set_view(0, 0, width as _, height as _, 0.0, 0, 0, width as _, height as _);
+            self.renderer.draw_stored(0, 0, width, height);
+            render_draw_data(&mut self.renderer, context.render());
+            self.renderer.finish(width, height, Colour::new(0.0, 0.0, 0.0));
+
+            if result.is_some() {
+                break result
+            }
+            crate::gml::datetime::sleep(std::time::Duration::from_millis(16));
+        };
+
+        self.renderer.delete_sprite(font);
+        self.renderer.resize_framebuffer(game_width, game_height, false);
+        self.renderer.set_state(&game_state);
+        self.renderer.set_view(0, 0, game_width as _, game_height as _, 0.0, 0, 0, game_width as _, game_height as _);
+        self.renderer.draw_stored(0, 0, game_width, game_height);
+        result
+    }
+}
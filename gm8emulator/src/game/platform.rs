@@ -1,4 +1,7 @@
 mod windows;
 
 #[cfg(windows)]
-pub use windows::{disk_free, disk_size, display_colour_depth, display_frequency, display_height, display_width};
+pub use windows::{
+    client_to_screen, cursor_position, disk_free, disk_size, display_colour_depth, display_frequency, display_height,
+    display_width, reset_display_mode, set_cursor_position, set_display_mode, shell_execute, test_display_mode,
+};
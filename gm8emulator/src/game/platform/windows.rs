@@ -1,6 +1,11 @@
 #![cfg(windows)]
 
-use std::{ffi::OsStr, mem, os::windows::ffi::OsStrExt, ptr};
+use std::{
+    ffi::{c_void, OsStr},
+    io, mem,
+    os::windows::ffi::OsStrExt,
+    ptr,
+};
 
 #[allow(non_snake_case)]
 #[repr(C)]
@@ -34,11 +39,29 @@ struct DEVMODEW {
     dmPanningHeight: u32,
 }
 
+#[repr(C)]
+struct POINT {
+    x: i32,
+    y: i32,
+}
+
 const ENUM_CURRENT_SETTINGS: u32 = u32::MAX;
+const CDS_TEST: u32 = 0x00000002;
+const CDS_FULLSCREEN: u32 = 0x00000004;
+const DISP_CHANGE_SUCCESSFUL: i32 = 0;
+const DM_BITSPERPEL: u32 = 0x00040000;
+const DM_PELSWIDTH: u32 = 0x00080000;
+const DM_PELSHEIGHT: u32 = 0x00100000;
+const DM_DISPLAYFREQUENCY: u32 = 0x00400000;
+const SW_SHOWNORMAL: i32 = 1;
 
 #[link(name = "user32")]
 extern "system" {
     fn EnumDisplaySettingsW(lpszDeviceName: *const u16, iModeNum: u32, lpDevMode: *mut DEVMODEW) -> i32;
+    fn ChangeDisplaySettingsW(lpDevMode: *mut DEVMODEW, dwFlags: u32) -> i32;
+    fn GetCursorPos(lpPoint: *mut POINT) -> i32;
+    fn SetCursorPos(x: i32, y: i32) -> i32;
+    fn ClientToScreen(hWnd: ramen::platform::win32::HWND, lpPoint: *mut POINT) -> i32;
     fn GetDiskFreeSpaceExW(
         lpDirectoryName: *const u16,
         lpFreeBytesAvailableToCaller: *mut u64,
@@ -47,6 +70,18 @@ extern "system" {
     ) -> i32;
 }
 
+#[link(name = "shell32")]
+extern "system" {
+    fn ShellExecuteW(
+        hwnd: *mut c_void,
+        lpOperation: *const u16,
+        lpFile: *const u16,
+        lpParameters: *const u16,
+        lpDirectory: *const u16,
+        nShowCmd: i32,
+    ) -> isize;
+}
+
 fn get_display_settings() -> Option<DEVMODEW> {
     unsafe {
         let mut device = DEVMODEW { dmSize: mem::size_of::<DEVMODEW>() as _, ..mem::zeroed() };
@@ -71,6 +106,49 @@ pub fn display_colour_depth() -> Option<u32> {
     get_display_settings().map(|dm| dm.dmBitsPerPel)
 }
 
+fn change_display_settings(width: u32, height: u32, frequency: u32, colour_depth: u32, flags: u32) -> bool {
+    unsafe {
+        let mut device = DEVMODEW {
+            dmSize: mem::size_of::<DEVMODEW>() as _,
+            dmFields: DM_PELSWIDTH | DM_PELSHEIGHT | DM_DISPLAYFREQUENCY | DM_BITSPERPEL,
+            dmPelsWidth: width,
+            dmPelsHeight: height,
+            dmDisplayFrequency: frequency,
+            dmBitsPerPel: colour_depth,
+            ..mem::zeroed()
+        };
+        ChangeDisplaySettingsW(&mut device, flags) == DISP_CHANGE_SUCCESSFUL
+    }
+}
+
+pub fn set_display_mode(width: u32, height: u32, frequency: u32, colour_depth: u32) -> bool {
+    change_display_settings(width, height, frequency, colour_depth, CDS_FULLSCREEN)
+}
+
+pub fn test_display_mode(width: u32, height: u32, frequency: u32, colour_depth: u32) -> bool {
+    change_display_settings(width, height, frequency, colour_depth, CDS_TEST)
+}
+
+pub fn reset_display_mode() -> bool {
+    unsafe { ChangeDisplaySettingsW(ptr::null_mut(), 0) == DISP_CHANGE_SUCCESSFUL }
+}
+
+pub fn cursor_position() -> Option<(i32, i32)> {
+    let mut point = POINT { x: 0, y: 0 };
+    let response = unsafe { GetCursorPos(&mut point) };
+    (response != 0).then(|| (point.x, point.y))
+}
+
+pub fn set_cursor_position(x: i32, y: i32) -> bool {
+    unsafe { SetCursorPos(x, y) != 0 }
+}
+
+pub fn client_to_screen(hwnd: ramen::platform::win32::HWND, x: i32, y: i32) -> Option<(i32, i32)> {
+    let mut point = POINT { x, y };
+    let response = unsafe { ClientToScreen(hwnd, &mut point) };
+    (response != 0).then(|| (point.x, point.y))
+}
+
 fn trim_drive(drive: Option<char>) -> Vec<u16> {
     match drive {
         Some(letter) => {
@@ -99,3 +177,21 @@ pub fn disk_size(drive: Option<char>) -> Option<u64> {
     let response = unsafe { GetDiskFreeSpaceExW(path.as_ptr(), ptr::null_mut(), &mut size, ptr::null_mut()) };
     (response != 0).then(|| size)
 }
+
+fn to_wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
+
+pub fn shell_execute(file: &str, parameters: &str) -> io::Result<()> {
+    let file = to_wide(file);
+    let parameters = to_wide(parameters);
+    let response = unsafe {
+        ShellExecuteW(ptr::null_mut(), ptr::null(), file.as_ptr(), parameters.as_ptr(), ptr::null(), SW_SHOWNORMAL)
+    };
+    // Anything above 32 means it worked, the rest are error codes
+    if response > 32 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
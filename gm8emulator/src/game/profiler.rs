@@ -0,0 +1,240 @@
+use crate::{
+    game::Game,
+    gml::{mappings, trace::Source},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Something the profiler keeps timings for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Entry {
+    Script(usize),
+    Event {
+        object: i32,
+        event_type: usize,
+        event_number: u32,
+    },
+    /// A kernel function, by its index in mappings::FUNCTIONS
+    Function(u16),
+}
+
+/// A node in the call tree. Each distinct call path gets its own node.
+struct CallNode {
+    entry: Option<Entry>, // None for the root
+    name: String,
+    parent: usize,
+    children: HashMap<Entry, usize>,
+    calls: u64,
+    total: Duration,
+    own: Duration, // total minus time spent in children
+}
+
+/// Times scripts, object events and kernel functions as the game runs them.
+/// Only exists on the Game while profiling is turned on, and isn't part of savestates.
+pub struct Profiler {
+    nodes: Vec<CallNode>,
+    stack: Vec<(usize, Instant, Duration)>, // (node, start time, time spent in children so far)
+    functions: HashMap<usize, u16>,         // function addresses to their index in mappings::FUNCTIONS
+
+    /// Where to write the reports when the profiler is dropped, if anywhere
+    pub output: Option<PathBuf>,
+}
+
+/// Timings for one entry summed over every call path it appeared in.
+pub struct FlatEntry {
+    pub name: String,
+    pub calls: u64,
+    pub total: Duration,
+    pub own: Duration,
+}
+
+impl Profiler {
+    pub fn new(output: Option<PathBuf>) -> Self {
+        let root = CallNode {
+            entry: None,
+            name: "root".into(),
+            parent: 0,
+            children: HashMap::new(),
+            calls: 0,
+            total: Duration::ZERO,
+            own: Duration::ZERO,
+        };
+        let functions = mappings::FUNCTIONS.values().enumerate().map(|(i, f)| (f.addr() as usize, i as u16)).collect();
+        Self { nodes: vec![root], stack: Vec::new(), functions, output }
+    }
+
+    /// Throws away everything recorded so far.
+    pub fn reset(&mut self) {
+        let output = self.output.take();
+        *self = Self::new(output);
+    }
+
+    /// Looks up a kernel function by its address.
+    pub fn function_entry(&self, addr: *const ()) -> Entry {
+        Entry::Function(self.functions.get(&(addr as usize)).copied().unwrap_or(u16::MAX))
+    }
+
+    fn enter(&mut self, entry: Entry, name: impl FnOnce(Entry) -> String) {
+        let parent = self.stack.last().map(|(node, _, _)| *node).unwrap_or(0);
+        let node = match self.nodes[parent].children.get(&entry) {
+            Some(node) => *node,
+            None => {
+                let node = self.nodes.len();
+                self.nodes.push(CallNode {
+                    entry: Some(entry),
+                    name: name(entry),
+                    parent,
+                    children: HashMap::new(),
+                    calls: 0,
+                    total: Duration::ZERO,
+                    own: Duration::ZERO,
+                });
+                self.nodes[parent].children.insert(entry, node);
+                node
+            },
+        };
+        self.nodes[node].calls += 1;
+        self.stack.push((node, Instant::now(), Duration::ZERO));
+    }
+
+    fn leave(&mut self) {
+        if let Some((node, start, children)) = self.stack.pop() {
+            let elapsed = start.elapsed();
+            self.nodes[node].total += elapsed;
+            self.nodes[node].own += elapsed.saturating_sub(children);
+            if let Some((_, _, parent_children)) = self.stack.last_mut() {
+                *parent_children += elapsed;
+            }
+        }
+    }
+
+    /// Sums up the call tree per entry, sorted by time spent in the entry itself.
+    /// Recursive calls only count towards the total time once.
+    pub fn flat(&self) -> Vec<FlatEntry> {
+        let mut entries: HashMap<Entry, FlatEntry> = HashMap::new();
+        for node in &self.nodes {
+            let entry = match node.entry {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let mut ancestors = HashSet::new();
+            let mut parent = node.parent;
+            while parent != 0 {
+                ancestors.extend(self.nodes[parent].entry);
+                parent = self.nodes[parent].parent;
+            }
+            let flat = entries.entry(entry).or_insert_with(|| FlatEntry {
+                name: node.name.clone(),
+                calls: 0,
+                total: Duration::ZERO,
+                own: Duration::ZERO,
+            });
+            flat.calls += node.calls;
+            flat.own += node.own;
+            if !ancestors.contains(&entry) {
+                flat.total += node.total;
+            }
+        }
+        let mut entries = entries.into_values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.own.cmp(&a.own));
+        entries
+    }
+
+    /// Writes a flat report to `path`, and the call stacks in collapsed format (as used by flamegraph tools)
+    /// next to it with the extension ".folded".
+    pub fn write_reports(&self, path: &Path) -> io::Result<()> {
+        let mut report = BufWriter::new(File::create(path)?);
+        writeln!(report, "{:>10} {:>12} {:>12}  name", "calls", "total ms", "self ms")?;
+        for entry in self.flat() {
+            writeln!(
+                report,
+                "{:>10} {:>12.3} {:>12.3}  {}",
+                entry.calls,
+                entry.total.as_secs_f64() * 1000.0,
+                entry.own.as_secs_f64() * 1000.0,
+                entry.name
+            )?;
+        }
+        report.flush()?;
+
+        let mut folded = BufWriter::new(File::create(path.with_extension("folded"))?);
+        for node in self.nodes.iter().skip(1) {
+            let micros = node.own.as_micros();
+            if micros == 0 {
+                continue
+            }
+            let mut names = vec![node.name.as_str()];
+            let mut parent = node.parent;
+            while parent != 0 {
+                names.push(&self.nodes[parent].name);
+                parent = self.nodes[parent].parent;
+            }
+            names.reverse();
+            writeln!(folded, "{} {}", names.join(";"), micros)?;
+        }
+        folded.flush()
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        if let Some(path) = &self.output {
+            match self.write_reports(path) {
+                Ok(()) => println!("Wrote profile to {}", path.display()),
+                Err(e) => eprintln!("Couldn't write profile to {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+impl Game {
+    /// Starts timing an entry, if the profiler is on.
+    pub fn profile_enter(&mut self, entry: Entry) {
+        if let Some(mut profiler) = self.profiler.take() {
+            profiler.enter(entry, |entry| self.profile_name(entry));
+            self.profiler = Some(profiler);
+        }
+    }
+
+    /// Starts timing a kernel function by its address, if the profiler is on.
+    pub fn profile_enter_function(&mut self, addr: *const ()) {
+        let entry = match &self.profiler {
+            Some(profiler) => profiler.function_entry(addr),
+            None => return,
+        };
+        self.profile_enter(entry);
+    }
+
+    /// Stops timing whatever was entered last.
+    pub fn profile_leave(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.leave();
+        }
+    }
+
+    fn profile_name(&self, entry: Entry) -> String {
+        // Semicolons separate stack frames in the collapsed format, so they can't appear in names
+        let name = match entry {
+            Entry::Script(id) => match self.script_source(id) {
+                Source::Script(name) => name,
+                _ => format!("<script {}>", id),
+            },
+            Entry::Event { object, event_type, event_number } => {
+                match self.event_source(object, event_type, event_number) {
+                    Source::Event { object, event } => format!("{} {}", object, event),
+                    _ => format!("<event {} {}>", event_type, event_number),
+                }
+            },
+            Entry::Function(index) => match mappings::FUNCTIONS.index(index.into()) {
+                Some((name, _)) => format!("{}()", name),
+                None => "<unknown function>".into(),
+            },
+        };
+        name.replace(';', ",")
+    }
+}
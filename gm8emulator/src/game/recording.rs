@@ -1,5 +1,9 @@
+mod branch_window;
+mod branches;
 mod console;
 mod control_window;
+mod debugger_window;
+mod dialog_window;
 mod game_window;
 mod input_edit;
 mod input_window;
@@ -7,30 +11,42 @@ mod instance_report;
 mod keybinds;
 mod macro_window;
 mod menu_bar;
+mod overlay;
 mod popup_dialog;
+mod profiler_window;
+mod rewind;
+mod rng_planner;
 mod savestate_window;
 mod set_mouse_dialog;
+mod variable_search;
 mod window;
 
 use crate::{
     game::{
         recording::{
+            branches::BranchTree,
             instance_report::InstanceReport,
+            rewind::RewindBuffer,
+            variable_search::WatchLog,
             window::{EmulatorContext, Openable, Window},
         },
-        replay::{self, Replay},
+        replay::{self, Metadata, Replay},
         savestate::{self, SaveState},
         Game, GameClock, SceneChange,
     },
     imgui_utils::*,
     input,
-    render::{atlas::AtlasRef, PrimitiveType, RendererState},
+    render::{atlas::AtlasRef, RendererState},
     types::Colour,
 };
-use imgui::{self, internal::RawWrapper, DrawCmd};
+use encoding_rs::Encoding;
 use ramen::{event::Event, input::Key};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, path::PathBuf, time::Instant};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use super::replay::FrameRng;
 const GRID_COLOUR_GOOD: Colour = Colour::new(0.25, 0.625, 0.38671875);
@@ -66,6 +82,12 @@ struct UIState<'g> {
     /// Buffer for lz4 stuff
     lz4_buffer: savestate::Buffer,
 
+    /// Compressed SaveStates taken every few frames, for seeking back through the replay
+    rewind: RewindBuffer,
+
+    /// Every branch of the project's input history, including the one being recorded
+    branches: BranchTree,
+
     /// Atlas ref for grid background
     grid_ref: AtlasRef,
 
@@ -103,6 +125,9 @@ struct UIState<'g> {
     /// Usually used for whichever savestate is "selected" for quick access
     cached_savestate: SaveState,
 
+    /// The game as it was before any of its startup ran, so replays can be played back from the very start
+    startup_state: SaveState,
+
     /// What the game thinks the current OpenGL state is, and will be briefly taken into use during frame advance
     game_renderer_state: RendererState,
 
@@ -121,9 +146,15 @@ struct UIState<'g> {
     /// Cached reports on the current state of any instances the user is "watching"
     instance_reports: Vec<(i32, Option<InstanceReport>)>,
 
+    /// Variables watched in the Variable Search window, logged every frame the game advances
+    watch_log: WatchLog,
+
     /// Until which frame the game should advance
     run_until_frame: Option<usize>,
 
+    /// Whether the game was seeked back, so recording over the replay from here counts as a rerecord
+    rerecord_pending: bool,
+
     /// Whether or not the current state of the game is clean or has potentially been modified
     clean_state: bool,
 
@@ -142,6 +173,9 @@ struct UIState<'g> {
     /// New RNG seed selected by the user, if they have changed it, to be taken into use on next frame advance
     new_rand: Option<FrameRng>,
 
+    /// Host clipboard text the user wants to paste into the game's emulated clipboard on next frame advance
+    new_clipboard: Option<String>,
+
     /// The currently open windows
     windows: Vec<(Box<dyn Window>, bool)>,
 
@@ -432,6 +466,12 @@ pub enum WindowKind {
     Keybindings,
     Macro(usize),
     Console(usize),
+    Dialogs,
+    Debugger,
+    Profiler,
+    Branches,
+    VariableSearch,
+    RngPlanner,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -449,9 +489,63 @@ pub struct ProjectConfig {
     is_read_only: bool,
     current_frame: usize,
     set_mouse_using_textbox: bool,
+    encoding: Option<String>,
+    author: String,
+    comments: String,
+}
+
+/// ProjectConfig as it was saved before it had the text encoding and replay metadata in it.
+#[cfg_attr(test, derive(Serialize))]
+#[derive(Deserialize)]
+struct ProjectConfigV0 {
+    ui_width: u16,
+    ui_height: u16,
+    ui_maximised: bool,
+    rerecords: u64,
+    watched_ids: Vec<i32>,
+    open_windows: Vec<WindowKind>,
+    full_keyboard: bool,
+    input_mode: InputMode,
+    quicksave_slot: usize,
+    config_path: PathBuf,
+    is_read_only: bool,
+    current_frame: usize,
+    set_mouse_using_textbox: bool,
+}
+
+impl From<ProjectConfigV0> for ProjectConfig {
+    fn from(old: ProjectConfigV0) -> Self {
+        Self {
+            ui_width: old.ui_width,
+            ui_height: old.ui_height,
+            ui_maximised: old.ui_maximised,
+            rerecords: old.rerecords,
+            watched_ids: old.watched_ids,
+            open_windows: old.open_windows,
+            full_keyboard: old.full_keyboard,
+            input_mode: old.input_mode,
+            quicksave_slot: old.quicksave_slot,
+            config_path: old.config_path,
+            is_read_only: old.is_read_only,
+            current_frame: old.current_frame,
+            set_mouse_using_textbox: old.set_mouse_using_textbox,
+            encoding: None,
+            author: String::new(),
+            comments: String::new(),
+        }
+    }
 }
 
 impl ProjectConfig {
+    fn read(config_path: &Path) -> Option<Self> {
+        Self::parse(&std::fs::read(config_path).ok()?)
+    }
+
+    /// Parses a configuration file, including ones saved before the newer fields were added to the end of it.
+    fn parse(data: &[u8]) -> Option<Self> {
+        bincode::deserialize(data).or_else(|_| bincode::deserialize::<ProjectConfigV0>(data).map(Self::from)).ok()
+    }
+
     fn from_file_or_default(config_path: &PathBuf) -> Self {
         let default_config = Self {
             ui_width: 1280,
@@ -467,12 +561,15 @@ impl ProjectConfig {
             is_read_only: false,
             current_frame: 0,
             set_mouse_using_textbox: false,
+            encoding: None,
+            author: String::new(),
+            comments: String::new(),
         };
 
         let mut config = if config_path.exists() {
-            match bincode::deserialize_from(File::open(&config_path).expect("Couldn't read project.cfg")) {
-                Ok(config) => config,
-                Err(_) => {
+            match Self::read(config_path) {
+                Some(config) => config,
+                None => {
                     println!("Warning: Couldn't parse project.cfg. Using default configuration.");
                     default_config
                 },
@@ -487,6 +584,35 @@ impl ProjectConfig {
         config
     }
 
+    /// Reads the text encoding a project was last run with, if there is one. This is needed before the game is
+    /// launched, so it doesn't create the configuration file if it's missing.
+    pub fn saved_encoding(project_path: &Path) -> Option<&'static Encoding> {
+        Self::read(&project_path.join("project.cfg"))?.encoding.and_then(|name| Encoding::for_label(name.as_bytes()))
+    }
+
+    /// The metadata to save along with this project's replays.
+    pub fn replay_metadata(&self, game: &Game) -> Metadata {
+        Metadata {
+            game_hash: game.game_hash,
+            author: self.author.clone(),
+            rerecords: self.rerecords,
+            comments: self.comments.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Takes back anything from a replay's metadata which the configuration has lost, such as by being reset,
+    /// so that the rerecord count in the project's replays never goes down.
+    fn restore_metadata(&mut self, metadata: &Metadata) {
+        self.rerecords = self.rerecords.max(metadata.rerecords);
+        if self.author.is_empty() {
+            self.author = metadata.author.clone();
+        }
+        if self.comments.is_empty() {
+            self.comments = metadata.comments.clone();
+        }
+    }
+
     /// Saves the configuration file. If that failed it will return a description of the error, otherwise None
     pub fn save(&self) -> Option<String> {
         File::create(&self.config_path)
@@ -507,6 +633,10 @@ impl Game {
             p
         };
         let mut config = ProjectConfig::from_file_or_default(&config_path);
+        if let Ok((_, Some(metadata))) = Replay::from_file_with_metadata(&project_path.join("backup.gmtas")) {
+            config.restore_metadata(&metadata);
+        }
+        config.encoding = Some(self.encoding.name().into());
         let mut replay = Replay::new(if let GameClock::SpoofedNanos(t) = self.clock { t } else { 0 }, self.rand.seed());
 
         let mut ini_filename = project_path.clone();
@@ -629,6 +759,10 @@ impl Game {
             load_backup_recording!();
         }
 
+        let mut startup_replay = replay.clone();
+        startup_replay.truncate_frames(0);
+        let startup_state = SaveState::from(self, startup_replay, self.renderer.state(), true);
+
         if !save_paths[config.quicksave_slot].exists() || (pause && start_save_path.is_none()) {
             if let Err(e) = match self.init() {
                 Ok(()) => match self.scene_change {
@@ -668,15 +802,60 @@ impl Game {
             config.current_frame = 0;
 
             if pause && save_paths[config.quicksave_slot].exists() {
-                match SaveState::from_file(&save_paths[config.quicksave_slot], &mut save_buffer) {
+                let save_path = &save_paths[config.quicksave_slot];
+                match SaveState::from_file(save_path, &mut save_buffer) {
                     Ok(save) => savestate = save,
-                    Err(e) => {
-                        // Just to initialize renderer_state and keep the compiler happy, this won't be used...
-                        renderer_state = ui_renderer_state.clone();
-                        err_string = Some(format!("(Fatal) Error loading quicksave file: {:?}", e));
-                        savestate = SaveState::from(self, replay.clone(), renderer_state.clone(), false);
-                        startup_successful = false;
-                        game_running = false;
+                    Err(e) => match SaveState::replay_from_file(save_path, &mut save_buffer) {
+                        // The game has already started, so the savestate's replay is played from here if it starts
+                        // the same way, and then the game is put back to frame 0
+                        Ok(rep) if rep.same_start(&replay) => {
+                            let mut start_replay = replay.clone();
+                            start_replay.truncate_frames(0);
+                            let start = SaveState::from(self, start_replay, renderer_state.clone(), true);
+                            let result = self.play_frames(&rep, 0);
+                            self.renderer.resize_framebuffer(config.ui_width.into(), config.ui_height.into(), true);
+                            let rebuilt_renderer_state = self.renderer.state();
+                            self.renderer.set_state(&ui_renderer_state);
+                            savestate = SaveState::from(self, rep, rebuilt_renderer_state, true);
+                            start.load_into(self);
+                            self.renderer.set_state(&ui_renderer_state);
+                            err_string = Some(match result {
+                                Ok(()) => match savestate.save_to_file(save_path, &mut save_buffer) {
+                                    Ok(()) => format!(
+                                        concat!(
+                                            "Warning: {:?} couldn't be loaded, so it was rebuilt from its replay\n\n",
+                                            "Error message: {:?}",
+                                        ),
+                                        save_path.file_name(),
+                                        e,
+                                    ),
+                                    Err(err) => format!(
+                                        "Warning: {:?} was rebuilt from its replay, but couldn't be saved: {:?}",
+                                        save_path.file_name(),
+                                        err,
+                                    ),
+                                },
+                                Err(err) => {
+                                    startup_successful = false;
+                                    game_running = false;
+                                    format!(
+                                        concat!(
+                                            "(Fatal) Error loading quicksave file: {:?}\n\n",
+                                            "Rebuilding it from its replay failed too: {}",
+                                        ),
+                                        e, err,
+                                    )
+                                },
+                            });
+                        },
+                        _ => {
+                            // Just to initialize renderer_state and keep the compiler happy, this won't be used...
+                            renderer_state = ui_renderer_state.clone();
+                            err_string = Some(format!("(Fatal) Error loading quicksave file: {:?}", e));
+                            savestate = SaveState::from(self, replay.clone(), renderer_state.clone(), false);
+                            startup_successful = false;
+                            game_running = false;
+                        },
                     },
                 }
             } else {
@@ -744,13 +923,53 @@ impl Game {
                     self.renderer.set_state(&ui_renderer_state);
                     savestate = state;
                 },
-                Err(e) => {
-                    // Just to initialize renderer_state and keep the compiler happy, this won't be used...
-                    renderer_state = ui_renderer_state.clone();
-                    err_string = Some(format!("(Fatal) Error loading quicksave file: {:?}", e));
-                    savestate = SaveState::from(self, replay.clone(), renderer_state.clone(), false);
-                    startup_successful = false;
-                    game_running = false;
+                Err(e) => match SaveState::replay_from_file(&save_path, &mut save_buffer) {
+                    // The savestate can't be loaded, but its replay can, so it's made again by playing that back
+                    Ok(rep) => {
+                        let result = self.play_from_start(&rep);
+                        self.renderer.resize_framebuffer(config.ui_width.into(), config.ui_height.into(), true);
+                        renderer_state = self.renderer.state();
+                        self.renderer.set_state(&ui_renderer_state);
+                        savestate = SaveState::from(self, rep.clone(), renderer_state.clone(), true);
+                        config.current_frame = rep.frame_count();
+                        replay = rep;
+                        err_string = Some(match result {
+                            Ok(()) => match savestate.save_to_file(&save_path, &mut save_buffer) {
+                                Ok(()) => format!(
+                                    concat!(
+                                        "Warning: {:?} couldn't be loaded, so it was rebuilt from its replay\n\n",
+                                        "Error message: {:?}",
+                                    ),
+                                    save_path.file_name(),
+                                    e,
+                                ),
+                                Err(err) => format!(
+                                    "Warning: {:?} was rebuilt from its replay, but couldn't be saved: {:?}",
+                                    save_path.file_name(),
+                                    err,
+                                ),
+                            },
+                            Err(err) => {
+                                startup_successful = false;
+                                game_running = false;
+                                format!(
+                                    concat!(
+                                        "(Fatal) Error loading quicksave file: {:?}\n\n",
+                                        "Rebuilding it from its replay failed too: {}",
+                                    ),
+                                    e, err,
+                                )
+                            },
+                        });
+                    },
+                    Err(_) => {
+                        // Just to initialize renderer_state and keep the compiler happy, this won't be used...
+                        renderer_state = ui_renderer_state.clone();
+                        err_string = Some(format!("(Fatal) Error loading quicksave file: {:?}", e));
+                        savestate = SaveState::from(self, replay.clone(), renderer_state.clone(), false);
+                        startup_successful = false;
+                        game_running = false;
+                    },
                 },
             }
         }
@@ -760,6 +979,7 @@ impl Game {
         } else {
             self.window.set_size((config.ui_width, config.ui_height));
         }
+        self.window_ui_size = Some((config.ui_width.into(), config.ui_height.into()));
 
         for (i, state) in keyboard_state.iter_mut().enumerate() {
             if self.input.keyboard_check_direct(i as u8) {
@@ -772,6 +992,17 @@ impl Game {
             }
         }
 
+        let mut rewind = RewindBuffer::new(rewind::REWIND_INTERVAL, rewind::REWIND_CAPACITY);
+        if game_running {
+            let state_replay = Replay::new(replay.start_time, replay.start_seed);
+            let state = SaveState::from(self, state_replay, renderer_state.clone(), clean_state);
+            if let Err(e) = rewind.push(config.current_frame, &state, &mut save_buffer) {
+                err_string = Some(format!("Warning: Failed to store rewind state: {:?}", e));
+            }
+        }
+
+        let branches = BranchTree::from_file_or_new(&project_path.join("branches.bin"), &replay);
+
         let mut keybind_path = project_path.clone();
         keybind_path.push("keybindings.cfg");
 
@@ -792,6 +1023,14 @@ impl Game {
                 WindowKind::Keybindings => windows.push((Box::new(keybinds::KeybindWindow::open(0)), false)),
                 WindowKind::Macro(id) => windows.push((Box::new(macro_window::MacroWindow::open(*id)), false)),
                 WindowKind::Console(id) => windows.push((Box::new(console::ConsoleWindow::open(*id)), false)),
+                WindowKind::Dialogs => windows.push((Box::new(dialog_window::DialogWindow::open(0)), false)),
+                WindowKind::Debugger => windows.push((Box::new(debugger_window::DebuggerWindow::open(0)), false)),
+                WindowKind::Profiler => windows.push((Box::new(profiler_window::ProfilerWindow::open(0)), false)),
+                WindowKind::Branches => windows.push((Box::new(branch_window::BranchWindow::open(0)), false)),
+                WindowKind::VariableSearch => {
+                    windows.push((Box::new(variable_search::VariableSearchWindow::open(0)), false))
+                },
+                WindowKind::RngPlanner => windows.push((Box::new(rng_planner::RngPlannerWindow::open(0)), false)),
                 WindowKind::Control
                 | WindowKind::Game
                 | WindowKind::InstanceReports
@@ -809,6 +1048,8 @@ impl Game {
             config,
             replay,
             lz4_buffer: save_buffer,
+            rewind,
+            branches,
             grid_ref,
             grid_start,
             game_running,
@@ -820,18 +1061,22 @@ impl Game {
             setting_mouse_pos: false,
             ui_renderer_state,
             cached_savestate: savestate,
+            startup_state,
             game_renderer_state: renderer_state,
             clear_context_menu: false,
             context_menu_window: None,
             context_menu_pos: Vec2(0.0, 0.0),
             run_until_frame: None,
+            rerecord_pending: false,
             clean_state,
             clean_state_previous: clean_state,
             clean_state_instant: None,
             grid_colour: GRID_COLOUR_GOOD,
             grid_colour_background: CLEAR_COLOUR_GOOD,
             instance_reports,
+            watch_log: WatchLog::new(),
             new_rand: None,
+            new_clipboard: None,
             save_paths,
             keybind_path,
             keybindings,
@@ -937,54 +1182,7 @@ impl UIState<'_> {
             Some(self.config.ui_height.into()),
         );
 
-        let draw_data = context.render();
-        for draw_list in draw_data.draw_lists() {
-            //let draw_list: &DrawList = draw_list;
-            let vertex_buffer = draw_list.vtx_buffer();
-            let index_buffer = draw_list.idx_buffer();
-            for cmd in draw_list.commands() {
-                match cmd {
-                    DrawCmd::Elements { count, cmd_params } => {
-                        // TODO: don't use the primitive builder for this, it allocates a lot and
-                        // also doesn't do instanced drawing I think?
-                        self.game.renderer.reset_primitive_2d(
-                            PrimitiveType::TriList,
-                            if cmd_params.texture_id.id() == 0 {
-                                None
-                            } else {
-                                Some(AtlasRef(cmd_params.texture_id.id() as i32))
-                            },
-                        );
-
-                        for i in 0..count {
-                            let vert: imgui::DrawVert = vertex_buffer
-                                [cmd_params.vtx_offset + usize::from(index_buffer[i + cmd_params.idx_offset])];
-                            self.game.renderer.vertex_2d(
-                                f64::from(vert.pos[0]) - 0.5,
-                                f64::from(vert.pos[1]) - 0.5,
-                                vert.uv[0].into(),
-                                vert.uv[1].into(),
-                                i32::from(vert.col[0]) | (i32::from(vert.col[1]) << 8) | (i32::from(vert.col[2]) << 16),
-                                f64::from(vert.col[3]) / 255.0,
-                            );
-                        }
-
-                        let clip_x = cmd_params.clip_rect[0] as i32;
-                        let clip_y = cmd_params.clip_rect[1] as i32;
-                        let clip_w = (cmd_params.clip_rect[2] - cmd_params.clip_rect[0]) as i32 + 1;
-                        let clip_h = (cmd_params.clip_rect[3] - cmd_params.clip_rect[1]) as i32 + 1;
-                        self.game
-                            .renderer
-                            .set_view(clip_x, clip_y, clip_w, clip_h, 0.0, clip_x, clip_y, clip_w, clip_h);
-                        self.game.renderer.draw_primitive_2d();
-                    },
-                    DrawCmd::RawCallback { callback, raw_cmd } => {
-                        unsafe { callback(draw_list.raw(), raw_cmd) };
-                    },
-                    DrawCmd::ResetRenderState => {},
-                }
-            }
-        }
+        render_draw_data(&mut self.game.renderer, context.render());
 
         self.game.renderer.finish(
             self.config.ui_width.into(),
@@ -996,6 +1194,16 @@ impl UIState<'_> {
     /// Polls new window events from operating system and updates config, imgui and renderer accordingly.
     /// Returns false if the program should exit (eg. the 'X' button was pressed), otherwise true.
     fn poll_window_events(&mut self, io: &mut imgui::Io) -> bool {
+        // A modal run by the game, such as the debugger, may have seen the window get resized instead
+        if let Some((width, height)) = self.game.window_ui_size {
+            if (width, height) != (u32::from(self.config.ui_width), u32::from(self.config.ui_height)) {
+                self.config.ui_width = u16::try_from(width).unwrap_or(u16::MAX);
+                self.config.ui_height = u16::try_from(height).unwrap_or(u16::MAX);
+                io.display_size = [width as f32, height as f32];
+                self.game.renderer.resize_framebuffer(width, height, false);
+                self.clear_context_menu = true;
+            }
+        }
         self.game.window.poll_events();
         for event in self.game.window.events().into_iter().copied() {
             match event {
@@ -1044,6 +1252,7 @@ impl UIState<'_> {
                 Event::Resize((width, height)) => {
                     self.config.ui_width = u16::try_from(width).unwrap_or(u16::MAX);
                     self.config.ui_height = u16::try_from(height).unwrap_or(u16::MAX);
+                    self.game.window_ui_size = Some((width.into(), height.into()));
                     io.display_size = [f32::from(width), f32::from(height)];
                     self.game.renderer.resize_framebuffer(width as _, height as _, false);
                     self.clear_context_menu = true;
@@ -1080,6 +1289,7 @@ impl UIState<'_> {
             setting_mouse_pos: &mut self.setting_mouse_pos,
             new_mouse_pos: &mut self.new_mouse_pos,
             new_rand: &mut self.new_rand,
+            new_clipboard: &mut self.new_clipboard,
             err_string: &mut self.err_string,
             replay: &mut self.replay,
             config: &mut self.config,
@@ -1087,12 +1297,17 @@ impl UIState<'_> {
             keyboard_state: &mut self.keyboard_state,
             mouse_state: &mut self.mouse_state,
             savestate: &mut self.cached_savestate,
+            startup_state: &self.startup_state,
             renderer_state: &mut self.game_renderer_state,
             save_buffer: &mut self.lz4_buffer,
+            rewind: &mut self.rewind,
+            branches: &mut self.branches,
             instance_reports: &mut self.instance_reports,
+            watch_log: &mut self.watch_log,
 
             clean_state: &mut self.clean_state,
             run_until_frame: &mut self.run_until_frame,
+            rerecord_pending: &mut self.rerecord_pending,
 
             startup_successful: &self.startup_successful,
             ui_renderer_state: &self.ui_renderer_state,
@@ -1198,12 +1413,16 @@ impl UIState<'_> {
     fn save_config(&mut self) {
         self.config.open_windows = self.windows.iter().filter_map(|(win, _)| win.stored_kind()).collect();
         self.err_string = self.config.save();
+        if let Some(err) = self.branches.save(&self.project_path.join("branches.bin"), &self.replay) {
+            self.err_string = Some(err);
+        }
 
         let _ = File::create(&self.keybind_path).map(|f| bincode::serialize_into(f, &self.keybindings));
 
         let mut backup_path = self.project_path.clone();
         backup_path.push("backup.gmtas");
-        self.replay.to_file(&backup_path).expect("backup.gmtas could not be saved.");
+        let metadata = self.config.replay_metadata(self.game);
+        self.replay.to_file(&backup_path, &metadata).expect("backup.gmtas could not be saved.");
     }
 }
 
@@ -1216,3 +1435,72 @@ impl Colour {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_project_config() {
+        let old = ProjectConfigV0 {
+            ui_width: 1600,
+            ui_height: 900,
+            ui_maximised: true,
+            rerecords: 1234,
+            watched_ids: vec![100001],
+            open_windows: vec![WindowKind::Control, WindowKind::Macro(2)],
+            full_keyboard: true,
+            input_mode: InputMode::Direct,
+            quicksave_slot: 3,
+            config_path: "project.cfg".into(),
+            is_read_only: true,
+            current_frame: 500,
+            set_mouse_using_textbox: true,
+        };
+        let config = ProjectConfig::parse(&bincode::serialize(&old).unwrap()).unwrap();
+        assert_eq!((config.ui_width, config.ui_height, config.ui_maximised), (1600, 900, true));
+        assert_eq!((config.rerecords, config.watched_ids.as_slice()), (1234, &[100001][..]));
+        assert!(matches!(config.open_windows[..], [WindowKind::Control, WindowKind::Macro(2)]));
+        assert!(config.full_keyboard && matches!(config.input_mode, InputMode::Direct));
+        assert_eq!((config.quicksave_slot, config.is_read_only, config.current_frame), (3, true, 500));
+        assert!(config.set_mouse_using_textbox);
+        assert_eq!((config.encoding, config.author.as_str(), config.comments.as_str()), (None, "", ""));
+
+        // Configurations saved now read back the same, rather than as the old layout
+        let config = ProjectConfig { encoding: Some("EUC-KR".into()), author: "someone".into(), ..config };
+        let config = ProjectConfig::parse(&bincode::serialize(&config).unwrap()).unwrap();
+        assert_eq!(
+            (config.encoding.as_deref(), config.author.as_str(), config.rerecords),
+            (Some("EUC-KR"), "someone", 1234),
+        );
+        assert!(ProjectConfig::parse(&[1, 2, 3]).is_none());
+    }
+    #[test]
+    fn restore_metadata() {
+        let mut config = ProjectConfig::from(ProjectConfigV0 {
+            ui_width: 1280,
+            ui_height: 720,
+            ui_maximised: false,
+            rerecords: 0,
+            watched_ids: Vec::new(),
+            open_windows: Vec::new(),
+            full_keyboard: false,
+            input_mode: InputMode::Mouse,
+            quicksave_slot: 0,
+            config_path: "project.cfg".into(),
+            is_read_only: false,
+            current_frame: 0,
+            set_mouse_using_textbox: false,
+        });
+        let metadata =
+            Metadata { author: "someone".into(), rerecords: 77, comments: "any%".into(), ..Default::default() };
+        config.restore_metadata(&metadata);
+        assert_eq!((config.rerecords, config.author.as_str(), config.comments.as_str()), (77, "someone", "any%"));
+
+        // Anything the configuration still has is newer than the replay
+        config.rerecords = 80;
+        config.author = "someone else".into();
+        config.restore_metadata(&metadata);
+        assert_eq!((config.rerecords, config.author.as_str()), (80, "someone else"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::game::{
+    recording::window::{EmulatorContext, Openable, Window},
+    replay,
+};
+use std::{fs, io};
+
+/// Shows the tree of input branches, and lets them be renamed, compared, restored and exported.
+pub struct BranchWindow {
+    is_open: bool,
+    selected: Option<usize>,
+    name_input: String,
+    message: Option<String>,
+}
+
+impl Openable<Self> for BranchWindow {
+    fn window_name() -> &'static str {
+        "Branches"
+    }
+
+    fn open(_id: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl Window for BranchWindow {
+    fn stored_kind(&self) -> Option<super::WindowKind> {
+        Some(super::WindowKind::Branches)
+    }
+
+    fn name(&self) -> String {
+        "Branches".into()
+    }
+
+    fn show_window(&mut self, info: &mut EmulatorContext) {
+        info.frame
+            .window(self.name())
+            .opened(&mut self.is_open)
+            .position([250.0, 250.0], imgui::Condition::FirstUseEver)
+            .size([360.0, 400.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let current = info.branches.current();
+                for (index, depth) in info.branches.tree_order() {
+                    let branch = &info.branches.branches()[index];
+                    let frames = info.branches.replay(index, info.replay).frame_count();
+                    let label = format!(
+                        "{}{}{} ({}-{})##branch{}",
+                        "    ".repeat(depth),
+                        if index == current { "* " } else { "" },
+                        branch.name,
+                        branch.fork_frame,
+                        frames,
+                        index,
+                    );
+                    if info.frame.selectable_config(&label).selected(self.selected == Some(index)).build() {
+                        self.selected = Some(index);
+                        self.name_input = branch.name.clone();
+                        self.message = None;
+                    }
+                }
+
+                let index = match self.selected {
+                    Some(index) if index < info.branches.branches().len() => index,
+                    _ => return,
+                };
+                info.frame.separator();
+
+                if info.frame.input_text("Name", &mut self.name_input).build() {
+                    info.branches.rename(index, self.name_input.clone());
+                }
+                let branch = &info.branches.branches()[index];
+                match branch.parent {
+                    Some(parent) => info.frame.text(format!(
+                        "Forked from {} at frame {}",
+                        info.branches.branches()[parent].name,
+                        branch.fork_frame,
+                    )),
+                    None => info.frame.text("Root branch"),
+                }
+                info.frame.text(format!("{} frames", info.branches.replay(index, info.replay).frame_count()));
+
+                if index != current {
+                    if info.frame.button("Restore") && *info.startup_successful {
+                        self.message = if info.restore_branch(index) {
+                            Some(format!("Restored at frame {}", info.config.current_frame))
+                        } else {
+                            None
+                        };
+                    }
+                    info.frame.same_line();
+                    if info.frame.button("Compare with current") {
+                        let replay = info.branches.replay(index, info.replay);
+                        let other = info.branches.replay(current, info.replay);
+                        let common = replay.common_frames(other);
+                        self.message = Some(if common == replay.frame_count() && common == other.frame_count() {
+                            "The inputs are the same".into()
+                        } else {
+                            format!(
+                                "Same inputs for the first {} frames. This has {} frames, and the current one has {}.",
+                                common,
+                                replay.frame_count(),
+                                other.frame_count(),
+                            )
+                        });
+                    }
+                    info.frame.same_line();
+                }
+                if info.frame.button("Export to .gmtas") {
+                    let filename = format!(
+                        "{}.gmtas",
+                        info.branches.branches()[index]
+                            .name
+                            .chars()
+                            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+                            .collect::<String>(),
+                    );
+                    // Exports go in their own folder so they can't replace the project's own files, like backup.gmtas
+                    let dir = info.project_path.join("exports");
+                    let path = dir.join(&filename);
+                    let metadata = info.config.replay_metadata(info.game);
+                    let result = if path.exists() {
+                        Err(replay::WriteError::IOErr(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            "there's already a file with that name. Rename the branch or move the file first",
+                        )))
+                    } else {
+                        fs::create_dir_all(&dir)
+                            .map_err(replay::WriteError::IOErr)
+                            .and_then(|()| info.branches.replay(index, info.replay).to_file(&path, &metadata))
+                    };
+                    self.message = Some(match result {
+                        Ok(()) => format!("Saved to {}", path.display()),
+                        Err(replay::WriteError::IOErr(err)) => format!("Failed to write {}: {}", filename, err),
+                        Err(replay::WriteError::CompressErr(err)) => {
+                            format!("Failed to compress {}: {}", filename, err)
+                        },
+                        Err(replay::WriteError::SerializeErr(err)) => {
+                            format!("Failed to serialize {}: {}", filename, err)
+                        },
+                    });
+                }
+                if let Some(message) = &self.message {
+                    info.frame.text_wrapped(message);
+                }
+            });
+    }
+
+    fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+impl BranchWindow {
+    pub fn new() -> Self {
+        Self { is_open: true, selected: None, name_input: String::new(), message: None }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::game::replay::Replay;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, path::Path};
+
+/// One line of input history. Every branch but the first was forked off another one at some frame.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Branch {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub fork_frame: usize,
+    replay: Replay,
+}
+
+/// Every branch of a project's input history. A new branch is forked whenever a frame is recorded over
+/// inputs that are already in a branch, so loading an old savestate and re-recording never loses anything.
+#[derive(Serialize, Deserialize)]
+pub struct BranchTree {
+    branches: Vec<Branch>,
+    current: usize,
+
+    /// Whether the replay being recorded carries on from the current branch. After a savestate is loaded it
+    /// doesn't, until the next frame is recorded and it's known which branch that belongs in.
+    #[serde(skip)]
+    attached: bool,
+}
+
+impl BranchTree {
+    pub fn new(replay: &Replay) -> Self {
+        Self {
+            branches: vec![Branch { name: "Main".into(), parent: None, fork_frame: 0, replay: replay.clone() }],
+            current: 0,
+            attached: true,
+        }
+    }
+
+    /// Loads the branch file, or starts a new tree from the given replay if there isn't one.
+    pub fn from_file_or_new(path: &Path, replay: &Replay) -> Self {
+        if path.exists() {
+            match File::open(path).map(bincode::deserialize_from::<_, Self>) {
+                Ok(Ok(tree)) if tree.current < tree.branches.len() => tree,
+                _ => {
+                    println!("Warning: Couldn't read {}. Starting a new branch tree.", path.display());
+                    Self::new(replay)
+                },
+            }
+        } else {
+            Self::new(replay)
+        }
+    }
+
+    /// Saves the branch file. If that failed it will return a description of the error, otherwise None
+    pub fn save(&mut self, path: &Path, live: &Replay) -> Option<String> {
+        self.sync(live);
+        File::create(path)
+            .map_err(|e| e.to_string())
+            .and_then(|f| bincode::serialize_into(f, &*self).map_err(|e| e.to_string()))
+            .err()
+            .map(|e| format!("Branches were not saved to disk because of an error: {}", e))
+    }
+
+    pub fn branches(&self) -> &[Branch] {
+        &self.branches
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn rename(&mut self, index: usize, name: String) {
+        if let Some(branch) = self.branches.get_mut(index) {
+            branch.name = name;
+        }
+    }
+
+    /// Gets a branch's inputs. The current branch's are in the replay being recorded, if that carries on from it.
+    pub fn replay<'a>(&'a self, index: usize, live: &'a Replay) -> &'a Replay {
+        if index == self.current && self.attached {
+            live
+        } else {
+            &self.branches[index].replay
+        }
+    }
+
+    /// Copies the replay being recorded into the current branch, if it carries on from it.
+    pub fn sync(&mut self, live: &Replay) {
+        if self.attached {
+            self.branches[self.current].replay = live.clone();
+        }
+    }
+
+    /// Called before the replay being recorded is replaced, such as by loading a savestate.
+    pub fn detach(&mut self, live: &Replay) {
+        self.sync(live);
+        self.attached = false;
+    }
+
+    /// Makes a branch the current one. The caller is expected to replace the replay being recorded with its inputs.
+    pub fn set_current(&mut self, index: usize) {
+        self.current = index;
+        self.attached = true;
+    }
+
+    /// Called before a frame is recorded into the replay. If that would overwrite inputs that are in a branch,
+    /// a new branch is forked off to record into instead.
+    pub fn record_frame(&mut self, live: &Replay, frame: usize) {
+        if self.attached {
+            if live.frame_count() > frame {
+                self.sync(live);
+                self.fork(self.current, frame, live);
+            }
+        } else {
+            // Find the branch this replay has the most in common with, preferring one it simply carries on
+            let (base, common) = self
+                .branches
+                .iter()
+                .enumerate()
+                .map(|(i, branch)| (i, branch.replay.common_frames(live).min(frame)))
+                .max_by_key(|(i, common)| {
+                    (*common, self.branches[*i].replay.frame_count() == *common, *i == self.current)
+                })
+                .unwrap_or((self.current, 0));
+            if common == self.branches[base].replay.frame_count() {
+                self.set_current(base);
+            } else {
+                self.fork(base, common, live);
+            }
+        }
+    }
+
+    fn fork(&mut self, parent: usize, frame: usize, live: &Replay) {
+        self.branches.push(Branch {
+            name: format!("Branch {}", self.branches.len()),
+            parent: Some(parent),
+            fork_frame: frame,
+            replay: live.clone(),
+        });
+        self.set_current(self.branches.len() - 1);
+    }
+
+    /// Lists the branches in tree order, each with how deeply it's nested.
+    pub fn tree_order(&self) -> Vec<(usize, usize)> {
+        let mut order = Vec::with_capacity(self.branches.len());
+        let mut stack = self
+            .branches
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, branch)| branch.parent.is_none())
+            .map(|(i, _)| (i, 0))
+            .collect::<Vec<_>>();
+        while let Some((index, depth)) = stack.pop() {
+            order.push((index, depth));
+            stack.extend(
+                self.branches
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .filter(|(_, b)| b.parent == Some(index))
+                    .map(|(i, _)| (i, depth + 1)),
+            );
+        }
+        order
+    }
+}
@@ -6,10 +6,10 @@ use crate::{
             InputMode, KeyState,
         },
         replay::{self, Frame, FrameRng},
-        Game, GameClock, SceneChange,
     },
     imgui_utils::{UiCustomFunction, Vec2},
 };
+use clipboard::{ClipboardContext, ClipboardProvider};
 
 use super::popup_dialog::{string_input::RNGSelect, Dialog, DialogState};
 
@@ -18,6 +18,8 @@ pub struct ControlWindow {
     rerecord_text: String,
     rng_select: RNGSelect,
     seed_base: (i32, i32, i32),
+    seek_frame: i32,
+    scrubbing: bool,
 }
 
 impl Window for ControlWindow {
@@ -72,7 +74,7 @@ impl Window for ControlWindow {
             {
                 let mut filepath = info.project_path.clone();
                 filepath.push("save.gmtas");
-                match info.replay.to_file(&filepath) {
+                match info.replay.to_file(&filepath, &info.config.replay_metadata(info.game)) {
                     Ok(()) => (),
                     Err(replay::WriteError::IOErr(err)) => {
                         *info.err_string = Some(format!("Failed to write save.gmtas: {}", err))
@@ -144,6 +146,69 @@ impl Window for ControlWindow {
                 info.config.save();
             }
 
+            let paste_label = match info.new_clipboard {
+                Some(_) => "Cancel clipboard paste###paste_clipboard",
+                None => "Paste host clipboard###paste_clipboard",
+            };
+            if info.frame.button_with_size(paste_label, [content_width, 20.0]) {
+                *info.new_clipboard = if info.new_clipboard.is_some() {
+                    None
+                } else {
+                    ClipboardContext::new().ok().and_then(|mut ctx| ctx.get_contents().ok())
+                };
+            }
+
+            if info.frame.button_with_size("Export to .gmtxt", [content_width, 20.0]) {
+                let mut filepath = info.project_path.clone();
+                filepath.push("save.gmtxt");
+                if let Err(err) = info.replay.to_text_file(&filepath) {
+                    *info.err_string = Some(format!("Failed to write save.gmtxt: {:?}", err))
+                }
+            }
+
+            // Timeline scrubber, which seeks to the chosen frame once it's let go of
+            let last_frame = info.replay.frame_count().max(info.config.current_frame);
+            if !self.scrubbing {
+                self.seek_frame = info.config.current_frame as i32;
+            }
+            info.frame.set_next_item_width(content_width);
+            info.frame.slider("##seek_frame", 0, last_frame as i32, &mut self.seek_frame);
+            self.scrubbing = info.frame.is_item_active();
+            if info.frame.is_item_deactivated_after_edit() && *info.startup_successful {
+                info.seek_to_frame(self.seek_frame.max(0) as usize);
+            }
+            if info.frame.is_item_hovered() {
+                let (count, size) = info.rewind.usage();
+                info.frame.tooltip_text(match info.rewind.first_frame() {
+                    Some(first) => format!(
+                        "Rewind states: {} ({:.1} MB), back to frame {}",
+                        count,
+                        size as f64 / (1024.0 * 1024.0),
+                        first,
+                    ),
+                    None => "No rewind states".into(),
+                });
+            }
+
+            if info.frame.collapsing_header("Replay info", imgui::TreeNodeFlags::empty()) {
+                info.frame.set_next_item_width(content_width);
+                info.frame.input_text("##author", &mut info.config.author).hint("Author").build();
+                let mut typing = info.frame.is_item_focused();
+                let mut edited = info.frame.is_item_deactivated_after_edit();
+                info.frame.input_text_multiline("##comments", &mut info.config.comments, [content_width, 60.0]).build();
+                typing |= info.frame.is_item_focused();
+                edited |= info.frame.is_item_deactivated_after_edit();
+                if typing {
+                    info.keybindings.disable_bindings();
+                }
+                if edited {
+                    info.config.save();
+                }
+                if info.frame.is_item_hovered() {
+                    info.frame.tooltip_text("Comments, saved in exported .gmtas files along with the author");
+                }
+            }
+
             if info.frame.button_with_size_and_pos(">", Vec2(18.0, 18.0), Vec2(content_width - 18.0, 138.0))
                 || info.keybind_pressed(Binding::NextRand)
             {
@@ -205,6 +270,8 @@ impl ControlWindow {
             seed_text: format!("Seed: {}", 0),
             rng_select: RNGSelect::new("Pick RNG"),
             seed_base: (0, 0, 0), // Stores (base_seed, cycles, result_seed) to not have to re-calculate that every frame
+            seek_frame: 0,
+            scrubbing: false,
         }
     }
 
@@ -235,14 +302,10 @@ impl ControlWindow {
     }
 
     fn advance_frame(&mut self, info: &mut EmulatorContext) {
-        info.game.input.mouse_step();
-
-        let frame: &mut Frame;
-        let mut current_frame: Frame;
+        let frame: Frame;
 
         if info.config.is_read_only && matches!(info.replay.get_frame(info.config.current_frame), Some(_)) {
-            current_frame = info.replay.get_frame(info.config.current_frame).unwrap().clone();
-            frame = &mut current_frame;
+            frame = info.replay.get_frame(info.config.current_frame).unwrap().clone();
         } else {
             if info.config.is_read_only {
                 // We're advancing at the end of the current replay while in read-only mode, possible options:
@@ -261,9 +324,18 @@ impl ControlWindow {
                 // info.config.save();
             }
 
+            // if this overwrites inputs from a branch, they're kept in it and a new branch is forked
+            info.branches.record_frame(info.replay, info.config.current_frame);
+
             // if we write a new frame in the middle of the recording, truncate all following frames
             if info.replay.frame_count() > info.config.current_frame {
                 info.replay.truncate_frames(info.config.current_frame);
+                info.rewind.truncate(info.config.current_frame);
+                if *info.rerecord_pending {
+                    *info.rerecord_pending = false;
+                    info.config.rerecords += 1;
+                    info.config.save();
+                }
             }
 
             let new_frame = info.replay.new_frame();
@@ -279,24 +351,41 @@ impl ControlWindow {
                 new_frame.new_seed = Some(rand.clone());
             }
 
-            frame = new_frame;
+            if let Some(text) = &*info.new_clipboard {
+                new_frame.events.push(replay::Event::PasteClipboard(match info.game.encode_str_maybe(text) {
+                    Some(encoded) => encoded.as_ref().into(),
+                    None => text.as_str().into(),
+                }));
+            }
+
+            frame = new_frame.clone();
         }
 
-        info.game.set_input_from_frame(frame);
+        let frame_mouse_pos = (frame.mouse_x, frame.mouse_y);
 
-        if let Some(error) = self.run_frame(info.game, info.renderer_state) {
+        if let Some(error) = info.run_frame(&frame) {
             *info.err_string = Some(error);
             *info.game_running = false;
+        } else if let Some(kind) = &info.game.unanswered_dialog {
+            *info.err_string = Some(format!(
+                "The game called {} with no answer prepared, so it was cancelled.\n\n\
+                To choose an answer, prepare one in the Dialogs window and redo this frame from a savestate.",
+                kind.function_name(),
+            ));
         }
 
-        info.config.current_frame += 1;
-
         if !info.config.is_read_only {
-            for ev in info.game.stored_events.iter() {
-                frame.events.push(ev.clone());
+            if let Some(frame) = info.replay.get_frame_mut(info.config.current_frame) {
+                for ev in info.game.stored_events.iter() {
+                    frame.events.push(ev.clone());
+                }
             }
         }
         info.game.stored_events.clear();
+
+        info.config.current_frame += 1;
+        info.log_watches();
+
         for (i, state) in info.keyboard_state.iter_mut().enumerate() {
             state.reset_to(info.game.input.keyboard_check_direct(i as u8));
         }
@@ -304,39 +393,20 @@ impl ControlWindow {
             state.reset_to(info.game.input.mouse_check_button(i as i8 + 1));
         }
 
-        // Fake frame limiter stuff (don't actually frame-limit in record mode)
-        if let GameClock::SpoofedNanos(t) = &mut info.game.clock {
-            *t += 1_000_000_000 / info.game.room.speed as u128;
-        }
-        if info.game.frame_counter == info.game.room.speed {
-            info.game.fps = info.game.room.speed;
-            info.game.frame_counter = 0;
-        }
-        info.game.frame_counter += 1;
-
-        info.game.renderer.resize_framebuffer(info.config.ui_width.into(), info.config.ui_height.into(), true);
-        info.game.renderer.set_view(
-            0, 0,
-            info.config.ui_width.into(),
-            info.config.ui_height.into(),
-            0.0,
-            0, 0,
-            info.config.ui_width.into(),
-            info.config.ui_height.into(),
-        );
-        info.game.renderer.clear_view(
-            if *info.clean_state {
-                crate::game::recording::CLEAR_COLOUR_GOOD
-            } else {
-                crate::game::recording::CLEAR_COLOUR_BAD
-            },
-            1.0,
-        );
-        *info.renderer_state = info.game.renderer.state();
-        info.game.renderer.set_state(info.ui_renderer_state);
         info.clear_context_menu();
         *info.new_rand = None;
         *info.new_mouse_pos = None;
+        *info.new_clipboard = None;
+
+        // If the game moved the (virtual) cursor, the next frame should start from there
+        let game_mouse_pos = (info.game.input.mouse_x(), info.game.input.mouse_y());
+        if game_mouse_pos != frame_mouse_pos {
+            *info.new_mouse_pos = Some(game_mouse_pos);
+        }
+
+        if *info.game_running && info.rewind.wants(info.config.current_frame) {
+            info.rewind_capture();
+        }
 
         info.update_instance_reports();
     }
@@ -380,42 +450,4 @@ impl ControlWindow {
             }
         }
     }
-
-    /// runs a frame of the game
-    /// if an error occured it will return a message, otherwise None
-    fn run_frame(&self, game: &mut Game, renderer_state: &crate::render::RendererState) -> Option<String> {
-        let (w, h) = game.renderer.stored_size();
-
-        game.renderer.set_state(&renderer_state);
-        game.renderer.resize_framebuffer(w, h, false);
-        game.renderer.set_view(
-            0,
-            0,
-            game.unscaled_width as _,
-            game.unscaled_height as _,
-            0.0,
-            0,
-            0,
-            game.unscaled_width as _,
-            game.unscaled_height as _,
-        );
-        game.renderer.draw_stored(0, 0, w, h);
-        if let Err(e) = match game.frame() {
-            Ok(()) => match game.scene_change {
-                Some(SceneChange::Room(id)) => game.load_room(id),
-                Some(SceneChange::Restart) => game.restart(),
-                Some(SceneChange::End) => game.restart(),
-                Some(SceneChange::Load(ref mut path)) => {
-                    let path = std::mem::take(path);
-                    game.load_gm_save(path)
-                },
-                None => Ok(()),
-            },
-            Err(e) => Err(e.into()),
-        } {
-            Some(format!("Game crashed: {}\n\nPlease load a savestate.", e))
-        } else {
-            None
-        }
-    }
 }
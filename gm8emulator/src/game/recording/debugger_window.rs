@@ -0,0 +1,157 @@
+use crate::game::{
+    debugger::{Breakpoint, Debugger, Location},
+    recording::window::{EmulatorContext, Openable, Window},
+};
+
+/// Attaches the GML debugger to the game and manages its breakpoints and watches.
+/// The debugger is detached again when this window is closed.
+pub struct DebuggerWindow {
+    is_open: bool,
+    attached: bool,
+
+    event_breakpoint: bool,
+    name_input: String,
+    event_input: String,
+    line_input: i32,
+    watch_input: String,
+}
+
+impl Openable<Self> for DebuggerWindow {
+    fn window_name() -> &'static str {
+        "Debugger"
+    }
+
+    fn open(_id: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl Window for DebuggerWindow {
+    fn stored_kind(&self) -> Option<super::WindowKind> {
+        Some(super::WindowKind::Debugger)
+    }
+
+    fn name(&self) -> String {
+        "Debugger".into()
+    }
+
+    fn show_window(&mut self, info: &mut EmulatorContext) {
+        let EmulatorContext { frame, game, keybindings, clean_state, .. } = info;
+
+        // Anything the user changed while paused means the current frame no longer matches the savestate
+        if game.debugger.as_mut().map(|d| d.take_modified()).unwrap_or(false) {
+            **clean_state = false;
+        }
+
+        frame
+            .window(self.name())
+            .opened(&mut self.is_open)
+            .position([200.0, 200.0], imgui::Condition::FirstUseEver)
+            .size([420.0, 400.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if frame.checkbox("Attached", &mut self.attached) {
+                    game.debugger = if self.attached { Some(Box::new(Debugger::new())) } else { None };
+                }
+                let debugger = match &mut game.debugger {
+                    Some(debugger) => debugger,
+                    None => {
+                        frame.text("Attach the debugger to set breakpoints.");
+                        return
+                    },
+                };
+                frame.same_line();
+                if frame.button("Break") {
+                    debugger.break_requested = true;
+                }
+                if frame.is_item_hovered() {
+                    frame.tooltip_text("Pause at the next line of GML that runs");
+                }
+
+                frame.separator();
+                frame.text("Breakpoints");
+                let mut remove = None;
+                for (i, breakpoint) in debugger.breakpoints.iter_mut().enumerate() {
+                    if frame.small_button(format!("x##breakpoint{}", i)) {
+                        remove = Some(i);
+                    }
+                    frame.same_line();
+                    let label = match breakpoint.line {
+                        Some(line) => format!("{}, line {}##breakpoint{}", breakpoint.location, line, i),
+                        None => format!("{}##breakpoint{}", breakpoint.location, i),
+                    };
+                    frame.checkbox(label, &mut breakpoint.enabled);
+                }
+                if let Some(i) = remove {
+                    debugger.breakpoints.remove(i);
+                }
+
+                frame.radio_button("Script", &mut self.event_breakpoint, false);
+                frame.same_line();
+                frame.radio_button("Event", &mut self.event_breakpoint, true);
+                frame.input_text(if self.event_breakpoint { "Object" } else { "Script" }, &mut self.name_input).build();
+                let mut typing = frame.is_item_focused();
+                if self.event_breakpoint {
+                    frame.input_text("Event", &mut self.event_input).hint("e.g. Step Event").build();
+                    typing |= frame.is_item_focused();
+                }
+                frame.input_int("Line (0 for any)", &mut self.line_input).build();
+                typing |= frame.is_item_focused();
+                if frame.button("Add breakpoint") && !self.name_input.is_empty() {
+                    let location = if self.event_breakpoint {
+                        Location::Event { object: self.name_input.clone(), event: self.event_input.clone() }
+                    } else {
+                        Location::Script(self.name_input.clone())
+                    };
+                    let line = if self.line_input > 0 { Some(self.line_input as usize) } else { None };
+                    debugger.breakpoints.push(Breakpoint { location, line, enabled: true });
+                }
+
+                frame.separator();
+                frame.text("Watches, shown while paused");
+                let mut remove = None;
+                for (i, watch) in debugger.watches.iter().enumerate() {
+                    if frame.small_button(format!("x##watch{}", i)) {
+                        remove = Some(i);
+                    }
+                    frame.same_line();
+                    frame.text(watch);
+                }
+                if let Some(i) = remove {
+                    debugger.watches.remove(i);
+                }
+                let add_watch =
+                    frame.input_text("##watchinput", &mut self.watch_input).enter_returns_true(true).build();
+                typing |= frame.is_item_focused();
+                frame.same_line();
+                if (frame.button("Add watch") || add_watch) && !self.watch_input.is_empty() {
+                    debugger.watches.push(std::mem::take(&mut self.watch_input));
+                }
+
+                if typing {
+                    keybindings.disable_bindings();
+                }
+            });
+
+        if !self.is_open {
+            game.debugger = None;
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+impl DebuggerWindow {
+    pub fn new() -> Self {
+        Self {
+            is_open: true,
+            attached: false,
+            event_breakpoint: false,
+            name_input: String::new(),
+            event_input: String::new(),
+            line_input: 0,
+            watch_input: String::new(),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    game::{
+        dialog::{Answer, Dialog, Kind},
+        recording::window::{EmulatorContext, Openable, Window},
+    },
+    types::Colour,
+};
+
+/// Lets the user prepare answers for dialogs the game opens, such as get_open_filename().
+/// Frames can't be paused while they're running, so dialogs in record mode are answered ahead of time.
+pub struct DialogWindow {
+    dialog: Option<Dialog>,
+    is_open: bool,
+}
+
+impl Openable<Self> for DialogWindow {
+    fn window_name() -> &'static str {
+        "Dialogs"
+    }
+
+    fn open(_id: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl Window for DialogWindow {
+    fn stored_kind(&self) -> Option<super::WindowKind> {
+        Some(super::WindowKind::Dialogs)
+    }
+
+    fn name(&self) -> String {
+        "Dialogs".into()
+    }
+
+    fn show_window(&mut self, info: &mut EmulatorContext) {
+        let EmulatorContext { frame, game, .. } = info;
+
+        frame
+            .window(self.name())
+            .opened(&mut self.is_open)
+            .position([150.0, 150.0], imgui::Condition::FirstUseEver)
+            .size([420.0, 360.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if let Some(dialog) = &mut self.dialog {
+                    frame.text(format!("Preparing answer for {}", dialog.kind().function_name()));
+                    frame.separator();
+                    match dialog.show(frame) {
+                        Some(Answer::Cancelled) => self.dialog = None,
+                        Some(answer) => {
+                            game.dialog_answers.push_back(answer);
+                            self.dialog = None;
+                        },
+                        None => (),
+                    }
+                    return
+                }
+
+                if let Some(kind) = &game.unanswered_dialog {
+                    frame.text(format!("{} was cancelled because no answer was prepared.", kind.function_name()));
+                    if frame.button("Answer it") {
+                        self.dialog = Some(Dialog::new(kind.clone()));
+                    }
+                    frame.separator();
+                }
+
+                if frame.button("Open file") {
+                    self.dialog = Some(Dialog::new(Kind::OpenFile { filter: String::new(), default: String::new() }));
+                }
+                frame.same_line();
+                if frame.button("Save file") {
+                    self.dialog = Some(Dialog::new(Kind::SaveFile { filter: String::new(), default: String::new() }));
+                }
+                frame.same_line();
+                if frame.button("Directory") {
+                    self.dialog = Some(Dialog::new(Kind::Directory { caption: String::new(), root: String::new() }));
+                }
+                frame.same_line();
+                if frame.button("Colour") {
+                    self.dialog = Some(Dialog::new(Kind::Colour(0)));
+                }
+                frame.same_line();
+                if frame.button("Cancel") {
+                    game.dialog_answers.push_back(Answer::Cancelled);
+                }
+
+                frame.separator();
+                frame.text("Prepared answers, used in order:");
+                let mut remove = None;
+                for (i, answer) in game.dialog_answers.iter().enumerate() {
+                    if frame.small_button(format!("x##answer{}", i)) {
+                        remove = Some(i);
+                    }
+                    frame.same_line();
+                    match answer {
+                        Answer::Cancelled => frame.text("Cancel"),
+                        Answer::Path(path) => frame.text(path),
+                        Answer::Colour(col) => frame.text(Colour::from(*col as u32).as_hexstring()),
+                    }
+                }
+                if let Some(i) = remove {
+                    game.dialog_answers.remove(i);
+                }
+            });
+    }
+
+    fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+impl DialogWindow {
+    pub fn new() -> Self {
+        Self { dialog: None, is_open: true }
+    }
+}
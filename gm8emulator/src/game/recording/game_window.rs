@@ -2,6 +2,7 @@ use crate::{
     game::{
         recording::{
             instance_report::InstanceReport,
+            overlay::Overlays,
             set_mouse_dialog::{MouseDialogResult, SetMouseDialog},
             window::{EmulatorContext, Window},
         },
@@ -24,6 +25,7 @@ pub struct GameWindow {
     context_menu_options: Option<Vec<(String, i32)>>,
     mouse_dialog: SetMouseDialog,
     set_screencover_focus: bool,
+    overlays: Overlays,
 }
 
 // Game window
@@ -62,6 +64,7 @@ impl GameWindow {
             context_menu_options: None,
             mouse_dialog: SetMouseDialog::new(),
             set_screencover_focus: true,
+            overlays: Overlays::default(),
         }
     }
 
@@ -144,6 +147,12 @@ impl GameWindow {
                 }
 
                 info.frame.callback(callback, &mut self.callback_data);
+                self.overlays.draw(
+                    info.game,
+                    &info.frame.get_window_draw_list(),
+                    [self.callback_data.x as f32, self.callback_data.y as f32],
+                    [w as f32, h as f32],
+                );
 
                 if *info.setting_mouse_pos && !info.config.set_mouse_using_textbox {
                     let Vec2(mouse_x, mouse_y) = info.frame.mouse_pos();
@@ -167,6 +176,17 @@ impl GameWindow {
     }
 
     fn display_context_menu(&mut self, info: &mut EmulatorContext) -> bool {
+        if let Some(overlay_menu_token) = info.frame.begin_menu("Overlays") {
+            let toggled = self.overlays.show_menu(info.frame);
+            overlay_menu_token.end();
+            if toggled {
+                self.context_menu_options = None;
+                return false
+            }
+        }
+        if self.context_menu_options.as_ref().map_or(false, |options| !options.is_empty()) {
+            info.frame.separator();
+        }
         for (label, id) in self.context_menu_options.as_ref().unwrap() {
             if info.frame.menu_item(&label) {
                 if !info.config.watched_ids.contains(&id) {
@@ -182,7 +202,7 @@ impl GameWindow {
         self.context_menu_options.is_some()
     }
 
-    /// Gets all the instances the mouse is hovered over and puts them in a context menu
+    /// Gets all the instances the mouse is hovered over and puts them in a context menu, under the overlay toggles
     fn set_context_menu_instances(&mut self, info: &mut EmulatorContext) {
         info.frame.focus_current_window();
         let offset = Vec2::from(info.frame.window_pos()) + Vec2(info.win_border_size, info.win_frame_height);
@@ -207,10 +227,8 @@ impl GameWindow {
             }
         }
 
-        if options.len() > 0 {
-            if info.request_context_menu() {
-                self.context_menu_options = Some(options);
-            }
+        if info.request_context_menu() {
+            self.context_menu_options = Some(options);
         }
     }
 }
@@ -2,10 +2,11 @@ use crate::{
     game::{
         recording::{
             keybinds::Binding,
+            rewind::RewindBuffer,
             window::{EmulatorContext, Openable, Window},
             KeyState,
         },
-        replay::{FrameRng, Input, Replay},
+        replay::{Frame, FrameRng, Input, Replay},
     },
     imgui_utils::*,
     input::Button,
@@ -30,6 +31,15 @@ enum TableColor {
     DEFAULT,
     NONE,
 }
+
+/// A change to the replay, stored as what to put back: `frames` goes in place of the `replaced` frames at `start`.
+/// Undoing an Edit gives back the Edit which redoes it.
+struct Edit {
+    start: usize,
+    replaced: Vec<Frame>,
+    frames: Vec<Frame>,
+}
+
 pub struct InputEditWindow {
     is_open: bool,
     updated: bool,
@@ -55,6 +65,11 @@ pub struct InputEditWindow {
     context_menu_keystate: KeyState,
 
     rng_select: RNGSelect,
+
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    /// The first frame changed since the last edit was stored, and every frame from there as it was before.
+    pending_edit: Option<(usize, Vec<Frame>)>,
 }
 
 struct RowColorStack<'a> {
@@ -125,6 +140,7 @@ const INPUT_TABLE_YPOS: f32 = 44.0;
 const TABLE_PADDING: f32 = 2.0;
 const TOTAL_INPUT_TABLE_HEIGHT: f32 = INPUT_TABLE_HEIGHT + TABLE_PADDING * 2.0; // total height = table height + top padding + bottom padding
 const TABLE_CLIPPING: f32 = TOTAL_INPUT_TABLE_HEIGHT * 2.0; // draw 2 elements above and below the visible region
+const MAX_UNDO: usize = 200;
 
 macro_rules! rgb {
     ($r:expr, $g:expr, $b:expr) => {
@@ -146,6 +162,18 @@ impl Window for InputEditWindow {
     }
 
     fn show_window(&mut self, info: &mut EmulatorContext) {
+        self.finish_edit(info.replay);
+        let undone = if info.keybind_pressed(Binding::Undo) {
+            self.undo(info.replay, info.config.current_frame)
+        } else if info.keybind_pressed(Binding::Redo) {
+            self.redo(info.replay, info.config.current_frame)
+        } else {
+            None
+        };
+        if let Some(start) = undone {
+            info.rewind.truncate(start);
+        }
+
         // todo: figure out a better system for when to update this.
         if self.last_frame != info.config.current_frame || !self.updated {
             self.updated = true;
@@ -167,6 +195,7 @@ impl Window for InputEditWindow {
                         (replay_frame.mouse_x, replay_frame.mouse_y)
                     }
                 });
+                self.begin_edit(info.replay, frame);
                 self.update_mouse_position_for_frame(
                     frame,
                     self.setting_mouse_pos_end_frame,
@@ -273,6 +302,7 @@ impl Window for InputEditWindow {
         self.is_open = is_open;
 
         window_padding_style_var.end();
+        self.drop_edited_rewind_states(info.rewind);
     }
 
     fn is_open(&self) -> bool {
@@ -284,7 +314,9 @@ impl Window for InputEditWindow {
     }
 
     fn show_context_menu(&mut self, info: &mut EmulatorContext) -> bool {
-        self.display_context_menu(info)
+        let open = self.display_context_menu(info);
+        self.drop_edited_rewind_states(info.rewind);
+        open
     }
 
     fn context_menu_close(&mut self) {
@@ -303,6 +335,7 @@ impl Window for InputEditWindow {
                 assert!(start >= info.config.current_frame);
                 assert!(end < info.replay.frame_count());
 
+                self.begin_edit(info.replay, start);
                 for frame_index in start..(end + 1) {
                     info.replay.get_frame_mut(frame_index).unwrap().new_seed = new_seed.clone();
                 }
@@ -317,6 +350,7 @@ impl Window for InputEditWindow {
             },
             _ => self.is_selecting = MouseSelection::None, // Once the dialog is closed, stop displaying the selection (Closed, Cancelled, Invalid, etc)
         };
+        self.drop_edited_rewind_states(info.rewind);
 
         any_open
     }
@@ -361,6 +395,10 @@ impl InputEditWindow {
             context_menu_keystate: KeyState::Neutral,
 
             rng_select: RNGSelect::new("Pick RNG"),
+
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            pending_edit: None,
         }
     }
 
@@ -408,6 +446,9 @@ impl InputEditWindow {
             replay,
             new_mouse_pos,
             setting_mouse_pos,
+            err_string,
+            config,
+            rewind,
             ..
         } = info;
 
@@ -417,6 +458,7 @@ impl InputEditWindow {
                 if !self.context_menu_keystate.menu(frame) {
                     let frame_index = self.context_menu_indices.0;
 
+                    self.begin_edit(replay, frame_index);
                     self.update_replay_keystate(frame_index, key_index, self.context_menu_keystate, replay);
 
                     self.is_selecting = MouseSelection::None;
@@ -427,6 +469,7 @@ impl InputEditWindow {
                     let start = usize::min(self.selection_start_index, self.selection_end_index);
                     let end = usize::max(self.selection_start_index, self.selection_end_index);
 
+                    self.begin_edit(replay, start);
                     for frame_index in start..end {
                         self.update_replay_keystate(frame_index, key_index, state, replay);
                     }
@@ -444,26 +487,63 @@ impl InputEditWindow {
             let end = usize::max(self.selection_start_index, self.selection_end_index);
 
             if frame.menu_item("Add 1 frame before") {
+                self.begin_edit(replay, start);
                 self.add_frames(info.replay, start, 1);
                 self.context_menu = false;
             } else if frame.menu_item("Add 10 frames before") {
+                self.begin_edit(replay, start);
                 self.add_frames(info.replay, start, 10);
                 self.context_menu = false;
             } else if frame.menu_item("Add 50 frames before") {
+                self.begin_edit(replay, start);
                 self.add_frames(info.replay, start, 50);
                 self.context_menu = false;
             } else if frame.menu_item("Add 1 frame after") {
+                self.begin_edit(replay, start);
                 self.add_frames(info.replay, start, 1);
                 self.context_menu = false;
             } else if frame.menu_item("Add 10 frames after") {
+                self.begin_edit(replay, start);
                 self.add_frames(info.replay, start, 10);
                 self.context_menu = false;
             } else if frame.menu_item("Add 50 frames after") {
+                self.begin_edit(replay, start);
                 self.add_frames(info.replay, start, 50);
                 self.context_menu = false;
             } else if frame.menu_item("Delete frame(s)") {
+                self.begin_edit(replay, start);
+                self.delete_frames(replay, start, end);
+                self.context_menu = false;
+            } else if frame.menu_item("Copy frame(s)") {
+                self.copy_frames(frame, replay, start, end);
+                self.context_menu = false;
+            } else if frame.menu_item("Cut frame(s)") {
+                self.copy_frames(frame, replay, start, end);
+                self.begin_edit(replay, start);
                 self.delete_frames(replay, start, end);
                 self.context_menu = false;
+            } else if frame.menu_item("Paste frames") {
+                self.begin_edit(replay, start);
+                if let Err(e) = self.paste_frames(replay, frame.clipboard_text(), start, false) {
+                    **err_string = Some(format!("Couldn't paste frames: {}", e));
+                }
+                self.context_menu = false;
+            } else if frame.menu_item("Insert copied frames") {
+                self.begin_edit(replay, start);
+                if let Err(e) = self.paste_frames(replay, frame.clipboard_text(), start, true) {
+                    **err_string = Some(format!("Couldn't insert frames: {}", e));
+                }
+                self.context_menu = false;
+            } else if frame.menu_item_config("Undo").enabled(self.can_undo(replay, config.current_frame)).build() {
+                if let Some(start) = self.undo(replay, config.current_frame) {
+                    rewind.truncate(start);
+                }
+                self.context_menu = false;
+            } else if frame.menu_item_config("Redo").enabled(self.can_redo(replay, config.current_frame)).build() {
+                if let Some(start) = self.redo(replay, config.current_frame) {
+                    rewind.truncate(start);
+                }
+                self.context_menu = false;
             } else if frame.menu_item("Set Mouse") {
                 if let Some(current_frame) = replay.get_frame(start) {
                     **setting_mouse_pos = true;
@@ -756,9 +836,8 @@ impl InputEditWindow {
             }
 
             // RNG Changer
-            let current_frame = replay.get_frame_mut(i).unwrap();
             frame.table_set_column_index(self.keys.len() + Self::ADDITIONAL_COLUMNS_INFRONT);
-            let text = match current_frame.new_seed {
+            let text = match replay.get_frame(i).unwrap().new_seed {
                 None => String::from("-"),
                 Some(FrameRng::Override(new_seed)) => format!("{}", new_seed),
                 Some(FrameRng::Increment(count)) => format!("+{}", count),
@@ -771,6 +850,13 @@ impl InputEditWindow {
             }
             // If this is a rng change we haven't reached yet and is hovered
             if i >= config.current_frame && hovered {
+                let clicked = [imgui::MouseButton::Left, imgui::MouseButton::Right, imgui::MouseButton::Middle]
+                    .iter()
+                    .any(|button| frame.is_mouse_clicked(*button));
+                if clicked {
+                    self.begin_edit(replay, i);
+                }
+                let current_frame = replay.get_frame_mut(i).unwrap();
                 if frame.is_mouse_clicked(imgui::MouseButton::Left) {
                     current_frame.new_seed = Some(FrameRng::Increment(match current_frame.new_seed {
                         None | Some(FrameRng::Override(_)) => 1,
@@ -825,13 +911,9 @@ impl InputEditWindow {
                     **new_mouse_pos = Some((current_frame.mouse_x, current_frame.mouse_y));
                     self.setting_mouse_pos_for_frame = Some(i);
                 } else if frame.is_mouse_clicked(imgui::MouseButton::Middle) && mouse_hovered {
-                    self.update_mouse_position_for_frame(
-                        i,
-                        None,
-                        prev_frame.map(|f| f.mouse_x).unwrap_or(0),
-                        prev_frame.map(|f| f.mouse_y).unwrap_or(0),
-                        *replay,
-                    );
+                    let (prev_x, prev_y) = prev_frame.map(|f| (f.mouse_x, f.mouse_y)).unwrap_or((0, 0));
+                    self.begin_edit(replay, i);
+                    self.update_mouse_position_for_frame(i, None, prev_x, prev_y, *replay);
                 }
             }
 
@@ -864,6 +946,7 @@ impl InputEditWindow {
         match self.is_selecting {
             MouseSelection::Left => {
                 if info.frame.is_mouse_released(imgui::MouseButton::Left) {
+                    self.begin_edit(info.replay, usize::min(self.selection_start_index, self.selection_end_index));
                     if self.selection_start_index == self.selection_end_index {
                         let mut target_state =
                             self.states[self.selection_start_index][self.selection_column.unwrap()].clone();
@@ -949,6 +1032,161 @@ impl InputEditWindow {
         self.update_keys(replay);
     }
 
+    /// Puts the frames from start to end on the host clipboard as a text replay, so they can be pasted into this
+    /// project or another one. The copy is changed to start with no keys held, and events aren't copied,
+    /// since they only make sense on the frame they were recorded on.
+    fn copy_frames(&self, frame: &Ui, replay: &Replay, start: usize, end: usize) {
+        let mut clipboard = Replay::new(0, 0);
+        for (i, copied) in replay.frames_from(start).iter().take(end + 1 - start).enumerate() {
+            let new_frame = clipboard.new_frame();
+            *new_frame = Frame { events: Vec::new(), ..copied.clone() };
+            if i == 0 {
+                for (key, state) in self.keys.iter().zip(&self.states[start]) {
+                    if state.starts_with_press() {
+                        flip_key_start(&mut new_frame.inputs, *key, false);
+                    }
+                }
+            }
+        }
+        frame.set_clipboard_text(clipboard.to_text());
+    }
+
+    /// Pastes the frames from a text replay over the ones starting at the given frame, or inserts them before it.
+    /// Keys are pressed and released where they need to be for the pasted frames to do what they did when they
+    /// were copied, and for the frames after them to carry on as they were.
+    fn paste_frames(
+        &mut self,
+        replay: &mut Replay,
+        text: Option<String>,
+        index: usize,
+        insert: bool,
+    ) -> Result<(), String> {
+        let text = text.ok_or_else(|| String::from("the clipboard is empty"))?;
+        let clipboard =
+            Replay::from_text(&text).map_err(|e| format!("the clipboard doesn't have any frames ({})", e))?;
+        let mut frames = clipboard.frames_from(0).to_vec();
+        if frames.is_empty() {
+            return Err("the clipboard doesn't have any frames".into())
+        }
+
+        for (key, state) in self.keys.iter().zip(&self.states[index]) {
+            if state.starts_with_press() {
+                flip_key_start(&mut frames[0].inputs, *key, true);
+            }
+        }
+
+        let old_keys = self.keys.clone();
+        let count = frames.len();
+        let replaced = if insert { 0 } else { count.min(replay.frame_count() - index) };
+        let next_states =
+            if index + replaced < replay.frame_count() { Some(self.states[index + replaced].clone()) } else { None };
+        let removed = replay.splice_frames(index..index + replaced, frames);
+        for (i, old_frame) in removed.into_iter().enumerate() {
+            // Events were recorded for what happened on the frame they're on, so the ones that were there stay
+            replay.get_frame_mut(index + i).unwrap().events = old_frame.events;
+        }
+        self.update_keys(replay);
+
+        // update the key states for the frame after the pasted ones to match the last pasted frame
+        if let Some(next_states) = next_states {
+            let next = index + count;
+            for key in 0..self.keys.len() {
+                self.states[next][key] = match old_keys.iter().position(|k| *k == self.keys[key]) {
+                    Some(old_key) => next_states[old_key].clone(),
+                    None => KeyState::Neutral,
+                };
+                let start_pressed = self.states[next - 1][key].ends_in_press();
+                let state = self.update_keystate_front(next, key, start_pressed).clone();
+                self.update_replay(next, key, replay, state);
+            }
+            self.update_keys(replay);
+        }
+        Ok(())
+    }
+
+    /// Remembers the replay from the given frame onwards, before it gets changed there.
+    /// The change is added to the undo history the next time finish_edit is called.
+    fn begin_edit(&mut self, replay: &Replay, start: usize) {
+        self.pending_edit = Some(match self.pending_edit.take() {
+            // Nothing before the earlier edit has changed yet, so the frames up to it can be taken as they are now
+            Some((pending_start, frames)) if start < pending_start => {
+                let mut before = replay.frames_from(start)[..pending_start - start].to_vec();
+                before.extend(frames);
+                (start, before)
+            },
+            Some(pending) => pending,
+            None => (start, replay.frames_from(start).to_vec()),
+        });
+    }
+
+    /// Adds the change made since begin_edit to the undo history, keeping only the frames which changed.
+    fn finish_edit(&mut self, replay: &Replay) {
+        if let Some((start, before)) = self.pending_edit.take() {
+            let after = replay.frames_from(start);
+            if before.as_slice() != after {
+                let same_start = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+                let same_end = before[same_start..]
+                    .iter()
+                    .rev()
+                    .zip(after[same_start..].iter().rev())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if self.undo_stack.len() == MAX_UNDO {
+                    self.undo_stack.remove(0);
+                }
+                self.undo_stack.push(Edit {
+                    start: start + same_start,
+                    replaced: after[same_start..after.len() - same_end].to_vec(),
+                    frames: before[same_start..before.len() - same_end].to_vec(),
+                });
+                self.redo_stack.clear();
+            }
+        }
+    }
+
+    fn can_undo(&self, replay: &Replay, current_frame: usize) -> bool {
+        self.undo_stack.last().map_or(false, |edit| edit.fits(replay, current_frame))
+    }
+
+    fn can_redo(&self, replay: &Replay, current_frame: usize) -> bool {
+        self.redo_stack.last().map_or(false, |edit| edit.fits(replay, current_frame))
+    }
+
+    /// Reverts the latest edit, giving back the frame the replay changed from if there was one.
+    fn undo(&mut self, replay: &mut Replay, current_frame: usize) -> Option<usize> {
+        self.finish_edit(replay);
+        if self.can_undo(replay, current_frame) {
+            let redo = self.undo_stack.pop().unwrap().apply(replay);
+            let start = redo.start;
+            self.redo_stack.push(redo);
+            self.update_keys(replay);
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    /// Makes the latest undone edit again, giving back the frame the replay changed from if there was one.
+    fn redo(&mut self, replay: &mut Replay, current_frame: usize) -> Option<usize> {
+        self.finish_edit(replay);
+        if self.can_redo(replay, current_frame) {
+            let undo = self.redo_stack.pop().unwrap().apply(replay);
+            let start = undo.start;
+            self.undo_stack.push(undo);
+            self.update_keys(replay);
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    /// Drops the rewind states from after the first frame being edited, as they don't match the replay any more.
+    fn drop_edited_rewind_states(&self, rewind: &mut RewindBuffer) {
+        if let Some((start, _)) = &self.pending_edit {
+            rewind.truncate(*start);
+        }
+    }
+
     fn update_replay(&mut self, frame_index: usize, key_index: usize, replay: &mut Replay, target_state: KeyState) {
         if let Some(replay_frame) = replay.get_frame_mut(frame_index) {
             let mut new_inputs: Vec<Input> = replay_frame
@@ -1058,3 +1296,157 @@ impl InputEditWindow {
         state
     }
 }
+
+impl Edit {
+    /// Whether this can still be applied. Frames that have already been played can't be changed any more,
+    /// and neither can frames that something outside the editor has changed since.
+    fn fits(&self, replay: &Replay, current_frame: usize) -> bool {
+        self.start >= current_frame
+            && self.start <= replay.frame_count()
+            && replay.frames_from(self.start).starts_with(&self.replaced)
+    }
+
+    /// Makes the change to the replay, and gives back the Edit which reverses it.
+    fn apply(self, replay: &mut Replay) -> Edit {
+        let end = self.start + self.replaced.len();
+        let replaced = self.frames.clone();
+        let frames = replay.splice_frames(self.start..end, self.frames);
+        Edit { start: self.start, replaced, frames }
+    }
+}
+
+/// Changes a frame's inputs for a key so they leave it in the same state when it starts off held, if `held` is true,
+/// as they did when it started off released, or the other way round if `held` is false.
+fn flip_key_start(inputs: &mut Vec<Input>, key: u8, held: bool) {
+    let (redundant, missing) = if held {
+        (Input::KeyPress(key), Input::KeyRelease(key))
+    } else {
+        (Input::KeyRelease(key), Input::KeyPress(key))
+    };
+    let first = inputs.iter().position(|input| matches!(input, Input::KeyPress(k) | Input::KeyRelease(k) if *k == key));
+    match first {
+        Some(i) if inputs[i] == redundant => {
+            inputs.remove(i);
+        },
+        _ => inputs.insert(0, missing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: u8 = b'A';
+    const B: u8 = b'B';
+
+    fn replay(frames: &[&[Input]]) -> Replay {
+        let mut replay = Replay::new(0, 0);
+        for inputs in frames {
+            replay.new_frame().inputs = inputs.to_vec();
+        }
+        replay
+    }
+
+    fn inputs(replay: &Replay) -> Vec<Vec<Input>> {
+        replay.frames_from(0).iter().map(|frame| frame.inputs.clone()).collect()
+    }
+
+    /// An editor open on a replay which holds A from the first frame until the fourth.
+    fn held() -> (InputEditWindow, Replay) {
+        let mut replay = replay(&[&[Input::KeyPress(A)], &[], &[], &[Input::KeyRelease(A)]]);
+        let mut editor = InputEditWindow::new();
+        editor.update_keys(&mut replay);
+        (editor, replay)
+    }
+
+    fn clipboard(frames: &[&[Input]]) -> Option<String> {
+        Some(replay(frames).to_text())
+    }
+
+    #[test]
+    fn flip_start() {
+        let mut inputs = Vec::new();
+        flip_key_start(&mut inputs, A, true);
+        assert_eq!(inputs, [Input::KeyRelease(A)]);
+        flip_key_start(&mut inputs, A, false);
+        assert!(inputs.is_empty());
+
+        let mut inputs = vec![Input::KeyPress(B), Input::KeyPress(A), Input::KeyRelease(A)];
+        flip_key_start(&mut inputs, A, true);
+        assert_eq!(inputs, [Input::KeyPress(B), Input::KeyRelease(A)]);
+        flip_key_start(&mut inputs, A, false);
+        assert_eq!(inputs, [Input::KeyPress(A), Input::KeyPress(B), Input::KeyRelease(A)]);
+    }
+
+    #[test]
+    fn undo_redo() {
+        let (mut editor, mut replay) = held();
+        let mut original = replay.clone();
+        editor.begin_edit(&replay, 1);
+        replay.get_frame_mut(2).unwrap().mouse_x = 5;
+        editor.finish_edit(&replay);
+        let mut edited = replay.clone();
+
+        // Frames recorded onto the end since don't get in the way
+        for replay in [&mut replay, &mut original, &mut edited] {
+            replay.new_frame();
+        }
+        editor.undo(&mut replay, 0);
+        assert_eq!(replay, original);
+        editor.redo(&mut replay, 0);
+        assert_eq!(replay, edited);
+        editor.undo(&mut replay, 0);
+        assert_eq!(replay, original);
+
+        // Frames which have been played can't be changed, and neither can ones changed from somewhere else
+        assert!(editor.can_redo(&replay, 2));
+        assert!(!editor.can_redo(&replay, 3));
+        replay.get_frame_mut(2).unwrap().mouse_y = 5;
+        assert!(!editor.can_redo(&replay, 0));
+    }
+
+    #[test]
+    fn paste_over_held_key() {
+        let (mut editor, mut replay) = held();
+        editor.begin_edit(&replay, 1);
+        editor.paste_frames(&mut replay, clipboard(&[&[]]), 1, false).unwrap();
+        editor.finish_edit(&replay);
+        assert_eq!(inputs(&replay), [
+            vec![Input::KeyPress(A)],
+            vec![Input::KeyRelease(A)],
+            vec![Input::KeyPress(A)],
+            vec![Input::KeyRelease(A)],
+        ]);
+
+        editor.undo(&mut replay, 0);
+        assert_eq!(replay, held().1);
+    }
+
+    #[test]
+    fn insert_across_held_key() {
+        let (mut editor, mut replay) = held();
+        editor.begin_edit(&replay, 2);
+        editor.paste_frames(&mut replay, clipboard(&[&[]]), 2, true).unwrap();
+        editor.finish_edit(&replay);
+        assert_eq!(inputs(&replay), [
+            vec![Input::KeyPress(A)],
+            vec![],
+            vec![Input::KeyRelease(A)],
+            vec![Input::KeyPress(A)],
+            vec![Input::KeyRelease(A)],
+        ]);
+
+        // A key the replay didn't have before
+        let (mut editor, mut replay) = held();
+        let pasted = clipboard(&[&[Input::KeyPress(B)], &[Input::KeyRelease(B)]]);
+        editor.paste_frames(&mut replay, pasted, 1, true).unwrap();
+        assert_eq!(inputs(&replay), [
+            vec![Input::KeyPress(A)],
+            vec![Input::KeyRelease(A), Input::KeyPress(B)],
+            vec![Input::KeyRelease(B)],
+            vec![Input::KeyPress(A)],
+            vec![],
+            vec![Input::KeyRelease(A)],
+        ]);
+    }
+}
@@ -29,6 +29,8 @@ pub enum Binding {
     ExportGmtas,
     ToggleMacros,
     SetMouse,
+    Undo,
+    Redo,
 }
 
 impl Display for Binding {
@@ -46,6 +48,8 @@ impl Display for Binding {
             Self::ExportGmtas => write!(f, "Export .gmtas"),
             Self::ToggleMacros => write!(f, "Toggle \"Run Macro\""),
             Self::SetMouse => write!(f, "Set Mouse"),
+            Self::Undo => write!(f, "Undo input edit"),
+            Self::Redo => write!(f, "Redo input edit"),
             //_ => write!(f, "{:?}", self),
         }
     }
@@ -68,6 +72,8 @@ impl Binding {
             },
             Self::ToggleMacros => Some(KeyCombination::from(&vec![imgui::Key::ModCtrl, imgui::Key::Alpha1])),
             Self::SetMouse => Some(KeyCombination::from(&vec![imgui::Key::ModCtrl, imgui::Key::M])),
+            Self::Undo => Some(KeyCombination::from(&vec![imgui::Key::ModCtrl, imgui::Key::Z])),
+            Self::Redo => Some(KeyCombination::from(&vec![imgui::Key::ModCtrl, imgui::Key::Y])),
             //_ => None,
         }
     }
@@ -146,6 +152,8 @@ impl Keybindings {
         insert!(Binding::ExportGmtas);
         insert!(Binding::ToggleMacros);
         insert!(Binding::SetMouse);
+        insert!(Binding::Undo);
+        insert!(Binding::Redo);
     }
 
     pub fn keybind_pressed(&self, bind: Binding, frame: &imgui::Ui) -> bool {
@@ -1,6 +1,7 @@
 use crate::game::recording::{
-    console::ConsoleWindow, input_edit::InputEditWindow, keybinds::KeybindWindow, macro_window::MacroWindow,
-    window::Openable, UIState,
+    branch_window::BranchWindow, console::ConsoleWindow, debugger_window::DebuggerWindow, dialog_window::DialogWindow,
+    input_edit::InputEditWindow, keybinds::KeybindWindow, macro_window::MacroWindow, profiler_window::ProfilerWindow,
+    rng_planner::RngPlannerWindow, variable_search::VariableSearchWindow, window::Openable, UIState,
 };
 
 impl UIState<'_> {
@@ -72,6 +73,12 @@ impl UIState<'_> {
                         single InputEditWindow,
                         multi ConsoleWindow,
                         multi MacroWindow,
+                        single DialogWindow,
+                        single DebuggerWindow,
+                        single ProfilerWindow,
+                        single BranchWindow,
+                        single VariableSearchWindow,
+                        single RngPlannerWindow,
                     }
 
                     open_menu_token.end();
@@ -0,0 +1,253 @@
+use crate::{
+    game::{Game, GetAsset},
+    util,
+};
+use imgui::{DrawListMut, ImColor32};
+
+const BBOX_COLOUR: ImColor32 = ImColor32::from_rgba(0, 255, 0, 255);
+const MASK_COLOUR: ImColor32 = ImColor32::from_rgba(255, 0, 255, 110);
+const GRID_COLOUR: ImColor32 = ImColor32::from_rgba(0, 160, 0, 90);
+const GRID_BLOCKED_COLOUR: ImColor32 = ImColor32::from_rgba(255, 0, 0, 90);
+const PATH_COLOUR: ImColor32 = ImColor32::from_rgba(0, 200, 255, 255);
+const VIEW_COLOUR: ImColor32 = ImColor32::from_rgba(255, 255, 0, 255);
+const LABEL_COLOUR: ImColor32 = ImColor32::from_rgba(255, 255, 255, 255);
+const VELOCITY_COLOUR: ImColor32 = ImColor32::from_rgba(255, 128, 0, 255);
+
+/// How many frames ahead velocity lines point, since one frame's worth of movement is usually too short to see.
+const VELOCITY_SCALE: f64 = 4.0;
+
+/// Precise masks aren't drawn for instances whose bounding box is bigger than this many pixels.
+const MAX_MASK_PIXELS: i32 = 256 * 256;
+
+/// Debugging information which can be drawn over the game in record mode.
+#[derive(Default)]
+pub struct Overlays {
+    bboxes: bool,
+    masks: bool,
+    grids: bool,
+    paths: bool,
+    views: bool,
+    labels: bool,
+    velocities: bool,
+}
+
+/// Maps room coordinates to the screen through one view, or straight through if views aren't enabled.
+struct Projection {
+    source: [f64; 4],
+    port: [f64; 4],
+    sin: f64,
+    cos: f64,
+    origin: [f32; 2],
+}
+
+impl Overlays {
+    /// Shows a menu item for each overlay. Returns true if one was toggled.
+    pub fn show_menu(&mut self, frame: &imgui::Ui) -> bool {
+        let mut toggled = false;
+        for (label, enabled) in [
+            ("Bounding boxes", &mut self.bboxes),
+            ("Collision masks", &mut self.masks),
+            ("Motion planning grids", &mut self.grids),
+            ("Paths", &mut self.paths),
+            ("Views", &mut self.views),
+            ("Object names and IDs", &mut self.labels),
+            ("Velocities", &mut self.velocities),
+        ] {
+            if frame.menu_item_config(label).selected(*enabled).build() {
+                *enabled = !*enabled;
+                toggled = true;
+            }
+        }
+        toggled
+    }
+
+    /// Draws the enabled overlays over a game image whose top-left corner is at `origin` on screen.
+    pub fn draw(&self, game: &Game, draw_list: &DrawListMut, origin: [f32; 2], size: [f32; 2]) {
+        let views = game.room.views.iter().filter(|view| game.room.views_enabled && view.visible);
+        let mut projections = views
+            .map(|view| {
+                // Rotating the other way to View::transform_point
+                let angle = view.angle.to_radians();
+                Projection {
+                    source: [view.source_x.into(), view.source_y.into(), view.source_w.into(), view.source_h.into()],
+                    port: [view.port_x.into(), view.port_y.into(), view.port_w.into(), view.port_h.into()],
+                    sin: -angle.sin().into_inner(),
+                    cos: angle.cos().into_inner(),
+                    origin,
+                }
+            })
+            .collect::<Vec<_>>();
+        if projections.is_empty() {
+            let (w, h) = (f64::from(size[0]), f64::from(size[1]));
+            projections.push(Projection {
+                source: [0.0, 0.0, w, h],
+                port: [0.0, 0.0, w, h],
+                sin: 0.0,
+                cos: 1.0,
+                origin,
+            });
+        }
+
+        for projection in &projections {
+            let [x, y, w, h] = projection.port;
+            let min = [origin[0] + x as f32, origin[1] + y as f32];
+            let max = [min[0] + w as f32, min[1] + h as f32];
+            draw_list.with_clip_rect_intersect(min, max, || self.draw_room(game, draw_list, projection));
+        }
+
+        if self.views && game.room.views_enabled {
+            for (i, view) in game.room.views.iter().enumerate().filter(|(_, view)| view.visible) {
+                let min = [origin[0] + view.port_x as f32, origin[1] + view.port_y as f32];
+                let max = [min[0] + view.port_w as f32, min[1] + view.port_h as f32];
+                draw_list.add_rect(min, max, VIEW_COLOUR).build();
+                draw_list.add_text(
+                    [min[0] + 2.0, min[1] + 2.0],
+                    VIEW_COLOUR,
+                    format!("view {}: {}, {} ({}x{})", i, view.source_x, view.source_y, view.source_w, view.source_h),
+                );
+            }
+        }
+    }
+
+    /// Draws everything that's in room coordinates through one view.
+    fn draw_room(&self, game: &Game, draw_list: &DrawListMut, projection: &Projection) {
+        if self.grids {
+            for grid in game.mpgrids.iter() {
+                for x in 0..grid.hcells {
+                    for y in 0..grid.vcells {
+                        let left = f64::from(grid.left + x as i32 * grid.cellwidth);
+                        let top = f64::from(grid.top + y as i32 * grid.cellheight);
+                        let colour = if grid.get(x, y) < 0 { GRID_BLOCKED_COLOUR } else { GRID_COLOUR };
+                        let corners = projection.rect(
+                            left,
+                            top,
+                            left + f64::from(grid.cellwidth),
+                            top + f64::from(grid.cellheight),
+                        );
+                        draw_list.add_polyline(corners.to_vec(), colour).build();
+                    }
+                }
+            }
+        }
+
+        let list = &game.room.instance_list;
+        let mut iter = list.iter_by_drawing();
+        while let Some(handle) = iter.next(list) {
+            let instance = list.get(handle);
+            let sprite = game.get_instance_mask_sprite(handle);
+            // Don't use update_bbox here, since caching the bbox would change the game's state
+            let (left, top, right, bottom) = instance.peek_bbox(sprite);
+            let (x, y) = (instance.x.get().into_inner(), instance.y.get().into_inner());
+
+            if let (true, Some(sprite)) = (self.masks, sprite) {
+                let collider = if sprite.per_frame_colliders {
+                    let index =
+                        instance.image_index.get().floor().to_i32().rem_euclid(sprite.colliders.len().max(1) as i32);
+                    sprite.colliders.get(index as usize)
+                } else {
+                    sprite.colliders.first()
+                };
+                if let (Some(collider), true) = (collider, (right - left + 1) * (bottom - top + 1) <= MAX_MASK_PIXELS) {
+                    let angle = instance.image_angle.get().to_radians();
+                    let (sin, cos) = (angle.sin().into_inner(), angle.cos().into_inner());
+                    let (inst_x, inst_y) = (instance.x.get().round().to_i32(), instance.y.get().round().to_i32());
+                    let (xscale, yscale) = (instance.image_xscale.get(), instance.image_yscale.get());
+                    // Draw each row as runs of pixels rather than one at a time
+                    for py in top..=bottom {
+                        let mut run_start = None;
+                        for px in left..=right + 1 {
+                            let hit = px <= right
+                                && collider.check_collision_point_precise(
+                                    px,
+                                    py,
+                                    inst_x,
+                                    inst_y,
+                                    sprite.origin_x,
+                                    sprite.origin_y,
+                                    xscale,
+                                    yscale,
+                                    sin,
+                                    cos,
+                                );
+                            match (hit, run_start) {
+                                (true, None) => run_start = Some(px),
+                                (false, Some(start)) => {
+                                    let corners =
+                                        projection.rect(start.into(), py.into(), px.into(), f64::from(py + 1));
+                                    draw_list.add_polyline(corners.to_vec(), MASK_COLOUR).filled(true).build();
+                                    run_start = None;
+                                },
+                                _ => (),
+                            }
+                        }
+                    }
+                }
+            }
+
+            if self.bboxes && sprite.is_some() {
+                let corners = projection.rect(left.into(), top.into(), f64::from(right + 1), f64::from(bottom + 1));
+                draw_list.add_polyline(corners.to_vec(), BBOX_COLOUR).build();
+            }
+
+            if self.paths {
+                if let Some(path) = game.assets.paths.get_asset(instance.path_index.get()) {
+                    let angle = instance.path_orientation.get().to_radians();
+                    let (sin, cos) = (angle.sin().into_inner(), angle.cos().into_inner());
+                    let scale = instance.path_scale.get();
+                    let (xstart, ystart) = (instance.path_xstart.get(), instance.path_ystart.get());
+                    let points = path
+                        .control_nodes
+                        .iter()
+                        .map(|node| {
+                            // The same as how an instance's position is worked out in apply_speeds
+                            let mut px = ((node.point.x - path.start.x) * scale).into_inner();
+                            let mut py = ((node.point.y - path.start.y) * scale).into_inner();
+                            util::rotate_around_center(&mut px, &mut py, sin, cos);
+                            projection.point(px + xstart.into_inner(), py + ystart.into_inner())
+                        })
+                        .collect::<Vec<_>>();
+                    draw_list.add_polyline(points, PATH_COLOUR).build();
+                }
+            }
+
+            if self.velocities {
+                let (hspeed, vspeed) = (instance.hspeed.get().into_inner(), instance.vspeed.get().into_inner());
+                if hspeed != 0.0 || vspeed != 0.0 {
+                    let end = projection.point(x + hspeed * VELOCITY_SCALE, y + vspeed * VELOCITY_SCALE);
+                    draw_list.add_line(projection.point(x, y), end, VELOCITY_COLOUR).thickness(2.0).build();
+                }
+            }
+
+            if self.labels {
+                let object = game
+                    .assets
+                    .objects
+                    .get_asset(instance.object_index.get())
+                    .map(|object| object.name.decode(game.encoding))
+                    .unwrap_or("<deleted object>".into());
+                draw_list.add_text(projection.point(x, y), LABEL_COLOUR, format!("{} {}", object, instance.id.get()));
+            }
+        }
+    }
+}
+
+impl Projection {
+    /// Works out where a point in the room ends up on screen. This is the reverse of View::transform_point.
+    fn point(&self, x: f64, y: f64) -> [f32; 2] {
+        let [src_x, src_y, src_w, src_h] = self.source;
+        let [port_x, port_y, port_w, port_h] = self.port;
+        let (mut x, mut y) = (x, y);
+        util::rotate_around(&mut x, &mut y, src_x + (src_w / 2.0), src_y + (src_h / 2.0), self.sin, self.cos);
+        [
+            self.origin[0] + (port_x + (x - src_x) * port_w / src_w) as f32,
+            self.origin[1] + (port_y + (y - src_y) * port_h / src_h) as f32,
+        ]
+    }
+
+    /// The corners of a rectangle in the room, which might not be a rectangle on screen if the view is rotated.
+    fn rect(&self, left: f64, top: f64, right: f64, bottom: f64) -> [[f32; 2]; 5] {
+        let corners =
+            [self.point(left, top), self.point(right, top), self.point(right, bottom), self.point(left, bottom)];
+        [corners[0], corners[1], corners[2], corners[3], corners[0]]
+    }
+}
@@ -0,0 +1,122 @@
+use crate::game::{
+    profiler::{FlatEntry, Profiler},
+    recording::window::{EmulatorContext, Openable, Window},
+};
+use imgui::{TableColumnFlags, TableColumnSetup, TableFlags};
+
+/// Turns the profiler on and off, and shows where the game's time is going.
+pub struct ProfilerWindow {
+    is_open: bool,
+    entries: Vec<FlatEntry>,
+    last_frame: Option<usize>,
+    message: Option<String>,
+}
+
+impl Openable<Self> for ProfilerWindow {
+    fn window_name() -> &'static str {
+        "Profiler"
+    }
+
+    fn open(_id: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl Window for ProfilerWindow {
+    fn stored_kind(&self) -> Option<super::WindowKind> {
+        Some(super::WindowKind::Profiler)
+    }
+
+    fn name(&self) -> String {
+        "Profiler".into()
+    }
+
+    fn show_window(&mut self, info: &mut EmulatorContext) {
+        let EmulatorContext { frame, game, config, project_path, .. } = info;
+
+        frame
+            .window(self.name())
+            .opened(&mut self.is_open)
+            .position([250.0, 250.0], imgui::Condition::FirstUseEver)
+            .size([520.0, 400.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let mut enabled = game.profiler.is_some();
+                if frame.checkbox("Profiling", &mut enabled) {
+                    game.profiler = if enabled { Some(Box::new(Profiler::new(None))) } else { None };
+                    self.last_frame = None;
+                }
+                let profiler = match &mut game.profiler {
+                    Some(profiler) => profiler,
+                    None => {
+                        self.entries.clear();
+                        return
+                    },
+                };
+                frame.same_line();
+                if frame.button("Reset") {
+                    profiler.reset();
+                    self.last_frame = None;
+                }
+                frame.same_line();
+                if frame.button("Save report") {
+                    let path = project_path.join("profile.txt");
+                    self.message = Some(match profiler.write_reports(&path) {
+                        Ok(()) => format!("Saved to {}", path.display()),
+                        Err(e) => format!("Couldn't save report: {}", e),
+                    });
+                }
+                if let Some(message) = &self.message {
+                    frame.text(message);
+                }
+
+                if self.last_frame != Some(config.current_frame) {
+                    self.entries = profiler.flat();
+                    self.last_frame = Some(config.current_frame);
+                }
+                if let Some(table) = frame.begin_table_header_with_flags(
+                    "ProfilerEntries",
+                    [
+                        TableColumnSetup::new("Name"),
+                        TableColumnSetup::with_flags_and_init_width_or_weight(
+                            "Calls",
+                            TableColumnFlags::WIDTH_FIXED,
+                            70.0,
+                        ),
+                        TableColumnSetup::with_flags_and_init_width_or_weight(
+                            "Total ms",
+                            TableColumnFlags::WIDTH_FIXED,
+                            80.0,
+                        ),
+                        TableColumnSetup::with_flags_and_init_width_or_weight(
+                            "Self ms",
+                            TableColumnFlags::WIDTH_FIXED,
+                            80.0,
+                        ),
+                    ],
+                    TableFlags::ROW_BG | TableFlags::BORDERS | TableFlags::SCROLL_Y,
+                ) {
+                    for entry in &self.entries {
+                        frame.table_next_column();
+                        frame.text(&entry.name);
+                        frame.table_next_column();
+                        frame.text(entry.calls.to_string());
+                        frame.table_next_column();
+                        frame.text(format!("{:.3}", entry.total.as_secs_f64() * 1000.0));
+                        frame.table_next_column();
+                        frame.text(format!("{:.3}", entry.own.as_secs_f64() * 1000.0));
+                    }
+                    table.end();
+                }
+            });
+    }
+
+    fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+impl ProfilerWindow {
+    pub fn new() -> Self {
+        Self { is_open: true, entries: Vec::new(), last_frame: None, message: None }
+    }
+}
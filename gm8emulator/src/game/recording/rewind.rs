@@ -0,0 +1,97 @@
+use crate::game::{
+    replay::Replay,
+    savestate::{self, SaveState},
+};
+use std::collections::VecDeque;
+
+/// How many frames apart the automatic rewind states are taken.
+pub const REWIND_INTERVAL: usize = 30;
+
+/// How much memory the compressed rewind states may take up in total, in bytes.
+pub const REWIND_CAPACITY: usize = 512 * 1024 * 1024;
+
+/// A ring of compressed SaveStates taken automatically while recording, used to seek back through the replay.
+/// States are kept in order of frame, and all of them are along the replay that's currently loaded, so they're
+/// stored without their replay and get the start of the loaded one back when they're loaded.
+/// When they take up too much memory, the oldest ones are dropped.
+pub struct RewindBuffer {
+    states: VecDeque<(usize, Box<[u8]>)>,
+    size: usize,
+    interval: usize,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        Self { states: VecDeque::new(), size: 0, interval: interval.max(1), capacity }
+    }
+
+    /// Whether a state should be taken now that the replay is at the given frame.
+    pub fn wants(&self, frame: usize) -> bool {
+        frame % self.interval == 0 && self.states.back().map_or(true, |(f, _)| *f < frame)
+    }
+
+    /// Stores a state for the given frame, replacing any from that frame or later.
+    pub fn push(
+        &mut self,
+        frame: usize,
+        state: &SaveState,
+        buffer: &mut savestate::Buffer,
+    ) -> Result<(), savestate::WriteError> {
+        let data = state.to_compressed(buffer)?;
+        match frame.checked_sub(1) {
+            Some(previous) => self.truncate(previous),
+            None => self.clear(),
+        }
+        self.size += data.len();
+        self.states.push_back((frame, data));
+        while self.size > self.capacity && self.states.len() > 1 {
+            if let Some((_, data)) = self.states.pop_front() {
+                self.size -= data.len();
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the latest state from the given frame or before it, if there is one, with the frames of the given
+    /// replay up to that state as its replay.
+    pub fn get(
+        &self,
+        frame: usize,
+        replay: &Replay,
+        buffer: &mut savestate::Buffer,
+    ) -> Option<Result<SaveState, savestate::ReadError>> {
+        let (frame, data) = self.states.iter().rev().find(|(f, _)| *f <= frame)?;
+        Some(SaveState::from_compressed(data, buffer).map(|state| {
+            let mut state_replay = replay.clone();
+            state_replay.truncate_frames(*frame);
+            state.with_replay(state_replay)
+        }))
+    }
+
+    /// Drops every state from after the given frame, such as when the replay is changed from that point on.
+    pub fn truncate(&mut self, frame: usize) {
+        while let Some((f, data)) = self.states.back() {
+            if *f <= frame {
+                break
+            }
+            self.size -= data.len();
+            self.states.pop_back();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.size = 0;
+    }
+
+    /// The frame of the earliest state that can still be rewound to, if any.
+    pub fn first_frame(&self) -> Option<usize> {
+        self.states.front().map(|(f, _)| *f)
+    }
+
+    /// The number of states stored and the memory they take up, in bytes.
+    pub fn usage(&self) -> (usize, usize) {
+        (self.states.len(), self.size)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::game::{
+    recording::window::{EmulatorContext, Openable, Window},
+    replay::FrameRng,
+};
+use imgui::{TableColumnSetup, TableFlags};
+
+const LOOKAHEAD_HEIGHT: f32 = 200.0;
+
+/// Shows what the next calls to random functions will give, and searches for the RNG change
+/// which makes something happen by a given frame.
+pub struct RngPlannerWindow {
+    is_open: bool,
+    calls: i32,
+    irandom_bound: i32,
+    choose_count: i32,
+    condition: String,
+    frames: i32,
+    max_increment: i32,
+    as_override: bool,
+    /// The increment that was found and the frame it was found on, since it's only any use on that frame.
+    found: Option<(usize, i32)>,
+    message: Option<String>,
+}
+
+impl Openable<Self> for RngPlannerWindow {
+    fn window_name() -> &'static str {
+        "RNG Planner"
+    }
+
+    fn open(_id: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl Window for RngPlannerWindow {
+    fn stored_kind(&self) -> Option<super::WindowKind> {
+        Some(super::WindowKind::RngPlanner)
+    }
+
+    fn name(&self) -> String {
+        "RNG Planner".into()
+    }
+
+    fn show_window(&mut self, info: &mut EmulatorContext) {
+        let frame = info.frame;
+        if self.found.map_or(false, |(found_frame, _)| found_frame != info.config.current_frame) {
+            self.found = None;
+            self.message = None;
+        }
+        frame
+            .window(self.name())
+            .opened(&mut self.is_open)
+            .position([300.0, 200.0], imgui::Condition::FirstUseEver)
+            .size([420.0, 500.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                // The seed the next frame will start with, including any change that's been set for it
+                let mut rng = info.game.rand.clone();
+                match info.new_rand {
+                    Some(FrameRng::Increment(amount)) => (0..*amount).for_each(|_| rng.cycle()),
+                    Some(FrameRng::Override(seed)) => rng.set_seed(*seed),
+                    None => (),
+                }
+
+                frame.text(format!("Seed next frame: {}", rng.seed()));
+                frame.input_int("Calls", &mut self.calls).build();
+                let mut typing = frame.is_item_focused();
+                frame.input_int("irandom(n), n =", &mut self.irandom_bound).build();
+                typing |= frame.is_item_focused();
+                frame.input_int("choose() arguments", &mut self.choose_count).build();
+                typing |= frame.is_item_focused();
+                self.calls = self.calls.clamp(1, 1000);
+                self.irandom_bound = self.irandom_bound.max(0);
+                self.choose_count = self.choose_count.max(1);
+
+                if let Some(table) = frame.begin_table_header_with_sizing(
+                    "RngLookahead",
+                    [
+                        TableColumnSetup::new("Call"),
+                        TableColumnSetup::new("Seed"),
+                        TableColumnSetup::new("random(1)"),
+                        TableColumnSetup::new(format!("irandom({})", self.irandom_bound)),
+                        TableColumnSetup::new("choose()"),
+                    ],
+                    TableFlags::ROW_BG | TableFlags::BORDERS | TableFlags::SCROLL_Y,
+                    [0.0, LOOKAHEAD_HEIGHT],
+                    0.0,
+                ) {
+                    for call in 1..=self.calls {
+                        frame.table_next_column();
+                        frame.text(call.to_string());
+                        frame.table_next_column();
+                        frame.text(rng.seed().to_string());
+                        frame.table_next_column();
+                        frame.text(format!("{:.6}", rng.clone().next(1.0)));
+                        frame.table_next_column();
+                        frame.text(rng.clone().next_int(self.irandom_bound as u32).to_string());
+                        frame.table_next_column();
+                        frame.text(format!("argument{}", rng.clone().next_int(self.choose_count as u32 - 1)));
+                        rng.cycle();
+                    }
+                    table.end();
+                }
+
+                frame.separator();
+                frame.text_wrapped(
+                    "Search for how many times to cycle the RNG on the next frame to make a condition true. \
+                    Frames are run with the replay's inputs, or with no new inputs after it ends.",
+                );
+                frame.input_text("Condition", &mut self.condition).hint("e.g. obj_enemy.x > 320").build();
+                typing |= frame.is_item_focused();
+                frame.input_int("Frames ahead", &mut self.frames).build();
+                typing |= frame.is_item_focused();
+                frame.input_int("Most increments", &mut self.max_increment).build();
+                typing |= frame.is_item_focused();
+                self.frames = self.frames.max(1);
+                self.max_increment = self.max_increment.max(0);
+                if typing {
+                    info.keybindings.disable_bindings();
+                }
+
+                if frame.button("Search") && !self.condition.is_empty() {
+                    let result = search(info, &self.condition, self.frames as usize, self.max_increment);
+                    self.found = result.as_ref().ok().map(|increment| (info.config.current_frame, *increment));
+                    self.message = Some(match result {
+                        Ok(increment) => {
+                            format!("The condition is true {} frame(s) ahead with +{} RNG.", self.frames, increment)
+                        },
+                        Err(e) => e,
+                    });
+                }
+                if let Some(message) = &self.message {
+                    frame.text_wrapped(message);
+                }
+                if let Some((_, increment)) = self.found {
+                    frame.checkbox("Apply as a seed override", &mut self.as_override);
+                    if frame.button("Apply to next frame") {
+                        *info.new_rand = if self.as_override {
+                            let mut rng = info.game.rand.clone();
+                            (0..increment).for_each(|_| rng.cycle());
+                            Some(FrameRng::Override(rng.seed()))
+                        } else if increment > 0 {
+                            Some(FrameRng::Increment(increment))
+                        } else {
+                            None
+                        };
+                    }
+                }
+            });
+    }
+
+    fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+impl RngPlannerWindow {
+    pub fn new() -> Self {
+        Self {
+            is_open: true,
+            calls: 10,
+            irandom_bound: 10,
+            choose_count: 2,
+            condition: String::new(),
+            frames: 1,
+            max_increment: 100,
+            as_override: false,
+            found: None,
+            message: None,
+        }
+    }
+}
+
+/// Compiles the condition and searches for the smallest RNG increment which makes it true.
+fn search(info: &mut EmulatorContext, condition: &str, frames: usize, max_increment: i32) -> Result<i32, String> {
+    if !*info.game_running {
+        return Err("The game isn't running. Please load a savestate.".into())
+    }
+    // Compiled on a copy of the compiler, so fields only named in the condition don't get registered in the game
+    let predicate = match info.game.compiler.clone().compile_expression(condition.as_bytes()) {
+        Ok(program) if program.is_pure() => program,
+        Ok(_) => return Err("The condition calls functions which could change the game.".into()),
+        Err(e) => return Err(format!("Couldn't compile the condition: {}", e)),
+    };
+    match info.plan_rng(&predicate, frames, max_increment)? {
+        Some(increment) => Ok(increment),
+        None => Err(format!("Nothing up to +{} RNG made the condition true.", max_increment)),
+    }
+}
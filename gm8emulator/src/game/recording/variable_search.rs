@@ -0,0 +1,394 @@
+use crate::{
+    game::{
+        recording::window::{EmulatorContext, Openable, Window},
+        Game, GetAsset,
+    },
+    gml::Value,
+    instance::Field,
+    types::ID,
+};
+use imgui::{TableColumnFlags, TableColumnSetup, TableFlags};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// How many search results are listed at once. The rest are still searched, just not shown.
+const MAX_SHOWN: usize = 500;
+
+const RESULTS_HEIGHT: f32 = 240.0;
+
+/// A global or instance field, or one element of it if it's an array.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Variable {
+    owner: Option<ID>,
+    field: usize,
+    index: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value,
+}
+
+/// Searches every global and instance field for ones that change in some way, like a RAM search.
+/// The variables it's asked to watch are kept in the project's WatchLog.
+pub struct VariableSearchWindow {
+    is_open: bool,
+    results: Vec<(Variable, Value)>,
+    searched: bool,
+    filter: Filter,
+    value_input: String,
+    message: Option<String>,
+}
+
+/// The watched variables, and the CSV file they're logged to every frame the game advances.
+/// Each file only has the columns for one set of watches, so a new one is started when they change.
+pub struct WatchLog {
+    watches: Vec<Variable>,
+    file: Option<File>,
+
+    /// The folder the files go in, and how many have been started since logging was turned on
+    dir: PathBuf,
+    files: usize,
+
+    /// Whether the current file has a header yet, and whether it's for the current watches
+    started: bool,
+    header: bool,
+    error: Option<String>,
+}
+
+impl Openable<Self> for VariableSearchWindow {
+    fn window_name() -> &'static str {
+        "Variable Search"
+    }
+
+    fn open(_id: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl Window for VariableSearchWindow {
+    fn stored_kind(&self) -> Option<super::WindowKind> {
+        Some(super::WindowKind::VariableSearch)
+    }
+
+    fn name(&self) -> String {
+        "Variable Search".into()
+    }
+
+    fn show_window(&mut self, info: &mut EmulatorContext) {
+        let EmulatorContext { frame, game, project_path, keybindings, watch_log, .. } = info;
+
+        if let Some(error) = watch_log.error.take() {
+            self.message = Some(error);
+        }
+
+        frame
+            .window(self.name())
+            .opened(&mut self.is_open)
+            .position([250.0, 250.0], imgui::Condition::FirstUseEver)
+            .size([480.0, 520.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if frame.button("New search") {
+                    self.results = all_variables(game);
+                    self.searched = true;
+                }
+                if self.searched {
+                    frame.same_line();
+                    if frame.button("Filter") {
+                        let target = parse_value(&self.value_input);
+                        let filter = self.filter;
+                        self.results.retain_mut(|(variable, previous)| match variable.read(game) {
+                            Some(current) => {
+                                let keep = filter.matches(previous, &current, &target);
+                                *previous = current;
+                                keep
+                            },
+                            None => false,
+                        });
+                    }
+                    frame.same_line();
+                    frame.text(format!("{} result(s)", self.results.len()));
+                }
+
+                frame.radio_button("Equal", &mut self.filter, Filter::Equal);
+                frame.same_line();
+                frame.radio_button("Changed", &mut self.filter, Filter::Changed);
+                frame.same_line();
+                frame.radio_button("Increased", &mut self.filter, Filter::Increased);
+                frame.same_line();
+                frame.radio_button("Decreased", &mut self.filter, Filter::Decreased);
+                frame.same_line();
+                frame.radio_button("Value", &mut self.filter, Filter::Value);
+                if self.filter == Filter::Value {
+                    frame.input_text("##value", &mut self.value_input).build();
+                    if frame.is_item_focused() {
+                        keybindings.disable_bindings();
+                    }
+                }
+
+                if self.searched {
+                    if let Some(table) = frame.begin_table_header_with_sizing(
+                        "VariableSearchResults",
+                        [
+                            TableColumnSetup::new("Variable"),
+                            TableColumnSetup::new("Previous"),
+                            TableColumnSetup::new("Current"),
+                            TableColumnSetup::with_flags_and_init_width_or_weight(
+                                "",
+                                TableColumnFlags::WIDTH_FIXED,
+                                30.0,
+                            ),
+                        ],
+                        TableFlags::ROW_BG | TableFlags::BORDERS | TableFlags::SCROLL_Y,
+                        [0.0, RESULTS_HEIGHT],
+                        0.0,
+                    ) {
+                        for (i, (variable, previous)) in self.results.iter().take(MAX_SHOWN).enumerate() {
+                            frame.table_next_column();
+                            frame.text(variable.name(game));
+                            frame.table_next_column();
+                            frame.text(previous.to_string());
+                            frame.table_next_column();
+                            frame.text(variable.read(game).map_or("<deleted>".into(), |v| v.to_string()));
+                            frame.table_next_column();
+                            if frame.small_button(format!("+##pin{}", i)) && !watch_log.watches.contains(variable) {
+                                watch_log.watches.push(*variable);
+                                watch_log.header = false;
+                            }
+                            if frame.is_item_hovered() {
+                                frame.tooltip_text("Watch");
+                            }
+                        }
+                        table.end();
+                    }
+                    if self.results.len() > MAX_SHOWN {
+                        frame.text(format!("Only the first {} results are shown", MAX_SHOWN));
+                    }
+                }
+
+                frame.separator();
+                frame.text("Watches");
+                let mut logging = watch_log.file.is_some();
+                if frame.checkbox("Log to watches.csv every frame", &mut logging) {
+                    self.message = if logging {
+                        watch_log.start(project_path).err().map(|e| format!("Couldn't create a log file: {}", e))
+                    } else {
+                        watch_log.file = None;
+                        None
+                    };
+                }
+                if let Some(message) = &self.message {
+                    frame.text_wrapped(message);
+                } else if let Some(path) = watch_log.path() {
+                    frame.text_wrapped(format!("Logging to {}", path.display()));
+                }
+                let mut unwatch = None;
+                for (i, variable) in watch_log.watches.iter().enumerate() {
+                    if frame.small_button(format!("x##unwatch{}", i)) {
+                        unwatch = Some(i);
+                    }
+                    frame.same_line();
+                    frame.text(format!(
+                        "{} = {}",
+                        variable.name(game),
+                        variable.read(game).map_or("<deleted>".into(), |v| v.to_string()),
+                    ));
+                }
+                if let Some(i) = unwatch {
+                    watch_log.watches.remove(i);
+                    watch_log.header = false;
+                }
+            });
+    }
+
+    fn is_open(&self) -> bool {
+        self.is_open
+    }
+}
+
+impl VariableSearchWindow {
+    pub fn new() -> Self {
+        Self {
+            is_open: true,
+            results: Vec::new(),
+            searched: false,
+            filter: Filter::Changed,
+            value_input: String::new(),
+            message: None,
+        }
+    }
+}
+
+impl WatchLog {
+    pub fn new() -> Self {
+        Self {
+            watches: Vec::new(),
+            file: None,
+            dir: PathBuf::new(),
+            files: 0,
+            started: false,
+            header: false,
+            error: None,
+        }
+    }
+
+    /// Starts logging to watches.csv in the given folder.
+    fn start(&mut self, dir: &Path) -> io::Result<()> {
+        self.dir = dir.to_path_buf();
+        self.files = 0;
+        self.next_file()
+    }
+
+    /// Starts the next log file, which is watches.csv, then watches-2.csv and so on.
+    fn next_file(&mut self) -> io::Result<()> {
+        self.file = None;
+        self.file = Some(File::create(self.file_path(self.files + 1))?);
+        self.files += 1;
+        self.started = false;
+        self.header = false;
+        Ok(())
+    }
+
+    fn file_path(&self, number: usize) -> PathBuf {
+        match number {
+            1 => self.dir.join("watches.csv"),
+            n => self.dir.join(format!("watches-{}.csv", n)),
+        }
+    }
+
+    /// The file being logged to, if logging is on.
+    fn path(&self) -> Option<PathBuf> {
+        self.file.as_ref().map(|_| self.file_path(self.files))
+    }
+
+    /// Logs the watched variables as they are at the given frame, if logging is on.
+    /// Logging stops if the file can't be written to, and the error is shown in the Variable Search window.
+    pub fn log_frame(&mut self, game: &Game, frame: usize) {
+        if let Err(e) = self.write_row(game, frame) {
+            self.error = Some(format!("Stopped logging because of an error: {}", e));
+            self.file = None;
+        }
+    }
+
+    /// Writes the watched variables' values to the CSV log, if there is one.
+    /// If the watches have changed since the file's header was written, the row goes in a new file instead.
+    fn write_row(&mut self, game: &Game, frame: usize) -> io::Result<()> {
+        if self.file.is_none() {
+            return Ok(())
+        }
+        if !self.header && self.started {
+            self.next_file()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        if !self.header {
+            let names = self.watches.iter().map(|v| csv_cell(&v.name(game))).collect::<Vec<_>>();
+            writeln!(file, "frame,{}", names.join(","))?;
+            self.started = true;
+            self.header = true;
+        }
+        let values = self
+            .watches
+            .iter()
+            .map(|v| v.read(game).map_or(String::new(), |value| csv_cell(&value.to_string())))
+            .collect::<Vec<_>>();
+        writeln!(file, "{},{}", frame, values.join(","))
+    }
+}
+
+impl Variable {
+    fn read(&self, game: &Game) -> Option<Value> {
+        match self.owner {
+            None => game.globals.fields.get(&self.field).and_then(|f| f.get(self.index)),
+            Some(id) => {
+                let handle = game.room.instance_list.get_by_instid(id)?;
+                game.room.instance_list.get(handle).fields.borrow().get(&self.field).and_then(|f| f.get(self.index))
+            },
+        }
+    }
+
+    /// Names the variable the way it would be written in GML, along with the object of the instance it's in.
+    fn name(&self, game: &Game) -> String {
+        let field = game.compiler.get_field_name(self.field).unwrap_or("<???>".into());
+        let index = if self.index == 0 { String::new() } else { format!("[{}]", self.index) };
+        match self.owner {
+            None => format!("global.{}{}", field, index),
+            Some(id) => {
+                let object = game
+                    .room
+                    .instance_list
+                    .get_by_instid(id)
+                    .and_then(|handle| {
+                        game.assets.objects.get_asset(game.room.instance_list.get(handle).object_index.get())
+                    })
+                    .map(|object| object.name.decode(game.encoding))
+                    .unwrap_or("<deleted object>".into());
+                format!("{}.{}{} ({})", id, field, index, object)
+            },
+        }
+    }
+}
+
+impl Filter {
+    fn matches(self, previous: &Value, current: &Value, target: &Value) -> bool {
+        match (self, previous, current) {
+            (Self::Equal, _, _) => previous == current,
+            (Self::Changed, _, _) => previous != current,
+            (Self::Increased, Value::Real(a), Value::Real(b)) => b > a,
+            (Self::Decreased, Value::Real(a), Value::Real(b)) => b < a,
+            (Self::Increased | Self::Decreased, _, _) => false,
+            (Self::Value, _, _) => current == target,
+        }
+    }
+}
+
+/// Lists every element of every global and instance field, with its current value.
+fn all_variables(game: &Game) -> Vec<(Variable, Value)> {
+    fn add(variables: &mut Vec<(Variable, Value)>, owner: Option<ID>, field: usize, value: &Field) {
+        match value {
+            Field::Single(value) => variables.push((Variable { owner, field, index: 0 }, value.clone())),
+            Field::Array(array) => variables
+                .extend(array.iter().map(|(index, value)| (Variable { owner, field, index: *index }, value.clone()))),
+        }
+    }
+
+    let mut variables = Vec::new();
+    for (field, value) in &game.globals.fields {
+        add(&mut variables, None, *field, value);
+    }
+    let list = &game.room.instance_list;
+    let (mut active, mut inactive) = (list.iter_by_drawing(), list.iter_inactive());
+    while let Some(handle) = active.next(list).or_else(|| inactive.next(list)) {
+        let instance = list.get(handle);
+        for (field, value) in instance.fields.borrow().iter() {
+            add(&mut variables, Some(instance.id.get()), *field, value);
+        }
+    }
+    variables.sort_by_key(|(v, _)| (v.owner, v.field, v.index));
+    variables
+}
+
+/// Reads a value to search for, which is a string unless it looks like a number.
+fn parse_value(input: &str) -> Value {
+    match input.trim().parse::<f64>() {
+        Ok(number) => Value::Real(number.into()),
+        Err(_) => Value::Str(input.into()),
+    }
+}
+
+fn csv_cell(text: &str) -> String {
+    if text.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.into()
+    }
+}
@@ -1,15 +1,19 @@
 use crate::{
     game::{
         recording::{
+            branches::BranchTree,
             instance_report::InstanceReport,
             keybinds::{Binding, Keybindings},
             popup_dialog::Dialog,
-            KeyState, ProjectConfig, WindowKind,
+            rewind::RewindBuffer,
+            variable_search::WatchLog,
+            KeyState, ProjectConfig, WindowKind, CLEAR_COLOUR_BAD, CLEAR_COLOUR_GOOD,
         },
-        replay::{FrameRng, Replay},
+        replay::{Frame, FrameRng, Replay},
         savestate::{self, SaveState},
-        Game,
+        Game, GameClock, SceneChange,
     },
+    gml::{bytecode::Program, Context},
     imgui_utils::Vec2,
     render::RendererState,
 };
@@ -22,6 +26,7 @@ pub struct EmulatorContext<'a> {
     pub setting_mouse_pos: &'a mut bool,
     pub new_mouse_pos: &'a mut Option<(i32, i32)>,
     pub new_rand: &'a mut Option<FrameRng>,
+    pub new_clipboard: &'a mut Option<String>,
     pub config: &'a mut ProjectConfig,
     pub err_string: &'a mut Option<String>,
     pub replay: &'a mut Replay,
@@ -29,12 +34,17 @@ pub struct EmulatorContext<'a> {
     pub keyboard_state: &'a mut [KeyState; 256],
     pub mouse_state: &'a mut [KeyState; 3],
     pub savestate: &'a mut SaveState,
+    pub startup_state: &'a SaveState,
     pub renderer_state: &'a mut RendererState,
     pub save_buffer: &'a mut savestate::Buffer,
+    pub rewind: &'a mut RewindBuffer,
+    pub branches: &'a mut BranchTree,
     pub instance_reports: &'a mut Vec<(i32, Option<InstanceReport>)>,
+    pub watch_log: &'a mut WatchLog,
 
     pub clean_state: &'a mut bool,
     pub run_until_frame: &'a mut Option<usize>,
+    pub rerecord_pending: &'a mut bool,
 
     pub save_paths: &'a Vec<PathBuf>,
     pub fps_text: &'a String,
@@ -117,6 +127,11 @@ impl EmulatorContext<'_> {
             self.config.watched_ids.iter().map(|id| (*id, InstanceReport::new(self.game, *id))).collect();
     }
 
+    /// Logs the watched variables for the frame that just finished. Called everywhere the game advances a frame.
+    pub fn log_watches(&mut self) {
+        self.watch_log.log_frame(self.game, self.config.current_frame);
+    }
+
     pub fn keybind_pressed(&self, binding: Binding) -> bool {
         self.keybindings.keybind_pressed(binding, self.frame)
     }
@@ -185,7 +200,8 @@ impl EmulatorContext<'_> {
 
     fn savestate_from_slot(&mut self, slot: usize) -> Option<SaveState> {
         if slot < self.save_paths.len() {
-            let path = &self.save_paths[slot];
+            let save_paths = self.save_paths;
+            let path = &save_paths[slot];
             if !path.exists() {
                 return None;
             }
@@ -194,7 +210,7 @@ impl EmulatorContext<'_> {
                 Ok(state) => Some(state),
                 Err(err) => {
                     let filename = path.to_string_lossy();
-                    *self.err_string = Some(match err {
+                    let message = match err {
                         savestate::ReadError::IOErr(err) => format!("Error reading {}:\n\n{}", filename, err),
                         savestate::ReadError::DecompressErr(err) => {
                             format!("Error decompressing {}:\n\n{}", filename, err)
@@ -202,8 +218,42 @@ impl EmulatorContext<'_> {
                         savestate::ReadError::DeserializeErr(err) => {
                             format!("Error deserializing {}:\n\n{}", filename, err)
                         },
-                    });
-                    None
+                        savestate::ReadError::UnknownVersion(version) => format!(
+                            "{} is from a newer version of OpenGMK (savestate version {}, this one reads up to {})",
+                            filename,
+                            version,
+                            savestate::VERSION,
+                        ),
+                        savestate::ReadError::UnknownSchema(schema) => {
+                            format!("{} isn't a savestate this version can read (its schema is {:?})", filename, schema)
+                        },
+                    };
+
+                    // If the replay in it can still be read, the savestate can be made again by playing that back
+                    match SaveState::replay_from_file(&path, self.save_buffer) {
+                        Ok(replay) => match self.savestate_rebuild(replay) {
+                            Ok(state) => {
+                                if let Err(err) = state.save_to_file(&path, self.save_buffer) {
+                                    *self.err_string = Some(format!(
+                                        "{} was rebuilt from its replay, but couldn't be saved again: {:?}",
+                                        filename, err,
+                                    ));
+                                }
+                                Some(state)
+                            },
+                            Err(rebuild_err) => {
+                                *self.err_string = Some(format!(
+                                    "{}\n\nIt couldn't be rebuilt from its replay either: {}",
+                                    message, rebuild_err,
+                                ));
+                                None
+                            },
+                        },
+                        Err(_) => {
+                            *self.err_string = Some(message);
+                            None
+                        },
+                    }
                 },
             }
         } else {
@@ -211,6 +261,46 @@ impl EmulatorContext<'_> {
         }
     }
 
+    /// Makes a savestate again from its replay, for when the savestate itself couldn't be loaded. The replay is
+    /// played back from the start of the game, or from the latest rewind state that's part of it if there is one,
+    /// and the game is put back how it was afterwards.
+    fn savestate_rebuild(&mut self, replay: Replay) -> Result<SaveState, String> {
+        // Keep the current state so it can be restored whether or not the rebuild works
+        let mut current_replay = self.replay.clone();
+        current_replay.truncate_frames(self.config.current_frame);
+        let current = SaveState::from(self.game, current_replay, self.renderer_state.clone(), *self.clean_state);
+        let current_frame = self.config.current_frame;
+        let unanswered_dialog = self.game.unanswered_dialog.take();
+
+        let start = replay.common_frames(self.replay);
+        let (clean_state, result) = match self.rewind.get(start, self.replay, self.save_buffer) {
+            Some(Ok(state)) => {
+                let clean_state = state.clean_state;
+                let (start_replay, renderer_state) = state.load_into(self.game);
+                self.game.renderer.set_state(&renderer_state);
+                (clean_state, self.game.play_frames(&replay, start_replay.frame_count()))
+            },
+            _ => {
+                let (_, renderer_state) = self.startup_state.clone().load_into(self.game);
+                self.game.renderer.set_state(&renderer_state);
+                (true, self.game.play_from_start(&replay))
+            },
+        };
+        self.game.renderer.resize_framebuffer(self.config.ui_width.into(), self.config.ui_height.into(), true);
+        let renderer_state = self.game.renderer.state();
+        self.game.renderer.set_state(self.ui_renderer_state);
+        let result = match result {
+            Ok(()) => Ok(SaveState::from(self.game, replay, renderer_state, clean_state)),
+            Err(err) => Err(err.to_string()),
+        };
+
+        let (_, renderer_state) = current.load_into(self.game);
+        *self.renderer_state = renderer_state;
+        self.game.unanswered_dialog = unanswered_dialog;
+        self.config.current_frame = current_frame;
+        result
+    }
+
     fn savestate_load_from_slot(&mut self, slot: usize) -> bool {
         if let Some(state) = self.savestate_from_slot(slot) {
             self.savestate_load_from_state(state);
@@ -221,37 +311,258 @@ impl EmulatorContext<'_> {
     }
 
     fn savestate_load_from_state(&mut self, state: SaveState) {
+        let new_replay = self.load_state(state);
+        let part_of_replay = self.replay.contains_part(&new_replay);
+
+        if self.config.is_read_only && !part_of_replay {
+            *self.err_string = Some("Savestate is not part of recording.\nPlease load a savestate that's part of the current recording or try again in Read/Write mode.".into());
+            *self.game_running = false;
+        } else if !self.config.is_read_only {
+            // The replay is cut off at this savestate, so rewind states from after it or from another branch of the
+            // replay can't be used anymore. Storing the new state drops the ones after it.
+            if !part_of_replay {
+                self.rewind.clear();
+            }
+            self.branches.detach(self.replay);
+            *self.replay = new_replay;
+            self.rewind_capture();
+        }
+        *self.rerecord_pending = false;
+        self.config.rerecords += 1;
+        self.config.save();
+
+        self.update_instance_reports();
+    }
+
+    /// Loads a SaveState into the game and resets the UI state to match, returning the state's replay.
+    fn load_state(&mut self, state: SaveState) -> Replay {
         *self.clean_state = state.clean_state;
         let (new_replay, new_renderer_state) = state.load_into(self.game);
         *self.renderer_state = new_renderer_state;
 
+        self.reset_key_states();
+        self.clear_context_menu();
+        *self.new_rand = None;
+        *self.new_mouse_pos = None;
+        *self.new_clipboard = None;
+        *self.err_string = None;
+        *self.game_running = true;
+
+        self.config.current_frame = new_replay.frame_count();
+        new_replay
+    }
+
+    fn reset_key_states(&mut self) {
         for (i, state) in self.keyboard_state.iter_mut().enumerate() {
             *state = if self.game.input.keyboard_check_direct(i as u8) { KeyState::Held } else { KeyState::Neutral };
         }
         for (i, state) in self.mouse_state.iter_mut().enumerate() {
             *state = if self.game.input.mouse_check_button(i as i8 + 1) { KeyState::Held } else { KeyState::Neutral };
         }
+    }
+
+    /// Stores the current state in the rewind buffer. Its replay is left empty, as the rewind buffer fills it in from
+    /// the loaded replay.
+    pub fn rewind_capture(&mut self) {
+        let state_replay = Replay::new(self.replay.start_time, self.replay.start_seed);
+        let state = SaveState::from(self.game, state_replay, self.renderer_state.clone(), *self.clean_state);
+        if let Err(err) = self.rewind.push(self.config.current_frame, &state, self.save_buffer) {
+            *self.err_string = Some(format!("Failed to store rewind state: {:?}", err));
+        }
+    }
+
+    /// Seeks to the given frame of the replay. To go back, the latest rewind state before that frame is loaded.
+    /// The replay's inputs are then run up to the frame, without drawing the UI in between.
+    /// Going back only counts as a rerecord once the replay is recorded over from there, and the rewind states after
+    /// the frame are kept until then too.
+    pub fn seek_to_frame(&mut self, frame: usize) -> bool {
+        *self.run_until_frame = None;
+        let frame = frame.min(self.replay.frame_count());
+        if frame < self.config.current_frame || !*self.game_running {
+            match self.rewind.get(frame, self.replay, self.save_buffer) {
+                Some(Ok(state)) => {
+                    self.load_state(state);
+                    *self.rerecord_pending = true;
+                },
+                Some(Err(err)) => {
+                    *self.err_string = Some(format!("Failed to load rewind state: {:?}", err));
+                    return false
+                },
+                None => {
+                    *self.err_string = Some(format!("There are no rewind states from frame {} or before.", frame));
+                    return false
+                },
+            }
+        }
 
+        while self.config.current_frame < frame {
+            let replay_frame = self.replay.get_frame(self.config.current_frame).unwrap().clone();
+            if let Some(error) = self.run_frame(&replay_frame) {
+                *self.err_string = Some(error);
+                *self.game_running = false;
+            }
+            self.game.stored_events.clear();
+            self.config.current_frame += 1;
+            self.log_watches();
+            if !*self.game_running {
+                break
+            }
+            if let Some(kind) = &self.game.unanswered_dialog {
+                *self.err_string = Some(format!(
+                    "Seeking stopped: the game called {} with no answer in the replay.",
+                    kind.function_name()
+                ));
+                break
+            }
+            if self.rewind.wants(self.config.current_frame) {
+                self.rewind_capture();
+            }
+        }
+
+        self.reset_key_states();
         self.clear_context_menu();
         *self.new_rand = None;
         *self.new_mouse_pos = None;
-        *self.err_string = None;
-        *self.game_running = true;
+        *self.new_clipboard = None;
+        self.update_instance_reports();
+        true
+    }
 
-        self.config.current_frame = new_replay.frame_count();
+    /// Makes a branch the one being recorded. If the game is past the point where its inputs differ from the
+    /// current ones, it seeks back to there first. Rewind states from after that point are dropped.
+    pub fn restore_branch(&mut self, index: usize) -> bool {
+        let common = self.branches.replay(index, self.replay).common_frames(self.replay);
+        if self.config.current_frame > common && !self.seek_to_frame(common) {
+            return false
+        }
+        self.branches.sync(self.replay);
+        *self.replay = self.branches.replay(index, self.replay).clone();
+        self.rewind.truncate(common);
+        self.branches.set_current(index);
+        true
+    }
 
-        if self.config.is_read_only {
-            if !self.replay.contains_part(&new_replay) {
-                *self.err_string = Some("Savestate is not part of recording.\nPlease load a savestate that's part of the current recording or try again in Read/Write mode.".into());
-                *self.game_running = false;
+    /// Looks for how many times the RNG needs to be cycled on the next frame for `predicate` to be true after
+    /// `frames` frames, trying each count up to `max_increment`. The replay's inputs are used for those frames,
+    /// or no new inputs once it runs out. The game is put back the way it was afterwards.
+    pub fn plan_rng(&mut self, predicate: &Program, frames: usize, max_increment: i32) -> Result<Option<i32>, String> {
+        let mut state_replay = self.replay.clone();
+        state_replay.truncate_frames(self.config.current_frame);
+        let last_frame = self.config.current_frame.checked_sub(1).and_then(|i| state_replay.get_frame(i));
+        let (mouse_x, mouse_y) = match last_frame {
+            Some(frame) => (frame.mouse_x, frame.mouse_y),
+            None => (0, 0),
+        };
+        let state = SaveState::from(self.game, state_replay, self.renderer_state.clone(), *self.clean_state);
+
+        let mut result = Ok(None);
+        'search: for increment in 0..=max_increment {
+            if increment > 0 {
+                let (_, renderer_state) = state.clone().load_into(self.game);
+                *self.renderer_state = renderer_state;
+            }
+            for i in 0..frames {
+                let mut frame = self.replay.get_frame(self.config.current_frame + i).cloned().unwrap_or(Frame {
+                    mouse_x,
+                    mouse_y,
+                    inputs: Vec::new(),
+                    events: Vec::new(),
+                    new_seed: None,
+                    new_time: None,
+                });
+                if i == 0 {
+                    frame.new_seed = if increment > 0 { Some(FrameRng::Increment(increment)) } else { None };
+                }
+                let crashed = self.run_frame(&frame).is_some();
+                self.game.stored_events.clear();
+                if crashed {
+                    break
+                }
+                if i + 1 == frames {
+                    match self.game.eval(predicate, &mut Context::with_single_instance(0)) {
+                        Ok(value) if value.is_truthy() => {
+                            result = Ok(Some(increment));
+                            break 'search
+                        },
+                        Ok(_) => (),
+                        Err(e) => {
+                            result = Err(format!("Couldn't evaluate the condition: {}", e));
+                            break 'search
+                        },
+                    }
+                }
             }
-        } else {
-            *self.replay = new_replay;
         }
-        self.config.rerecords += 1;
-        self.config.save();
+        let (_, renderer_state) = state.load_into(self.game);
+        *self.renderer_state = renderer_state;
+        self.game.stored_events.clear();
+        result
+    }
 
-        self.update_instance_reports();
+    /// Runs a frame of the game with the given inputs, then sets the renderer back up for the UI.
+    /// If an error occured it will return a message, otherwise None
+    pub fn run_frame(&mut self, frame: &Frame) -> Option<String> {
+        self.game.input.mouse_step();
+        self.game.set_input_from_frame(frame);
+        self.game.unanswered_dialog = None;
+
+        let game = &mut *self.game;
+        let (w, h) = game.renderer.stored_size();
+        game.renderer.set_state(self.renderer_state);
+        game.renderer.resize_framebuffer(w, h, false);
+        game.renderer.set_view(
+            0,
+            0,
+            game.unscaled_width as _,
+            game.unscaled_height as _,
+            0.0,
+            0,
+            0,
+            game.unscaled_width as _,
+            game.unscaled_height as _,
+        );
+        game.renderer.draw_stored(0, 0, w, h);
+        let result = match game.frame() {
+            Ok(()) => match game.scene_change {
+                Some(SceneChange::Room(id)) => game.load_room(id),
+                Some(SceneChange::Restart) => game.restart(),
+                Some(SceneChange::End) => game.restart(),
+                Some(SceneChange::Load(ref mut path)) => {
+                    let path = std::mem::take(path);
+                    game.load_gm_save(path)
+                },
+                None => Ok(()),
+            },
+            Err(e) => Err(e.into()),
+        };
+
+        // Fake frame limiter stuff (don't actually frame-limit in record mode)
+        if let GameClock::SpoofedNanos(t) = &mut game.clock {
+            *t += 1_000_000_000 / game.room.speed as u128;
+        }
+        if game.frame_counter == game.room.speed {
+            game.fps = game.room.speed;
+            game.frame_counter = 0;
+        }
+        game.frame_counter += 1;
+
+        game.renderer.resize_framebuffer(self.config.ui_width.into(), self.config.ui_height.into(), true);
+        game.renderer.set_view(
+            0,
+            0,
+            self.config.ui_width.into(),
+            self.config.ui_height.into(),
+            0.0,
+            0,
+            0,
+            self.config.ui_width.into(),
+            self.config.ui_height.into(),
+        );
+        game.renderer.clear_view(if *self.clean_state { CLEAR_COLOUR_GOOD } else { CLEAR_COLOUR_BAD }, 1.0);
+        *self.renderer_state = game.renderer.state();
+        game.renderer.set_state(self.ui_renderer_state);
+
+        result.err().map(|e| format!("Game crashed: {}\n\nPlease load a savestate.", e))
     }
 
     pub fn clear_context_menu(&mut self) {
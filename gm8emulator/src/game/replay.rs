@@ -1,3 +1,5 @@
+pub mod text;
+
 use crate::gml::Value;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use lzzzz::lz4;
@@ -5,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use std::{
     fs::{File, OpenOptions},
     io::{self, Read, Write},
+    ops::Range,
     path::PathBuf,
 };
 
@@ -25,6 +28,22 @@ pub struct Replay {
     frames: Vec<Frame>,
 }
 
+// The version of the emulator, which is stored in the metadata of replays made with it
+pub const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Information about a replay which is stored at the start of .gmtas files
+// None of it is needed to play the replay back, but it's checked on load to warn about anything that could desync
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    // CRC-32 of the exe the replay was recorded on, or 0 if that's not known
+    pub game_hash: u32,
+    pub emulator_version: String,
+    pub author: String,
+    pub rerecords: u64,
+    pub frame_count: usize,
+    pub comments: String,
+}
+
 #[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum FrameRng {
     Override(i32),
@@ -71,6 +90,12 @@ pub enum Event {
     ShowMenu(Value),     // value returned from show_menu()
     ShowMessage,         // acknowledges that a show_message() does not need to be shown during replay
     ShowQuestion(Value), // value returned from show_question()
+    GetColor(Value),        // value returned from get_color()
+    GetOpenFilename(Value), // value returned from get_open_filename()
+    GetSaveFilename(Value), // value returned from get_save_filename()
+    GetDirectory(Value),    // value returned from get_directory() or get_directory_alt()
+    PasteClipboard(Value),  // host clipboard text pasted into the emulated clipboard at the start of a frame
+    ExecuteProgram(bool),   // whether execute_program() waited for the program to finish
 }
 
 // An input event which takes place during a frame
@@ -90,6 +115,7 @@ pub enum ReadError {
     DecompressErr(lzzzz::Error),
     DeserializeErr(Box<bincode::ErrorKind>),
     UnknownVersion(u32),
+    ParseErr(text::ParseError),
 }
 
 #[derive(Debug)]
@@ -106,50 +132,67 @@ impl Replay {
 
     // Loads a Replay from a gmtas-format file (doesn't check the file extension)
     pub fn from_file(path: &PathBuf) -> Result<Self, ReadError> {
+        Self::from_file_with_metadata(path).map(|(replay, _)| replay)
+    }
+
+    // Loads a Replay from a gmtas-format file, along with its metadata if the file is new enough to have any
+    pub fn from_file_with_metadata(path: &PathBuf) -> Result<(Self, Option<Metadata>), ReadError> {
         let mut lz4_buf = Vec::new();
         let mut bin_buf = Vec::new();
         let mut file = File::open(path).map_err(ReadError::IOErr)?;
 
-        match file.read_u32::<LE>() {
-            Ok(1) => {
-                let init_size = file.metadata().map(|m| m.len() as usize + 1).unwrap_or(0);
-                lz4_buf.reserve(init_size);
-                match file.read_to_end(&mut lz4_buf) {
-                    Ok(_) => match (lz4_buf.as_slice().read_u64::<LE>().map(|x| x as usize), lz4_buf.get(8..)) {
-                        (Ok(len), Some(block)) => {
-                            bin_buf.reserve(len);
+        let metadata = match file.read_u32::<LE>() {
+            Ok(1) => None,
+            Ok(2) => {
+                let len = file.read_u64::<LE>().map_err(ReadError::IOErr)?;
+                let mut meta_buf = Vec::new();
+                (&mut file).take(len).read_to_end(&mut meta_buf).map_err(ReadError::IOErr)?;
+                Some(bincode::deserialize::<'_, Metadata>(meta_buf.as_slice()).map_err(ReadError::DeserializeErr)?)
+            },
+            Ok(v) => return Err(ReadError::UnknownVersion(v)),
+            Err(e) => return Err(ReadError::IOErr(e)),
+        };
+
+        let init_size = file.metadata().map(|m| m.len() as usize + 1).unwrap_or(0);
+        lz4_buf.reserve(init_size);
+        let replay = match file.read_to_end(&mut lz4_buf) {
+            Ok(_) => match (lz4_buf.as_slice().read_u64::<LE>().map(|x| x as usize), lz4_buf.get(8..)) {
+                (Ok(len), Some(block)) => {
+                    bin_buf.reserve(len);
+                    unsafe { bin_buf.set_len(len) };
+                    match lz4::decompress(block, bin_buf.as_mut_slice()) {
+                        Ok(len) => {
                             unsafe { bin_buf.set_len(len) };
-                            match lz4::decompress(block, bin_buf.as_mut_slice()) {
-                                Ok(len) => {
-                                    unsafe { bin_buf.set_len(len) };
-                                    bincode::deserialize::<'_, Self>(bin_buf.as_slice())
-                                        .map_err(ReadError::DeserializeErr)
-                                },
-                                Err(err) => Err(ReadError::DecompressErr(err)),
-                            }
+                            bincode::deserialize::<'_, Self>(bin_buf.as_slice()).map_err(ReadError::DeserializeErr)
                         },
-                        (Ok(_), None) => Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
-                        (Err(err), _) => Err(ReadError::IOErr(err)),
-                    },
-                    Err(err) => Err(ReadError::IOErr(err)),
-                }
+                        Err(err) => Err(ReadError::DecompressErr(err)),
+                    }
+                },
+                (Ok(_), None) => Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
+                (Err(err), _) => Err(ReadError::IOErr(err)),
             },
-            Ok(v) => Err(ReadError::UnknownVersion(v)),
-            Err(e) => Err(ReadError::IOErr(e)),
-        }
+            Err(err) => Err(ReadError::IOErr(err)),
+        }?;
+        Ok((replay, metadata))
     }
 
-    // Serializes this replay into a file
-    pub fn to_file(&self, path: &PathBuf) -> Result<(), WriteError> {
+    // Serializes this replay into a file, headed by the given metadata
+    // The metadata's frame count and emulator version are filled in here
+    pub fn to_file(&self, path: &PathBuf, metadata: &Metadata) -> Result<(), WriteError> {
+        let metadata =
+            Metadata { frame_count: self.frame_count(), emulator_version: EMULATOR_VERSION.into(), ..metadata.clone() };
+        let meta_buf = bincode::serialize(&metadata).map_err(WriteError::SerializeErr)?;
         let mut lz4_buf = Vec::new();
         let mut bin_buf = Vec::new();
         match bincode::serialize_into(&mut bin_buf, self) {
             Ok(()) => match lz4::compress_to_vec(bin_buf.as_slice(), lz4_buf.as_mut(), lz4::ACC_LEVEL_DEFAULT) {
                 Ok(_length) => {
                     match OpenOptions::new().create(true).write(true).truncate(true).open(path).and_then(|mut f| {
-                        f.write_u32::<LE>(1).and_then(|_| {
-                            f.write_u64::<LE>(bin_buf.len() as u64).and_then(|_| f.write_all(lz4_buf.as_slice()))
-                        })
+                        f.write_u32::<LE>(2)?;
+                        f.write_u64::<LE>(meta_buf.len() as u64)?;
+                        f.write_all(meta_buf.as_slice())?;
+                        f.write_u64::<LE>(bin_buf.len() as u64)?;
+                        f.write_all(lz4_buf.as_slice())
                     }) {
                         Ok(()) => Ok(()),
                         Err(e) => Err(WriteError::IOErr(e)),
@@ -213,6 +256,16 @@ impl Replay {
         self.frames.get_mut(index)
     }
 
+    // Gets every frame from the given one onwards
+    pub fn frames_from(&self, index: usize) -> &[Frame] {
+        self.frames.get(index..).unwrap_or(&[])
+    }
+
+    // Replaces a range of frames with some others, returning the ones that were taken out
+    pub fn splice_frames(&mut self, range: Range<usize>, frames: Vec<Frame>) -> Vec<Frame> {
+        self.frames.splice(range, frames).collect()
+    }
+
     // Gets the replay's frame count
     pub fn frame_count(&self) -> usize {
         self.frames.len()
@@ -222,6 +275,23 @@ impl Replay {
         self.frames.truncate(len)
     }
 
+    // Returns whether the game starts up the same way in this replay as in the other one, before any frames.
+    pub fn same_start(&self, other: &Replay) -> bool {
+        self.start_time == other.start_time
+            && self.start_seed == other.start_seed
+            && self.startup_events == other.startup_events
+    }
+
+    // Returns how many frames at the start of this replay are the same as in the other one.
+    // If the replays don't start the same way, that's none of them.
+    pub fn common_frames(&self, other: &Replay) -> usize {
+        if self.same_start(other) {
+            self.frames.iter().zip(other.frames.iter()).take_while(|(a, b)| a == b).count()
+        } else {
+            0
+        }
+    }
+
     // Returns whether this replay begins the same way as the other one.
     pub fn contains_part(&self, other: &Replay) -> bool {
         if self.frame_count() > other.frame_count() {
@@ -0,0 +1,356 @@
+use crate::{
+    game::replay::{Event, Frame, FrameRng, Input, ReadError, Replay, WriteError},
+    gml::{self, Value},
+};
+use std::{fmt, fs, path::PathBuf, str::FromStr};
+
+// Bump this whenever the format changes in a way older versions couldn't read
+const VERSION: u32 = 1;
+
+/// Where and why a text replay couldn't be read.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Replay {
+    /// Writes the replay as text. Each frame starts with a `frame` line giving its mouse position,
+    /// followed by a line for each input, event or RNG or time change on that frame.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            "# OpenGMK replay".to_string(),
+            format!("version {}", VERSION),
+            format!("start_time {}", self.start_time),
+            format!("start_seed {}", self.start_seed),
+        ];
+        lines.extend(self.startup_events.iter().map(|event| format!("startup {}", event_text(event))));
+        for (i, frame) in self.frames.iter().enumerate() {
+            lines.push(format!("frame {} mouse {} {}", i, frame.mouse_x, frame.mouse_y));
+            lines.extend(frame.inputs.iter().map(|input| format!("    {}", input_text(input))));
+            lines.extend(frame.events.iter().map(|event| format!("    event {}", event_text(event))));
+            match frame.new_seed {
+                Some(FrameRng::Increment(amount)) => lines.push(format!("    rng_increment {}", amount)),
+                Some(FrameRng::Override(seed)) => lines.push(format!("    rng_seed {}", seed)),
+                None => (),
+            }
+            if let Some(time) = frame.new_time {
+                lines.push(format!("    time {}", time));
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Reads a replay written by to_text. Blank lines and lines starting with # are ignored.
+    pub fn from_text(text: &str) -> Result<Self, ParseError> {
+        let mut version = None;
+        let mut start_time = None;
+        let mut start_seed = None;
+        let mut startup_events = Vec::new();
+        let mut frames: Vec<Frame> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError { line: i + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let (word, rest) = split_word(line);
+            if version.is_none() {
+                match (word, parse_number::<u32>(rest)) {
+                    ("version", Ok(VERSION)) => version = Some(VERSION),
+                    ("version", Ok(v)) => return Err(error(format!("unsupported version {}", v))),
+                    _ => return Err(error("expected a version first".into())),
+                }
+                continue
+            }
+            match word {
+                "start_time" => start_time = Some(parse_number(rest).map_err(error)?),
+                "start_seed" => start_seed = Some(parse_number(rest).map_err(error)?),
+                "startup" => startup_events.push(parse_event(rest).map_err(error)?),
+                "frame" => {
+                    let frame = match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+                        [index, "mouse", x, y] => {
+                            if parse_number::<usize>(index).map_err(error)? != frames.len() {
+                                return Err(error(format!("expected frame {}", frames.len())))
+                            }
+                            Frame {
+                                mouse_x: parse_number(x).map_err(error)?,
+                                mouse_y: parse_number(y).map_err(error)?,
+                                inputs: Vec::new(),
+                                events: Vec::new(),
+                                new_seed: None,
+                                new_time: None,
+                            }
+                        },
+                        _ => return Err(error("expected frame N mouse X Y".into())),
+                    };
+                    frames.push(frame);
+                },
+                _ => {
+                    let frame = frames.last_mut().ok_or_else(|| error(format!("{} before the first frame", word)))?;
+                    match word {
+                        "press" => frame.inputs.push(Input::KeyPress(parse_key_arg(rest).map_err(error)?)),
+                        "release" => frame.inputs.push(Input::KeyRelease(parse_key_arg(rest).map_err(error)?)),
+                        "mouse_press" => frame.inputs.push(Input::MousePress(parse_button(rest).map_err(error)?)),
+                        "mouse_release" => frame.inputs.push(Input::MouseRelease(parse_button(rest).map_err(error)?)),
+                        "wheel_up" => frame.inputs.push(Input::MouseWheelUp),
+                        "wheel_down" => frame.inputs.push(Input::MouseWheelDown),
+                        "event" => frame.events.push(parse_event(rest).map_err(error)?),
+                        "rng_increment" | "rng_seed" if frame.new_seed.is_some() => {
+                            return Err(error("the RNG is changed twice on this frame".into()))
+                        },
+                        "rng_increment" => {
+                            frame.new_seed = Some(FrameRng::Increment(parse_number(rest).map_err(error)?))
+                        },
+                        "rng_seed" => frame.new_seed = Some(FrameRng::Override(parse_number(rest).map_err(error)?)),
+                        "time" if frame.new_time.is_some() => {
+                            return Err(error("the time is changed twice on this frame".into()))
+                        },
+                        "time" => frame.new_time = Some(parse_number(rest).map_err(error)?),
+                        _ => return Err(error(format!("unknown line {}", word))),
+                    }
+                },
+            }
+        }
+
+        let end = text.lines().count();
+        let missing = |name: &str| ParseError { line: end, message: format!("missing {}", name) };
+        let start_time = start_time.ok_or_else(|| missing("start_time"))?;
+        let start_seed = start_seed.ok_or_else(|| missing("start_seed"))?;
+        let mut replay = Self::new(start_time, start_seed);
+        replay.startup_events = startup_events;
+        replay.frames = frames;
+        Ok(replay)
+    }
+
+    // Loads a Replay from a text file (doesn't check the file extension)
+    pub fn from_text_file(path: &PathBuf) -> Result<Self, ReadError> {
+        let text = fs::read_to_string(path).map_err(ReadError::IOErr)?;
+        Self::from_text(&text).map_err(ReadError::ParseErr)
+    }
+
+    // Writes this replay to a text file
+    pub fn to_text_file(&self, path: &PathBuf) -> Result<(), WriteError> {
+        fs::write(path, self.to_text()).map_err(WriteError::IOErr)
+    }
+}
+
+/// Reads a key name: a GML constant such as vk_left, a letter or digit, or a key code.
+pub fn parse_key(name: &str) -> Option<u8> {
+    let lower = name.to_ascii_lowercase();
+    if lower.starts_with("vk_") {
+        gml::mappings::CONSTANTS.get(lower.as_str()).map(|code| *code as u8)
+    } else if name.len() == 1 && name.as_bytes()[0].is_ascii_alphanumeric() {
+        Some(name.as_bytes()[0].to_ascii_uppercase())
+    } else {
+        name.parse().ok()
+    }
+}
+
+/// The reverse of parse_key. Codes with no name are written with at least two digits,
+/// so they can't be mistaken for the digit keys.
+pub fn key_name(code: u8) -> String {
+    if code.is_ascii_uppercase() || code.is_ascii_digit() {
+        return char::from(code).into()
+    }
+    gml::mappings::CONSTANTS
+        .entries()
+        .find(|(name, value)| name.starts_with("vk_") && **value == f64::from(code))
+        .map_or_else(|| format!("{:02}", code), |(name, _)| name.to_string())
+}
+
+fn input_text(input: &Input) -> String {
+    match input {
+        Input::KeyPress(key) => format!("press {}", key_name(*key)),
+        Input::KeyRelease(key) => format!("release {}", key_name(*key)),
+        Input::MousePress(button) => format!("mouse_press {}", button_name(*button)),
+        Input::MouseRelease(button) => format!("mouse_release {}", button_name(*button)),
+        Input::MouseWheelUp => "wheel_up".into(),
+        Input::MouseWheelDown => "wheel_down".into(),
+    }
+}
+
+fn event_text(event: &Event) -> String {
+    match event {
+        Event::GetInteger(value) => format!("get_integer {}", value_text(value)),
+        Event::GetString(value) => format!("get_string {}", value_text(value)),
+        Event::Randomize(seed) => format!("randomize {}", seed),
+        Event::ShowMenu(value) => format!("show_menu {}", value_text(value)),
+        Event::ShowMessage => "show_message".into(),
+        Event::ShowQuestion(value) => format!("show_question {}", value_text(value)),
+        Event::GetColor(value) => format!("get_color {}", value_text(value)),
+        Event::GetOpenFilename(value) => format!("get_open_filename {}", value_text(value)),
+        Event::GetSaveFilename(value) => format!("get_save_filename {}", value_text(value)),
+        Event::GetDirectory(value) => format!("get_directory {}", value_text(value)),
+        Event::PasteClipboard(value) => format!("paste_clipboard {}", value_text(value)),
+        Event::ExecuteProgram(waited) => format!("execute_program {}", waited),
+    }
+}
+
+fn parse_event(text: &str) -> Result<Event, String> {
+    let (name, rest) = split_word(text);
+    Ok(match name {
+        "get_integer" => Event::GetInteger(parse_value(rest)?),
+        "get_string" => Event::GetString(parse_value(rest)?),
+        "randomize" => Event::Randomize(parse_number(rest)?),
+        "show_menu" => Event::ShowMenu(parse_value(rest)?),
+        "show_message" => Event::ShowMessage,
+        "show_question" => Event::ShowQuestion(parse_value(rest)?),
+        "get_color" => Event::GetColor(parse_value(rest)?),
+        "get_open_filename" => Event::GetOpenFilename(parse_value(rest)?),
+        "get_save_filename" => Event::GetSaveFilename(parse_value(rest)?),
+        "get_directory" => Event::GetDirectory(parse_value(rest)?),
+        "paste_clipboard" => Event::PasteClipboard(parse_value(rest)?),
+        "execute_program" => Event::ExecuteProgram(parse_number(rest)?),
+        _ => return Err(format!("unknown event {}", name)),
+    })
+}
+
+fn button_name(button: i8) -> String {
+    match button {
+        1 => "left".into(),
+        2 => "right".into(),
+        3 => "middle".into(),
+        _ => button.to_string(),
+    }
+}
+
+fn parse_button(text: &str) -> Result<i8, String> {
+    match text {
+        "left" => Ok(1),
+        "right" => Ok(2),
+        "middle" => Ok(3),
+        _ => parse_number(text),
+    }
+}
+
+fn parse_key_arg(text: &str) -> Result<u8, String> {
+    parse_key(text).ok_or_else(|| format!("unknown key {}", text))
+}
+
+/// Writes a value so it reads back exactly the same: reals as the shortest number which parses back to them,
+/// and strings quoted, with anything that isn't printable ASCII escaped byte by byte.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Real(real) => format!("{:?}", real.into_inner()),
+        Value::Str(string) => {
+            let mut text = String::from('"');
+            for &byte in string.as_ref() {
+                match byte {
+                    b'"' => text.push_str("\\\""),
+                    b'\\' => text.push_str("\\\\"),
+                    b'\n' => text.push_str("\\n"),
+                    b'\r' => text.push_str("\\r"),
+                    b'\t' => text.push_str("\\t"),
+                    b' '..=b'~' => text.push(char::from(byte)),
+                    _ => text.push_str(&format!("\\x{:02x}", byte)),
+                }
+            }
+            text.push('"');
+            text
+        },
+    }
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    let quoted = match text.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => return parse_number::<f64>(text).map(Value::from),
+    };
+    let mut bytes = Vec::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next() {
+            Some('"') if chars.as_str().is_empty() => break Ok(bytes.into()),
+            Some('"') => break Err(format!("unexpected {} after string", chars.as_str().trim())),
+            Some('\\') => match chars.next() {
+                Some('"') => bytes.push(b'"'),
+                Some('\\') => bytes.push(b'\\'),
+                Some('n') => bytes.push(b'\n'),
+                Some('r') => bytes.push(b'\r'),
+                Some('t') => bytes.push(b'\t'),
+                Some('x') => {
+                    let byte = chars.as_str().get(..2).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    match byte {
+                        Some(byte) => bytes.push(byte),
+                        None => break Err("expected two hex digits after \\x".into()),
+                    }
+                    chars.nth(1);
+                },
+                _ => break Err("invalid escape sequence in string".into()),
+            },
+            Some(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            None => break Err("unterminated string".into()),
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("invalid number {}", text))
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut replay = Replay::new(1_234_567_890_123, -42);
+        replay.startup_events.push(Event::Randomize(7));
+        replay.startup_events.push(Event::ShowMessage);
+        let frame = replay.new_frame();
+        frame.mouse_x = 320;
+        frame.mouse_y = -5;
+        frame.inputs = vec![
+            Input::KeyPress(b'A'),
+            Input::KeyPress(b'7'),
+            Input::KeyPress(7),
+            Input::KeyPress(200),
+            Input::KeyRelease(37),
+            Input::MousePress(1),
+            Input::MouseRelease(4),
+            Input::MouseWheelUp,
+            Input::MouseWheelDown,
+        ];
+        frame.events = vec![
+            Event::GetInteger(Value::from(-0.1)),
+            Event::GetString(Value::from(&b"quote \" slash \\ line\n\x00\xff caf\xc3\xa9"[..])),
+            Event::ShowQuestion(Value::from(1e300)),
+            Event::ExecuteProgram(true),
+        ];
+        frame.new_seed = Some(FrameRng::Override(i32::MIN));
+        frame.new_time = Some(u128::MAX);
+        replay.new_frame().new_seed = Some(FrameRng::Increment(3));
+        replay.new_frame();
+
+        let text = replay.to_text();
+        assert_eq!(Replay::from_text(&text).unwrap(), replay);
+        assert_eq!(Replay::from_text(&text).unwrap().to_text(), text);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Replay::from_text("start_time 0\nstart_seed 0").is_err());
+        assert!(Replay::from_text("version 1\nstart_time 0").is_err());
+        assert!(Replay::from_text("version 1\nstart_time 0\nstart_seed 0\npress A").is_err());
+        assert!(Replay::from_text("version 1\nstart_time 0\nstart_seed 0\nframe 1 mouse 0 0").is_err());
+        assert_eq!(
+            Replay::from_text("version 1\nstart_time 0\nstart_seed 0\nframe 0 mouse 0 0\nfoo").unwrap_err().line,
+            5
+        );
+    }
+}
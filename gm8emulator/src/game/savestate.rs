@@ -1,8 +1,10 @@
+mod v0;
+
 use crate::{
     game::{
         audio::AudioState, draw, external, includedfile::IncludedFile, model::Model, particle,
         pathfinding::PotentialStepSettings, surface::Surface, transition::UserTransition, Assets,
-        Game, GameClock, Replay, RoomState, Version,
+        display::Display, Game, GameClock, Replay, RoomState, Version,
     },
     gml::{self, ds, rand::Random, Compiler},
     handleman::HandleList,
@@ -13,6 +15,7 @@ use crate::{
     types::{Colour, ID},
 };
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use encoding_rs::Encoding;
 use indexmap::IndexMap;
 use lzzzz::lz4;
 use serde::{Deserialize, Serialize};
@@ -25,6 +28,23 @@ use std::{
     rc::Rc,
 };
 
+/// The version of the savestate file format. This needs bumping, along with a new entry in MIGRATIONS, whenever
+/// anything in SaveState is serialized differently.
+pub const VERSION: u32 = 1;
+
+/// Savestate files start with this, followed by the version. Files from before savestates were versioned don't,
+/// and are treated as version 0.
+const MAGIC: &[u8; 4] = b"GMKS";
+
+/// Says what the body of a savestate file holds and how it's encoded. This comes after the version in the header,
+/// so that something else which happens to start with MAGIC isn't mistaken for a savestate.
+const SCHEMA: &[u8] = b"gm8emulator.SaveState;bincode;lz4";
+
+/// Turns the serialized body of each version of SaveState into the next version's, so MIGRATIONS[0] goes from
+/// version 0 to 1. To write one, copy the old definitions that changed into a module for the old version,
+/// deserialize the body as that, convert it, and serialize it again as the new version.
+const MIGRATIONS: [fn(&mut Vec<u8>) -> Result<(), ReadError>; VERSION as usize] = [v0::migrate];
+
 /// Represents a savestate. Very similar to the Game struct, but without things which aren't serialized.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SaveState {
@@ -114,6 +134,13 @@ pub struct SaveState {
     unscaled_height: u32,
     window_width: u32,
     window_height: u32,
+    window_offset: (i32, i32),
+    window_fullscreen: bool,
+    window_stayontop: bool,
+    display: Display,
+    clipboard: Option<gml::String>,
+    #[serde(with = "crate::game::encoding::by_name")]
+    encoding: &'static Encoding,
 
     audio_state: AudioState,
 
@@ -200,6 +227,12 @@ impl SaveState {
             unscaled_height: game.unscaled_height,
             window_width,
             window_height,
+            window_offset: game.window_offset_spoof,
+            window_fullscreen: game.window_fullscreen,
+            window_stayontop: game.window_stayontop,
+            display: game.display,
+            clipboard: game.clipboard.clone(),
+            encoding: game.encoding,
             audio_state: game.audio.state(),
             replay,
             screenshot,
@@ -289,6 +322,12 @@ impl SaveState {
         game.scaling = self.scaling;
         game.unscaled_width = self.unscaled_width;
         game.unscaled_height = self.unscaled_height;
+        game.window_offset_spoof = self.window_offset;
+        game.window_fullscreen = self.window_fullscreen;
+        game.window_stayontop = self.window_stayontop;
+        game.display = self.display;
+        game.clipboard = self.clipboard;
+        game.encoding = self.encoding;
         (self.replay, self.renderer_state)
     }
 
@@ -297,62 +336,86 @@ impl SaveState {
         self.replay
     }
 
-    /// Loads a SaveState from a file. The format will always match the one used by `save_to_file()`.
+    /// Replaces this SaveState's Replay component.
+    pub fn with_replay(self, replay: Replay) -> Self {
+        Self { replay, ..self }
+    }
+
+    /// Loads a SaveState from a file, migrating it first if it was saved by an older version.
     pub fn from_file(path: &PathBuf, buffer: &mut Buffer) -> Result<Self, ReadError> {
-        match File::open(path).map(|f| (f.metadata().map(|m| m.len() as usize + 1).unwrap_or(0), f)) {
-            Ok((init_size, mut file)) => {
-                buffer.lz4_buf.clear();
-                buffer.lz4_buf.reserve(init_size);
-                match file.read_to_end(&mut buffer.lz4_buf) {
-                    Ok(_) => {
-                        match (buffer.lz4_buf.as_slice().read_u64::<LE>().map(|x| x as usize), buffer.lz4_buf.get(8..))
-                        {
-                            (Ok(len), Some(block)) => {
-                                buffer.bin_buf.clear();
-                                buffer.bin_buf.reserve(len);
-                                unsafe { buffer.bin_buf.set_len(len) };
-                                match lz4::decompress(block, buffer.bin_buf.as_mut_slice()) {
-                                    Ok(len) => {
-                                        unsafe { buffer.bin_buf.set_len(len) };
-                                        bincode::deserialize::<'_, SaveState>(buffer.bin_buf.as_slice())
-                                            .map_err(ReadError::DeserializeErr)
-                                    },
-                                    Err(err) => Err(ReadError::DecompressErr(err)),
-                                }
-                            },
-                            (Ok(_), None) => Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
-                            (Err(err), _) => Err(ReadError::IOErr(err)),
-                        }
-                    },
-                    Err(err) => Err(ReadError::IOErr(err)),
-                }
-            },
-            Err(err) => Err(ReadError::IOErr(err)),
+        Self::read_file(path, buffer)?;
+        let container = split_file(buffer.lz4_buf.as_slice())?;
+        Self::decompress(container.body, &mut buffer.bin_buf, container.version)
+    }
+
+    /// Reads just the replay from a savestate file. This still works when the rest of the savestate can't be loaded,
+    /// so the savestate can be rebuilt by playing the replay back, unless the file is from before savestates were
+    /// versioned, when the replay wasn't stored separately.
+    pub fn replay_from_file(path: &PathBuf, buffer: &mut Buffer) -> Result<Replay, ReadError> {
+        Self::read_file(path, buffer)?;
+        let container = split_file(buffer.lz4_buf.as_slice())?;
+        match container.replay {
+            Some(replay) => bincode::deserialize(replay.as_slice()).map_err(ReadError::DeserializeErr),
+            None => Self::decompress(container.body, &mut buffer.bin_buf, container.version).map(Self::into_replay),
         }
     }
 
-    /// Saves a SaveState to a file. The SaveState object is formatted with Serde/bincode and compressed with lz4.
+    /// Saves a SaveState to a file. The file has a header with the version, the schema and the replay compressed on
+    /// its own, so that the replay can be read even if the rest can't, and then the SaveState object formatted with
+    /// Serde/bincode and compressed with lz4.
     /// A Buffer object is needed for the lz4 compression. Ideally, the same buffer should be re-used on each call.
     pub fn save_to_file(&self, path: &PathBuf, buffer: &mut Buffer) -> Result<(), WriteError> {
+        let replay = bincode::serialize(&self.replay).map_err(WriteError::SerializeErr)?;
+        let mut replay_block = Vec::new();
+        compress_block(replay.as_slice(), &mut replay_block).map_err(WriteError::CompressErr)?;
+        self.compress(buffer)?;
+        match OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .and_then(|mut f| write_file(&mut f, replay_block.as_slice(), buffer.lz4_buf.as_slice()))
+        {
+            Ok(()) => Ok(()),
+            Err(e) => Err(WriteError::IOErr(e)),
+        }
+    }
+
+    /// Reads a whole savestate file into the Buffer's lz4_buf.
+    fn read_file(path: &PathBuf, buffer: &mut Buffer) -> Result<(), ReadError> {
+        let mut file = File::open(path).map_err(ReadError::IOErr)?;
+        let init_size = file.metadata().map(|m| m.len() as usize + 1).unwrap_or(0);
+        buffer.lz4_buf.clear();
+        buffer.lz4_buf.reserve(init_size);
+        file.read_to_end(&mut buffer.lz4_buf).map_err(ReadError::IOErr)?;
+        Ok(())
+    }
+
+    /// Loads a SaveState from memory. The data must have come from `to_compressed()`.
+    pub fn from_compressed(data: &[u8], buffer: &mut Buffer) -> Result<Self, ReadError> {
+        Self::decompress(data, &mut buffer.bin_buf, VERSION)
+    }
+
+    /// Compresses a SaveState into memory, in the same format as the body of a file. This has no header, since it's
+    /// only meant to be loaded by the same build.
+    pub fn to_compressed(&self, buffer: &mut Buffer) -> Result<Box<[u8]>, WriteError> {
+        self.compress(buffer)?;
+        Ok(buffer.lz4_buf.as_slice().into())
+    }
+
+    /// Serializes this SaveState into the Buffer's bin_buf, then compresses it into its lz4_buf.
+    fn compress(&self, buffer: &mut Buffer) -> Result<(), WriteError> {
         buffer.bin_buf.clear();
         buffer.lz4_buf.clear();
-        match bincode::serialize_into(&mut buffer.bin_buf, self) {
-            Ok(()) => {
-                match lz4::compress_to_vec(buffer.bin_buf.as_slice(), buffer.lz4_buf.as_mut(), lz4::ACC_LEVEL_DEFAULT) {
-                    Ok(_length) => {
-                        match OpenOptions::new().create(true).write(true).truncate(true).open(path).and_then(|mut f| {
-                            f.write_u64::<LE>(buffer.bin_buf.len() as u64)
-                                .and_then(|_| f.write_all(buffer.lz4_buf.as_slice()))
-                        }) {
-                            Ok(()) => Ok(()),
-                            Err(e) => Err(WriteError::IOErr(e)),
-                        }
-                    },
-                    Err(err) => Err(WriteError::CompressErr(err)),
-                }
-            },
-            Err(err) => Err(WriteError::SerializeErr(err)),
-        }
+        bincode::serialize_into(&mut buffer.bin_buf, self).map_err(WriteError::SerializeErr)?;
+        compress_block(buffer.bin_buf.as_slice(), &mut buffer.lz4_buf).map_err(WriteError::CompressErr)
+    }
+
+    /// Decompresses, migrates and deserializes a SaveState of the given version, using bin_buf as scratch space.
+    fn decompress(data: &[u8], bin_buf: &mut Vec<u8>, version: u32) -> Result<Self, ReadError> {
+        decompress_block(data, bin_buf)?;
+        migrate(version, bin_buf)?;
+        bincode::deserialize::<'_, SaveState>(bin_buf.as_slice()).map_err(ReadError::DeserializeErr)
     }
 }
 
@@ -372,6 +435,8 @@ pub enum ReadError {
     IOErr(io::Error),
     DecompressErr(lzzzz::Error),
     DeserializeErr(Box<bincode::ErrorKind>),
+    UnknownVersion(u32),
+    UnknownSchema(String),
 }
 
 #[derive(Debug)]
@@ -380,3 +445,261 @@ pub enum WriteError {
     CompressErr(lzzzz::Error),
     SerializeErr(Box<bincode::ErrorKind>),
 }
+
+/// The parts of a savestate file.
+struct Container<'a> {
+    version: u32,
+    /// The serialized replay, if the file has one stored separately.
+    replay: Option<Vec<u8>>,
+    /// The compressed SaveState, as a block from `compress_block()`.
+    body: &'a [u8],
+}
+
+/// Writes a savestate file from its compressed replay and body, both blocks from `compress_block()`.
+fn write_file(out: &mut impl Write, replay_block: &[u8], body: &[u8]) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_u32::<LE>(VERSION)?;
+    out.write_u64::<LE>(SCHEMA.len() as u64)?;
+    out.write_all(SCHEMA)?;
+    out.write_u64::<LE>(replay_block.len() as u64)?;
+    out.write_all(replay_block)?;
+    out.write_all(body)
+}
+
+/// Splits a savestate file into its version, its replay if it's stored separately, and its compressed body.
+fn split_file(data: &[u8]) -> Result<Container<'_>, ReadError> {
+    let mut rest = match data.strip_prefix(MAGIC) {
+        Some(rest) => rest,
+        None => return Ok(Container { version: 0, replay: None, body: data }),
+    };
+    let version = rest.read_u32::<LE>().map_err(ReadError::IOErr)?;
+    if version > VERSION {
+        return Err(ReadError::UnknownVersion(version))
+    }
+    let schema = take(&mut rest)?;
+    if schema != SCHEMA {
+        return Err(ReadError::UnknownSchema(String::from_utf8_lossy(schema).into_owned()))
+    }
+    let mut replay = Vec::new();
+    decompress_block(take(&mut rest)?, &mut replay)?;
+    Ok(Container { version, replay: Some(replay), body: rest })
+}
+
+/// Reads a length-prefixed section of a savestate file's header, moving past it.
+fn take<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8], ReadError> {
+    let len = rest.read_u64::<LE>().map_err(ReadError::IOErr)?;
+    match usize::try_from(len).ok().filter(|len| *len <= rest.len()) {
+        Some(len) => {
+            let (section, after) = rest.split_at(len);
+            *rest = after;
+            Ok(section)
+        },
+        None => Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
+    }
+}
+
+/// Compresses data with lz4 onto the end of `out`, prefixed by its uncompressed length.
+fn compress_block(data: &[u8], out: &mut Vec<u8>) -> Result<(), lzzzz::Error> {
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    lz4::compress_to_vec(data, out, lz4::ACC_LEVEL_DEFAULT)?;
+    Ok(())
+}
+
+/// Decompresses a block from `compress_block()` into `out`, replacing what was there.
+fn decompress_block(block: &[u8], out: &mut Vec<u8>) -> Result<(), ReadError> {
+    match ((&block[..]).read_u64::<LE>().map(|x| x as usize), block.get(8..)) {
+        (Ok(len), Some(data)) => {
+            out.clear();
+            out.reserve(len);
+            unsafe { out.set_len(len) };
+            match lz4::decompress(data, out.as_mut_slice()) {
+                Ok(len) => {
+                    unsafe { out.set_len(len) };
+                    Ok(())
+                },
+                Err(err) => {
+                    out.clear();
+                    Err(ReadError::DecompressErr(err))
+                },
+            }
+        },
+        (Ok(_), None) => Err(ReadError::IOErr(io::Error::from(io::ErrorKind::UnexpectedEof))),
+        (Err(err), _) => Err(ReadError::IOErr(err)),
+    }
+}
+
+/// Brings the serialized body of a SaveState of the given version up to the current version.
+fn migrate(version: u32, body: &mut Vec<u8>) -> Result<(), ReadError> {
+    match MIGRATIONS.get(version as usize..) {
+        Some(migrations) => migrations.iter().try_for_each(|migrate| migrate(body)),
+        None => Err(ReadError::UnknownVersion(version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action,
+        game::replay::Event,
+        gml::{
+            bytecode::Program,
+            runtime::{BinaryOperator, Instruction, Node},
+            Value,
+        },
+    };
+
+    fn replay() -> Replay {
+        let mut replay = Replay::new(1_234_567_890_123, -42);
+        replay.startup_events.push(Event::Randomize(7));
+        let frame = replay.new_frame();
+        frame.mouse_x = 320;
+        frame.mouse_y = 240;
+        replay.new_frame();
+        replay
+    }
+
+    fn block(data: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        compress_block(data, &mut block).unwrap();
+        block
+    }
+
+    fn file(replay: &Replay, body: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        write_file(&mut file, block(&bincode::serialize(replay).unwrap()).as_slice(), body).unwrap();
+        file
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"not really a savestate, but the body isn't looked at until it's deserialized".repeat(4);
+        let body = block(&data);
+        let file = file(&replay(), &body);
+
+        let container = split_file(&file).unwrap();
+        assert_eq!(container.version, VERSION);
+        assert_eq!(bincode::deserialize::<Replay>(&container.replay.unwrap()).unwrap(), replay());
+        assert_eq!(container.body, body.as_slice());
+
+        let mut out = Vec::new();
+        decompress_block(container.body, &mut out).unwrap();
+        migrate(container.version, &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn unversioned() {
+        // Before versioning, savestate files were just the body
+        let data = b"an old savestate".repeat(4);
+        let file = block(&data);
+
+        let container = split_file(&file).unwrap();
+        assert_eq!(container.version, 0);
+        assert!(container.replay.is_none());
+        assert_eq!(container.body, file.as_slice());
+
+        let mut out = Vec::new();
+        decompress_block(container.body, &mut out).unwrap();
+        assert_eq!(out, data);
+        assert!(matches!(migrate(container.version, &mut out), Err(ReadError::DeserializeErr(_))));
+    }
+
+    #[test]
+    fn unversioned_state() {
+        // Version 0 stored GML as the compiler's instruction trees
+        let number = |n: f64| Node::Literal { value: Value::Real(n.into()) };
+        let code = || -> Rc<[Instruction]> {
+            let (left, right) = (Box::new(number(2.0)), Box::new(number(3.0)));
+            let value = Node::Binary { left, right, operator: BinaryOperator::Multiply, type_unsafe: false };
+            vec![Instruction::SetReturnValue { value }].into()
+        };
+        let program = || format!("{:?}", Program::from_instructions(&code()));
+
+        // Building a whole game isn't possible here, so everything else is left as it reads from zeroes
+        let mut old = bincode::deserialize::<v0::SaveState>(&[0; 0x10000]).unwrap();
+        old.compiler.fields = vec![b"hp".to_vec().into()];
+        old.compiler.script_names.insert(b"scr_damage".to_vec().into(), 0);
+        old.assets.scripts = vec![Some(Box::new(v0::Script {
+            name: "scr_damage".into(),
+            source: "return 2*3".into(),
+            compiled: code(),
+        }))];
+        let action = v0::Action {
+            index: 0,
+            target: None,
+            relative: false,
+            invert_condition: false,
+            body: v0::Body::Repeat { count: number(4.0) },
+        };
+        let mut events: [HashMap<u32, Rc<RefCell<v0::ActionTree>>>; 12] = Default::default();
+        events[0].insert(0, Rc::new(RefCell::new(v0::ActionTree(vec![action]))));
+        old.assets.objects = vec![Some(Box::new(v0::Object {
+            name: "obj_player".into(),
+            solid: false,
+            visible: true,
+            persistent: false,
+            depth: 0,
+            sprite_index: -1,
+            mask_index: -1,
+            parent_index: -1,
+            events,
+            children: Default::default(),
+            parents: Default::default(),
+        }))];
+        old.assets.triggers = vec![Some(Box::new(v0::Trigger {
+            name: "trg_always".into(),
+            condition: code(),
+            moment: crate::asset::trigger::TriggerTime::Step,
+        }))];
+        old.fps = 50;
+        old.window_width = 800;
+        old.replay = replay();
+        old.screenshot = vec![1, 2, 3, 4].into();
+
+        let state = SaveState::decompress(&block(&bincode::serialize(&old).unwrap()), &mut Vec::new(), 0).unwrap();
+        assert_eq!(state.compiler.get_field_name(0).as_deref(), Some("hp"));
+        let script = state.assets.scripts[0].as_ref().unwrap();
+        assert_eq!(script.name.as_ref(), b"scr_damage");
+        assert_eq!(format!("{:?}", script.compiled), program());
+        let tree = &state.assets.objects[0].as_ref().unwrap().events[0][&0];
+        let action = format!(
+            "Action {{ index: 0, target: None, relative: false, invert_condition: false, body: {:?} }}",
+            action::Body::Repeat { count: Program::from_expression(&number(4.0)) },
+        );
+        assert_eq!(format!("{:?}", tree.borrow()), format!("Tree([{}])", action));
+        assert_eq!(format!("{:?}", state.assets.triggers[0].as_ref().unwrap().condition), program());
+        assert_eq!(state.fps, 50);
+        assert_eq!(state.window_width, 800);
+        assert_eq!(state.replay, replay());
+        assert_eq!(state.screenshot.as_ref(), &[1, 2, 3, 4]);
+        assert_eq!(state.display, Display::default());
+        assert_eq!(state.clipboard, None);
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut file = file(&replay(), &block(b"body"));
+        file[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(split_file(&file), Err(ReadError::UnknownVersion(v)) if v == VERSION + 1));
+        assert!(matches!(migrate(VERSION + 1, &mut Vec::new()), Err(ReadError::UnknownVersion(_))));
+    }
+
+    #[test]
+    fn unknown_schema() {
+        let mut file = file(&replay(), &block(b"body"));
+        let schema_start = MAGIC.len() + 4 + 8;
+        file[schema_start..schema_start + 4].copy_from_slice(b"json");
+        assert!(matches!(split_file(&file), Err(ReadError::UnknownSchema(_))));
+    }
+
+    #[test]
+    fn truncated_header() {
+        let body = block(b"body");
+        let file = file(&replay(), &body);
+        for len in MAGIC.len()..file.len() - body.len() {
+            assert!(split_file(&file[..len]).is_err(), "{} bytes of the header were accepted", len);
+        }
+        assert!(split_file(&file[..file.len() - body.len()]).is_ok());
+    }
+}
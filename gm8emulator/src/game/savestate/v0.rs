@@ -0,0 +1,503 @@
+//! SaveState as it was serialized before savestates had a version, and how to turn it into version 1.
+//! Only the definitions which changed since then are copied here. Back then, all GML was stored as the
+//! compiler's instruction trees rather than as bytecode, and the compiler didn't fold any constants.
+
+use super::SaveState as SaveStateV1;
+use crate::{
+    action::{self, Tree},
+    asset::{self, trigger::TriggerTime},
+    game::{
+        audio::AudioState, display::Display, draw, external, includedfile::IncludedFile, model::Model, particle,
+        pathfinding::PotentialStepSettings, surface::Surface, transition::UserTransition, Background, GameClock,
+        Replay, RoomState, Version, View,
+    },
+    gml::{
+        self,
+        bytecode::Program,
+        ds,
+        rand::Random,
+        runtime::{Instruction, Node},
+        Value,
+    },
+    handleman::HandleList,
+    input::Input,
+    instance::DummyFieldHolder,
+    math::Real,
+    render::{RendererState, SavedTexture, Scaling},
+    tile::Tile,
+    types::{Colour, ID},
+};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
+};
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct SaveState {
+    pub compiler: Compiler,
+    pub rand: Random,
+    pub input: Input,
+    pub assets: Assets,
+    pub event_holders: [IndexMap<u32, Rc<RefCell<Vec<ID>>>>; 12],
+    pub custom_draw_objects: HashSet<ID>,
+
+    pub background_colour: Colour,
+    pub textures: Vec<Option<SavedTexture>>,
+
+    pub externals: external::ExternalState,
+    pub surface_fix: bool,
+
+    pub view_current: usize,
+
+    pub last_instance_id: ID,
+    pub last_tile_id: ID,
+
+    pub particles: particle::Manager,
+
+    pub room: RoomState,
+    pub stored_rooms: Vec<RoomState>,
+    pub room_order: Box<[i32]>,
+    pub user_transitions: HashMap<i32, UserTransition>,
+
+    pub globals: DummyFieldHolder,
+    pub globalvars: HashSet<usize>,
+    pub game_start: bool,
+
+    pub stacks: HandleList<ds::Stack>,
+    pub queues: HandleList<ds::Queue>,
+    pub lists: HandleList<ds::List>,
+    pub maps: HandleList<ds::Map>,
+    pub priority_queues: HandleList<ds::Priority>,
+    pub grids: HandleList<ds::Grid>,
+    pub ds_precision: Real,
+
+    pub draw_font_id: ID,
+    pub draw_colour: Colour,
+    pub draw_alpha: Real,
+    pub draw_halign: draw::Halign,
+    pub draw_valign: draw::Valign,
+    pub surfaces: HandleList<Surface>,
+    pub surface_target: Option<i32>,
+    pub models: HandleList<Model>,
+    pub model_matrix_stack: Vec<[f32; 16]>,
+    pub auto_draw: bool,
+    pub renderer_state: RendererState,
+
+    pub uninit_fields_are_zero: bool,
+    pub uninit_args_are_zero: bool,
+
+    pub potential_step_settings: PotentialStepSettings,
+
+    pub fps: u32,
+    pub frame_counter: u32,
+    pub transition_kind: i32,
+    pub transition_steps: i32,
+    pub cursor_sprite: i32,
+    pub cursor_sprite_frame: u32,
+    pub score: i32,
+    pub score_capt: gml::String,
+    pub score_capt_d: bool,
+    pub has_set_show_score: bool,
+    pub lives: i32,
+    pub lives_capt: gml::String,
+    pub lives_capt_d: bool,
+    pub health: Real,
+    pub health_capt: gml::String,
+    pub health_capt_d: bool,
+    pub error_occurred: bool,
+    pub error_last: gml::String,
+
+    pub game_id: i32,
+    pub program_directory: gml::String,
+    pub included_files: Vec<IncludedFile>,
+    pub gm_version: Version,
+    pub clock: GameClock,
+
+    pub clean_state: bool,
+
+    pub scaling: Scaling,
+    pub unscaled_width: u32,
+    pub unscaled_height: u32,
+    pub window_width: u32,
+    pub window_height: u32,
+
+    pub audio_state: AudioState,
+
+    pub replay: Replay,
+    pub screenshot: Box<[u8]>,
+    pub zbuffer: Box<[f32]>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Compiler {
+    pub constants: HashMap<Box<[u8]>, Value>,
+    pub user_constant_names: HashMap<Box<[u8]>, usize>,
+    pub script_names: HashMap<Box<[u8]>, usize>,
+    pub extension_fn_names: HashMap<Box<[u8]>, usize>,
+    pub fields: Vec<Box<[u8]>>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Assets {
+    pub backgrounds: Vec<Option<Box<asset::Background>>>,
+    pub fonts: Vec<Option<Box<asset::Font>>>,
+    pub objects: Vec<Option<Box<Object>>>,
+    pub paths: Vec<Option<Box<asset::Path>>>,
+    pub rooms: Vec<Option<Box<Room>>>,
+    pub scripts: Vec<Option<Box<Script>>>,
+    pub sounds: Vec<Option<Box<asset::Sound>>>,
+    pub sprites: Vec<Option<Box<asset::Sprite>>>,
+    pub timelines: Vec<Option<Box<Timeline>>>,
+    pub triggers: Vec<Option<Box<Trigger>>>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Object {
+    pub name: gml::String,
+    pub solid: bool,
+    pub visible: bool,
+    pub persistent: bool,
+    pub depth: i32,
+    pub sprite_index: i32,
+    pub mask_index: i32,
+    pub parent_index: i32,
+
+    pub events: [HashMap<u32, Rc<RefCell<ActionTree>>>; 12],
+    pub children: Rc<RefCell<HashSet<i32>>>,
+    pub parents: Rc<RefCell<HashSet<i32>>>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Room {
+    pub name: gml::String,
+    pub caption: gml::String,
+    pub width: u32,
+    pub height: u32,
+    pub speed: u32,
+    pub persistent: bool,
+    pub bg_colour: Colour,
+    pub clear_screen: bool,
+    pub creation_code: Result<Rc<[Instruction]>, String>,
+
+    pub backgrounds: Vec<Background>,
+    pub views_enabled: bool,
+    pub views: Vec<View>,
+    pub instances: Vec<RoomInstance>,
+    pub tiles: Vec<Tile>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct RoomInstance {
+    pub x: i32,
+    pub y: i32,
+    pub object: i32,
+    pub id: ID,
+    pub creation: Result<Rc<[Instruction]>, String>,
+    pub xscale: f64,
+    pub yscale: f64,
+    pub blend: u32,
+    pub angle: f64,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Script {
+    pub name: gml::String,
+    pub source: gml::String,
+    pub compiled: Rc<[Instruction]>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Timeline {
+    pub name: gml::String,
+    pub moments: Rc<RefCell<BTreeMap<i32, Rc<RefCell<ActionTree>>>>>,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Trigger {
+    pub name: gml::String,
+    pub condition: Rc<[Instruction]>,
+    pub moment: TriggerTime,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Default, Deserialize)]
+pub struct ActionTree(pub Vec<Action>);
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub struct Action {
+    pub index: usize,
+    pub target: Option<i32>,
+    pub relative: bool,
+    pub invert_condition: bool,
+    pub body: Body,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub enum Body {
+    Normal { args: Box<[Node]>, body: GmlBody, is_condition: bool },
+    Else,
+    Repeat { count: Node },
+    BlockBegin,
+    BlockEnd,
+    Comment,
+    Exit,
+}
+
+#[cfg_attr(test, derive(serde::Serialize))]
+#[derive(Deserialize)]
+pub enum GmlBody {
+    ContextFunction(gml::ContextFunction),
+    StateFunction(gml::StateFunction),
+    RoutineFunction(gml::RoutineFunction),
+    ValueFunction(gml::ValueFunction),
+    Code(Rc<[Instruction]>),
+}
+
+/// Turns a version 0 body into a version 1 body. The things version 0 didn't store are given the values a freshly
+/// launched game has, since that's what loading such a savestate used to leave them as.
+pub fn migrate(body: &mut Vec<u8>) -> Result<(), super::ReadError> {
+    let state = bincode::deserialize::<SaveState>(body.as_slice()).map_err(super::ReadError::DeserializeErr)?;
+    body.clear();
+    bincode::serialize_into(body, &SaveStateV1::from(state)).map_err(super::ReadError::DeserializeErr)
+}
+
+fn code(instructions: Rc<[Instruction]>) -> Rc<Program> {
+    Rc::new(Program::from_instructions(&instructions))
+}
+
+fn tree(tree: Rc<RefCell<ActionTree>>) -> Rc<RefCell<Tree>> {
+    let actions = tree.take().0.into_iter().map(action::Action::from).collect::<Vec<_>>();
+    Rc::new(RefCell::new(Tree::from(actions)))
+}
+
+impl From<SaveState> for SaveStateV1 {
+    fn from(old: SaveState) -> Self {
+        Self {
+            compiler: old.compiler.into(),
+            rand: old.rand,
+            input: old.input,
+            assets: old.assets.into(),
+            event_holders: old.event_holders,
+            custom_draw_objects: old.custom_draw_objects,
+            background_colour: old.background_colour,
+            textures: old.textures,
+            externals: old.externals,
+            surface_fix: old.surface_fix,
+            view_current: old.view_current,
+            last_instance_id: old.last_instance_id,
+            last_tile_id: old.last_tile_id,
+            particles: old.particles,
+            room: old.room,
+            stored_rooms: old.stored_rooms,
+            room_order: old.room_order,
+            user_transitions: old.user_transitions,
+            globals: old.globals,
+            globalvars: old.globalvars,
+            game_start: old.game_start,
+            stacks: old.stacks,
+            queues: old.queues,
+            lists: old.lists,
+            maps: old.maps,
+            priority_queues: old.priority_queues,
+            grids: old.grids,
+            ds_precision: old.ds_precision,
+            draw_font_id: old.draw_font_id,
+            draw_colour: old.draw_colour,
+            draw_alpha: old.draw_alpha,
+            draw_halign: old.draw_halign,
+            draw_valign: old.draw_valign,
+            surfaces: old.surfaces,
+            surface_target: old.surface_target,
+            models: old.models,
+            model_matrix_stack: old.model_matrix_stack,
+            auto_draw: old.auto_draw,
+            renderer_state: old.renderer_state,
+            uninit_fields_are_zero: old.uninit_fields_are_zero,
+            uninit_args_are_zero: old.uninit_args_are_zero,
+            potential_step_settings: old.potential_step_settings,
+            fps: old.fps,
+            frame_counter: old.frame_counter,
+            transition_kind: old.transition_kind,
+            transition_steps: old.transition_steps,
+            cursor_sprite: old.cursor_sprite,
+            cursor_sprite_frame: old.cursor_sprite_frame,
+            score: old.score,
+            score_capt: old.score_capt,
+            score_capt_d: old.score_capt_d,
+            has_set_show_score: old.has_set_show_score,
+            lives: old.lives,
+            lives_capt: old.lives_capt,
+            lives_capt_d: old.lives_capt_d,
+            health: old.health,
+            health_capt: old.health_capt,
+            health_capt_d: old.health_capt_d,
+            error_occurred: old.error_occurred,
+            error_last: old.error_last,
+            game_id: old.game_id,
+            program_directory: old.program_directory,
+            included_files: old.included_files,
+            gm_version: old.gm_version,
+            clock: old.clock,
+            clean_state: old.clean_state,
+            scaling: old.scaling,
+            unscaled_width: old.unscaled_width,
+            unscaled_height: old.unscaled_height,
+            window_width: old.window_width,
+            window_height: old.window_height,
+            window_offset: (0, 0),
+            window_fullscreen: false,
+            window_stayontop: false,
+            display: Display::default(),
+            clipboard: None,
+            encoding: encoding_rs::WINDOWS_1252,
+            audio_state: old.audio_state,
+            replay: old.replay,
+            screenshot: old.screenshot,
+            zbuffer: old.zbuffer,
+        }
+    }
+}
+
+impl From<Compiler> for gml::Compiler {
+    fn from(old: Compiler) -> Self {
+        Self::from_tables(old.constants, old.user_constant_names, old.script_names, old.extension_fn_names, old.fields)
+    }
+}
+
+impl From<Assets> for crate::game::Assets {
+    fn from(old: Assets) -> Self {
+        fn convert<T, U: From<T>>(assets: Vec<Option<Box<T>>>) -> Vec<Option<Box<U>>> {
+            assets.into_iter().map(|asset| asset.map(|asset| Box::new((*asset).into()))).collect()
+        }
+        Self {
+            backgrounds: old.backgrounds,
+            fonts: old.fonts,
+            objects: convert(old.objects),
+            paths: old.paths,
+            rooms: convert(old.rooms),
+            scripts: convert(old.scripts),
+            sounds: old.sounds,
+            sprites: old.sprites,
+            timelines: convert(old.timelines),
+            triggers: convert(old.triggers),
+        }
+    }
+}
+
+impl From<Object> for asset::Object {
+    fn from(old: Object) -> Self {
+        Self {
+            name: old.name,
+            solid: old.solid,
+            visible: old.visible,
+            persistent: old.persistent,
+            depth: old.depth,
+            sprite_index: old.sprite_index,
+            mask_index: old.mask_index,
+            parent_index: old.parent_index,
+            events: old.events.map(|events| events.into_iter().map(|(sub, t)| (sub, tree(t))).collect()),
+            children: old.children,
+            parents: old.parents,
+        }
+    }
+}
+
+impl From<Room> for asset::Room {
+    fn from(old: Room) -> Self {
+        Self {
+            name: old.name,
+            caption: old.caption,
+            width: old.width,
+            height: old.height,
+            speed: old.speed,
+            persistent: old.persistent,
+            bg_colour: old.bg_colour,
+            clear_screen: old.clear_screen,
+            creation_code: old.creation_code.map(code),
+            backgrounds: old.backgrounds,
+            views_enabled: old.views_enabled,
+            views: old.views,
+            instances: old.instances.into_iter().map(asset::room::Instance::from).collect(),
+            tiles: old.tiles,
+        }
+    }
+}
+
+impl From<RoomInstance> for asset::room::Instance {
+    fn from(old: RoomInstance) -> Self {
+        Self {
+            x: old.x,
+            y: old.y,
+            object: old.object,
+            id: old.id,
+            creation: old.creation.map(code),
+            xscale: old.xscale,
+            yscale: old.yscale,
+            blend: old.blend,
+            angle: old.angle,
+        }
+    }
+}
+
+impl From<Script> for asset::Script {
+    fn from(old: Script) -> Self {
+        Self { name: old.name, source: old.source, compiled: code(old.compiled) }
+    }
+}
+
+impl From<Timeline> for asset::Timeline {
+    fn from(old: Timeline) -> Self {
+        let moments = old.moments.take().into_iter().map(|(moment, t)| (moment, tree(t))).collect();
+        Self { name: old.name, moments: Rc::new(RefCell::new(moments)) }
+    }
+}
+
+impl From<Trigger> for asset::Trigger {
+    fn from(old: Trigger) -> Self {
+        Self { name: old.name, condition: code(old.condition), moment: old.moment }
+    }
+}
+
+impl From<Action> for action::Action {
+    fn from(old: Action) -> Self {
+        Self {
+            index: old.index,
+            target: old.target,
+            relative: old.relative,
+            invert_condition: old.invert_condition,
+            body: match old.body {
+                Body::Normal { args, body, is_condition } => action::Body::Normal {
+                    args: args.iter().map(Program::from_expression).collect(),
+                    body: match body {
+                        GmlBody::ContextFunction(f) => action::GmlBody::ContextFunction(f),
+                        GmlBody::StateFunction(f) => action::GmlBody::StateFunction(f),
+                        GmlBody::RoutineFunction(f) => action::GmlBody::RoutineFunction(f),
+                        GmlBody::ValueFunction(f) => action::GmlBody::ValueFunction(f),
+                        GmlBody::Code(instructions) => action::GmlBody::Code(code(instructions)),
+                    },
+                    is_condition,
+                },
+                Body::Else => action::Body::Else,
+                Body::Repeat { count } => action::Body::Repeat { count: Program::from_expression(&count) },
+                Body::BlockBegin => action::Body::BlockBegin,
+                Body::BlockEnd => action::Body::BlockEnd,
+                Body::Comment => action::Body::Comment,
+                Body::Exit => action::Body::Exit,
+            },
+        }
+    }
+}
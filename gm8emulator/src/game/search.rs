@@ -0,0 +1,177 @@
+use crate::{
+    game::{
+        replay::{Frame, FrameRng, Input},
+        savestate::SaveState,
+        Game,
+    },
+    gml::{bytecode::Program, Context},
+};
+use std::cmp::Ordering;
+
+/// What to try from a savestate, and how to judge it.
+#[derive(Clone)]
+pub struct Settings {
+    /// The keys which may be held down, as virtual key codes.
+    pub keys: Vec<u8>,
+
+    /// The most frames each candidate is run for.
+    pub frames: usize,
+
+    /// How many frames in a row the same keys are held for.
+    pub hold: usize,
+
+    /// The most RNG increments to try on the first frame.
+    pub max_rng: i32,
+
+    /// GML expression to score each candidate by, evaluated once it's stopped.
+    pub score: String,
+
+    /// Whether lower scores are better.
+    pub minimise: bool,
+
+    /// GML expression which stops a candidate once it's true, evaluated after each frame.
+    pub until: Option<String>,
+}
+
+impl Settings {
+    fn segments(&self) -> usize {
+        (self.frames + self.hold - 1) / self.hold
+    }
+
+    /// How many candidates there are to try, or None if there are too many to count.
+    pub fn candidate_count(&self) -> Option<usize> {
+        2usize
+            .checked_pow(self.keys.len() as u32)?
+            .checked_pow(self.segments() as u32)?
+            .checked_mul(self.max_rng as usize + 1)
+    }
+
+    /// Gets the RNG increment and the keys held on each segment of frames for the candidate with the given index.
+    /// Bit N of each segment is set if the Nth key is held.
+    fn candidate(&self, index: usize) -> (i32, Vec<usize>) {
+        let combinations = 1 << self.keys.len();
+        let increment = (index % (self.max_rng as usize + 1)) as i32;
+        let mut rest = index / (self.max_rng as usize + 1);
+        let segments = (0..self.segments())
+            .map(|_| {
+                let held = rest % combinations;
+                rest /= combinations;
+                held
+            })
+            .collect();
+        (increment, segments)
+    }
+}
+
+/// How one candidate did.
+#[derive(Clone, Copy, Debug)]
+pub struct Outcome {
+    pub index: usize,
+    pub score: f64,
+
+    /// How many frames it took for the `until` expression to be true, if it ever was.
+    pub success: Option<usize>,
+}
+
+impl Outcome {
+    /// Orders outcomes from best to worst: successes come first, the sooner the better, and then the best scores.
+    pub fn rank(&self, other: &Self, minimise: bool) -> Ordering {
+        let score = self.score.partial_cmp(&other.score).unwrap_or(Ordering::Equal);
+        match (self.success, other.success) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then(if minimise { score } else { score.reverse() })
+    }
+}
+
+/// A search for the inputs which do best from a savestate, set up to run in one Game.
+/// Games can't be sent between threads, so a search over several threads needs a Game and a Search for each.
+pub struct Search {
+    settings: Settings,
+    state: SaveState,
+    score: Program,
+    until: Option<Program>,
+}
+
+impl Search {
+    /// Compiles the search's expressions against the savestate, and gets the game ready to load it.
+    /// The game should have been launched in record mode, so that candidates store any events they need, such as
+    /// for dialogs or the clipboard, rather than expecting them from a replay.
+    pub fn new(game: &mut Game, settings: Settings, mut state: SaveState) -> Result<Self, String> {
+        // Any fields only named in the expressions get registered in the savestate's compiler, so that the game
+        // can't give the same IDs to fields it registers later on
+        let mut compile = |name: &str, source: &str| match state.compiler.compile_expression(source.as_bytes()) {
+            Ok(program) if program.is_pure() => Ok(program),
+            Ok(_) => Err(format!("the {} expression calls functions which could change the game", name)),
+            Err(e) => Err(format!("couldn't compile {} expression: {}", name, e)),
+        };
+        let score = compile("score", &settings.score)?;
+        let until = settings.until.as_deref().map(|source| compile("until", source)).transpose()?;
+
+        game.upload_tas_ui_sprites();
+
+        Ok(Self { settings, state, score, until })
+    }
+
+    /// The savestate the search runs candidates from.
+    pub fn state(&self) -> &SaveState {
+        &self.state
+    }
+
+    /// Loads the savestate and runs the candidate with the given index from it.
+    /// Gives back how it did, along with the frames of input which were run, to be appended to the savestate's replay.
+    pub fn run(&self, game: &mut Game, index: usize) -> Result<(Outcome, Vec<Frame>), String> {
+        let settings = &self.settings;
+        let (replay, renderer_state) = self.state.clone().load_into(game);
+        game.renderer.set_state(&renderer_state);
+        game.stored_events.clear();
+
+        let (increment, segments) = settings.candidate(index);
+        let (mouse_x, mouse_y) = match replay.frame_count().checked_sub(1).and_then(|i| replay.get_frame(i)) {
+            Some(frame) => (frame.mouse_x, frame.mouse_y),
+            None => (0, 0),
+        };
+        let mut held = settings.keys.iter().map(|key| game.input.keyboard_check_direct(*key)).collect::<Vec<_>>();
+        let mut frames = Vec::with_capacity(settings.frames);
+        let mut success = None;
+        for i in 0..settings.frames {
+            let mut frame = Frame {
+                mouse_x,
+                mouse_y,
+                inputs: Vec::new(),
+                events: Vec::new(),
+                new_seed: if i == 0 && increment > 0 { Some(FrameRng::Increment(increment)) } else { None },
+                new_time: None,
+            };
+            for (k, (key, was_held)) in settings.keys.iter().zip(held.iter_mut()).enumerate() {
+                let now_held = segments[i / settings.hold] & (1 << k) != 0;
+                if now_held != *was_held {
+                    frame.inputs.push(if now_held { Input::KeyPress(*key) } else { Input::KeyRelease(*key) });
+                    *was_held = now_held;
+                }
+            }
+
+            game.run_replay_frame(&frame).map_err(|e| format!("candidate {} crashed on frame {}: {}", index, i, e))?;
+            frame.events.extend(game.stored_events.drain(..));
+            frames.push(frame);
+
+            if let Some(until) = &self.until {
+                let value = game
+                    .eval(until, &mut Context::with_single_instance(0))
+                    .map_err(|e| format!("candidate {} couldn't evaluate until expression: {}", index, e))?;
+                if value.is_truthy() {
+                    success = Some(i + 1);
+                    break
+                }
+            }
+        }
+
+        let score = game
+            .eval(&self.score, &mut Context::with_single_instance(0))
+            .map_err(|e| format!("candidate {} couldn't evaluate score expression: {}", index, e))?;
+        Ok((Outcome { index, score: score.into(), success }, frames))
+    }
+}
@@ -0,0 +1,180 @@
+#[cfg(target_os = "windows")]
+use crate::game::platform;
+use crate::game::PlayType;
+#[cfg(not(target_os = "windows"))]
+use std::process::Command;
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+    str::FromStr,
+};
+
+/// What happens when the game tries to run another program with execute_program() or execute_shell().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Actually run it on the host
+    Allow,
+    /// Fail with an error, as if the program couldn't be found
+    Deny,
+    /// Pretend it ran and finished successfully, without touching the host
+    Emulate,
+}
+
+impl Policy {
+    /// The policy used unless one is given on the command line.
+    /// Only normal play gets to run things on the host, since it can't be part of a recording.
+    pub fn default_for(play_type: PlayType) -> Self {
+        match play_type {
+            PlayType::Normal => Self::Allow,
+            PlayType::Record | PlayType::Replay => Self::Emulate,
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            "emulate" => Ok(Self::Emulate),
+            _ => Err(format!("unknown policy '{}', expected 'allow', 'deny' or 'emulate'", s)),
+        }
+    }
+}
+
+/// Kinds of execute_shell() targets which get special handling instead of being passed to the shell as they are.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A .txt file, opened in the host's text viewer or printed to the log
+    Text,
+    /// A .html or .htm file, opened in the host's browser
+    Html,
+    /// A .url shortcut, containing the web address it points to if it's one we're willing to open
+    Url(Option<String>),
+    /// Anything else, which may well be a program
+    Other,
+}
+
+impl Target {
+    pub fn classify(target: &str) -> Self {
+        let extension = Path::new(target).extension().and_then(|x| x.to_str()).map(|x| x.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("txt") => Self::Text,
+            Some("html" | "htm") => Self::Html,
+            Some("url") => Self::Url(read_url_shortcut(target)),
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Reads the address out of a .url shortcut. Only web addresses are returned, as shortcuts can point anywhere.
+fn read_url_shortcut(path: &str) -> Option<String> {
+    let contents = fs::read(path).ok()?;
+    String::from_utf8_lossy(&contents)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("URL="))
+        .map(str::to_string)
+        .find(|url| url.starts_with("http://") || url.starts_with("https://"))
+}
+
+/// Splits a command line into its arguments the way Windows would.
+/// Rust doesn't let you execute a program with just a string, so this has to be done manually.
+pub fn split_command_line(command: &str) -> Vec<String> {
+    let mut command_array = Vec::new();
+    let mut buf = Some(String::new());
+    let mut quote_count = 0;
+    for c in command.replace("\\\"", "\"\"\"").chars() {
+        match c {
+            '"' => {
+                buf = buf.or(Some("".into()));
+                quote_count += 1;
+                if quote_count > 2 {
+                    quote_count = 0;
+                    buf.as_mut().unwrap().push('"');
+                }
+            },
+            c if c.is_whitespace() && quote_count != 1 => {
+                quote_count %= 2;
+                if let Some(s) = buf {
+                    command_array.push(s);
+                    buf = None;
+                }
+            },
+            c => {
+                quote_count %= 2;
+                buf = buf.or(Some("".into()));
+                buf.as_mut().unwrap().push(c);
+            },
+        }
+    }
+    if let Some(s) = buf {
+        command_array.push(s);
+    }
+    command_array
+}
+
+/// Opens something with the host's default handler for it, which is what execute_shell() does.
+#[cfg(target_os = "windows")]
+pub fn open(target: &str, args: &str) -> io::Result<()> {
+    // This goes straight to the shell, as passing it through cmd would let the target run commands of its own
+    platform::shell_execute(target, args)
+}
+
+/// Opens something with the host's default handler for it, which is what execute_shell() does.
+#[cfg(not(target_os = "windows"))]
+pub fn open(target: &str, args: &str) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(not(target_os = "macos"))]
+    let mut command = Command::new("xdg-open");
+    command.arg(target);
+    if !args.is_empty() {
+        command.args(split_command_line(args));
+    }
+    command.spawn().map(drop)
+}
+
+/// Runs execute_shell() on the host. Text and web pages go to their viewers, shortcuts only to web addresses.
+pub fn execute(target: &str, args: &str) -> io::Result<()> {
+    match Target::classify(target) {
+        Target::Text | Target::Html => open(target, ""),
+        Target::Url(Some(url)) => open(&url, ""),
+        Target::Url(None) => {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "shortcut doesn't point to a web address"))
+        },
+        Target::Other => open(target, args),
+    }
+}
+
+/// How much of a text file execute_shell() would have opened is logged when emulating it.
+const PREVIEW_LEN: u64 = 80;
+
+/// Logs what execute_shell() would have done, without running anything.
+pub fn emulate(target: &str) {
+    match Target::classify(target) {
+        Target::Text => match preview(target) {
+            Ok(preview) => println!("Not opening text file {} ({})", target, preview),
+            Err(e) => println!("Not opening text file {} (couldn't read it: {})", target, e),
+        },
+        Target::Html => println!("Not opening web page {}", target),
+        Target::Url(Some(url)) => println!("Not opening {} (from {})", url, target),
+        Target::Url(None) => println!("Not opening shortcut {}", target),
+        Target::Other => println!("Not running {}", target),
+    }
+}
+
+/// Reads the start of a text file as one line, for logging.
+fn preview(path: &str) -> io::Result<String> {
+    let mut start = Vec::new();
+    File::open(path)?.take(PREVIEW_LEN + 1).read_to_end(&mut start)?;
+    let cut = start.len() as u64 > PREVIEW_LEN;
+    start.truncate(PREVIEW_LEN as usize);
+    let mut preview = String::from_utf8_lossy(&start).split_whitespace().collect::<Vec<_>>().join(" ");
+    if cut {
+        preview.push_str("...");
+    }
+    Ok(format!("{:?}", preview))
+}
@@ -1,14 +1,20 @@
+pub mod bytecode;
+pub mod compat;
 pub mod compiler;
 pub mod context;
 pub mod datetime;
 pub mod ds;
 pub mod file;
 pub mod kernel;
+#[cfg(test)]
+mod kernel_scan;
+pub mod lint;
 pub mod mappings;
 pub mod network;
 pub mod rand;
 pub mod runtime;
 pub mod string;
+pub mod trace;
 pub mod value;
 
 pub use compiler::Compiler;
@@ -123,6 +129,15 @@ impl<T: FunctionTryFromEnum> Serialize for FunctionPtr<T> {
     }
 }
 
+impl<T: FunctionTryFromEnum> std::fmt::Debug for FunctionPtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match mappings::FUNCTIONS.entries().find(|(_, v)| v.addr() == self.0.addr()) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "?unknown?"),
+        }
+    }
+}
+
 impl<'de, T: FunctionTryFromEnum> Deserialize<'de> for FunctionPtr<T> {
     fn deserialize<D>(d: D) -> std::result::Result<Self, D::Error>
     where
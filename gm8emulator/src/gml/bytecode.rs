@@ -0,0 +1,496 @@
+use crate::gml::{
+    self,
+    runtime::{ArrayAccessor, BinaryOperator, Error, InstanceIdentifier, Instruction, Node, ReturnType, UnaryOperator},
+    InstanceVariable, Value,
+};
+use gml_parser::lexer::Position;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A piece of compiled GML: a flat list of operations working on a value stack, along with the literals it uses.
+/// Expressions leave their result on top of the stack; statements leave the stack as they found it.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Program {
+    pub ops: Box<[Op]>,
+    pub consts: Box<[Value]>,
+}
+
+/// Where the instance(s) for a field or variable access come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Owner {
+    Unknown,
+    Own,
+    Other,
+    Global,
+    Local,
+    /// An instance or object ID popped from the value stack
+    Stack,
+    /// A target already resolved by Op::Target, popped from the target stack
+    Resolved,
+}
+
+/// A single operation. Any array indices an access needs are popped from the value stack first,
+/// so they have to be pushed after the owner.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Op {
+    /// Marks the start of a line of code, so runtime errors and the debugger know where they are
+    Line(Position),
+
+    /// Pushes a value from the constant pool
+    Push(u32),
+    /// Pushes a user-defined constant
+    Constant(usize),
+    /// Discards the top of the value stack
+    Pop,
+
+    /// Resolves which instance(s) an access refers to and puts the result on the target stack.
+    /// Used when code runs between resolving the target and using it, so this happens at the same point as in GM8.
+    /// `field` is the field ID for a field access, since whether it's a globalvar changes the target.
+    Target { owner: Owner, field: Option<usize> },
+    GetField { index: usize, owner: Owner, dims: u8 },
+    GetVar { var: InstanceVariable, owner: Owner, dims: u8 },
+    /// Pops a value and assigns it, also making it the return value
+    SetField { index: usize, owner: Owner, dims: u8 },
+    SetVar { var: InstanceVariable, owner: Owner, dims: u8 },
+    /// Checks the array index on top of the stack without popping it, so an assignment to a bad index fails
+    /// before its value is evaluated
+    CheckIndex(u8),
+
+    /// Function calls pop up to 16 arguments, but pass on the original count
+    CallContext { function: gml::ContextFunction, argc: usize },
+    CallState { function: gml::StateFunction, argc: usize },
+    CallRoutine { function: gml::RoutineFunction, argc: usize },
+    CallValue { function: gml::ValueFunction, argc: usize },
+    CallScript { script_id: usize, argc: usize },
+    CallExtension { id: usize, argc: usize },
+
+    Unary(UnaryOperator),
+    /// Type-unsafe operators keep the left-hand value when the operation fails, which is how += and such behave
+    Binary { operator: BinaryOperator, type_unsafe: bool },
+
+    /// Pops a value and makes it the return value
+    SetReturnValue,
+
+    Jump(usize),
+    /// Pops a value and jumps if it isn't truthy
+    JumpIfFalse(usize),
+    /// Pops a case value and compares it with the switch input below it. If they match, pops the input too and jumps
+    Case(usize),
+
+    /// Pops a count and starts a repeat loop with it
+    RepeatStart,
+    /// Counts down the innermost repeat loop, or jumps if it's finished
+    RepeatNext(usize),
+    RepeatEnd,
+
+    /// Pops a target and starts iterating over the instances it refers to
+    WithStart,
+    /// Moves the innermost with loop on to its next instance, or jumps if there are none left
+    WithNext(usize),
+    WithEnd,
+
+    GlobalVar(Box<[usize]>),
+    Error(Error),
+    /// Stops running the program. If this leaves any with loops, self and other are restored first
+    Return(ReturnType),
+}
+
+/// A loop or switch being assembled, with the jumps that need to point at its end or its next iteration.
+struct Scope {
+    breaks: Vec<usize>,
+    continues: Option<Vec<usize>>, // None for switch, which continue passes straight through
+}
+
+struct Assembler {
+    ops: Vec<Op>,
+    consts: Vec<Value>,
+    scopes: Vec<Scope>,
+    line: Option<Position>,
+}
+
+impl Program {
+    /// Flattens a compiled tree of statements.
+    pub fn from_instructions(instructions: &[Instruction]) -> Self {
+        let mut asm = Assembler::new();
+        asm.block(instructions);
+        asm.finish()
+    }
+
+    /// Flattens a compiled expression. Running it leaves the value on the stack.
+    pub fn from_expression(node: &Node) -> Self {
+        let mut asm = Assembler::new();
+        asm.expr(node);
+        asm.finish()
+    }
+
+    /// Whether running this can't change anything in the game. Only value functions are allowed,
+    /// since anything taking the game state or the context might change it.
+    pub fn is_pure(&self) -> bool {
+        self.ops.iter().all(|op| {
+            !matches!(
+                op,
+                Op::SetField { .. }
+                    | Op::SetVar { .. }
+                    | Op::CallContext { .. }
+                    | Op::CallState { .. }
+                    | Op::CallRoutine { .. }
+                    | Op::CallScript { .. }
+                    | Op::CallExtension { .. }
+                    | Op::GlobalVar(_)
+            )
+        })
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, op) in self.ops.iter().enumerate() {
+            match op {
+                Op::Push(index) => writeln!(f, "{:5} Push({:?})", i, self.consts[*index as usize])?,
+                op => writeln!(f, "{:5} {:?}", i, op)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self { ops: Vec::new(), consts: Vec::new(), scopes: Vec::new(), line: None }
+    }
+
+    fn finish(self) -> Program {
+        Program { ops: self.ops.into(), consts: self.consts.into() }
+    }
+
+    fn here(&self) -> usize {
+        self.ops.len()
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    /// Points a jump emitted earlier at `target`.
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.ops[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::Case(t) | Op::RepeatNext(t) | Op::WithNext(t) => *t = target,
+            _ => unreachable!("patched something that isn't a jump"),
+        }
+    }
+
+    /// Goes back to the line a loop started on before checking its condition again.
+    fn reline(&mut self, line: Option<Position>) {
+        if let Some(pos) = line {
+            self.emit(Op::Line(pos));
+        }
+    }
+
+    fn push_const(&mut self, value: &Value) {
+        let same = |other: &Value| match (value, other) {
+            (Value::Real(a), Value::Real(b)) => a.into_inner().to_bits() == b.into_inner().to_bits(),
+            (Value::Str(a), Value::Str(b)) => AsRef::<[u8]>::as_ref(a) == AsRef::<[u8]>::as_ref(b),
+            _ => false,
+        };
+        let index = match self.consts.iter().position(same) {
+            Some(index) => index,
+            None => {
+                self.consts.push(value.clone());
+                self.consts.len() - 1
+            },
+        };
+        self.emit(Op::Push(index as u32));
+    }
+
+    fn open_scope(&mut self, continues: bool) {
+        self.scopes.push(Scope { breaks: Vec::new(), continues: if continues { Some(Vec::new()) } else { None } });
+    }
+
+    /// Closes the innermost scope, pointing its breaks and continues where they should go.
+    fn close_scope(&mut self, break_target: usize, continue_target: usize) {
+        let scope = self.scopes.pop().expect("closed a scope that wasn't opened");
+        for at in scope.breaks {
+            self.patch(at, break_target);
+        }
+        for at in scope.continues.into_iter().flatten() {
+            self.patch(at, continue_target);
+        }
+    }
+
+    fn block(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions {
+            self.statement(instruction);
+        }
+    }
+
+    fn statement(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::SetField { accessor, value } => {
+                let owner = self.resolve_owner(&accessor.owner, Some(accessor.index), true);
+                let dims = self.array(&accessor.array);
+                self.check_index(dims);
+                self.expr(value);
+                self.emit(Op::SetField { index: accessor.index, owner, dims });
+            },
+            Instruction::SetVariable { accessor, value } => {
+                let owner = self.resolve_owner(&accessor.owner, None, true);
+                let dims = self.array(&accessor.array);
+                self.check_index(dims);
+                self.expr(value);
+                self.emit(Op::SetVar { var: accessor.var, owner, dims });
+            },
+            Instruction::EvalExpression { node: value } | Instruction::SetReturnValue { value } => {
+                self.expr(value);
+                self.emit(Op::SetReturnValue);
+            },
+            Instruction::IfElse { cond, if_body, else_body } => {
+                self.expr(cond);
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.block(if_body);
+                if else_body.is_empty() {
+                    let end = self.here();
+                    self.patch(to_else, end);
+                } else {
+                    let to_end = self.emit(Op::Jump(0));
+                    let else_start = self.here();
+                    self.patch(to_else, else_start);
+                    self.block(else_body);
+                    let end = self.here();
+                    self.patch(to_end, end);
+                }
+            },
+            Instruction::LoopUntil { cond, body } => {
+                // continue goes straight back to the start of the body without checking the condition
+                let line = self.line;
+                let start = self.here();
+                self.open_scope(true);
+                self.block(body);
+                self.reline(line);
+                self.expr(cond);
+                self.emit(Op::JumpIfFalse(start));
+                let end = self.here();
+                self.close_scope(end, start);
+            },
+            Instruction::LoopWhile { cond, body } => {
+                let line = self.line;
+                let start = self.here();
+                self.expr(cond);
+                let to_end = self.emit(Op::JumpIfFalse(0));
+                self.open_scope(true);
+                self.block(body);
+                let next = self.here();
+                self.reline(line);
+                self.emit(Op::Jump(start));
+                let end = self.here();
+                self.patch(to_end, end);
+                self.close_scope(end, next);
+            },
+            Instruction::LoopFor { cond, body, step } => {
+                let line = self.line;
+                let start = self.here();
+                self.expr(cond);
+                let to_end = self.emit(Op::JumpIfFalse(0));
+                self.open_scope(true);
+                self.block(body);
+                let next = self.here();
+                self.reline(line);
+                self.block(step);
+                self.emit(Op::Jump(start));
+                let end = self.here();
+                self.patch(to_end, end);
+                self.close_scope(end, next);
+            },
+            Instruction::Repeat { count, body } => {
+                self.expr(count);
+                self.emit(Op::RepeatStart);
+                let next = self.emit(Op::RepeatNext(0));
+                self.open_scope(true);
+                self.block(body);
+                self.emit(Op::Jump(next));
+                let end = self.emit(Op::RepeatEnd);
+                self.patch(next, end);
+                self.close_scope(end, next);
+            },
+            Instruction::Switch { input, cases, default, body } => {
+                // Case values are only evaluated until one matches
+                self.expr(input);
+                let mut jumps = Vec::with_capacity(cases.len());
+                for (value, start) in cases.iter() {
+                    self.expr(value);
+                    jumps.push((self.emit(Op::Case(0)), *start));
+                }
+                self.emit(Op::Pop);
+                let to_default = self.emit(Op::Jump(0));
+
+                let mut starts = Vec::with_capacity(body.len() + 1);
+                self.open_scope(false);
+                for instruction in body.iter() {
+                    starts.push(self.here());
+                    self.statement(instruction);
+                }
+                let end = self.here();
+                starts.push(end);
+                self.close_scope(end, end);
+
+                for (at, start) in jumps {
+                    self.patch(at, starts[start]);
+                }
+                self.patch(to_default, default.map(|start| starts[start]).unwrap_or(end));
+            },
+            Instruction::With { target, body } => {
+                self.expr(target);
+                self.emit(Op::WithStart);
+                let next = self.emit(Op::WithNext(0));
+                self.open_scope(true);
+                self.block(body);
+                self.emit(Op::Jump(next));
+                let end = self.emit(Op::WithEnd);
+                self.patch(next, end);
+                self.close_scope(end, next);
+            },
+            Instruction::Return { return_type: ReturnType::Break } => match self.scopes.last() {
+                Some(_) => {
+                    let at = self.emit(Op::Jump(0));
+                    self.scopes.last_mut().unwrap().breaks.push(at);
+                },
+                None => {
+                    self.emit(Op::Return(ReturnType::Break));
+                },
+            },
+            Instruction::Return { return_type: ReturnType::Continue } => {
+                match self.scopes.iter().rposition(|scope| scope.continues.is_some()) {
+                    Some(index) => {
+                        let at = self.emit(Op::Jump(0));
+                        self.scopes[index].continues.as_mut().unwrap().push(at);
+                    },
+                    None => {
+                        self.emit(Op::Return(ReturnType::Continue));
+                    },
+                }
+            },
+            Instruction::Return { return_type } => {
+                self.emit(Op::Return(*return_type));
+            },
+            Instruction::GlobalVar { fields } => {
+                self.emit(Op::GlobalVar(fields.clone().into_boxed_slice()));
+            },
+            Instruction::RuntimeError { error } => {
+                self.emit(Op::Error(error.clone()));
+            },
+            Instruction::Line { pos } => {
+                self.line = Some(*pos);
+                self.emit(Op::Line(*pos));
+            },
+        }
+    }
+
+    fn expr(&mut self, node: &Node) {
+        match node {
+            Node::Constant { constant_id } => {
+                self.emit(Op::Constant(*constant_id));
+            },
+            Node::Literal { value } => self.push_const(value),
+            Node::Field { accessor } => {
+                let owner = self.resolve_owner(
+                    &accessor.owner,
+                    Some(accessor.index),
+                    !matches!(accessor.array, ArrayAccessor::None),
+                );
+                let dims = self.array(&accessor.array);
+                self.emit(Op::GetField { index: accessor.index, owner, dims });
+            },
+            Node::Variable { accessor } => {
+                let owner = self.resolve_owner(&accessor.owner, None, !matches!(accessor.array, ArrayAccessor::None));
+                let dims = self.array(&accessor.array);
+                self.emit(Op::GetVar { var: accessor.var, owner, dims });
+            },
+            Node::ContextFunction { args, function } => {
+                self.args(args);
+                self.emit(Op::CallContext { function: function.clone(), argc: args.len() });
+            },
+            Node::StateFunction { args, function } => {
+                self.args(args);
+                self.emit(Op::CallState { function: function.clone(), argc: args.len() });
+            },
+            Node::RoutineFunction { args, function } => {
+                self.args(args);
+                self.emit(Op::CallRoutine { function: function.clone(), argc: args.len() });
+            },
+            Node::ValueFunction { args, function } => {
+                self.args(args);
+                self.emit(Op::CallValue { function: function.clone(), argc: args.len() });
+            },
+            Node::Script { args, script_id } => {
+                self.args(args);
+                self.emit(Op::CallScript { script_id: *script_id, argc: args.len() });
+            },
+            Node::ExtensionFunction { args, id } => {
+                self.args(args);
+                self.emit(Op::CallExtension { id: *id, argc: args.len() });
+            },
+            Node::Unary { child, operator } => {
+                self.expr(child);
+                self.emit(Op::Unary(*operator));
+            },
+            Node::Binary { left, right, operator, type_unsafe } => {
+                self.expr(left);
+                self.expr(right);
+                self.emit(Op::Binary { operator: *operator, type_unsafe: *type_unsafe });
+            },
+            Node::RuntimeError { error } => {
+                self.emit(Op::Error(error.clone()));
+            },
+        }
+    }
+
+    /// Only the first 16 arguments are ever evaluated.
+    fn args(&mut self, args: &[Node]) {
+        for arg in args.iter().take(16) {
+            self.expr(arg);
+        }
+    }
+
+    fn array(&mut self, array: &ArrayAccessor) -> u8 {
+        match array {
+            ArrayAccessor::None => 0,
+            ArrayAccessor::Single(index) => {
+                self.expr(index);
+                1
+            },
+            ArrayAccessor::Double(index1, index2) => {
+                self.expr(index1);
+                self.expr(index2);
+                2
+            },
+        }
+    }
+
+    fn check_index(&mut self, dims: u8) {
+        if dims > 0 {
+            self.emit(Op::CheckIndex(dims));
+        }
+    }
+
+    /// Works out the owner for an access. If more code runs before the access happens (`deferred`),
+    /// owners which depend on the game state get resolved onto the target stack first.
+    fn resolve_owner(&mut self, owner: &InstanceIdentifier, field: Option<usize>, deferred: bool) -> Owner {
+        let owner = match owner {
+            InstanceIdentifier::Unknown => Owner::Unknown,
+            InstanceIdentifier::Own => return Owner::Own,
+            InstanceIdentifier::Other => return Owner::Other,
+            InstanceIdentifier::Global => return Owner::Global,
+            InstanceIdentifier::Local => return Owner::Local,
+            InstanceIdentifier::Expression(node) => {
+                self.expr(node);
+                Owner::Stack
+            },
+        };
+        if deferred {
+            self.emit(Op::Target { owner, field });
+            Owner::Resolved
+        } else {
+            owner
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Lists the kernel functions and variables a game uses, and how much of each one the emulator has,
+//! so that games which will crash can be found before starting a TAS rather than in the middle of one.
+
+use crate::gml::{
+    lint::{self, Code, Location},
+    mappings::{self, Status},
+};
+use encoding_rs::Encoding;
+use gm8exe::GameAssets;
+use gml_parser::ast::{self, Expr, ExprKind};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Display},
+    str,
+};
+
+/// A kernel function or variable used by a game, along with everywhere it's used.
+pub struct Usage {
+    pub status: Status,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub functions: BTreeMap<&'static str, Usage>,
+    pub variables: BTreeMap<&'static str, Usage>,
+
+    /// Syntax errors, in code which is otherwise checked as far as it can be parsed
+    pub errors: Vec<(Location, String)>,
+}
+
+/// Finds every kernel function and variable used anywhere in a game's code.
+pub fn report(assets: &GameAssets, encoding: &'static Encoding) -> Report {
+    // Scripts and extension functions are called instead of kernel functions with the same name
+    let files = assets.extensions.iter().flat_map(|x| x.files.iter());
+    let mut overridden: HashSet<&[u8]> = files.flat_map(|x| x.functions.iter()).map(|x| x.name.0.as_ref()).collect();
+    overridden.extend(assets.scripts.iter().flatten().map(|x| x.name.0.as_ref()));
+
+    let mut report = Report::default();
+    lint::for_each_code(assets, encoding, |location, code| report.add(location, code, &overridden));
+    report
+}
+
+impl Report {
+    /// Adds the kernel functions and variables used in a piece of code, and any syntax errors in it.
+    fn add(&mut self, location: &Location, code: Code, overridden: &HashSet<&[u8]>) {
+        let mut location = location.clone();
+        match code {
+            Code::Lines(source) => {
+                let (ast, errors) = ast::AST::with_errors(source);
+                ast.iter().for_each(|x| self.visit(x, &mut location, overridden));
+                for e in errors {
+                    self.errors.push((Location { pos: e.pos, ..location.clone() }, e.message));
+                }
+            },
+            Code::Expression(source) => match ast::AST::expression(source) {
+                Ok(expr) => self.visit(&expr, &mut location, overridden),
+                Err(e) => self.errors.push((Location { pos: e.pos, ..location }, e.message)),
+            },
+            Code::Function(name) => self.use_function(name, &location),
+        }
+    }
+
+    fn visit(&mut self, expr: &Expr, location: &mut Location, overridden: &HashSet<&[u8]>) {
+        let mut visit = |expr: &Expr| self.visit(expr, location, overridden);
+        match &expr.kind {
+            ExprKind::Line(line) => {
+                location.pos = Some(line.pos);
+                self.visit(&line.expr, location, overridden);
+            },
+            ExprKind::LiteralIdentifier(name) => self.use_variable(name, location),
+            ExprKind::Function(call) => {
+                if !overridden.contains(call.name) {
+                    self.use_function(call.name, location);
+                }
+                call.params.iter().for_each(|x| self.visit(x, location, overridden));
+            },
+            ExprKind::Unary(unary) => visit(&unary.child),
+            ExprKind::Binary(binary) => {
+                visit(&binary.left);
+                visit(&binary.right);
+            },
+            ExprKind::DoUntil(do_until) => {
+                visit(&do_until.body);
+                visit(&do_until.cond);
+            },
+            ExprKind::For(for_expr) => {
+                visit(&for_expr.start);
+                visit(&for_expr.cond);
+                visit(&for_expr.step);
+                visit(&for_expr.body);
+            },
+            ExprKind::Group(group) => group.iter().for_each(visit),
+            ExprKind::If(if_expr) => {
+                visit(&if_expr.cond);
+                visit(&if_expr.body);
+                if_expr.else_body.iter().for_each(visit);
+            },
+            ExprKind::Repeat(repeat) => {
+                visit(&repeat.count);
+                visit(&repeat.body);
+            },
+            ExprKind::Switch(switch) => {
+                visit(&switch.input);
+                visit(&switch.body);
+            },
+            ExprKind::With(with) => {
+                visit(&with.target);
+                visit(&with.body);
+            },
+            ExprKind::While(while_expr) => {
+                visit(&while_expr.cond);
+                visit(&while_expr.body);
+            },
+            ExprKind::Case(value) | ExprKind::Return(value) => visit(value),
+            _ => (),
+        }
+    }
+
+    fn use_function(&mut self, name: &[u8], location: &Location) {
+        if let Some((name, info)) = str::from_utf8(name).ok().and_then(|x| mappings::FUNCTION_INFO.get_entry(x)) {
+            let usage =
+                self.functions.entry(name).or_insert_with(|| Usage { status: info.status, locations: Vec::new() });
+            usage.locations.push(location.clone());
+        }
+    }
+
+    fn use_variable(&mut self, name: &[u8], location: &Location) {
+        if let Some((name, status)) = str::from_utf8(name).ok().and_then(|x| mappings::VARIABLE_STATUS.get_entry(x)) {
+            let usage = self.variables.entry(name).or_insert_with(|| Usage { status: *status, locations: Vec::new() });
+            usage.locations.push(location.clone());
+        }
+    }
+}
+
+/// Lists everything by how well it's supported, worst first. Only the problems are listed with their locations,
+/// unless the alternate flag (`{:#}`) is used.
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sections = [
+            (Status::Unimplemented, "Unimplemented"),
+            (Status::Stubbed, "Stubbed"),
+            (Status::Implemented, "Implemented"),
+        ];
+        for (kind, usages) in [("functions", &self.functions), ("variables", &self.variables)] {
+            for (status, heading) in sections {
+                let usages = usages.iter().filter(|(_, x)| x.status == status).collect::<Vec<_>>();
+                if usages.is_empty() {
+                    continue
+                }
+                writeln!(f, "{} {} ({}):", heading, kind, usages.len())?;
+                for (name, usage) in usages {
+                    let count = usage.locations.len();
+                    writeln!(f, "    {} ({} use{})", name, count, if count == 1 { "" } else { "s" })?;
+                    if status != Status::Implemented || f.alternate() {
+                        for location in &usage.locations {
+                            writeln!(f, "        in {}", location)?;
+                        }
+                    }
+                }
+            }
+        }
+        if !self.errors.is_empty() {
+            writeln!(f, "Syntax errors ({}):", self.errors.len())?;
+            for (location, message) in &self.errors {
+                writeln!(f, "    in {}: {}", location, message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gml::trace::Source;
+    use gml_parser::lexer::Position;
+
+    fn report(codes: &[Code], overridden: &[&[u8]]) -> Report {
+        let overridden = overridden.iter().copied().collect();
+        let location = Location { source: Source::Script("test".into()), action: None, pos: None };
+        let mut report = Report::default();
+        codes.iter().for_each(|code| report.add(&location, *code, &overridden));
+        report
+    }
+
+    fn statuses<'a>(usages: &BTreeMap<&'a str, Usage>) -> Vec<(&'a str, Status)> {
+        usages.iter().map(|(name, usage)| (*name, usage.status)).collect()
+    }
+
+    /// The name of some kernel function with the given status.
+    fn function_with(status: Status) -> &'static str {
+        *mappings::FUNCTION_INFO.entries().find(|(_, info)| info.status == status).unwrap().0
+    }
+
+    #[test]
+    fn supported_and_unsupported() {
+        let (stubbed, unimplemented) = (function_with(Status::Stubbed), function_with(Status::Unimplemented));
+        let code = format!("x = keyboard_string + foo; instance_create(0, 0, 0); {}(); {}();", stubbed, unimplemented);
+        let report = report(&[Code::Lines(code.as_bytes())], &[]);
+
+        let mut functions = vec![
+            ("instance_create", Status::Implemented),
+            (stubbed, Status::Stubbed),
+            (unimplemented, Status::Unimplemented),
+        ];
+        functions.sort_by_key(|(name, _)| *name);
+        assert_eq!(statuses(&report.functions), functions);
+        // Fields that aren't built-in variables aren't the emulator's business
+        assert_eq!(
+            statuses(&report.variables),
+            [("keyboard_string", Status::Unimplemented), ("x", Status::Implemented)],
+        );
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn locations() {
+        let report = report(
+            &[
+                Code::Lines(b"x = 1;\ny = instance_create(0, 0, 0);"),
+                Code::Expression(b"instance_create(x, y, 0)"),
+                Code::Function(b"instance_create"),
+                Code::Lines(b"x = 1;\ny = ;"),
+            ],
+            &[],
+        );
+        let lines = report.functions["instance_create"].locations.iter().map(|x| x.pos.map(|pos| pos.line));
+        assert_eq!(lines.collect::<Vec<_>>(), [Some(2), None, None]);
+        // The lines before a syntax error are still checked
+        assert_eq!(report.variables["x"].locations.len(), 3);
+        assert!(matches!(report.errors[..], [(Location { pos: Some(Position { line: 2, column: 5 }), .. }, _)]));
+    }
+
+    #[test]
+    fn overridden() {
+        let report = report(&[Code::Lines(b"instance_create(0, 0, 0); instance_destroy();")], &[b"instance_create"]);
+        assert_eq!(report.functions.keys().copied().collect::<Vec<_>>(), ["instance_destroy"]);
+    }
+
+    #[test]
+    fn display() {
+        let report = report(&[Code::Lines(b"x = keyboard_string;\ninstance_create(0, 0, 0);")], &[]);
+        assert_eq!(
+            report.to_string(),
+            "Implemented functions (1):\n    instance_create (1 use)\n\
+            Unimplemented variables (1):\n    keyboard_string (1 use)\n        in script test at line 1, position 1\n\
+            Implemented variables (1):\n    x (1 use)\n",
+        );
+        assert_eq!(
+            format!("{:#}", report),
+            "Implemented functions (1):\n    instance_create (1 use)\n        in script test at line 2, position 1\n\
+            Unimplemented variables (1):\n    keyboard_string (1 use)\n        in script test at line 1, position 1\n\
+            Implemented variables (1):\n    x (1 use)\n        in script test at line 1, position 1\n",
+        );
+    }
+}
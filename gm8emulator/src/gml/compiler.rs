@@ -1,4 +1,5 @@
 use super::{
+    bytecode::Program,
     mappings,
     runtime::{
         ArrayAccessor, BinaryOperator, FieldAccessor, InstanceIdentifier, Instruction, Node, ReturnType, UnaryOperator,
@@ -9,7 +10,11 @@ use super::{
 use crate::{gml, math::Real};
 use gml_parser::{ast, token::Operator};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, rc::Rc, str};
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    str,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Compiler {
@@ -19,6 +24,9 @@ pub struct Compiler {
     /// Table of user-defined constants to IDs
     user_constant_names: HashMap<Box<[u8]>, usize>,
 
+    /// Values of user-defined constants which could be worked out at compile time
+    user_constant_values: BTreeMap<usize, Value>,
+
     /// Table of script names to IDs
     script_names: HashMap<Box<[u8]>, usize>,
 
@@ -35,12 +43,32 @@ impl Compiler {
         Self {
             constants: HashMap::new(),
             user_constant_names: HashMap::new(),
+            user_constant_values: BTreeMap::new(),
             script_names: HashMap::new(),
             extension_fn_names: HashMap::new(),
             fields: Vec::new(),
         }
     }
 
+    /// Rebuild a compiler from its tables, with no constants folded.
+    /// This is for savestates from before constants were folded, which only stored the tables.
+    pub fn from_tables(
+        constants: HashMap<Box<[u8]>, Value>,
+        user_constant_names: HashMap<Box<[u8]>, usize>,
+        script_names: HashMap<Box<[u8]>, usize>,
+        extension_fn_names: HashMap<Box<[u8]>, usize>,
+        fields: Vec<Box<[u8]>>,
+    ) -> Self {
+        Self {
+            constants,
+            user_constant_names,
+            user_constant_values: BTreeMap::new(),
+            script_names,
+            extension_fn_names,
+            fields,
+        }
+    }
+
     /// Reserve space to register at least the given number of constants.
     pub fn reserve_constants(&mut self, size: usize) {
         self.constants.reserve(size)
@@ -83,8 +111,33 @@ impl Compiler {
         self.user_constant_names.insert(name, index);
     }
 
-    /// Compile a GML string into instructions.
-    pub fn compile(&mut self, source: &[u8]) -> Result<Rc<[Instruction]>, ast::Error> {
+    /// Works out a user-defined constant's value at compile time if it doesn't depend on the game state,
+    /// so code using the constant can be folded. Constants must be given in the order they're evaluated in,
+    /// since each one can only refer to those before it.
+    pub fn fold_user_constant(&mut self, index: usize, source: &[u8]) {
+        if let Ok(expr) = ast::AST::expression(source) {
+            if let Node::Literal { value } = self.compile_ast_expr(&expr, &[]) {
+                self.user_constant_values.insert(index, value);
+            }
+        }
+    }
+
+    /// Compile a user-defined constant's expression to be evaluated at startup. Constants are evaluated in order,
+    /// so only the ones before it can be folded into it.
+    pub fn compile_user_constant(&mut self, index: usize, source: &[u8]) -> Result<Program, ast::Error> {
+        let later = self.user_constant_values.split_off(&index);
+        let program = self.compile_expression(source);
+        self.user_constant_values.extend(later);
+        program
+    }
+
+    /// Compile a GML string into a program.
+    pub fn compile(&mut self, source: &[u8]) -> Result<Rc<Program>, ast::Error> {
+        Ok(Rc::new(Program::from_instructions(&self.compile_instructions(source)?)))
+    }
+
+    /// Compile a GML string into a tree of instructions, before it gets flattened into a program.
+    pub fn compile_instructions(&mut self, source: &[u8]) -> Result<Vec<Instruction>, ast::Error> {
         let ast = ast::AST::new(source)?;
 
         let mut instructions = Vec::new();
@@ -92,40 +145,40 @@ impl Compiler {
         for node in ast.iter() {
             self.compile_ast_line(node, &mut instructions, &mut locals);
         }
-        Ok(instructions.into())
+        Ok(instructions)
     }
 
-    /// Compile an expression into a format which can be evaluated.
-    pub fn compile_expression(&mut self, source: &[u8]) -> Result<Node, ast::Error> {
+    /// Compile an expression into a program which can be evaluated.
+    pub fn compile_expression(&mut self, source: &[u8]) -> Result<Program, ast::Error> {
         let expr = ast::AST::expression(source)?;
-        Ok(self.compile_ast_expr(&expr, &[]))
+        Ok(Program::from_expression(&self.compile_ast_expr(&expr, &[])))
     }
 
     /// Compile a single line of code from an AST expression.
     fn compile_ast_line<'a>(&mut self, line: &'a ast::Expr, output: &mut Vec<Instruction>, locals: &mut Vec<&'a [u8]>) {
-        match line {
+        match &line.kind {
             // Line of code identified by an assignment operator
-            ast::Expr::Binary(binary_expr) => {
+            ast::ExprKind::Binary(binary_expr) => {
                 output.push(self.binary_to_instruction(binary_expr.as_ref(), &locals));
             },
 
             // Break
-            ast::Expr::Break => {
+            ast::ExprKind::Break => {
                 output.push(Instruction::Return { return_type: ReturnType::Break });
             },
 
             // Continue
-            ast::Expr::Continue => {
+            ast::ExprKind::Continue => {
                 output.push(Instruction::Return { return_type: ReturnType::Continue });
             },
 
             // Exit
-            ast::Expr::Exit => {
+            ast::ExprKind::Exit => {
                 output.push(Instruction::Return { return_type: ReturnType::Exit });
             },
 
             // For loop
-            ast::Expr::For(for_expr) => {
+            ast::ExprKind::For(for_expr) => {
                 self.compile_ast_line(&for_expr.start, output, locals);
                 let cond = self.compile_ast_expr(&for_expr.cond, locals);
                 let mut body = Vec::new();
@@ -140,19 +193,19 @@ impl Compiler {
             },
 
             // Function or Script
-            f @ ast::Expr::Function(_) => {
-                output.push(Instruction::EvalExpression { node: self.compile_ast_expr(f, locals) });
+            ast::ExprKind::Function(_) => {
+                output.push(Instruction::EvalExpression { node: self.compile_ast_expr(line, locals) });
             },
 
             // Group of expressions
-            ast::Expr::Group(group) => {
+            ast::ExprKind::Group(group) => {
                 for expr in group {
                     self.compile_ast_line(expr, output, locals);
                 }
             },
 
             // If/else body
-            ast::Expr::If(if_expr) => {
+            ast::ExprKind::If(if_expr) => {
                 let cond = self.compile_ast_expr(&if_expr.cond, locals);
                 if let Node::Literal { value: v } = cond {
                     // The "if" condition is constant, so we can optimize this away
@@ -177,7 +230,7 @@ impl Compiler {
             },
 
             // "repeat" block
-            ast::Expr::Repeat(repeat_expr) => {
+            ast::ExprKind::Repeat(repeat_expr) => {
                 let count = self.compile_ast_expr(&repeat_expr.count, locals);
                 let mut body = Vec::new();
                 self.compile_ast_line(&repeat_expr.body, &mut body, locals);
@@ -185,25 +238,25 @@ impl Compiler {
             },
 
             // Return
-            ast::Expr::Return(expr) => {
+            ast::ExprKind::Return(expr) => {
                 let value = self.compile_ast_expr(&expr, locals);
                 output.push(Instruction::SetReturnValue { value });
                 output.push(Instruction::Return { return_type: ReturnType::Exit });
             },
 
             // "switch" block
-            ast::Expr::Switch(switch_expr) => {
+            ast::ExprKind::Switch(switch_expr) => {
                 let input = self.compile_ast_expr(&switch_expr.input, locals);
-                if let ast::Expr::Group(group) = &switch_expr.body {
+                if let ast::ExprKind::Group(group) = &switch_expr.body.kind {
                     let mut cases = Vec::new();
                     let mut body = Vec::new();
                     let mut default: Option<usize> = None;
                     for expr in group {
-                        if let ast::Expr::Case(case_expr) = expr {
+                        if let ast::ExprKind::Case(case_expr) = &expr.kind {
                             if default.is_none() {
                                 cases.push((self.compile_ast_expr(case_expr, locals), body.len()));
                             }
-                        } else if let ast::Expr::Default = expr {
+                        } else if let ast::ExprKind::Default = expr.kind {
                             if default.is_none() {
                                 default = Some(body.len());
                             }
@@ -225,7 +278,7 @@ impl Compiler {
             },
 
             // "do-until" block
-            ast::Expr::DoUntil(while_expr) => {
+            ast::ExprKind::DoUntil(while_expr) => {
                 let cond = self.compile_ast_expr(&while_expr.cond, locals);
                 let mut body = Vec::new();
                 self.compile_ast_line(&while_expr.body, &mut body, locals);
@@ -233,18 +286,18 @@ impl Compiler {
             },
 
             // "var" declaration
-            ast::Expr::Var(var_expr) => {
+            ast::ExprKind::Var(var_expr) => {
                 locals.extend_from_slice(&var_expr.vars);
             },
 
-            ast::Expr::GlobalVar(globalvar_expr) => {
+            ast::ExprKind::GlobalVar(globalvar_expr) => {
                 // globalvar doesn't work on builtins
                 let fields = globalvar_expr.vars.iter().map(|x| self.get_field_id(x)).collect();
                 output.push(Instruction::GlobalVar { fields });
             },
 
             // "while" block
-            ast::Expr::While(while_expr) => {
+            ast::ExprKind::While(while_expr) => {
                 let cond = self.compile_ast_expr(&while_expr.cond, locals);
                 let mut body = Vec::new();
                 self.compile_ast_line(&while_expr.body, &mut body, locals);
@@ -252,13 +305,19 @@ impl Compiler {
             },
 
             // "with" block
-            ast::Expr::With(with_expr) => {
+            ast::ExprKind::With(with_expr) => {
                 let target = self.compile_ast_expr(&with_expr.target, locals);
                 let mut body = Vec::new();
                 self.compile_ast_line(&with_expr.body, &mut body, locals);
                 output.push(Instruction::With { target, body: body.into_boxed_slice() });
             },
 
+            // Line of code tagged with its position, which is kept so that runtime errors can point at it
+            ast::ExprKind::Line(line_expr) => {
+                output.push(Instruction::Line { pos: line_expr.pos });
+                self.compile_ast_line(&line_expr.expr, output, locals);
+            },
+
             // Unknown/invalid AST
             _ => {
                 output.push(Instruction::RuntimeError { error: gml::Error::UnexpectedASTExpr(line.to_string()) });
@@ -268,16 +327,16 @@ impl Compiler {
 
     /// Compile an AST expression into a Node.
     fn compile_ast_expr(&mut self, expr: &ast::Expr, locals: &[&[u8]]) -> Node {
-        match expr {
-            ast::Expr::LiteralReal(real) => Node::Literal { value: Value::Real(Real::from(*real)) },
+        match &expr.kind {
+            ast::ExprKind::LiteralReal(real) => Node::Literal { value: Value::Real(Real::from(*real)) },
 
-            ast::Expr::LiteralString(string) => Node::Literal { value: Value::Str((*string).into()) },
+            ast::ExprKind::LiteralString(string) => Node::Literal { value: Value::Str((*string).into()) },
 
-            ast::Expr::LiteralIdentifier(string) => {
+            ast::ExprKind::LiteralIdentifier(string) => {
                 if let Some(entry) = self.constants.get(*string) {
                     Node::Literal { value: entry.clone() }
                 } else if let Some(constant_id) = self.user_constant_names.get(*string) {
-                    Node::Constant { constant_id: *constant_id }
+                    self.fold(Node::Constant { constant_id: *constant_id })
                 } else if let Some(&v) = str::from_utf8(string).ok().and_then(|n| mappings::CONSTANTS.get(n)) {
                     Node::Literal { value: Value::Real(Real::from(v)) }
                 } else {
@@ -285,29 +344,29 @@ impl Compiler {
                 }
             },
 
-            ast::Expr::Binary(binary_expr) => match &binary_expr.op {
-                Operator::Deref => match &binary_expr.right {
-                    ast::Expr::LiteralIdentifier(var_name) => {
+            ast::ExprKind::Binary(binary_expr) => match &binary_expr.op {
+                Operator::Deref => match &binary_expr.right.kind {
+                    ast::ExprKind::LiteralIdentifier(var_name) => {
                         let owner = self.make_instance_identifier(&binary_expr.left, locals);
                         self.identifier_to_variable(var_name, Some(owner), ArrayAccessor::None, locals)
                     },
                     _ => Node::RuntimeError { error: gml::Error::InvalidDeref(binary_expr.right.to_string()) },
                 },
 
-                Operator::Index => match &binary_expr.right {
-                    ast::Expr::Group(dimensions) => {
+                Operator::Index => match &binary_expr.right.kind {
+                    ast::ExprKind::Group(dimensions) => {
                         let accessor = match self.make_array_accessor(dimensions, locals) {
                             Ok(a) => a,
                             Err(e) => return Node::RuntimeError { error: gml::Error::TooManyArrayDimensions(e) },
                         };
-                        match &binary_expr.left {
-                            ast::Expr::LiteralIdentifier(string) => {
+                        match &binary_expr.left.kind {
+                            ast::ExprKind::LiteralIdentifier(string) => {
                                 self.identifier_to_variable(string, None, accessor, locals)
                             },
-                            ast::Expr::Binary(binary_expr) => {
+                            ast::ExprKind::Binary(binary_expr) => {
                                 if let ast::BinaryExpr {
                                     left,
-                                    right: ast::Expr::LiteralIdentifier(i),
+                                    right: ast::Expr { kind: ast::ExprKind::LiteralIdentifier(i), .. },
                                     op: Operator::Deref,
                                 } = binary_expr.as_ref()
                                 {
@@ -355,24 +414,16 @@ impl Compiler {
                     let left = self.compile_ast_expr(&binary_expr.left, locals);
                     let right = self.compile_ast_expr(&binary_expr.right, locals);
 
-                    match (left, right) {
-                        (Node::Literal { value: lhs @ _ }, Node::Literal { value: rhs @ _ }) => {
-                            match op_function.call(lhs, rhs) {
-                                Ok(value) => Node::Literal { value },
-                                Err(error) => Node::RuntimeError { error },
-                            }
-                        },
-                        (left, right) => Node::Binary {
-                            left: Box::new(left),
-                            right: Box::new(right),
-                            operator: op_function,
-                            type_unsafe: false,
-                        },
-                    }
+                    self.fold(Node::Binary {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        operator: op_function,
+                        type_unsafe: false,
+                    })
                 },
             },
 
-            ast::Expr::Function(function) => {
+            ast::ExprKind::Function(function) => {
                 let args = function
                     .params
                     .iter()
@@ -392,7 +443,9 @@ impl Compiler {
                         gml::Function::Engine(f) => Node::StateFunction { args, function: gml::FunctionPtr(*f) },
                         gml::Function::Volatile(f) |
                         gml::Function::Constant(f) => Node::RoutineFunction { args, function: gml::FunctionPtr(*f) },
-                        gml::Function::Pure(f) => Node::ValueFunction { args, function: gml::FunctionPtr(*f) },
+                        gml::Function::Pure(f) => {
+                            self.fold(Node::ValueFunction { args, function: gml::FunctionPtr(*f) })
+                        },
                     }
                 } else {
                     Node::RuntimeError {
@@ -401,7 +454,7 @@ impl Compiler {
                 }
             },
 
-            ast::Expr::Unary(unary_expr) => {
+            ast::ExprKind::Unary(unary_expr) => {
                 let new_node = self.compile_ast_expr(&unary_expr.child, locals);
                 let operator = match unary_expr.op {
                     Operator::Add => return new_node,
@@ -410,24 +463,63 @@ impl Compiler {
                     Operator::Complement => UnaryOperator::Complement,
                     _ => return Node::RuntimeError { error: gml::Error::InvalidUnaryOperator(unary_expr.op) },
                 };
-                match new_node {
-                    Node::Literal { value } => match operator.call(value) {
-                        Ok(value) => Node::Literal { value },
-                        Err(error) => Node::RuntimeError { error },
-                    },
-                    node => Node::Unary { child: Box::new(node), operator },
-                }
+                self.fold(Node::Unary { child: Box::new(new_node), operator })
             },
 
             _ => Node::RuntimeError { error: gml::Error::UnexpectedASTExpr(expr.to_string()) },
         }
     }
 
+    /// Evaluates a node at compile time if it would give the same result every time it ran.
+    /// Its children must already have been folded. Anything that would fail becomes the same runtime error.
+    fn fold(&self, node: Node) -> Node {
+        let result = match node {
+            Node::Constant { constant_id } => match self.user_constant_values.get(&constant_id) {
+                Some(value) => return Node::Literal { value: value.clone() },
+                None => return node,
+            },
+            Node::Binary { left, right, operator, type_unsafe } => match (*left, *right) {
+                (Node::Literal { value: lhs }, Node::Literal { value: rhs }) if type_unsafe => {
+                    Ok(operator.call(lhs.clone(), rhs).unwrap_or(lhs))
+                },
+                (Node::Literal { value: lhs }, Node::Literal { value: rhs }) => operator.call(lhs, rhs),
+                (left, right) => {
+                    return Node::Binary { left: Box::new(left), right: Box::new(right), operator, type_unsafe }
+                },
+            },
+            Node::Unary { child, operator } => match *child {
+                Node::Literal { value } => operator.call(value),
+                child => return Node::Unary { child: Box::new(child), operator },
+            },
+            Node::ValueFunction { args, function } => {
+                let values = args
+                    .iter()
+                    .map(|arg| if let Node::Literal { value } = arg { Some(value.clone()) } else { None })
+                    .collect::<Option<Vec<_>>>();
+                match values {
+                    // Calls with more than 16 arguments don't work at runtime, so they're left alone
+                    Some(values) if values.len() <= 16 => function.0(&values),
+                    _ => return Node::ValueFunction { args, function },
+                }
+            },
+            node => return node,
+        };
+        match result {
+            Ok(value) => Node::Literal { value },
+            Err(error) => Node::RuntimeError { error },
+        }
+    }
+
     /// Searches for the fieldname id.
     pub fn find_field_id(&self, name: &[u8]) -> Option<usize> {
         self.fields.iter().position(|x| x.as_ref() == name)
     }
 
+    /// Gets how many fieldnames have been registered.
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+
     /// Gets the unique id of a fieldname, registering one if it doesn't already exist.
     pub fn get_field_id(&mut self, name: &[u8]) -> usize {
         if let Some(i) = self.find_field_id(name) {
@@ -459,16 +551,16 @@ impl Compiler {
         };
 
         let value = self.compile_ast_expr(&binary_expr.right, locals);
-        match &binary_expr.left {
-            ast::Expr::LiteralIdentifier(string) => {
+        match &binary_expr.left.kind {
+            ast::ExprKind::LiteralIdentifier(string) => {
                 if let Some(mod_type) = modification_type {
                     self.make_modify_instruction(string, None, ArrayAccessor::None, mod_type, value, locals)
                 } else {
                     self.make_set_instruction(string, None, ArrayAccessor::None, value, locals)
                 }
             },
-            ast::Expr::Binary(binary_expr) if binary_expr.op == Operator::Deref => {
-                if let ast::Expr::LiteralIdentifier(string) = binary_expr.right {
+            ast::ExprKind::Binary(binary_expr) if binary_expr.op == Operator::Deref => {
+                if let ast::ExprKind::LiteralIdentifier(string) = binary_expr.right.kind {
                     let owner = self.make_instance_identifier(&binary_expr.left, locals);
                     if let Some(mod_type) = modification_type {
                         self.make_modify_instruction(string, Some(owner), ArrayAccessor::None, mod_type, value, locals)
@@ -479,22 +571,22 @@ impl Compiler {
                     Instruction::RuntimeError { error: gml::Error::InvalidDeref(binary_expr.right.to_string()) }
                 }
             },
-            ast::Expr::Binary(binary_expr) if binary_expr.op == Operator::Index => {
-                if let ast::Expr::Group(dimensions) = &binary_expr.right {
+            ast::ExprKind::Binary(binary_expr) if binary_expr.op == Operator::Index => {
+                if let ast::ExprKind::Group(dimensions) = &binary_expr.right.kind {
                     let accessor = match self.make_array_accessor(dimensions, locals) {
                         Ok(a) => a,
                         Err(e) => return Instruction::RuntimeError { error: gml::Error::TooManyArrayDimensions(e) },
                     };
-                    match &binary_expr.left {
-                        ast::Expr::LiteralIdentifier(string) => {
+                    match &binary_expr.left.kind {
+                        ast::ExprKind::LiteralIdentifier(string) => {
                             if let Some(mod_type) = modification_type {
                                 self.make_modify_instruction(string, None, accessor, mod_type, value, locals)
                             } else {
                                 self.make_set_instruction(string, None, accessor, value, locals)
                             }
                         },
-                        ast::Expr::Binary(binary_expr) if binary_expr.op == Operator::Deref => {
-                            if let ast::Expr::LiteralIdentifier(string) = binary_expr.right {
+                        ast::ExprKind::Binary(binary_expr) if binary_expr.op == Operator::Deref => {
+                            if let ast::ExprKind::LiteralIdentifier(string) = binary_expr.right.kind {
                                 let owner = self.make_instance_identifier(&binary_expr.left, locals);
                                 if let Some(mod_type) = modification_type {
                                     self.make_modify_instruction(string, Some(owner), accessor, mod_type, value, locals)
@@ -656,3 +748,79 @@ impl Compiler {
         self.fields.get(id).map(|s| String::from_utf8_lossy(s).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gml::bytecode::Op;
+
+    fn compile(compiler: &mut Compiler, source: &str) -> Node {
+        compiler.compile_ast_expr(&ast::AST::expression(source.as_bytes()).unwrap(), &[])
+    }
+
+    fn folded(compiler: &mut Compiler, source: &str) -> Value {
+        match compile(compiler, source) {
+            Node::Literal { value } => value,
+            node => panic!("{} wasn't folded: {:?}", source, node),
+        }
+    }
+
+    fn fold(source: &str) -> Value {
+        folded(&mut Compiler::new(), source)
+    }
+
+    #[test]
+    fn fold_rounding() {
+        assert_eq!(fold("1 + 2 * 3"), Value::from(7));
+        assert_eq!(fold("5 / 2"), Value::from(2.5));
+        assert_eq!(fold("1 / 3"), Value::from(1.0 / 3.0));
+        // div floors, mod truncates
+        assert_eq!(fold("7 div 2"), Value::from(3));
+        assert_eq!(fold("-7 div 2"), Value::from(-4));
+        assert_eq!(fold("7.5 mod 2"), Value::from(1.5));
+        assert_eq!(fold("-7 mod 2"), Value::from(-1));
+        // Bitwise operators round their operands to even
+        assert_eq!(fold("2.5 | 0"), Value::from(2));
+        assert_eq!(fold("3.5 | 0"), Value::from(4));
+        assert_eq!(fold("1.4 << 2.6"), Value::from(8));
+        assert_eq!(fold("floor(-2.5) + 0.25"), Value::from(-2.75));
+    }
+
+    #[test]
+    fn fold_coercion() {
+        assert_eq!(fold("2 < 2.5"), Value::from(1));
+        assert_eq!(fold("0.4 && 1"), Value::from(0));
+        assert_eq!(fold("!0.4"), Value::from(1));
+        assert_eq!(fold(r#""ab" + "c""#), Value::from("abc"));
+        assert_eq!(fold(r#"3 * "ab""#), Value::from("ababab"));
+        assert_eq!(fold(r#""a" < "b""#), Value::from(1));
+    }
+
+    #[test]
+    fn fold_keeps_errors() {
+        // These have to fail when they run, not when they compile
+        for source in [r#""a" + 1"#, r#"1 - "a""#, r#""a" < 1"#, r#"-"a""#, r#""a" div "b""#] {
+            assert!(matches!(compile(&mut Compiler::new(), source), Node::RuntimeError { .. }), "{}", source);
+        }
+    }
+
+    #[test]
+    fn fold_user_constants_in_order() {
+        let mut compiler = Compiler::new();
+        for (index, name) in ["a", "b", "c"].into_iter().enumerate() {
+            compiler.register_user_constant(name.as_bytes().into(), index);
+        }
+        // a refers to b, which doesn't have a value yet when a gets evaluated
+        compiler.fold_user_constant(0, b"b + 1");
+        compiler.fold_user_constant(1, b"2");
+        compiler.fold_user_constant(2, b"b * 3");
+        assert!(matches!(compile(&mut compiler, "a"), Node::Constant { constant_id: 0 }));
+        assert_eq!(folded(&mut compiler, "b + c"), Value::from(8));
+
+        // So a still reads b before it's been set when it's evaluated at startup, like it did without folding
+        let uses_b = |program: Program| program.ops.iter().any(|op| matches!(op, Op::Constant(1)));
+        assert!(uses_b(compiler.compile_user_constant(0, b"b + 1").unwrap()));
+        assert!(!uses_b(compiler.compile_user_constant(2, b"b * 3").unwrap()));
+        assert!(!uses_b(compiler.compile_expression(b"b + 1").unwrap()));
+    }
+}
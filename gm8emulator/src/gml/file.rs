@@ -412,6 +412,102 @@ pub fn delete(path: &str) -> Result<()> {
     Ok(())
 }
 
+// Windows file attributes, same as the fa_* constants
+pub const FA_READONLY: u32 = 1;
+pub const FA_HIDDEN: u32 = 2;
+pub const FA_SYSFILE: u32 = 4;
+pub const FA_VOLUMEID: u32 = 8;
+pub const FA_DIRECTORY: u32 = 16;
+pub const FA_ARCHIVE: u32 = 32;
+
+/// Name of the file which stores attributes the host filesystem can't represent, one per directory.
+/// Each line is a set of attribute letters (as shown by `attrib`: R, H, S, A) followed by a tab and a file name.
+#[cfg(not(windows))]
+pub const ATTRIBUTE_STORE: &str = ".gm8attributes";
+
+/// Parses an attribute store, mapping lowercase file names to their attributes.
+#[cfg(not(windows))]
+fn parse_attribute_store(contents: &str) -> std::collections::HashMap<String, u32> {
+    contents
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(flags, name)| {
+            let attributes = flags.chars().fold(0, |attr, c| match c.to_ascii_uppercase() {
+                'R' => attr | FA_READONLY,
+                'H' => attr | FA_HIDDEN,
+                'S' => attr | FA_SYSFILE,
+                'A' => attr | FA_ARCHIVE,
+                _ => attr,
+            });
+            (name.to_lowercase(), attributes)
+        })
+        .collect()
+}
+
+/// Looks up the Windows attributes of files, reading each directory's attribute store only once.
+/// Keep one around while looking at many files, such as for file_find_first().
+#[derive(Default)]
+pub struct Attributes {
+    #[cfg(not(windows))]
+    stores: std::collections::HashMap<std::path::PathBuf, std::collections::HashMap<String, u32>>,
+}
+
+impl Attributes {
+    /// Gets the Windows attributes of a file or directory, or None if it doesn't exist.
+    #[cfg(windows)]
+    pub fn get(&mut self, path: &Path) -> Option<u32> {
+        use std::os::windows::fs::MetadataExt;
+        path.metadata().ok().map(|md| md.file_attributes() & 0x3f)
+    }
+
+    /// Gets the Windows attributes of a file or directory, or None if it doesn't exist.
+    /// Hidden means the name starts with a dot and read-only comes from the permissions,
+    /// unless the directory's attribute store says otherwise. Files are always archive by default, like on NTFS.
+    #[cfg(not(windows))]
+    pub fn get(&mut self, path: &Path) -> Option<u32> {
+        let md = path.metadata().ok()?;
+        let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase());
+        let directory = if md.is_dir() { FA_DIRECTORY } else { 0 };
+        let stored = name.as_ref().and_then(|name| {
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            let store = self.stores.entry(dir.to_path_buf()).or_insert_with(|| {
+                std::fs::read_to_string(dir.join(ATTRIBUTE_STORE))
+                    .map(|contents| parse_attribute_store(&contents))
+                    .unwrap_or_default()
+            });
+            store.get(name).copied()
+        });
+        Some(directory
+            | stored.unwrap_or_else(|| {
+                let hidden = name.filter(|n| n.starts_with('.') && n != "." && n != "..").map_or(0, |_| FA_HIDDEN);
+                let readonly = if !md.is_dir() && md.permissions().readonly() { FA_READONLY } else { 0 };
+                let archive = if md.is_dir() { 0 } else { FA_ARCHIVE };
+                hidden | readonly | archive
+            }))
+    }
+}
+
+/// Gets the Windows attributes of a single file or directory, or None if it doesn't exist.
+pub fn attributes(path: &Path) -> Option<u32> {
+    Attributes::default().get(path)
+}
+
+/// Sorts paths the way NTFS orders directory entries: by name, with each UTF-16 unit uppercased.
+pub fn sort_like_ntfs(paths: &mut [std::path::PathBuf]) {
+    paths.sort_by_cached_key(|path| {
+        path.to_string_lossy()
+            .encode_utf16()
+            .map(|unit| match char::from_u32(unit.into()).map(|c| c.to_uppercase()) {
+                Some(mut upper) if upper.len() == 1 => {
+                    let c = upper.next().unwrap();
+                    if c.len_utf16() == 1 { c as u16 } else { unit }
+                },
+                _ => unit,
+            })
+            .collect::<Vec<_>>()
+    });
+}
+
 pub fn load_image(path: &str) -> Result<RgbaImage> {
     Ok(image::open(path)?.into_rgba8())
 }
@@ -454,3 +550,26 @@ pub fn save_image<P: AsRef<Path>>(path: P, image: RgbaImage) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_store() {
+        let store = parse_attribute_store("RH\tSave.dat\nA\tlog.txt\nsx\tconfig.ini\n\tplain\nno tab here\n");
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.get("save.dat"), Some(&(FA_READONLY | FA_HIDDEN)));
+        assert_eq!(store.get("log.txt"), Some(&FA_ARCHIVE));
+        assert_eq!(store.get("config.ini"), Some(&FA_SYSFILE));
+        assert_eq!(store.get("plain"), Some(&0));
+        assert_eq!(store.get("Save.dat"), None);
+    }
+
+    #[test]
+    fn attribute_store_names() {
+        let store = parse_attribute_store("H\tName With Spaces.txt\nR\ttab\tin name\r\n");
+        assert_eq!(store.get("name with spaces.txt"), Some(&FA_HIDDEN));
+        assert_eq!(store.get("tab\tin name"), Some(&FA_READONLY));
+    }
+}
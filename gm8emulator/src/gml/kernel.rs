@@ -5,15 +5,18 @@
 use crate::{
     action, asset,
     game::{
-        draw, external, gm_save::GMSave, model, particle, pathfinding, platform, replay, surface::Surface,
-        transition::UserTransition, view::View, Game, GameClock, GetAsset, PlayType, SceneChange, Version,
+        dialog, draw, external, gm_save::GMSave, model, particle, pathfinding, platform, profiler, replay,
+        shell, surface::Surface, transition::UserTransition, view::View, Game, GameClock, GetAsset, PlayType,
+        SceneChange, Version,
     },
     gml::{
         self,
         datetime::{self, DateTime},
         ds, file,
         mappings::{self, constants as gml_consts},
-        network, Context, Value,
+        network,
+        trace::Source,
+        Context, Value,
     },
     handleman::HandleManager,
     input::MouseButton,
@@ -22,6 +25,7 @@ use crate::{
     render::{BlendType, Fog, Light, Renderer, Scaling},
     tile::Tile,
 };
+use clipboard::{ClipboardContext, ClipboardProvider};
 use image::RgbaImage;
 use ramen::window::Cursor;
 use std::{
@@ -103,7 +107,7 @@ impl Game {
                 gml::Error::FunctionError("display_get_width".into(), "getting display width failed".into())
             });
         }
-        Ok(1280.into())
+        Ok(self.display.width.into())
     }
 
     pub fn display_get_height(&self, args: &[Value]) -> gml::Result<Value> {
@@ -114,7 +118,7 @@ impl Game {
                 gml::Error::FunctionError("display_get_height".into(), "getting display height failed".into())
             });
         }
-        Ok(720.into())
+        Ok(self.display.height.into())
     }
 
     pub fn display_get_colordepth(&self, args: &[Value]) -> gml::Result<Value> {
@@ -125,7 +129,7 @@ impl Game {
                 gml::Error::FunctionError("display_get_colordepth".into(), "getting display colour depth failed".into())
             });
         }
-        Ok(32.into())
+        Ok(self.display.colour_depth.into())
     }
 
     pub fn display_get_frequency(&self, args: &[Value]) -> gml::Result<Value> {
@@ -136,51 +140,107 @@ impl Game {
                 gml::Error::FunctionError("display_get_frequency".into(), "getting display frequency failed".into())
             });
         }
-        Ok(60.into())
+        Ok(self.display.frequency.into())
+    }
+
+    /// Applies a display mode change with GM8's -1 convention, returning whether it worked.
+    /// Outside of normal play on Windows, this only ever changes the virtual display.
+    fn change_display(&mut self, width: i32, height: i32, frequency: i32, colour_depth: i32) -> bool {
+        match self.display.with(width, height, frequency, colour_depth) {
+            Some(display) => {
+                #[cfg(target_os = "windows")]
+                if self.play_type == PlayType::Normal
+                    && !platform::set_display_mode(
+                        display.width,
+                        display.height,
+                        display.frequency,
+                        display.colour_depth,
+                    )
+                {
+                    return false
+                }
+                self.display = display;
+                true
+            },
+            None => false,
+        }
     }
 
-    pub fn display_set_size(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function display_set_size")
+    pub fn display_set_size(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (width, height) = expect_args!(args, [int, int])?;
+        Ok(self.change_display(width, height, -1, -1).into())
     }
 
-    pub fn display_set_colordepth(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function display_set_colordepth")
+    pub fn display_set_colordepth(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let colour_depth = expect_args!(args, [int])?;
+        Ok(self.change_display(-1, -1, -1, colour_depth).into())
     }
 
-    pub fn display_set_frequency(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function display_set_frequency")
+    pub fn display_set_frequency(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let frequency = expect_args!(args, [int])?;
+        Ok(self.change_display(-1, -1, frequency, -1).into())
     }
 
-    pub fn display_set_all(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function display_set_all")
+    pub fn display_set_all(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (width, height, frequency, colour_depth) = expect_args!(args, [int, int, int, int])?;
+        Ok(self.change_display(width, height, frequency, colour_depth).into())
     }
 
-    pub fn display_test_all(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function display_test_all")
+    pub fn display_test_all(&self, args: &[Value]) -> gml::Result<Value> {
+        let (width, height, frequency, colour_depth) = expect_args!(args, [int, int, int, int])?;
+        Ok(match self.display.with(width, height, frequency, colour_depth) {
+            #[cfg(target_os = "windows")]
+            Some(display) if self.play_type == PlayType::Normal => {
+                platform::test_display_mode(display.width, display.height, display.frequency, display.colour_depth)
+            },
+            Some(_) => true,
+            None => false,
+        }
+        .into())
     }
 
-    pub fn display_reset(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        unimplemented!("Called unimplemented kernel function display_reset")
+    pub fn display_reset(&mut self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        #[cfg(target_os = "windows")]
+        if self.play_type == PlayType::Normal {
+            platform::reset_display_mode();
+        }
+        self.display = Default::default();
+        Ok(Default::default())
     }
 
-    pub fn display_mouse_get_x(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_mouse_get_x")
+    // NB: In record and replay mode, the cursor only exists in the virtual display, at the window's spoofed position.
+    pub fn display_mouse_get_x(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        #[cfg(target_os = "windows")]
+        if self.play_type == PlayType::Normal {
+            if let Some((x, _)) = platform::cursor_position() {
+                return Ok(x.into())
+            }
+        }
+        Ok((self.window_offset_spoof.0 + self.input.mouse_x()).into())
     }
 
-    pub fn display_mouse_get_y(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function display_mouse_get_y")
+    pub fn display_mouse_get_y(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        #[cfg(target_os = "windows")]
+        if self.play_type == PlayType::Normal {
+            if let Some((_, y)) = platform::cursor_position() {
+                return Ok(y.into())
+            }
+        }
+        Ok((self.window_offset_spoof.1 + self.input.mouse_y()).into())
     }
 
-    pub fn display_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function display_mouse_set")
+    pub fn display_mouse_set(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (x, y) = expect_args!(args, [int, int])?;
+        #[cfg(target_os = "windows")]
+        if self.play_type == PlayType::Normal {
+            platform::set_cursor_position(x, y);
+            return Ok(Default::default())
+        }
+        self.input.mouse_move_to((x - self.window_offset_spoof.0, y - self.window_offset_spoof.1));
+        Ok(Default::default())
     }
 
     pub fn window_set_visible(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -196,15 +256,18 @@ impl Game {
     }
 
     pub fn window_set_fullscreen(&mut self, args: &[Value]) -> gml::Result<Value> {
-        let _full = expect_args!(args, [bool])?;
-        // TODO
+        let full = expect_args!(args, [bool])?;
+        if full != self.window_fullscreen {
+            self.window_fullscreen = full;
+            self.update_window_fullscreen();
+        }
         Ok(Default::default())
     }
 
+    // NB: This function is constant because fullscreen state is tracked.
     pub fn window_get_fullscreen(&self, args: &[Value]) -> gml::Result<Value> {
         expect_args!(args, [])?;
-        // TODO
-        Ok(false.into())
+        Ok(self.window_fullscreen.into())
     }
 
     pub fn window_set_showborder(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -241,14 +304,16 @@ impl Game {
         Ok(self.window_icons.into())
     }
 
-    pub fn window_set_stayontop(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function window_set_stayontop")
+    pub fn window_set_stayontop(&mut self, args: &[Value]) -> gml::Result<Value> {
+        // ramen has no way of keeping a window on top, so this is only tracked
+        self.window_stayontop = expect_args!(args, [bool])?;
+        Ok(Default::default())
     }
 
-    pub fn window_get_stayontop(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_get_stayontop")
+    // NB: This function is constant because stay-on-top state is tracked.
+    pub fn window_get_stayontop(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        Ok(self.window_stayontop.into())
     }
 
     pub fn window_set_sizeable(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -350,19 +415,32 @@ impl Game {
         Ok(Default::default())
     }
 
-    pub fn window_set_rectangle(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 4
-        unimplemented!("Called unimplemented kernel function window_set_rectangle")
+    pub fn window_set_rectangle(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (x, y, width, height) = expect_args!(args, [int, int, int, int])?;
+        self.window_set_position(&[x.into(), y.into()])?;
+        self.window_set_size(&[width.into(), height.into()])
     }
 
-    pub fn window_center(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // TODO: make this do something!
+    pub fn window_center(&mut self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        let (width, height) = self.window_inner_size;
+        self.window_offset_spoof = self.display.center(width, height);
         Ok(Default::default())
     }
 
-    pub fn window_default(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_default")
+    pub fn window_default(&mut self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        let (width, height) = match self.scaling {
+            Scaling::Fixed(scale) => {
+                ((f64::from(self.unscaled_width) * scale) as u32, (f64::from(self.unscaled_height) * scale) as u32)
+            },
+            _ => (self.unscaled_width, self.unscaled_height),
+        };
+        self.window_inner_size = (width, height);
+        if self.play_type != PlayType::Record {
+            self.window.set_size((width as _, height as _));
+        }
+        self.window_center(&[])
     }
 
     pub fn window_get_x(&self, args: &[Value]) -> gml::Result<Value> {
@@ -385,12 +463,32 @@ impl Game {
         Ok(self.window_inner_size.1.into())
     }
 
-    pub fn window_set_region_size(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 3
-        // unscaled_width and unscaled_height will need to be separated into framebuffer size
-        // and window region size for this to work
-        // probably keep the framebuffer size on the renderer and make a getter?
-        unimplemented!("Called unimplemented kernel function window_set_region_size")
+    pub fn window_set_region_size(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (width, height, adapt_window) = expect_args!(args, [int, int, bool])?;
+        if width > 0 && height > 0 {
+            // The region is the framebuffer, so this lasts until the next room change resizes it again
+            let (width, height) = (width as u32, height as u32);
+            self.unscaled_width = width;
+            self.unscaled_height = height;
+            self.renderer.resize_framebuffer(width, height, false);
+
+            // With fixed scaling the window always fits the region exactly, otherwise only grow it if asked to
+            let (window_w, window_h) = self.window_inner_size;
+            let new_size = match self.scaling {
+                Scaling::Fixed(scale) => Some(((f64::from(width) * scale) as u32, (f64::from(height) * scale) as u32)),
+                _ if adapt_window && (width > window_w || height > window_h) => {
+                    Some((width.max(window_w), height.max(window_h)))
+                },
+                _ => None,
+            };
+            if let Some((window_w, window_h)) = new_size {
+                self.window_inner_size = (window_w, window_h);
+                if self.play_type != PlayType::Record {
+                    self.window.set_size((window_w as _, window_h as _));
+                }
+            }
+        }
+        Ok(Default::default())
     }
 
     pub fn window_get_region_width(&self, args: &[Value]) -> gml::Result<Value> {
@@ -446,39 +544,85 @@ impl Game {
         Ok(self.input.mouse_y().into())
     }
 
-    pub fn window_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function window_mouse_set")
+    pub fn window_mouse_set(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (x, y) = expect_args!(args, [int, int])?;
+        self.warp_mouse(x, y);
+        Ok(Default::default())
     }
 
-    pub fn window_view_mouse_get_x(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function window_view_mouse_get_x")
+    /// Moves the mouse to the given window coordinates.
+    /// In record and replay mode this only moves the virtual cursor, which the TAS UI carries over to the next frame.
+    fn warp_mouse(&mut self, x: i32, y: i32) {
+        #[cfg(target_os = "windows")]
+        if self.play_type == PlayType::Normal {
+            if let Some((x, y)) = platform::client_to_screen(self.window.hwnd(), x, y) {
+                platform::set_cursor_position(x, y);
+            }
+        }
+        self.input.mouse_move_to((x, y));
     }
 
-    pub fn window_view_mouse_get_y(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function window_view_mouse_get_y")
+    pub fn window_view_mouse_get_x(&self, args: &[Value]) -> gml::Result<Value> {
+        let view_id = expect_args!(args, [int])?;
+        let (x, y) = (self.input.mouse_x(), self.input.mouse_y());
+        Ok(match self.room.views.get(view_id as usize) {
+            Some(view) if view_id >= 0 => view.transform_point(x, y).0,
+            _ => x,
+        }
+        .into())
     }
 
-    pub fn window_view_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 3
-        unimplemented!("Called unimplemented kernel function window_view_mouse_set")
+    pub fn window_view_mouse_get_y(&self, args: &[Value]) -> gml::Result<Value> {
+        let view_id = expect_args!(args, [int])?;
+        let (x, y) = (self.input.mouse_x(), self.input.mouse_y());
+        Ok(match self.room.views.get(view_id as usize) {
+            Some(view) if view_id >= 0 => view.transform_point(x, y).1,
+            _ => y,
+        }
+        .into())
     }
 
-    pub fn window_views_mouse_get_x(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_views_mouse_get_x")
+    pub fn window_view_mouse_set(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (view_id, x, y) = expect_args!(args, [int, int, int])?;
+        let (x, y) = match self.room.views.get(view_id as usize) {
+            Some(view) if view_id >= 0 => view.untransform_point(x, y),
+            _ => (x, y),
+        };
+        self.warp_mouse(x, y);
+        Ok(Default::default())
     }
 
-    pub fn window_views_mouse_get_y(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function window_views_mouse_get_y")
+    pub fn window_views_mouse_get_x(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        Ok(self.get_mouse_in_room().0.into())
     }
 
-    pub fn window_views_mouse_set(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function window_views_mouse_set")
+    pub fn window_views_mouse_get_y(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        Ok(self.get_mouse_in_room().1.into())
+    }
+
+    pub fn window_views_mouse_set(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (x, y) = expect_args!(args, [int, int])?;
+        // Same view selection as translate_screen_to_room, based on where the mouse currently is
+        let (mouse_x, mouse_y) = (self.input.mouse_x(), self.input.mouse_y());
+        let (x, y) = if self.room.views_enabled {
+            match self
+                .room
+                .views
+                .iter()
+                .rev()
+                .find(|view| view.visible && view.contains_point(mouse_x, mouse_y))
+                .or_else(|| self.room.views.iter().find(|view| view.visible))
+            {
+                Some(view) => view.untransform_point(x, y),
+                None => (x, y),
+            }
+        } else {
+            (x, y)
+        };
+        self.warp_mouse(x, y);
+        Ok(Default::default())
     }
 
     pub fn set_synchronization(&mut self, args: &[Value]) -> gml::Result<Value> {
@@ -6268,43 +6412,37 @@ impl Game {
         }
         // unwrap arguments
         let path: &str = path.as_ref();
-        let include_read_only = (attribs & 1) != 0;
-        let include_hidden = (attribs & 2) != 0;
-        let include_sys_file = (attribs & 4) != 0;
-        let include_volume_id = (attribs & 8) != 0;
-        let include_directory = (attribs & 16) != 0;
-        let include_archive = (attribs & 32) != 0;
+        // Like Delphi's FindFirst, only hidden, system, volume ID and directory are ever filtered out.
+        // Everything else is always included, whatever the mask says about read-only and archive.
+        let filtered = file::FA_HIDDEN | file::FA_SYSFILE | file::FA_VOLUMEID | file::FA_DIRECTORY;
+        let excluded = !(attribs as u32) & filtered;
         match glob::glob_with(path, glob::MatchOptions { case_sensitive: false, ..Default::default() }) {
             Ok(paths) => {
-                // add . and .. to start if necessary
+                // add . and .. to start if necessary, they always come first on NTFS
                 let path: &std::path::Path = path.as_ref();
                 let preceding: Vec<std::path::PathBuf> = match path.file_name().and_then(|p| p.to_str()) {
+                    _ if excluded & file::FA_DIRECTORY != 0 => vec![],
                     Some("*") | Some(".*") | Some("*.") => vec![".".into(), "..".into()],
                     Some(".") => vec![".".into()],
                     Some("..") => vec!["..".into()],
                     _ => vec![],
                 };
+                let mut attributes = file::Attributes::default();
+                let mut found = paths
+                    .filter_map(Result::ok)
+                    .filter(|p| {
+                        #[cfg(not(windows))]
+                        if p.file_name().map_or(false, |name| name == file::ATTRIBUTE_STORE) {
+                            return false
+                        }
+                        attributes.get(p).map_or(false, |attr| attr & excluded == 0)
+                    })
+                    .collect::<Vec<_>>();
+                file::sort_like_ntfs(&mut found);
                 self.file_finder = Some(Box::new(
-                    preceding.into_iter().chain(
-                        paths
-                            .filter_map(Result::ok)
-                            .filter(move |p| {
-                                let md = match p.metadata() {
-                                    Ok(m) => m,
-                                    Err(_) => return false,
-                                };
-                                let is_dir = md.is_dir();
-                                // false means the check isn't in yet
-                                // also note: apparently directories are read only?
-                                (include_read_only || is_dir || !md.permissions().readonly())
-                                    && (include_hidden || !false)
-                                    && (include_sys_file || !false)
-                                    && (include_volume_id || !false)
-                                    && (include_directory || !is_dir)
-                                    && (include_archive || !false)
-                            })
-                            .map(|p| p.file_name().map(|p| p.into()).unwrap_or(p)),
-                    ),
+                    preceding
+                        .into_iter()
+                        .chain(found.into_iter().map(|p| p.file_name().map(|p| p.into()).unwrap_or(p))),
                 ));
                 self.file_find_next(&[])
             },
@@ -6329,9 +6467,12 @@ impl Game {
         Ok(Default::default())
     }
 
-    pub fn file_attributes(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function file_attributes")
+    pub fn file_attributes(&self, args: &[Value]) -> gml::Result<Value> {
+        let (path, attribs) = expect_args!(args, [string, int])?;
+        let attribs = attribs as u32;
+        let path = file::to_path(path.as_ref());
+        let path: &str = path.as_ref();
+        Ok(file::attributes(path.as_ref()).map_or(false, |attr| attr & attribs == attribs).into())
     }
 
     pub fn filename_name(args: &[Value]) -> gml::Result<Value> {
@@ -6428,64 +6569,79 @@ impl Game {
 
     pub fn execute_program(&mut self, args: &[Value]) -> gml::Result<Value> {
         let (prog, prog_args, wait) = expect_args!(args, [string, string, bool])?;
-        // Rust doesn't let you execute a program with just a string, so unescape it manually
-        let mut command_array = Vec::new();
-        let mut buf = Some(String::new());
-        let mut quote_count = 0;
-        for c in format!("{} {}", prog, prog_args).replace("\\\"", "\"\"\"").chars() {
-            match c {
-                '"' => {
-                    buf = buf.or(Some("".into()));
-                    quote_count += 1;
-                    if quote_count > 2 {
-                        quote_count = 0;
-                        buf.as_mut().unwrap().push('"');
-                    }
-                },
-                c if c.is_whitespace() && quote_count != 1 => {
-                    quote_count %= 2;
-                    if let Some(s) = buf {
-                        command_array.push(s);
-                        buf = None;
-                    }
-                },
-                c => {
-                    quote_count %= 2;
-                    buf = buf.or(Some("".into()));
-                    buf.as_mut().unwrap().push(c);
-                },
-            }
-        }
-        if let Some(s) = buf {
-            command_array.push(s);
-        }
+        let command_array = shell::split_command_line(&format!("{} {}", prog, prog_args));
         if command_array.is_empty() {
             return Err(gml::Error::FunctionError("execute_program".into(), "Cannot execute an empty string".into()))
         }
-        // Actually run the program
-        match Command::new(&command_array[0]).args(&command_array[1..]).spawn() {
-            Ok(mut child) => {
-                if wait {
-                    // wait() closes stdin. This is inaccurate, but Rust doesn't offer an alternative.
-                    if let Err(e) = child.wait() {
-                        return Err(gml::Error::FunctionError(
-                            "execute_program".into(),
-                            format!("Cannot wait for {}: {}", prog, e),
-                        ))
+        println!("execute_program: {:?} (wait: {}, policy: {:?})", command_array, wait, self.shell_policy);
+
+        // In replay mode, whether the game waited is taken from the recording, not from what happens on this machine.
+        // Replays from before this was recorded don't have the event, and those always did what the game asked.
+        let wait = match self.play_type {
+            PlayType::Replay => match self.stored_events.front() {
+                Some(replay::Event::ExecuteProgram(waited)) => {
+                    let waited = *waited;
+                    self.stored_events.pop_front();
+                    waited
+                },
+                _ => wait,
+            },
+            _ => wait,
+        };
+
+        match self.shell_policy {
+            shell::Policy::Allow => match Command::new(&command_array[0]).args(&command_array[1..]).spawn() {
+                Ok(mut child) => {
+                    if wait {
+                        // wait() closes stdin. This is inaccurate, but Rust doesn't offer an alternative.
+                        if let Err(e) = child.wait() {
+                            return Err(gml::Error::FunctionError(
+                                "execute_program".into(),
+                                format!("Cannot wait for {}: {}", prog, e),
+                            ))
+                        }
                     }
-                    self.process_window_events();
-                }
-                Ok(Default::default())
+                },
+                Err(e) => {
+                    return Err(gml::Error::FunctionError(
+                        "execute_program".into(),
+                        format!("Cannot execute {}: {}", prog, e),
+                    ))
+                },
             },
-            Err(e) => {
-                Err(gml::Error::FunctionError("execute_program".into(), format!("Cannot execute {}: {}", prog, e)))
+            shell::Policy::Deny => {
+                return Err(gml::Error::FunctionError(
+                    "execute_program".into(),
+                    format!("Cannot execute {}: denied by policy", prog),
+                ))
             },
+            shell::Policy::Emulate => (),
+        }
+        if wait {
+            self.process_window_events();
+        }
+        if self.play_type == PlayType::Record {
+            self.stored_events.push_back(replay::Event::ExecuteProgram(wait));
         }
+        Ok(Default::default())
     }
 
-    pub fn execute_shell(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function execute_shell")
+    pub fn execute_shell(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (prog, prog_args) = expect_args!(args, [string, string])?;
+        println!("execute_shell: {:?} {:?} (policy: {:?})", prog, prog_args, self.shell_policy);
+        match self.shell_policy {
+            shell::Policy::Allow => shell::execute(&prog, &prog_args).map_err(|e| {
+                gml::Error::FunctionError("execute_shell".into(), format!("Cannot execute {}: {}", prog, e))
+            })?,
+            shell::Policy::Deny => {
+                return Err(gml::Error::FunctionError(
+                    "execute_shell".into(),
+                    format!("Cannot execute {}: denied by policy", prog),
+                ))
+            },
+            shell::Policy::Emulate => shell::emulate(&prog),
+        }
+        Ok(Default::default())
     }
 
     pub fn parameter_count(&self, args: &[Value]) -> gml::Result<Value> {
@@ -7051,29 +7207,71 @@ impl Game {
         unimplemented!("Called unimplemented kernel function get_string")
     }
 
-    pub fn get_color(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function get_color")
+    /// Opens an emulated dialog and returns what it was closed with.
+    /// In record mode, the answer comes from those prepared in the TAS UI, since a frame can't be paused halfway.
+    fn open_dialog(&mut self, kind: dialog::Kind) -> gml::Result<Value> {
+        let answer = match self.play_type {
+            PlayType::Normal => self.run_dialog(kind.clone()),
+            PlayType::Record => match self.dialog_answers.pop_front() {
+                Some(answer) => answer,
+                None => {
+                    self.unanswered_dialog = Some(kind.clone());
+                    dialog::Answer::Cancelled
+                },
+            },
+            PlayType::Replay => {
+                return match self.stored_events.pop_front().and_then(|event| kind.from_event(event)) {
+                    Some(value) => Ok(value),
+                    None => Err(gml::Error::ReplayError(kind.function_name().into())),
+                }
+            },
+        };
+        let cancelled = if let dialog::Kind::Colour(_) = kind { Value::from(-1) } else { "".into() };
+        let value = match (answer, &kind) {
+            (dialog::Answer::Colour(col), dialog::Kind::Colour(_)) => col.into(),
+            (dialog::Answer::Path(path), dialog::Kind::OpenFile { .. })
+            | (dialog::Answer::Path(path), dialog::Kind::SaveFile { .. })
+            | (dialog::Answer::Path(path), dialog::Kind::Directory { .. }) => {
+                self.encode_str_maybe(&path).map(|path| Value::from(path.as_ref())).unwrap_or(cancelled)
+            },
+            _ => cancelled,
+        };
+        if self.play_type == PlayType::Record {
+            self.stored_events.push_back(kind.to_event(value.clone()));
+        }
+        Ok(value)
     }
 
-    pub fn get_open_filename(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_open_filename")
+    pub fn get_color(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let default = expect_args!(args, [int])?;
+        self.open_dialog(dialog::Kind::Colour(default))
     }
 
-    pub fn get_save_filename(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_save_filename")
+    pub fn get_open_filename(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (filter, default) = expect_args!(args, [bytes, bytes])?;
+        let filter = self.decode_str(filter.as_ref()).into_owned();
+        let default = self.decode_str(default.as_ref()).into_owned();
+        self.open_dialog(dialog::Kind::OpenFile { filter, default })
     }
 
-    pub fn get_directory(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function get_directory")
+    pub fn get_save_filename(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (filter, default) = expect_args!(args, [bytes, bytes])?;
+        let filter = self.decode_str(filter.as_ref()).into_owned();
+        let default = self.decode_str(default.as_ref()).into_owned();
+        self.open_dialog(dialog::Kind::SaveFile { filter, default })
     }
 
-    pub fn get_directory_alt(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 2
-        unimplemented!("Called unimplemented kernel function get_directory_alt")
+    pub fn get_directory(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let root = expect_args!(args, [bytes])?;
+        let root = self.decode_str(root.as_ref()).into_owned();
+        self.open_dialog(dialog::Kind::Directory { caption: String::new(), root })
+    }
+
+    pub fn get_directory_alt(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let (caption, root) = expect_args!(args, [bytes, bytes])?;
+        let caption = self.decode_str(caption.as_ref()).into_owned();
+        let root = self.decode_str(root.as_ref()).into_owned();
+        self.open_dialog(dialog::Kind::Directory { caption, root })
     }
 
     // NB: This function is constant because numlock state is tracked.
@@ -7706,10 +7904,10 @@ impl Game {
                     }
                     // Note: GM8 does not update the argument_count here to (args.len() - 1) as it should
                     let mut new_context = Context::copy_with_args(context, new_args, context.argument_count);
-                    self.execute(&instrs, &mut new_context)?;
+                    self.execute(&instrs, &mut new_context).map_err(|e| e.within(Source::ExecuteString))?;
                     Ok(new_context.return_value)
                 },
-                Err(e) => Err(gml::Error::FunctionError("execute_string".into(), e.message)),
+                Err(e) => Err(gml::Error::FunctionError("execute_string".into(), e.to_string())),
             }
         } else {
             // eg execute_string(42) - does nothing, returns 0
@@ -7906,19 +8104,38 @@ impl Game {
         self.variable_local_array_set(context, &[identifier, ((index1 * 32000) + index2).into(), value])
     }
 
-    pub fn clipboard_has_text(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function clipboard_has_text")
+    // NB: Outside of normal play, the clipboard functions only ever see the emulated clipboard.
+    // Text from the host clipboard can only get in there by being pasted from the TAS UI.
+    pub fn clipboard_has_text(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        if self.play_type == PlayType::Normal {
+            let text = ClipboardContext::new().ok().and_then(|mut ctx| ctx.get_contents().ok());
+            Ok(text.is_some().into())
+        } else {
+            Ok(self.clipboard.is_some().into())
+        }
     }
 
-    pub fn clipboard_set_text(&mut self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 1
-        unimplemented!("Called unimplemented kernel function clipboard_set_text")
+    pub fn clipboard_set_text(&mut self, args: &[Value]) -> gml::Result<Value> {
+        let text = expect_args!(args, [bytes])?;
+        if self.play_type == PlayType::Normal {
+            if let Ok(mut ctx) = ClipboardContext::new() {
+                ctx.set_contents(self.decode_str(text.as_ref()).into_owned()).ok();
+            }
+        } else {
+            self.clipboard = Some(text);
+        }
+        Ok(Default::default())
     }
 
-    pub fn clipboard_get_text(&self, _args: &[Value]) -> gml::Result<Value> {
-        // Expected arg count: 0
-        unimplemented!("Called unimplemented kernel function clipboard_get_text")
+    pub fn clipboard_get_text(&self, args: &[Value]) -> gml::Result<Value> {
+        expect_args!(args, [])?;
+        if self.play_type == PlayType::Normal {
+            let text = ClipboardContext::new().ok().and_then(|mut ctx| ctx.get_contents().ok()).unwrap_or_default();
+            Ok(self.encode_str_maybe(&text).map(|text| Value::from(text.as_ref())).unwrap_or_else(|| "".into()))
+        } else {
+            Ok(self.clipboard.clone().map(Value::from).unwrap_or_else(|| "".into()))
+        }
     }
 
     pub fn date_current_datetime(&self, args: &[Value]) -> gml::Result<Value> {
@@ -9357,7 +9574,12 @@ impl Game {
                     *dest = src.clone();
                 }
                 let mut new_context = Context::copy_with_args(context, new_args, args.len() - 1);
-                self.execute(&instructions, &mut new_context)?;
+                self.debug_enter(|game| game.script_source(script_id as usize));
+                self.profile_enter(profiler::Entry::Script(script_id as usize));
+                let result = self.execute(&instructions, &mut new_context);
+                self.profile_leave();
+                self.debug_leave();
+                result.map_err(|e| e.within(self.script_source(script_id as usize)))?;
                 Ok(new_context.return_value)
             } else {
                 Err(gml::Error::NonexistentAsset(asset::Type::Script, script_id))
@@ -9750,7 +9972,7 @@ impl Game {
             let instrs = self
                 .compiler
                 .compile(code.as_ref())
-                .map_err(|e| gml::Error::FunctionError("timeline_moment_add".into(), e.message))?;
+                .map_err(|e| gml::Error::FunctionError("timeline_moment_add".into(), e.to_string()))?;
 
             timeline.moments.borrow_mut().entry(moment).or_insert(Default::default()).borrow_mut().push_code(instrs);
         }
@@ -9971,7 +10193,7 @@ impl Game {
         if let Some(object) = self.assets.objects.get_asset_mut(object_index) {
             let instrs = match self.compiler.compile(code.as_ref()) {
                 Ok(instrs) => instrs,
-                Err(e) => return Err(gml::Error::FunctionError("object_event_add".into(), e.message)),
+                Err(e) => return Err(gml::Error::FunctionError("object_event_add".into(), e.to_string())),
             };
             let object_event_map = &mut object.events[ev_type as usize];
             match object_event_map.get_mut(&(ev_number as u32)) {
@@ -10129,7 +10351,7 @@ impl Game {
             persistent: false,
             bg_colour: 0xc0c0c0.into(),
             clear_screen: true,
-            creation_code: Ok(std::rc::Rc::new([])),
+            creation_code: Ok(Default::default()),
             backgrounds: vec![
                 crate::game::background::Background {
                     visible: false,
@@ -10216,7 +10438,7 @@ impl Game {
                 y,
                 object,
                 id: self.last_instance_id,
-                creation: Ok(std::rc::Rc::new([])),
+                creation: Ok(Default::default()),
                 xscale: 1.0,
                 yscale: 1.0,
                 blend: u32::MAX,
@@ -0,0 +1,216 @@
+//! Works out how complete each kernel function and variable is from the source of kernel.rs, mappings.rs and
+//! runtime.rs, for the linter and compatibility report. This is run by the build script, so it only uses std.
+
+use std::collections::{HashMap, HashSet};
+
+/// A kernel function's signature, after `pub fn `, and the trimmed non-empty lines of its body.
+type Method<'a> = (&'a str, Vec<&'a str>);
+
+/// Generates the FUNCTION_INFO and VARIABLE_STATUS maps included by mappings.rs.
+/// Fails if any mapped function is written in a way the scanner can't classify.
+pub fn kernel_info(kernel: &str, mappings: &str, runtime: &str) -> Result<String, String> {
+    let methods = kernel_methods(kernel)?;
+
+    let mut info = String::from("pub const FUNCTION_INFO: phf::Map<&'static str, FunctionInfo> = phf_map! {\n");
+    for line in mappings.lines() {
+        // "name" => Function::Kind(Game::method),
+        let mapping = line.trim().strip_prefix('"').and_then(|rest| rest.split_once("\" => Function::"));
+        let (name, method) = match mapping.and_then(|(name, rest)| Some((name, rest.split_once("(Game::")?.1))) {
+            Some((name, rest)) => (name, rest.trim_end_matches("),")),
+            None => continue,
+        };
+        let (signature, body) = methods
+            .get(method)
+            .ok_or_else(|| format!("{} is mapped to Game::{}, which isn't in kernel.rs", name, method))?;
+        let arg_count = match kernel_arg_count(body).map_err(|e| format!("Game::{}: {}", method, e))? {
+            Some(count) => format!("Some({})", count),
+            None => "None".into(),
+        };
+        info += &format!(
+            "    \"{}\" => FunctionInfo {{ status: Status::{}, arg_count: {} }},\n",
+            name,
+            kernel_status(signature, body),
+            arg_count,
+        );
+    }
+    info += "};\n\n";
+
+    // Instance variables whose getter or setter in runtime.rs panics, like `InstanceVariable::X => todo!(..),`
+    let mut unimplemented = HashSet::new();
+    for line in runtime.lines() {
+        if let Some((variables, body)) = line.trim().split_once(" => ") {
+            if body.starts_with("todo!(") || body.starts_with("unimplemented!(") {
+                let variables = variables.split('|').filter_map(|x| x.trim().strip_prefix("InstanceVariable::"));
+                unimplemented.extend(variables);
+            }
+        }
+    }
+    info += "pub const VARIABLE_STATUS: phf::Map<&'static str, Status> = phf_map! {\n";
+    for line in mappings.lines() {
+        // ("name", InstanceVariable::Variant),
+        let mapping = line.trim().strip_prefix("(\"").and_then(|rest| rest.split_once("\", InstanceVariable::"));
+        if let Some((name, variable)) = mapping {
+            let variable = variable.trim_end_matches("),");
+            let status = if unimplemented.contains(variable) { "Unimplemented" } else { "Implemented" };
+            info += &format!("    \"{}\" => Status::{},\n", name, status);
+        }
+    }
+    info += "};\n";
+    Ok(info)
+}
+
+/// Every `pub fn` in kernel.rs, by name. Each one has to have its signature on one line and end with `    }`.
+fn kernel_methods(kernel: &str) -> Result<HashMap<&str, Method<'_>>, String> {
+    let mut methods = HashMap::new();
+    let mut lines = kernel.lines();
+    while let Some(line) = lines.next() {
+        if let Some(signature) = line.strip_prefix("    pub fn ") {
+            let name = signature.split('(').next().unwrap_or_default();
+            if !signature.ends_with('{') {
+                return Err(format!("the signature of Game::{} isn't on one line", name))
+            }
+            let mut body = Vec::new();
+            loop {
+                match lines.next() {
+                    Some("    }") => break,
+                    Some(line) if !line.trim().is_empty() => body.push(line.trim()),
+                    Some(_) => (),
+                    None => return Err(format!("Game::{} doesn't end with a closing brace on its own line", name)),
+                }
+            }
+            methods.insert(name, (signature, body));
+        }
+    }
+    Ok(methods)
+}
+
+/// How much of a kernel function is there, judging by the conventions used in kernel.rs.
+fn kernel_status(signature: &str, body: &[&str]) -> &'static str {
+    let (comments, code): (Vec<&str>, Vec<&str>) = body.iter().partition(|line| line.starts_with("//"));
+    if code.iter().any(|line| line.contains("unimplemented!(") || line.contains("todo!(")) {
+        return "Unimplemented"
+    }
+
+    // A stub validates its arguments at most, then returns a constant without doing anything,
+    // and either ignores its arguments completely or has a note saying it needs work.
+    let does_nothing = code.iter().all(|line| {
+        let line = line.strip_prefix("let _").map_or(*line, |rest| rest.split_once(" = ").map_or(rest, |x| x.1));
+        (line.starts_with("expect_args!(") && line.ends_with(")?;"))
+            || (line.starts_with("Ok(") && !line.contains("self") && !line.contains("args"))
+    });
+    let flagged = signature.contains("_args: ")
+        || comments.iter().any(|line| ["TODO", "FIXME", "unimplemented"].iter().any(|x| line.contains(x)));
+    if does_nothing && flagged { "Stubbed" } else { "Implemented" }
+}
+
+/// How many arguments a kernel function takes, if it always takes the same number.
+/// That's taken from its `expect_args!` call, of which there can only be one, or else an `Expected arg count` comment.
+fn kernel_arg_count(body: &[&str]) -> Result<Option<usize>, String> {
+    let code = body.iter().filter(|line| !line.starts_with("//")).copied().collect::<Vec<_>>().join(" ");
+    let mut calls = code.match_indices("expect_args!(").map(|(i, _)| &code[i..]);
+    match (calls.next(), calls.next()) {
+        (Some(call), None) => match call.split_once('[').and_then(|(_, rest)| rest.split_once(']')) {
+            Some((list, _)) => Ok(Some(list.split(',').filter(|x| !x.trim().is_empty()).count())),
+            None => Err("expect_args! isn't given a list of argument types".into()),
+        },
+        (Some(_), Some(_)) => Err("expect_args! is called more than once".into()),
+        (None, _) => match body.iter().find_map(|line| line.strip_prefix("// Expected arg count: ")) {
+            Some(count) => count.trim().parse().map(Some).map_err(|_| format!("bad expected arg count {}", count)),
+            None => Ok(None),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(source: &str) -> &'static str {
+        let methods = kernel_methods(source).unwrap();
+        let (signature, body) = methods.values().next().unwrap();
+        kernel_status(signature, body)
+    }
+
+    fn arg_count(source: &str) -> Result<Option<usize>, String> {
+        kernel_arg_count(&kernel_methods(source).unwrap().values().next().unwrap().1)
+    }
+
+    #[test]
+    fn methods() {
+        let kernel = "impl Game {\n    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {\n        \
+            let x = 1;\n\n        Ok(x.into())\n    }\n\n    fn private() {\n    }\n}\n";
+        let methods = kernel_methods(kernel).unwrap();
+        assert_eq!(methods.len(), 1);
+        assert_eq!(methods["a"].1, ["let x = 1;", "Ok(x.into())"]);
+
+        assert!(kernel_methods("    pub fn a(\n        &mut self,\n    ) -> gml::Result<Value> {\n    }\n").is_err());
+        assert!(
+            kernel_methods("    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {\n        Ok(1)\n").is_err()
+        );
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(
+            status("    pub fn a(&mut self, _args: &[Value]) -> gml::Result<Value> {\n        Ok(Default::default())\n    }"),
+            "Stubbed",
+        );
+        assert_eq!(
+            status(
+                "    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {\n        \
+                let _x = expect_args!(args, [int])?;\n        // TODO\n        Ok(0.into())\n    }"
+            ),
+            "Stubbed",
+        );
+        assert_eq!(
+            status(
+                "    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {\n        \
+                unimplemented!(\"Called unimplemented kernel function a\")\n    }"
+            ),
+            "Unimplemented",
+        );
+        // Returning a constant is fine when nothing says it's wrong, and so is a note on a function that does things
+        assert_eq!(
+            status("    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {\n        Ok(0.into())\n    }"),
+            "Implemented",
+        );
+        assert_eq!(
+            status(
+                "    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {\n        // TODO: more precise\n        \
+                let x = expect_args!(args, [real])?;\n        Ok(x.sqrt().into())\n    }"
+            ),
+            "Implemented",
+        );
+    }
+
+    #[test]
+    fn arg_counts() {
+        let fun =
+            |body: &str| format!("    pub fn a(&mut self, args: &[Value]) -> gml::Result<Value> {{\n{}\n    }}", body);
+        assert_eq!(arg_count(&fun("let (a, b) = expect_args!(args, [int, any])?;")), Ok(Some(2)));
+        assert_eq!(arg_count(&fun("expect_args!(args, [])?;")), Ok(Some(0)));
+        assert_eq!(arg_count(&fun("let (a, b) = expect_args!(\nargs,\n[int,\nint,]\n)?;")), Ok(Some(2)));
+        assert_eq!(arg_count(&fun("// Expected arg count: 3\nOk(args.len().into())")), Ok(Some(3)));
+        assert_eq!(arg_count(&fun("Ok(args.len().into())")), Ok(None));
+        assert!(arg_count(&fun("let a = expect_args!(args, [int])?;\nlet b = expect_args!(args, [real])?;")).is_err());
+        assert!(arg_count(&fun("let a = expect_args!(args, TYPES)?;")).is_err());
+        assert!(arg_count(&fun("// Expected arg count: some")).is_err());
+    }
+
+    #[test]
+    fn info() {
+        let kernel = "    pub fn ok(&mut self, args: &[Value]) -> gml::Result<Value> {\n        \
+            let x = expect_args!(args, [int])?;\n        Ok(self.f(x))\n    }\n";
+        let mappings = "    \"good\" => Function::Volatile(Game::ok),\n    (\"x\", InstanceVariable::X),\n    \
+            (\"y\", InstanceVariable::Y),\n";
+        let runtime = "            InstanceVariable::Y => todo!(),\n";
+        assert_eq!(
+            kernel_info(kernel, mappings, runtime).unwrap(),
+            "pub const FUNCTION_INFO: phf::Map<&'static str, FunctionInfo> = phf_map! {\n    \
+            \"good\" => FunctionInfo { status: Status::Implemented, arg_count: Some(1) },\n};\n\n\
+            pub const VARIABLE_STATUS: phf::Map<&'static str, Status> = phf_map! {\n    \
+            \"x\" => Status::Implemented,\n    \"y\" => Status::Unimplemented,\n};\n",
+        );
+        assert!(kernel_info(kernel, "    \"bad\" => Function::Volatile(Game::missing),\n", runtime).is_err());
+    }
+}
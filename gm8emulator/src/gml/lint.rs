@@ -0,0 +1,658 @@
+//! Static checks over all of a game's GML, for finding problems in it without having to run it.
+
+use crate::{
+    action::{execution_type, kind},
+    gml::{
+        mappings::{self, Status},
+        trace::{self, Source},
+    },
+};
+use encoding_rs::Encoding;
+use gm8exe::{
+    asset::{CodeAction, Object, PascalString},
+    GameAssets,
+};
+use gml_parser::{
+    ast::{self, Expr, ExprKind},
+    lexer::Position,
+    token::Operator,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    str,
+};
+
+/// Where some GML is in a game.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub source: Source,
+
+    /// The index of the action in its event or timeline moment, if it's in one
+    pub action: Option<usize>,
+
+    /// The position of the line of code, if it's known
+    pub pos: Option<Position>,
+}
+
+/// A piece of GML from a game.
+#[derive(Clone, Copy)]
+pub enum Code<'a> {
+    /// Lines of code, such as a script
+    Lines(&'a [u8]),
+
+    /// A single expression, such as an action's argument
+    Expression(&'a [u8]),
+
+    /// A drag-n-drop action which calls a kernel function by name
+    Function(&'a [u8]),
+}
+
+/// Something which looks wrong in a game's code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    SyntaxError(String),
+    UninitializedLocal(String),
+    UnassignedField(String),
+    UnknownFunction(String),
+    WrongArgumentCount { function: String, expected: usize, given: usize },
+    UnreachableCode,
+    WithNonexistentObject(i32),
+    WithNonObject { name: String, kind: &'static str },
+    UnimplementedFunction(String),
+    StubbedFunction(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub problem: Problem,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(index) = self.action {
+            write!(f, "{} of ", Source::Action(index))?;
+        }
+        write!(f, "{}", self.source)?;
+        if let Some(pos) = self.pos {
+            write!(f, " at line {}, position {}", pos.line, pos.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SyntaxError(message) => write!(f, "syntax error: {}", message),
+            Self::UninitializedLocal(name) => write!(f, "local variable {} is read before it's assigned", name),
+            Self::UnassignedField(name) => write!(f, "variable {} is read, but never assigned anywhere", name),
+            Self::UnknownFunction(name) => write!(f, "unknown function or script {}", name),
+            Self::WrongArgumentCount { function, expected, given } => {
+                write!(f, "{} takes {} arguments, but is given {}", function, expected, given)
+            },
+            Self::UnreachableCode => write!(f, "unreachable code"),
+            Self::WithNonexistentObject(id) => write!(f, "with statement on object {}, which doesn't exist", id),
+            Self::WithNonObject { name, kind } => {
+                write!(f, "with statement on {} {}, which isn't an object", kind, name)
+            },
+            Self::UnimplementedFunction(name) => write!(f, "{} isn't implemented in the emulator yet", name),
+            Self::StubbedFunction(name) => write!(f, "{} is only stubbed in the emulator", name),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.problem)
+    }
+}
+
+/// Calls `f` with every piece of GML in a game, along with where it is.
+/// Names are decoded with the given encoding, which should be UTF-8 for GM8.1 games.
+pub fn for_each_code(assets: &GameAssets, encoding: &'static Encoding, mut f: impl FnMut(&Location, Code)) {
+    fn actions(source: Source, list: &[CodeAction], f: &mut impl FnMut(&Location, Code)) {
+        for (i, action) in list.iter().enumerate() {
+            let location = Location { source: source.clone(), action: Some(i), pos: None };
+            // Arguments of type 1 and 2 are strings, everything else is an expression
+            let args = action.param_strings.iter().zip(action.param_types.iter()).take(action.param_count);
+            let expressions = args.filter(|(_, t)| !matches!(**t, 1 | 2)).map(|(x, _)| x.0.as_ref());
+            match action.action_kind {
+                kind::NORMAL => match action.execution_type {
+                    execution_type::NONE => (),
+                    execution_type::FUNCTION => {
+                        expressions.for_each(|x| f(&location, Code::Expression(x)));
+                        f(&location, Code::Function(&action.fn_name.0));
+                    },
+                    _ => {
+                        expressions.for_each(|x| f(&location, Code::Expression(x)));
+                        f(&location, Code::Lines(&action.fn_code.0));
+                    },
+                },
+                kind::REPEAT => f(&location, Code::Expression(&action.param_strings[0].0)),
+                kind::VARIABLE => {
+                    let mut code = action.param_strings[0].0.to_vec();
+                    code.extend_from_slice(if action.is_relative { b"+=" } else { b"=" });
+                    code.extend_from_slice(&action.param_strings[1].0);
+                    f(&location, Code::Lines(&code));
+                },
+                kind::CODE => f(&location, Code::Lines(&action.param_strings[0].0)),
+                _ => (),
+            }
+        }
+    }
+    let name = |x: &PascalString| encoding.decode_without_bom_handling(&x.0).0.into_owned();
+    let at = |source| Location { source, action: None, pos: None };
+
+    for constant in assets.constants.iter() {
+        f(&at(Source::Constant(name(&constant.name))), Code::Expression(&constant.expression.0));
+    }
+    for trigger in assets.triggers.iter().flatten() {
+        f(&at(Source::Trigger(name(&trigger.name))), Code::Lines(&trigger.condition.0));
+    }
+    for script in assets.scripts.iter().flatten() {
+        f(&at(Source::Script(name(&script.name))), Code::Lines(&script.source.0));
+    }
+    for object in assets.objects.iter().flatten() {
+        for (event_type, events) in object.events.iter().enumerate() {
+            for (event_number, list) in events.iter() {
+                let event = trace::event_name(
+                    event_type,
+                    *event_number,
+                    |id| assets.objects.get(id as usize).and_then(Option::as_ref).map(|x| name(&x.name)),
+                    |id| assets.triggers.get(id as usize).and_then(Option::as_ref).map(|x| name(&x.name)),
+                );
+                actions(Source::Event { object: name(&object.name), event }, list, &mut f);
+            }
+        }
+    }
+    for timeline in assets.timelines.iter().flatten() {
+        for (moment, list) in timeline.moments.iter() {
+            actions(Source::Timeline { timeline: name(&timeline.name), moment: *moment as i32 }, list, &mut f);
+        }
+    }
+    for room in assets.rooms.iter().flatten() {
+        f(&at(Source::RoomCreation(name(&room.name))), Code::Lines(&room.creation_code.0));
+        for instance in room.instances.iter() {
+            f(&at(Source::InstanceCreation(instance.id)), Code::Lines(&instance.creation_code.0));
+        }
+    }
+}
+
+/// Checks all of a game's code for things which are probably mistakes, or which the emulator can't run properly.
+pub fn lint(assets: &GameAssets, encoding: &'static Encoding) -> Vec<Diagnostic> {
+    let mut linter = Linter::new(assets, encoding);
+
+    // Any field could be assigned from anywhere, so all assignments have to be found before checking any reads
+    linter.collecting = true;
+    for_each_code(assets, encoding, |location, code| linter.check(location, code));
+    linter.collecting = false;
+    for_each_code(assets, encoding, |location, code| linter.check(location, code));
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    objects: &'a [Option<Box<Object>>],
+    encoding: &'static Encoding,
+
+    /// Asset names, which are constants, along with what kind of asset they are
+    asset_names: HashMap<&'a [u8], &'static str>,
+    user_constants: HashSet<&'a [u8]>,
+    scripts: HashSet<&'a [u8]>,
+
+    /// Extension function names along with how many arguments they take, or -1 if it varies
+    extension_functions: HashMap<&'a [u8], i32>,
+
+    /// Names of all the fields which get assigned anywhere in the game
+    assigned: HashSet<Box<[u8]>>,
+
+    /// Whether this is the first pass, which only looks for assignments
+    collecting: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// What's known while checking a single piece of code.
+struct Scope<'b> {
+    location: Location,
+    locals: Vec<&'b [u8]>,
+    initialized: HashSet<&'b [u8]>,
+
+    /// Variables which have already been reported here, so each one only comes up once per piece of code
+    reported: HashSet<&'b [u8]>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(assets: &'a GameAssets, encoding: &'static Encoding) -> Self {
+        fn names<'a, T>(
+            out: &mut HashMap<&'a [u8], &'static str>,
+            assets: &'a [Option<Box<T>>],
+            kind: &'static str,
+            name: fn(&T) -> &PascalString,
+        ) {
+            for asset in assets.iter().flatten() {
+                out.entry(name(asset).0.as_ref()).or_insert(kind);
+            }
+        }
+
+        // Same order as the compiler registers them in, so the same asset wins when names clash
+        let mut asset_names = HashMap::new();
+        names(&mut asset_names, &assets.objects, "object", |x| &x.name);
+        names(&mut asset_names, &assets.sprites, "sprite", |x| &x.name);
+        names(&mut asset_names, &assets.sounds, "sound", |x| &x.name);
+        names(&mut asset_names, &assets.backgrounds, "background", |x| &x.name);
+        names(&mut asset_names, &assets.paths, "path", |x| &x.name);
+        names(&mut asset_names, &assets.fonts, "font", |x| &x.name);
+        names(&mut asset_names, &assets.timelines, "timeline", |x| &x.name);
+        names(&mut asset_names, &assets.scripts, "script", |x| &x.name);
+        names(&mut asset_names, &assets.rooms, "room", |x| &x.name);
+        names(&mut asset_names, &assets.triggers, "trigger", |x| &x.constant_name);
+
+        let files = assets.extensions.iter().flat_map(|x| x.files.iter());
+        let mut user_constants: HashSet<&[u8]> =
+            files.clone().flat_map(|x| x.consts.iter()).map(|x| x.name.0.as_ref()).collect();
+        user_constants.extend(assets.constants.iter().map(|x| x.name.0.as_ref()));
+
+        Self {
+            objects: &assets.objects,
+            encoding,
+            asset_names,
+            user_constants,
+            scripts: assets.scripts.iter().flatten().map(|x| x.name.0.as_ref()).collect(),
+            extension_functions: files
+                .flat_map(|x| x.functions.iter())
+                .map(|x| (x.name.0.as_ref(), x.arg_count))
+                .collect(),
+            assigned: HashSet::new(),
+            collecting: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn check(&mut self, location: &Location, code: Code) {
+        let mut scope = Scope {
+            location: location.clone(),
+            locals: Vec::new(),
+            initialized: HashSet::new(),
+            reported: HashSet::new(),
+        };
+        match code {
+            Code::Lines(source) => {
+                // Whatever parsed around a syntax error isn't checked, since it's missing the lines with errors
+                let (ast, errors) = ast::AST::with_errors(source);
+                if errors.is_empty() {
+                    self.block(&ast, &mut scope);
+                }
+                for e in errors {
+                    scope.location.pos = e.pos;
+                    self.report(&scope, Problem::SyntaxError(e.message));
+                }
+            },
+            Code::Expression(source) => match ast::AST::expression(source) {
+                Ok(expr) => self.expression(&expr, &mut scope),
+                Err(e) => {
+                    scope.location.pos = e.pos;
+                    self.report(&scope, Problem::SyntaxError(e.message));
+                },
+            },
+            Code::Function(name) => self.call(name, None, &scope),
+        }
+    }
+
+    fn report(&mut self, scope: &Scope, problem: Problem) {
+        if !self.collecting {
+            self.diagnostics.push(Diagnostic { location: scope.location.clone(), problem });
+        }
+    }
+
+    fn decode(&self, name: &[u8]) -> String {
+        self.encoding.decode_without_bom_handling(name).0.into_owned()
+    }
+
+    /// Checks a list of lines which run one after another.
+    fn block<'b>(&mut self, lines: &'b [Expr<'b>], scope: &mut Scope<'b>) {
+        let (mut unreachable, mut reported) = (false, false);
+        for line in lines {
+            let expr = unwrap_line(line);
+            if matches!(expr.kind, ExprKind::Case(_) | ExprKind::Default) {
+                // Switch labels can be jumped to, so code after them is reachable again
+                unreachable = false;
+                reported = false;
+            } else if unreachable && !reported {
+                if let ExprKind::Line(line) = &line.kind {
+                    scope.location.pos = Some(line.pos);
+                }
+                self.report(scope, Problem::UnreachableCode);
+                reported = true;
+            }
+            self.statement(line, scope);
+            unreachable |=
+                matches!(expr.kind, ExprKind::Exit | ExprKind::Return(_) | ExprKind::Break | ExprKind::Continue);
+        }
+    }
+
+    fn statement<'b>(&mut self, expr: &'b Expr<'b>, scope: &mut Scope<'b>) {
+        match &expr.kind {
+            ExprKind::Line(line) => {
+                scope.location.pos = Some(line.pos);
+                self.statement(&line.expr, scope);
+            },
+            ExprKind::Binary(binary) => {
+                if binary.op != Operator::Assign {
+                    // Modifying a variable reads it first
+                    self.expression(&binary.left, scope);
+                }
+                self.expression(&binary.right, scope);
+                self.assign(&binary.left, scope);
+            },
+            ExprKind::Function(_) | ExprKind::Case(_) => self.expression(expr, scope),
+            ExprKind::Group(lines) => self.block(lines, scope),
+            ExprKind::If(if_expr) => {
+                self.expression(&if_expr.cond, scope);
+                self.statement(&if_expr.body, scope);
+                if let Some(else_body) = &if_expr.else_body {
+                    self.statement(else_body, scope);
+                }
+            },
+            ExprKind::For(for_expr) => {
+                self.statement(&for_expr.start, scope);
+                self.expression(&for_expr.cond, scope);
+                self.statement(&for_expr.body, scope);
+                self.statement(&for_expr.step, scope);
+            },
+            ExprKind::While(while_expr) => {
+                self.expression(&while_expr.cond, scope);
+                self.statement(&while_expr.body, scope);
+            },
+            ExprKind::DoUntil(do_until) => {
+                self.statement(&do_until.body, scope);
+                self.expression(&do_until.cond, scope);
+            },
+            ExprKind::Repeat(repeat) => {
+                self.expression(&repeat.count, scope);
+                self.statement(&repeat.body, scope);
+            },
+            ExprKind::Switch(switch) => {
+                self.expression(&switch.input, scope);
+                self.statement(&switch.body, scope);
+            },
+            ExprKind::With(with) => {
+                self.with_target(&with.target, scope);
+                self.expression(&with.target, scope);
+                self.statement(&with.body, scope);
+            },
+            ExprKind::Return(value) => self.expression(value, scope),
+            ExprKind::Var(var) => scope.locals.extend_from_slice(&var.vars),
+            ExprKind::GlobalVar(globalvar) => self.assigned.extend(globalvar.vars.iter().map(|&x| x.into())),
+            _ => (),
+        }
+    }
+
+    /// Checks the left-hand side of an assignment, and notes the variable as assigned.
+    fn assign<'b>(&mut self, target: &'b Expr<'b>, scope: &mut Scope<'b>) {
+        match &target.kind {
+            ExprKind::LiteralIdentifier(name) => {
+                if scope.locals.contains(name) {
+                    scope.initialized.insert(*name);
+                } else {
+                    self.assigned.insert((*name).into());
+                }
+            },
+            ExprKind::Binary(binary) if binary.op == Operator::Deref => {
+                self.expression(&binary.left, scope);
+                if let ExprKind::LiteralIdentifier(name) = binary.right.kind {
+                    self.assigned.insert(name.into());
+                }
+            },
+            ExprKind::Binary(binary) if binary.op == Operator::Index => {
+                self.expression(&binary.right, scope);
+                self.assign(&binary.left, scope);
+            },
+            _ => (),
+        }
+    }
+
+    fn expression<'b>(&mut self, expr: &'b Expr<'b>, scope: &mut Scope<'b>) {
+        match &expr.kind {
+            ExprKind::LiteralIdentifier(name) => self.read(*name, scope),
+            ExprKind::Unary(unary) => self.expression(&unary.child, scope),
+            ExprKind::Binary(binary) if binary.op == Operator::Deref => {
+                self.expression(&binary.left, scope);
+                if let ExprKind::LiteralIdentifier(name) = binary.right.kind {
+                    self.read_field(name, scope);
+                }
+            },
+            ExprKind::Binary(binary) => {
+                self.expression(&binary.left, scope);
+                self.expression(&binary.right, scope);
+            },
+            ExprKind::Function(call) => {
+                call.params.iter().for_each(|x| self.expression(x, scope));
+                // variable_global_set("name", value) and the like assign fields by name
+                let name = str::from_utf8(call.name).unwrap_or_default();
+                if name.starts_with("variable_") && name.ends_with("_set") {
+                    if let Some(ExprKind::LiteralString(field)) = call.params.first().map(|x| &x.kind) {
+                        self.assigned.insert((*field).into());
+                    }
+                }
+                self.call(call.name, Some(call.params.len()), scope);
+            },
+            ExprKind::Group(group) => group.iter().for_each(|x| self.expression(x, scope)),
+            ExprKind::Case(value) => self.expression(value, scope),
+            _ => (),
+        }
+    }
+
+    /// Checks an identifier being read, resolving it the same way the compiler does.
+    fn read<'b>(&mut self, name: &'b [u8], scope: &mut Scope<'b>) {
+        if self.asset_names.contains_key(name)
+            || self.user_constants.contains(name)
+            || str::from_utf8(name).map_or(false, |x| mappings::CONSTANTS.contains_key(x))
+        {
+            return
+        }
+        if scope.locals.contains(&name) {
+            if !scope.initialized.contains(name) && scope.reported.insert(name) {
+                self.report(scope, Problem::UninitializedLocal(self.decode(name)));
+            }
+        } else {
+            self.read_field(name, scope);
+        }
+    }
+
+    fn read_field<'b>(&mut self, name: &'b [u8], scope: &mut Scope<'b>) {
+        if self.collecting
+            || mappings::get_instance_variable_by_name(name).is_some()
+            || self.assigned.contains(name)
+            || !scope.reported.insert(name)
+        {
+            return
+        }
+        self.report(scope, Problem::UnassignedField(self.decode(name)));
+    }
+
+    /// Checks a call to a function or script. `argc` is None for drag-n-drop actions, which pass their own arguments.
+    fn call(&mut self, name: &[u8], argc: Option<usize>, scope: &Scope) {
+        if self.collecting || self.scripts.contains(name) {
+            return
+        }
+        let function = self.decode(name);
+        let expected = if let Some(&arg_count) = self.extension_functions.get(name) {
+            usize::try_from(arg_count).ok()
+        } else if let Some(info) = mappings::FUNCTION_INFO.get(function.as_str()) {
+            match info.status {
+                Status::Implemented => (),
+                Status::Stubbed => self.report(scope, Problem::StubbedFunction(function.clone())),
+                Status::Unimplemented => self.report(scope, Problem::UnimplementedFunction(function.clone())),
+            }
+            info.arg_count
+        } else if mappings::FUNCTIONS.contains_key(function.as_str()) {
+            None
+        } else {
+            self.report(scope, Problem::UnknownFunction(function));
+            return
+        };
+        if let (Some(expected), Some(given)) = (expected, argc) {
+            if expected != given {
+                self.report(scope, Problem::WrongArgumentCount { function, expected, given });
+            }
+        }
+    }
+
+    /// Checks that the target of a `with` statement is an object, if it's something that can be known in advance.
+    fn with_target(&mut self, target: &Expr, scope: &Scope) {
+        let value = match &target.kind {
+            ExprKind::LiteralReal(value) => *value,
+            ExprKind::Unary(unary) => match (unary.op, &unary.child.kind) {
+                (Operator::Subtract, ExprKind::LiteralReal(value)) => -value,
+                _ => return,
+            },
+            ExprKind::LiteralIdentifier(name) => match self.asset_names.get(*name) {
+                Some(&"object") => return,
+                Some(&kind) => {
+                    let name = self.decode(name);
+                    self.report(scope, Problem::WithNonObject { name, kind });
+                    return
+                },
+                None if self.user_constants.contains(*name) => return,
+                None => match str::from_utf8(name).ok().and_then(|x| mappings::CONSTANTS.get(x)) {
+                    Some(value) => *value,
+                    None => return,
+                },
+            },
+            _ => return,
+        };
+
+        // Anything else in this range must be an object, since instance IDs start at 100001
+        let id = value.round() as i32;
+        if (0..=100000).contains(&id) && self.objects.get(id as usize).map_or(true, Option::is_none) {
+            self.report(scope, Problem::WithNonexistentObject(id));
+        }
+    }
+}
+
+fn unwrap_line<'b, 'c>(expr: &'c Expr<'b>) -> &'c Expr<'b> {
+    match &expr.kind {
+        ExprKind::Line(line) => unwrap_line(&line.expr),
+        _ => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linter for a game with no assets.
+    fn linter() -> Linter<'static> {
+        Linter {
+            objects: &[],
+            encoding: encoding_rs::UTF_8,
+            asset_names: HashMap::new(),
+            user_constants: HashSet::new(),
+            scripts: HashSet::new(),
+            extension_functions: HashMap::new(),
+            assigned: HashSet::new(),
+            collecting: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Lints each piece of code in order, the same way lint() goes over a whole game.
+    fn problems(mut linter: Linter, codes: &[Code]) -> Vec<Problem> {
+        let location = Location { source: Source::Script("test".into()), action: None, pos: None };
+        for collecting in [true, false] {
+            linter.collecting = collecting;
+            codes.iter().for_each(|code| linter.check(&location, *code));
+        }
+        linter.diagnostics.into_iter().map(|x| x.problem).collect()
+    }
+
+    fn lines(source: &str) -> Vec<Problem> {
+        problems(linter(), &[Code::Lines(source.as_bytes())])
+    }
+
+    /// The name of some kernel function with the given status.
+    fn function_with(status: Status) -> &'static str {
+        *mappings::FUNCTION_INFO.entries().find(|(_, info)| info.status == status).unwrap().0
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(matches!(lines("x = ;")[..], [Problem::SyntaxError(_)]));
+        assert!(matches!(problems(linter(), &[Code::Expression(b"1 +")])[..], [Problem::SyntaxError(_)]));
+    }
+
+    #[test]
+    fn locals() {
+        assert_eq!(lines("var a; x = a; y = a;"), [Problem::UninitializedLocal("a".into())]);
+        assert!(lines("var a; a = 1; x = a;").is_empty());
+        assert_eq!(lines("var a; a += 1;"), [Problem::UninitializedLocal("a".into())]);
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(lines("x = foo;"), [Problem::UnassignedField("foo".into())]);
+        assert_eq!(lines("x = other.foo;"), [Problem::UnassignedField("foo".into())]);
+        // Fields can be assigned anywhere, even after they're read
+        assert!(problems(linter(), &[Code::Lines(b"x = foo;"), Code::Lines(b"foo = 1;")]).is_empty());
+        assert!(lines("x = foo; global.foo = 1;").is_empty());
+        assert!(lines("globalvar foo; x = foo;").is_empty());
+        assert!(lines("variable_global_set(\"foo\", 1); x = foo;").is_empty());
+        assert!(lines("x = c_white + fa_left + y;").is_empty());
+
+        let mut constants = linter();
+        constants.asset_names.insert(b"spr_a", "sprite");
+        constants.user_constants.insert(b"SPEED");
+        assert!(problems(constants, &[Code::Lines(b"x = spr_a + SPEED;")]).is_empty());
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(lines("nope(1);"), [Problem::UnknownFunction("nope".into())]);
+        let mut script = linter();
+        script.scripts.insert(b"nope");
+        assert!(problems(script, &[Code::Lines(b"nope(1);")]).is_empty());
+
+        assert_eq!(
+            lines("instance_create(0, 0);"),
+            [Problem::WrongArgumentCount { function: "instance_create".into(), expected: 3, given: 2 }],
+        );
+        // Drag-n-drop actions pass their own arguments
+        assert!(problems(linter(), &[Code::Function(b"instance_create")]).is_empty());
+
+        let mut extension = linter();
+        extension.extension_functions.insert(b"ext_two", 2);
+        extension.extension_functions.insert(b"ext_any", -1);
+        assert_eq!(
+            problems(extension, &[Code::Lines(b"ext_two(1); ext_any(1, 2, 3);")]),
+            [Problem::WrongArgumentCount { function: "ext_two".into(), expected: 2, given: 1 }],
+        );
+
+        let stubbed = function_with(Status::Stubbed);
+        assert!(lines(&format!("{}();", stubbed)).contains(&Problem::StubbedFunction(stubbed.into())));
+        let unimplemented = function_with(Status::Unimplemented);
+        let problem = Problem::UnimplementedFunction(unimplemented.into());
+        assert!(lines(&format!("{}();", unimplemented)).contains(&problem));
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(lines("exit; x = 1; y = 2;"), [Problem::UnreachableCode]);
+        assert!(lines("if (x) exit; y = 1;").is_empty());
+        assert!(lines("switch (x) { case 1: exit; case 2: y = 1; break; default: y = 2; }").is_empty());
+        assert_eq!(lines("while (x) { break; y = 1; }"), [Problem::UnreachableCode]);
+    }
+
+    #[test]
+    fn with_targets() {
+        assert_eq!(lines("with (3) x = 1;"), [Problem::WithNonexistentObject(3)]);
+        assert!(lines("with (other) x = 1; with (all) x = 1; with (100001) x = 1;").is_empty());
+
+        let mut sprite = linter();
+        sprite.asset_names.insert(b"spr_a", "sprite");
+        assert_eq!(
+            problems(sprite, &[Code::Lines(b"with (spr_a) x = 1;")]),
+            [Problem::WithNonObject { name: "spr_a".into(), kind: "sprite" }],
+        );
+    }
+}
@@ -2144,3 +2144,26 @@ pub fn find_function_by_address<T>(func: &gml::FunctionPtr<T>) -> Option<(usize,
         .find(|(_, (_, &v))| v.addr() == std::ptr::from_ref(&func.0) as *const ())
         .map(|(i, (&k, _))| (i, k))
 }
+
+/// How much of a kernel function the emulator has, as worked out from kernel.rs at build time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Implemented,
+
+    // Does nothing and returns a dummy value, but doesn't crash
+    Stubbed,
+
+    // Panics when called
+    Unimplemented,
+}
+
+pub struct FunctionInfo {
+    pub status: Status,
+
+    // None if the function takes a variable number of arguments
+    pub arg_count: Option<usize>,
+}
+
+// FUNCTION_INFO: mappings of GM function names to FunctionInfo
+// VARIABLE_STATUS: mappings of GM instance variable names to their Status
+include!(concat!(env!("OUT_DIR"), "/kernel_info.rs"));
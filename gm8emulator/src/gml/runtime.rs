@@ -1,16 +1,19 @@
 use crate::{
     asset,
-    game::{Game, GameClock, GetAsset, SceneChange, Version},
+    game::{profiler, Game, GameClock, GetAsset, SceneChange, Version},
     gml::{
         self,
+        bytecode::{Op, Owner, Program},
         datetime::DateTime,
         mappings::{self, constants as gml_constants},
+        trace::Trace,
         Context, InstanceVariable, Value,
     },
     instance::Field,
+    instancelist::{ILIterDrawOrder, IdentityIter, InstanceList},
     math::Real,
 };
-use gml_parser::token::Operator;
+use gml_parser::{lexer::Position, token::Operator};
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Display},
@@ -36,6 +39,7 @@ pub enum Instruction {
     With { target: Node, body: Box<[Instruction]> },
     GlobalVar { fields: Vec<usize> },
     RuntimeError { error: Error },
+    Line { pos: Position },
 }
 
 /// Node representing one value in an expression.
@@ -181,6 +185,7 @@ pub enum Error {
     BadDirectoryError(String),
     ExternalFunction(String, String),
     InvalidExternal(i32),
+    Traced(Box<Trace>),
 }
 
 impl std::error::Error for Error {}
@@ -228,6 +233,7 @@ impl Display for Error {
             Self::BadDirectoryError(s) => write!(f, "cannot encode working directory {} with current encoding", s),
             Self::ExternalFunction(s, e) => write!(f, "failed to call external function \"{}\": {}", s, e),
             Self::InvalidExternal(i) => write!(f, "tried to call nonexistent external function with id {}", i),
+            Self::Traced(trace) => write!(f, "{}", trace),
         }
     }
 }
@@ -262,6 +268,7 @@ impl fmt::Debug for Instruction {
             Instruction::With { target, body } => write!(f, "With({:?}, {:?})", target, body),
             Instruction::GlobalVar { fields } => write!(f, "GlobalVar({:?})", fields),
             Instruction::RuntimeError { error } => write!(f, "RuntimeError({:?})", error),
+            Instruction::Line { pos } => write!(f, "Line({}:{})", pos.line, pos.column),
         }
     }
 }
@@ -349,522 +356,488 @@ impl UnaryOperator {
     }
 }
 
-impl Game {
-    pub fn execute(&mut self, instructions: &[Instruction], context: &mut Context) -> gml::Result<ReturnType> {
-        for instruction in instructions.iter() {
-            match self.exec_instruction(instruction, context)? {
-                ReturnType::Normal => (),
-                r => return Ok(r),
-            }
+/// Working state of a running Program.
+#[derive(Default)]
+struct Vm {
+    stack: Vec<Value>,
+    targets: Vec<Target>,
+    counters: Vec<i32>, // repeat loops
+    withs: Vec<With>,
+}
+
+/// A with loop in progress, along with the self and other to restore when it's done.
+struct With {
+    instances: WithInstances,
+    this: usize,
+    other: usize,
+}
+
+enum WithInstances {
+    Single(Option<usize>),
+    All(ILIterDrawOrder),
+    Objects(IdentityIter),
+}
+
+/// What to do after the Vm has stepped an op.
+enum Step {
+    Next,
+    Return(ReturnType),
+    /// The op needs the rest of the game, so it's up to Game::exec_op.
+    Host,
+}
+
+impl Vm {
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or_default()
+    }
+
+    /// Pops a function's arguments. Only the first 16 are ever pushed.
+    fn args(&mut self, argc: usize) -> [Value; 16] {
+        let mut args: [Value; 16] = Default::default();
+        let start = self.stack.len().saturating_sub(argc.min(16));
+        for (dest, src) in args.iter_mut().zip(self.stack.drain(start..)) {
+            *dest = src;
         }
-        Ok(ReturnType::Normal)
+        args
     }
 
-    fn exec_instruction(&mut self, instruction: &Instruction, context: &mut Context) -> gml::Result<ReturnType> {
-        match instruction {
-            Instruction::SetField { accessor, value } => {
-                let target = self.get_target(context, &accessor.owner, self.globalvars.contains(&accessor.index))?;
-                let array_index = self.get_array_index(&accessor.array, context)?;
-                let value = self.eval(value, context)?;
-                context.return_value = value.clone();
-                match target {
-                    Target::Single(None) => (),
-                    Target::Single(Some(instance)) => {
-                        self.set_instance_field(instance, accessor.index, array_index, value);
-                    },
-                    Target::Objects(index) => {
-                        let mut iter = self.room.instance_list.iter_by_identity(index);
-                        while let Some(instance) = iter.next(&self.room.instance_list) {
-                            self.set_instance_field(instance, accessor.index, array_index, value.clone());
-                        }
-                    },
-                    Target::All => {
-                        let mut iter = self.room.instance_list.iter_by_drawing();
-                        while let Some(instance) = iter.next(&self.room.instance_list) {
-                            self.set_instance_field(instance, accessor.index, array_index, value.clone());
-                        }
-                    },
-                    Target::Global => {
-                        if let Some(field) = self.globals.fields.get_mut(&accessor.index) {
-                            field.set(array_index, value)
-                        } else {
-                            self.globals.fields.insert(accessor.index, Field::new(array_index, value));
-                        }
-                    },
-                    Target::Local => {
-                        if let Some(field) = context.locals.fields.get_mut(&accessor.index) {
-                            field.set(array_index, value)
-                        } else {
-                            context.locals.fields.insert(accessor.index, Field::new(array_index, value));
-                        }
+    /// Pops an array accessor with the given number of dimensions and resolves it to an index.
+    fn array_index(&mut self, dims: u8) -> gml::Result<u32> {
+        let index = self.peek_array_index(dims);
+        self.stack.truncate(self.stack.len().saturating_sub(dims.into()));
+        index
+    }
+
+    /// Resolves the array accessor on top of the stack without popping it.
+    fn peek_array_index(&self, dims: u8) -> gml::Result<u32> {
+        let index = |depth: usize| self.stack.len().checked_sub(depth).map_or(0, |i| self.stack[i].round());
+        let check = |index: i32| {
+            if index < 0 || index >= 32000 { Err(Error::InvalidArrayIndex(index)) } else { Ok(index as u32) }
+        };
+        match dims {
+            0 => Ok(0),
+            1 => check(index(1)),
+            _ => {
+                let index1 = check(index(2))?;
+                let index2 = check(index(1))?;
+                Ok((index1 * 32000) + index2)
+            },
+        }
+    }
+
+    /// Runs an op which doesn't need anything from the game except its instance list.
+    fn step(
+        &mut self,
+        op: &Op,
+        program: &Program,
+        context: &mut Context,
+        instance_list: &InstanceList,
+        pc: &mut usize,
+    ) -> gml::Result<Step> {
+        match op {
+            Op::Line(_) => (),
+            Op::Push(index) => self.stack.push(program.consts[*index as usize].clone()),
+            Op::Pop => {
+                self.stack.pop();
+            },
+            Op::GetField { index, owner: Owner::Local, dims } => {
+                // Uninitialized locals are left to the game, which knows how to report them
+                let array_index = self.peek_array_index(*dims)?;
+                match context.locals.fields.get(index).and_then(|x| x.get(array_index)) {
+                    Some(value) => {
+                        self.array_index(*dims)?;
+                        self.stack.push(value);
                     },
+                    None => return Ok(Step::Host),
                 }
             },
-            Instruction::SetVariable { accessor, value } => {
-                let target = self.get_target(context, &accessor.owner, false)?;
-                let array_index = self.get_array_index(&accessor.array, context)?;
-                let value = self.eval(value, context)?;
+            Op::SetField { index, owner: Owner::Local, dims } => {
+                let value = self.pop();
+                let array_index = self.array_index(*dims)?;
                 context.return_value = value.clone();
-                match target {
-                    Target::Single(None) => (),
-                    Target::Single(Some(instance)) => {
-                        self.set_instance_var(instance, &accessor.var, array_index, value, context)?;
-                    },
-                    Target::Objects(index) => {
-                        let mut iter = self.room.instance_list.iter_by_identity(index);
-                        while let Some(instance) = iter.next(&self.room.instance_list) {
-                            self.set_instance_var(instance, &accessor.var, array_index, value.clone(), context)?;
-                        }
-                    },
-                    Target::All => {
-                        let mut iter = self.room.instance_list.iter_by_drawing();
-                        while let Some(instance) = iter.next(&self.room.instance_list) {
-                            self.set_instance_var(instance, &accessor.var, array_index, value.clone(), context)?;
-                        }
-                    },
-                    Target::Global => {
-                        if let Some(field) = self.globals.vars.get_mut(&accessor.var) {
-                            field.set(array_index, value)
-                        } else {
-                            self.globals.vars.insert(accessor.var, Field::new(array_index, value));
-                        }
-                    },
-                    Target::Local => {
-                        if let Some(field) = context.locals.vars.get_mut(&accessor.var) {
-                            field.set(array_index, value)
-                        } else {
-                            context.locals.vars.insert(accessor.var, Field::new(array_index, value));
-                        }
-                    },
+                if let Some(field) = context.locals.fields.get_mut(index) {
+                    field.set(array_index, value)
+                } else {
+                    context.locals.fields.insert(*index, Field::new(array_index, value));
                 }
             },
-            Instruction::EvalExpression { node } => {
-                context.return_value = self.eval(node, context)?;
+            Op::CheckIndex(dims) => {
+                self.peek_array_index(*dims)?;
             },
-            Instruction::IfElse { cond, if_body, else_body } => {
-                let return_type = if self.eval(cond, context)?.is_truthy() {
-                    self.execute(if_body, context)
+            Op::Unary(operator) => {
+                let value = self.pop();
+                self.stack.push(operator.call(value)?);
+            },
+            Op::Binary { operator, type_unsafe } => {
+                let right = self.pop();
+                let left = self.pop();
+                let value = if *type_unsafe {
+                    // the + in += can happen here, and += ignores errors in the + portion
+                    operator.call(left.clone(), right).unwrap_or(left)
                 } else {
-                    self.execute(else_body, context)
-                }?;
-                if return_type != ReturnType::Normal {
-                    return Ok(return_type)
+                    operator.call(left, right)?
+                };
+                self.stack.push(value);
+            },
+            Op::SetReturnValue => context.return_value = self.pop(),
+            Op::Jump(target) => *pc = *target,
+            Op::JumpIfFalse(target) => {
+                if !self.pop().is_truthy() {
+                    *pc = *target;
                 }
             },
-            Instruction::LoopUntil { cond, body } => loop {
-                match self.execute(body, context)? {
-                    ReturnType::Normal => (),
-                    ReturnType::Continue => continue,
-                    ReturnType::Break => break,
-                    ReturnType::Exit => return Ok(ReturnType::Exit),
-                }
-                if self.eval(cond, context)?.is_truthy() {
-                    break
+            Op::Case(target) => {
+                let value = self.pop();
+                if self.stack.last().map_or(false, |input| value.almost_equals(input)) {
+                    self.stack.pop();
+                    *pc = *target;
                 }
             },
-            Instruction::LoopWhile { cond, body } => {
-                while self.eval(cond, context)?.is_truthy() {
-                    match self.execute(body, context)? {
-                        ReturnType::Normal => (),
-                        ReturnType::Continue => continue,
-                        ReturnType::Break => break,
-                        ReturnType::Exit => return Ok(ReturnType::Exit),
-                    }
-                }
+            Op::RepeatStart => {
+                let count = self.pop().round();
+                self.counters.push(count);
             },
-            Instruction::LoopFor { cond, body, step } => {
-                while self.eval(cond, context)?.is_truthy() {
-                    match self.execute(body, context)? {
-                        ReturnType::Normal => {
-                            self.execute(step, context)?;
-                        },
-                        ReturnType::Continue => {
-                            self.execute(step, context)?;
-                            continue
-                        },
-                        ReturnType::Break => break,
-                        ReturnType::Exit => return Ok(ReturnType::Exit),
-                    }
-                }
+            Op::RepeatNext(target) => match self.counters.last_mut() {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => *pc = *target,
             },
-            Instruction::Return { return_type } => return Ok(*return_type),
-            Instruction::Repeat { count, body } => {
-                for _ in 0..self.eval(count, context)?.round() {
-                    match self.execute(body, context)? {
-                        ReturnType::Normal => (),
-                        ReturnType::Continue => continue,
-                        ReturnType::Break => break,
-                        ReturnType::Exit => return Ok(ReturnType::Exit),
-                    }
-                }
+            Op::RepeatEnd => {
+                self.counters.pop();
             },
-            Instruction::SetReturnValue { value } => {
-                context.return_value = self.eval(value, context)?;
+            Op::WithStart => {
+                let target = i32::from(self.pop());
+                let (this, other) = (context.this, context.other);
+                context.other = context.this;
+                let instances = match target {
+                    gml::SELF | gml::UNSPECIFIED => WithInstances::Single(Some(this)),
+                    gml::OTHER => WithInstances::Single(Some(other)),
+                    gml::ALL => WithInstances::All(instance_list.iter_by_drawing()),
+                    i if i < 0 => WithInstances::Single(None),
+                    i if i < 100_000 => WithInstances::Objects(instance_list.iter_by_identity(i)),
+                    i => WithInstances::Single(instance_list.get_by_instid(i)),
+                };
+                self.withs.push(With { instances, this, other });
+            },
+            Op::WithNext(target) => {
+                let next = match self.withs.last_mut().map(|with| &mut with.instances) {
+                    Some(WithInstances::Single(instance)) => instance.take(),
+                    Some(WithInstances::All(iter)) => iter.next(instance_list),
+                    Some(WithInstances::Objects(iter)) => iter.next(instance_list),
+                    None => None,
+                };
+                match next {
+                    Some(instance) => context.this = instance,
+                    None => *pc = *target,
+                }
             },
-            Instruction::Switch { input, cases, default, body } => {
-                let input = self.eval(input, context)?;
-                for (cond, start) in cases.iter() {
-                    if self.eval(cond, context)?.almost_equals(&input) {
-                        return Ok(match self.execute(&body[*start..], context)? {
-                            ReturnType::Break => ReturnType::Normal,
-                            x => x,
-                        })
-                    }
+            Op::WithEnd => {
+                if let Some(with) = self.withs.pop() {
+                    context.this = with.this;
+                    context.other = with.other;
                 }
-                if let Some(start) = default {
-                    return Ok(match self.execute(&body[*start..], context)? {
-                        ReturnType::Break => ReturnType::Normal,
-                        x => x,
-                    })
+            },
+            Op::Error(error) => return Err(error.clone()),
+            Op::Return(return_type) => {
+                if let Some(with) = self.withs.first() {
+                    context.this = with.this;
+                    context.other = with.other;
                 }
+                return Ok(Step::Return(*return_type))
             },
-            Instruction::With { target, body } => {
-                let old_this = context.this;
-                let old_other = context.other;
+            _ => return Ok(Step::Host),
+        }
+        Ok(Step::Next)
+    }
+}
 
-                let target_id = i32::from(self.eval(target, context)?);
-                context.other = context.this;
+impl Game {
+    /// Runs some compiled GML.
+    pub fn execute(&mut self, program: &Program, context: &mut Context) -> gml::Result<ReturnType> {
+        self.run(program, context, &mut Vm::default())
+    }
 
-                match target_id {
-                    gml::SELF | gml::UNSPECIFIED => {
-                        if self.execute(body, context)? == ReturnType::Exit {
-                            context.other = old_other;
-                            return Ok(ReturnType::Exit)
-                        }
-                    },
-                    gml::OTHER => {
-                        context.this = old_other;
-                        if self.execute(body, context)? == ReturnType::Exit {
-                            context.this = old_this;
-                            context.other = old_other;
-                            return Ok(ReturnType::Exit)
-                        }
-                    },
-                    gml::ALL => {
-                        let mut iter = self.room.instance_list.iter_by_drawing();
-                        while let Some(instance) = iter.next(&self.room.instance_list) {
-                            context.this = instance;
-                            match self.execute(body, context)? {
-                                ReturnType::Normal => (),
-                                ReturnType::Continue => continue,
-                                ReturnType::Break => break,
-                                ReturnType::Exit => {
-                                    context.this = old_this;
-                                    context.other = old_other;
-                                    return Ok(ReturnType::Exit)
-                                },
-                            }
-                        }
-                    },
-                    i if i < 0 => (),
-                    i if i < 100_000 => {
-                        let mut iter = self.room.instance_list.iter_by_identity(i);
-                        while let Some(instance) = iter.next(&self.room.instance_list) {
-                            context.this = instance;
-                            match self.execute(body, context)? {
-                                ReturnType::Normal => (),
-                                ReturnType::Continue => continue,
-                                ReturnType::Break => break,
-                                ReturnType::Exit => {
-                                    context.this = old_this;
-                                    context.other = old_other;
-                                    return Ok(ReturnType::Exit)
-                                },
-                            }
-                        }
-                    },
-                    i => {
-                        if let Some(instance) = self.room.instance_list.get_by_instid(i) {
-                            context.this = instance;
-                            match self.execute(body, context)? {
-                                ReturnType::Exit => {
-                                    context.this = old_this;
-                                    context.other = old_other;
-                                    return Ok(ReturnType::Exit)
-                                },
-                                _ => (),
-                            }
-                        }
-                    },
+    /// Evaluates a compiled expression.
+    pub fn eval(&mut self, program: &Program, context: &mut Context) -> gml::Result<Value> {
+        let mut vm = Vm::default();
+        self.run(program, context, &mut vm)?;
+        Ok(vm.pop())
+    }
+
+    fn run(&mut self, program: &Program, context: &mut Context, vm: &mut Vm) -> gml::Result<ReturnType> {
+        let mut line = None;
+        let mut pc = 0;
+        while let Some(op) = program.ops.get(pc) {
+            pc += 1;
+            if let Op::Line(pos) = op {
+                line = Some(*pos);
+                if self.debugger.is_some() {
+                    self.debug_line(*pos, context);
                 }
+                continue
+            }
+            let step = match vm.step(op, program, context, &self.room.instance_list, &mut pc) {
+                Ok(Step::Host) => self.exec_op(op, context, vm).map(|()| Step::Next),
+                step => step,
+            };
+            match step {
+                Ok(Step::Next | Step::Host) => (),
+                Ok(Step::Return(return_type)) => return Ok(return_type),
+                Err(e) => return Err(if let Some(pos) = line { e.at(pos) } else { e }),
+            }
+        }
+        Ok(ReturnType::Normal)
+    }
 
-                context.this = old_this;
-                context.other = old_other;
+    /// Runs an op which the Vm couldn't run by itself.
+    fn exec_op(&mut self, op: &Op, context: &mut Context, vm: &mut Vm) -> gml::Result<()> {
+        match op {
+            Op::Constant(constant_id) => match self.constants.get(*constant_id) {
+                Some(value) => vm.stack.push(value.clone()),
+                None => return Err(Error::NonexistentAsset(asset::Type::Constant, *constant_id as i32)),
+            },
+            Op::Target { owner, field } => {
+                let in_globalvars = field.map_or(false, |field| self.globalvars.contains(&field));
+                let target = self.get_target(context, vm, *owner, in_globalvars);
+                vm.targets.push(target);
+            },
+            Op::GetField { index, owner, dims } => {
+                let array_index = vm.array_index(*dims)?;
+                let target = self.get_target(context, vm, *owner, self.globalvars.contains(index));
+                let value = self.read_field(target, *index, array_index, context)?;
+                vm.stack.push(value);
+            },
+            Op::GetVar { var, owner, dims } => {
+                let array_index = vm.array_index(*dims)?;
+                let target = self.get_target(context, vm, *owner, false);
+                let value = self.read_var(target, var, array_index, context)?;
+                vm.stack.push(value);
+            },
+            Op::SetField { index, owner, dims } => {
+                let value = vm.pop();
+                let array_index = vm.array_index(*dims)?;
+                let target = self.get_target(context, vm, *owner, self.globalvars.contains(index));
+                context.return_value = value.clone();
+                self.write_field(target, *index, array_index, value, context);
             },
-            Instruction::GlobalVar { fields } => {
-                self.globalvars.extend(fields);
-                for &field in fields {
+            Op::SetVar { var, owner, dims } => {
+                let value = vm.pop();
+                let array_index = vm.array_index(*dims)?;
+                let target = self.get_target(context, vm, *owner, false);
+                context.return_value = value.clone();
+                self.write_var(target, var, array_index, value, context)?;
+            },
+            Op::CallContext { function, argc } => {
+                let args = vm.args(*argc);
+                self.profile_enter_function(function.0 as *const ());
+                let result = function.0(self, context, &args[..*argc]);
+                self.profile_leave();
+                vm.stack.push(result?);
+            },
+            Op::CallState { function, argc } => {
+                let args = vm.args(*argc);
+                self.profile_enter_function(function.0 as *const ());
+                let result = function.0(self, &args[..*argc]);
+                self.profile_leave();
+                vm.stack.push(result?);
+            },
+            Op::CallRoutine { function, argc } => {
+                let args = vm.args(*argc);
+                self.profile_enter_function(function.0 as *const ());
+                let result = function.0(self, &args[..*argc]);
+                self.profile_leave();
+                vm.stack.push(result?);
+            },
+            Op::CallValue { function, argc } => {
+                let args = vm.args(*argc);
+                self.profile_enter_function(function.0 as *const ());
+                let result = function.0(&args[..*argc]);
+                self.profile_leave();
+                vm.stack.push(result?);
+            },
+            Op::CallScript { script_id, argc } => {
+                let args = vm.args(*argc);
+                let script = match self.assets.scripts.get(*script_id) {
+                    Some(Some(script)) => script.compiled.clone(),
+                    _ => return Err(Error::NonexistentAsset(asset::Type::Script, *script_id as i32)),
+                };
+                let mut new_context = Context::copy_with_args(context, args, *argc);
+                self.debug_enter(|game| game.script_source(*script_id));
+                self.profile_enter(profiler::Entry::Script(*script_id));
+                let result = self.execute(&script, &mut new_context);
+                self.profile_leave();
+                self.debug_leave();
+                result.map_err(|e| e.within(self.script_source(*script_id)))?;
+                vm.stack.push(new_context.return_value);
+            },
+            Op::CallExtension { id, argc } => {
+                let args = vm.args(*argc);
+                let value = self.run_extension_function(*id, context, args, *argc)?;
+                vm.stack.push(value);
+            },
+            Op::GlobalVar(fields) => {
+                self.globalvars.extend(fields.iter());
+                for &field in fields.iter() {
                     self.globals.fields.entry(field).or_insert(Field::new(0, Default::default()));
                 }
             },
-            Instruction::RuntimeError { error } => return Err(error.clone()),
+            _ => unreachable!("the vm runs {:?} by itself", op),
         }
-
-        Ok(ReturnType::Normal)
+        Ok(())
     }
 
-    pub fn eval(&mut self, node: &Node, context: &mut Context) -> gml::Result<Value> {
-        match node {
-            Node::Literal { value } => Ok(value.clone()),
-            Node::Constant { constant_id } => {
-                if let Some(value) = self.constants.get(*constant_id) {
-                    Ok(value.clone())
-                } else {
-                    Err(gml::Error::NonexistentAsset(asset::Type::Constant, *constant_id as i32))
+    /// Reads a field from whatever a target refers to. Multiple instances read from the first one.
+    fn read_field(&self, target: Target, index: usize, array_index: u32, context: &Context) -> gml::Result<Value> {
+        let uninitialized = || {
+            if self.uninit_fields_are_zero {
+                Ok(Default::default())
+            } else {
+                Err(Error::UninitializedVariable(self.compiler.get_field_name(index).unwrap(), array_index))
+            }
+        };
+        match target {
+            Target::Single(None) => uninitialized(),
+            Target::Single(Some(instance)) => self.get_instance_field(instance, index, array_index),
+            Target::Objects(object) => {
+                match self.room.instance_list.iter_by_identity(object).next(&self.room.instance_list) {
+                    Some(instance) => self.get_instance_field(instance, index, array_index),
+                    None => uninitialized(),
                 }
             },
-            Node::ContextFunction { args, function } => {
-                let mut arg_values: [Value; 16] = Default::default();
-                for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
-                    *dest = self.eval(src, context)?;
-                }
-                function.0(self, context, &arg_values[..args.len()])
+            Target::All => match self.room.instance_list.iter_by_drawing().next(&self.room.instance_list) {
+                Some(instance) => self.get_instance_field(instance, index, array_index),
+                None => uninitialized(),
             },
-            Node::StateFunction { args, function } => {
-                let mut arg_values: [Value; 16] = Default::default();
-                for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
-                    *dest = self.eval(src, context)?;
-                }
-                function.0(self, &arg_values[..args.len()])
+            Target::Global => match self.globals.fields.get(&index).and_then(|x| x.get(array_index)) {
+                Some(value) => Ok(value),
+                None => uninitialized(),
             },
-            Node::RoutineFunction { args, function } => {
-                let mut arg_values: [Value; 16] = Default::default();
-                for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
-                    *dest = self.eval(src, context)?;
-                }
-                function.0(self, &arg_values[..args.len()])
+            Target::Local => match context.locals.fields.get(&index).and_then(|x| x.get(array_index)) {
+                Some(value) => Ok(value),
+                None => uninitialized(),
             },
-            Node::ValueFunction { args, function } => {
-                let mut arg_values: [Value; 16] = Default::default();
-                for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
-                    *dest = self.eval(src, context)?;
+        }
+    }
+
+    /// Reads an instance variable from whatever a target refers to. Multiple instances read from the first one.
+    fn read_var(
+        &self,
+        target: Target,
+        var: &InstanceVariable,
+        array_index: u32,
+        context: &Context,
+    ) -> gml::Result<Value> {
+        let uninitialized = || {
+            if self.uninit_fields_are_zero {
+                Ok(Default::default())
+            } else {
+                Err(Error::UninitializedVariable(
+                    String::from(mappings::INSTANCE_VARIABLES.iter().find(|(_, x)| x == var).unwrap().0),
+                    array_index,
+                ))
+            }
+        };
+        match target {
+            Target::Single(None) => uninitialized(),
+            Target::Single(Some(instance)) => self.get_instance_var(instance, var, array_index, context),
+            Target::Objects(object) => {
+                match self.room.instance_list.iter_by_identity(object).next(&self.room.instance_list) {
+                    Some(instance) => self.get_instance_var(instance, var, array_index, context),
+                    None => uninitialized(),
                 }
-                function.0(&arg_values[..args.len()])
             },
-            Node::Script { args, script_id } => {
-                if let Some(Some(script)) = self.assets.scripts.get(*script_id) {
-                    let instructions = script.compiled.clone();
-
-                    let mut arg_values: [Value; 16] = Default::default();
-                    for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
-                        *dest = self.eval(src, context)?;
-                    }
+            Target::All => match self.room.instance_list.iter_by_drawing().next(&self.room.instance_list) {
+                Some(instance) => self.get_instance_var(instance, var, array_index, context),
+                None => uninitialized(),
+            },
+            Target::Global => match self.globals.vars.get(var).and_then(|x| x.get(array_index)) {
+                Some(value) => Ok(value),
+                None => uninitialized(),
+            },
+            Target::Local => match context.locals.vars.get(var).and_then(|x| x.get(array_index)) {
+                Some(value) => Ok(value),
+                None => uninitialized(),
+            },
+        }
+    }
 
-                    let mut new_context = Context::copy_with_args(context, arg_values, args.len());
-                    self.execute(&instructions, &mut new_context)?;
-                    Ok(new_context.return_value)
-                } else {
-                    Err(Error::NonexistentAsset(asset::Type::Script, *script_id as i32))
+    /// Assigns a field on everything a target refers to.
+    fn write_field(&mut self, target: Target, index: usize, array_index: u32, value: Value, context: &mut Context) {
+        match target {
+            Target::Single(None) => (),
+            Target::Single(Some(instance)) => {
+                self.set_instance_field(instance, index, array_index, value);
+            },
+            Target::Objects(object) => {
+                let mut iter = self.room.instance_list.iter_by_identity(object);
+                while let Some(instance) = iter.next(&self.room.instance_list) {
+                    self.set_instance_field(instance, index, array_index, value.clone());
                 }
             },
-            Node::ExtensionFunction { args, id } => {
-                let mut arg_values: [Value; 16] = Default::default();
-                for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
-                    *dest = self.eval(src, context)?;
-                }
-
-                self.run_extension_function(*id, context, arg_values, args.len())
-            },
-            Node::Field { accessor } => {
-                let target = self.get_target(context, &accessor.owner, self.globalvars.contains(&accessor.index))?;
-                let array_index = self.get_array_index(&accessor.array, context)?;
-                match target {
-                    Target::Single(None) if self.uninit_fields_are_zero => Ok(Default::default()),
-                    Target::Single(None) => Err(Error::UninitializedVariable(
-                        self.compiler.get_field_name(accessor.index).unwrap(),
-                        array_index,
-                    )),
-                    Target::Single(Some(instance)) => self.get_instance_field(instance, accessor.index, array_index),
-                    Target::Objects(index) => {
-                        if let Some(instance) =
-                            self.room.instance_list.iter_by_identity(index).next(&self.room.instance_list)
-                        {
-                            self.get_instance_field(instance, accessor.index, array_index)
-                        } else {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                Err(Error::UninitializedVariable(
-                                    self.compiler.get_field_name(accessor.index).unwrap(),
-                                    array_index,
-                                ))
-                            }
-                        }
-                    },
-                    Target::All => {
-                        if let Some(instance) = self.room.instance_list.iter_by_drawing().next(&self.room.instance_list)
-                        {
-                            self.get_instance_field(instance, accessor.index, array_index)
-                        } else {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                Err(Error::UninitializedVariable(
-                                    self.compiler.get_field_name(accessor.index).unwrap(),
-                                    array_index,
-                                ))
-                            }
-                        }
-                    },
-                    Target::Global => match self.globals.fields.get(&accessor.index).and_then(|x| x.get(array_index)) {
-                        Some(i) => Ok(i),
-                        None => {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                return Err(Error::UninitializedVariable(
-                                    self.compiler.get_field_name(accessor.index).unwrap(),
-                                    array_index,
-                                ))
-                            }
-                        },
-                    },
-                    Target::Local => {
-                        match context.locals.fields.get(&accessor.index).and_then(|x| x.get(array_index)) {
-                            Some(i) => Ok(i),
-                            None => {
-                                if self.uninit_fields_are_zero {
-                                    Ok(Default::default())
-                                } else {
-                                    return Err(Error::UninitializedVariable(
-                                        self.compiler.get_field_name(accessor.index).unwrap(),
-                                        array_index,
-                                    ))
-                                }
-                            },
-                        }
-                    },
+            Target::All => {
+                let mut iter = self.room.instance_list.iter_by_drawing();
+                while let Some(instance) = iter.next(&self.room.instance_list) {
+                    self.set_instance_field(instance, index, array_index, value.clone());
                 }
             },
-            Node::Variable { accessor } => {
-                let target = self.get_target(context, &accessor.owner, false)?;
-                let array_index = self.get_array_index(&accessor.array, context)?;
-                match target {
-                    Target::Single(None) if self.uninit_fields_are_zero => Ok(Default::default()),
-                    Target::Single(None) => Err(Error::UninitializedVariable(
-                        String::from(mappings::INSTANCE_VARIABLES.iter().find(|(_, x)| x == &accessor.var).unwrap().0),
-                        array_index,
-                    )),
-                    Target::Single(Some(instance)) => {
-                        self.get_instance_var(instance, &accessor.var, array_index, context)
-                    },
-                    Target::Objects(index) => {
-                        if let Some(instance) =
-                            self.room.instance_list.iter_by_identity(index).next(&self.room.instance_list)
-                        {
-                            self.get_instance_var(instance, &accessor.var, array_index, context)
-                        } else {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                Err(Error::UninitializedVariable(
-                                    String::from(
-                                        mappings::INSTANCE_VARIABLES
-                                            .iter()
-                                            .find(|(_, x)| x == &accessor.var)
-                                            .unwrap()
-                                            .0,
-                                    ),
-                                    array_index,
-                                ))
-                            }
-                        }
-                    },
-                    Target::All => {
-                        if let Some(instance) = self.room.instance_list.iter_by_drawing().next(&self.room.instance_list)
-                        {
-                            self.get_instance_var(instance, &accessor.var, array_index, context)
-                        } else {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                Err(Error::UninitializedVariable(
-                                    String::from(
-                                        mappings::INSTANCE_VARIABLES
-                                            .iter()
-                                            .find(|(_, x)| x == &accessor.var)
-                                            .unwrap()
-                                            .0,
-                                    ),
-                                    array_index,
-                                ))
-                            }
-                        }
-                    },
-                    Target::Global => match self.globals.vars.get(&accessor.var).and_then(|x| x.get(array_index)) {
-                        Some(i) => Ok(i),
-                        None => {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                return Err(Error::UninitializedVariable(
-                                    String::from(
-                                        mappings::INSTANCE_VARIABLES
-                                            .iter()
-                                            .find(|(_, x)| x == &accessor.var)
-                                            .unwrap()
-                                            .0,
-                                    ),
-                                    array_index,
-                                ))
-                            }
-                        },
-                    },
-                    Target::Local => match context.locals.vars.get(&accessor.var).and_then(|x| x.get(array_index)) {
-                        Some(i) => Ok(i),
-                        None => {
-                            if self.uninit_fields_are_zero {
-                                Ok(Default::default())
-                            } else {
-                                return Err(Error::UninitializedVariable(
-                                    String::from(
-                                        mappings::INSTANCE_VARIABLES
-                                            .iter()
-                                            .find(|(_, x)| x == &accessor.var)
-                                            .unwrap()
-                                            .0,
-                                    ),
-                                    array_index,
-                                ))
-                            }
-                        },
-                    },
+            Target::Global => {
+                if let Some(field) = self.globals.fields.get_mut(&index) {
+                    field.set(array_index, value)
+                } else {
+                    self.globals.fields.insert(index, Field::new(array_index, value));
                 }
             },
-            Node::Binary { left, right, operator, type_unsafe } => {
-                // the + in += can happen here, and += ignores errors in the + portion
-                let left = self.eval(left, context)?;
-                match operator.call(left.clone(), self.eval(right, context)?) {
-                    res @ Ok(_) => res,
-                    Err(_) if *type_unsafe => Ok(left),
-                    res => res,
+            Target::Local => {
+                if let Some(field) = context.locals.fields.get_mut(&index) {
+                    field.set(array_index, value)
+                } else {
+                    context.locals.fields.insert(index, Field::new(array_index, value));
                 }
             },
-            Node::Unary { child, operator } => operator.call(self.eval(child, context)?),
-            Node::RuntimeError { error } => Err(error.clone()),
         }
     }
 
-    // Resolves an ArrayAccessor to an index (u32)
-    fn get_array_index(&mut self, accessor: &ArrayAccessor, context: &mut Context) -> gml::Result<u32> {
-        match accessor {
-            ArrayAccessor::None => Ok(0),
-            ArrayAccessor::Single(node) => {
-                let index = self.eval(node, context)?.round();
-                if index < 0 || index >= 32000 { Err(Error::InvalidArrayIndex(index)) } else { Ok(index as u32) }
+    /// Assigns an instance variable on everything a target refers to.
+    fn write_var(
+        &mut self,
+        target: Target,
+        var: &InstanceVariable,
+        array_index: u32,
+        value: Value,
+        context: &mut Context,
+    ) -> gml::Result<()> {
+        match target {
+            Target::Single(None) => (),
+            Target::Single(Some(instance)) => {
+                self.set_instance_var(instance, var, array_index, value, context)?;
+            },
+            Target::Objects(object) => {
+                let mut iter = self.room.instance_list.iter_by_identity(object);
+                while let Some(instance) = iter.next(&self.room.instance_list) {
+                    self.set_instance_var(instance, var, array_index, value.clone(), context)?;
+                }
+            },
+            Target::All => {
+                let mut iter = self.room.instance_list.iter_by_drawing();
+                while let Some(instance) = iter.next(&self.room.instance_list) {
+                    self.set_instance_var(instance, var, array_index, value.clone(), context)?;
+                }
             },
-            ArrayAccessor::Double(node1, node2) => {
-                let index1 = self.eval(node1, context)?.round();
-                let index2 = self.eval(node2, context)?.round();
-                if index1 < 0 || index1 >= 32000 {
-                    Err(Error::InvalidArrayIndex(index1))
-                } else if index2 < 0 || index2 >= 32000 {
-                    Err(Error::InvalidArrayIndex(index2))
+            Target::Global => {
+                if let Some(field) = self.globals.vars.get_mut(var) {
+                    field.set(array_index, value)
                 } else {
-                    Ok(((index1 * 32000) + index2) as u32)
+                    self.globals.vars.insert(*var, Field::new(array_index, value));
+                }
+            },
+            Target::Local => {
+                if let Some(field) = context.locals.vars.get_mut(var) {
+                    field.set(array_index, value)
+                } else {
+                    context.locals.vars.insert(*var, Field::new(array_index, value));
                 }
             },
         }
+        Ok(())
     }
 
     // Get a field value from an instance
@@ -1607,38 +1580,517 @@ impl Game {
         }
     }
 
-    // Resolves an InstanceIdentifier to a Target
-    fn get_target(
-        &mut self,
-        context: &mut Context,
-        identifier: &InstanceIdentifier,
-        in_globalvars: bool,
-    ) -> gml::Result<Target> {
-        match identifier {
-            InstanceIdentifier::Own => Ok(Target::Single(Some(context.this))),
-            InstanceIdentifier::Other => Ok(Target::Single(Some(context.other))),
-            InstanceIdentifier::Global => Ok(Target::Global),
-            InstanceIdentifier::Local => Ok(Target::Local),
-            InstanceIdentifier::Unknown => {
+    // Resolves an Owner to a Target, popping anything it needs from the VM
+    fn get_target(&self, context: &Context, vm: &mut Vm, owner: Owner, in_globalvars: bool) -> Target {
+        match owner {
+            Owner::Own => Target::Single(Some(context.this)),
+            Owner::Other => Target::Single(Some(context.other)),
+            Owner::Global => Target::Global,
+            Owner::Local => Target::Local,
+            Owner::Unknown => {
                 if in_globalvars {
-                    Ok(Target::Global)
+                    Target::Global
                 } else {
-                    Ok(Target::Single(Some(context.this)))
+                    Target::Single(Some(context.this))
                 }
             },
-            InstanceIdentifier::Expression(node) => {
-                let value = self.eval(node, context).map(i32::from)?;
-                match value {
-                    gml::SELF | gml::UNSPECIFIED => Ok(Target::Single(Some(context.this))),
-                    gml::OTHER => Ok(Target::Single(Some(context.other))),
-                    gml::ALL => Ok(Target::All),
-                    gml::NOONE => Ok(Target::Single(None)),
-                    gml::GLOBAL => Ok(Target::Global),
-                    gml::LOCAL => Ok(Target::Local),
-                    i if i >= 100_000 => Ok(Target::Single(self.room.instance_list.get_by_instid(i))),
-                    i => Ok(Target::Objects(i)),
+            Owner::Stack => match i32::from(vm.pop()) {
+                gml::SELF | gml::UNSPECIFIED => Target::Single(Some(context.this)),
+                gml::OTHER => Target::Single(Some(context.other)),
+                gml::ALL => Target::All,
+                gml::NOONE => Target::Single(None),
+                gml::GLOBAL => Target::Global,
+                gml::LOCAL => Target::Local,
+                i if i >= 100_000 => Target::Single(self.room.instance_list.get_by_instid(i)),
+                i => Target::Objects(i),
+            },
+            Owner::Resolved => vm.targets.pop().unwrap_or(Target::Single(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gml::Compiler, instance::Instance};
+
+    /// Runs some GML on a Vm without a game. Panics if it needs one.
+    fn run(source: &str, context: &mut Context, instance_list: &InstanceList) -> gml::Result<ReturnType> {
+        let program = Compiler::new().compile(source.as_bytes()).unwrap();
+        let mut vm = Vm::default();
+        let mut pc = 0;
+        while let Some(op) = program.ops.get(pc) {
+            pc += 1;
+            match vm.step(op, &program, context, instance_list, &mut pc)? {
+                Step::Next => (),
+                Step::Return(return_type) => return Ok(return_type),
+                Step::Host => panic!("{:?} needs a game to run", op),
+            }
+        }
+        Ok(ReturnType::Normal)
+    }
+
+    fn result(source: &str) -> Value {
+        let mut context = Context::default();
+        run(source, &mut context, &InstanceList::new()).unwrap();
+        context.return_value
+    }
+
+    fn fails(source: &str) -> bool {
+        run(source, &mut Context::default(), &InstanceList::new()).is_err()
+    }
+
+    /// An instance list with three instances of object 3, with ids 100001 to 100003.
+    fn instances() -> InstanceList {
+        let mut list = InstanceList::new();
+        for id in 100001..=100003 {
+            let instance = Instance::new_ext(
+                id,
+                Real::from(0),
+                Real::from(0),
+                3,
+                None,
+                Real::from(1),
+                Real::from(1),
+                0xFFFFFF,
+                Real::from(1),
+                Real::from(0),
+            );
+            instance.parents.borrow_mut().insert(3);
+            list.insert(instance);
+        }
+        list
+    }
+
+    /// Runs some GML counting in the local `n` with the instances above.
+    fn count_with(source: &str) -> Value {
+        let list = instances();
+        let this = list.iter_by_drawing().next(&list).unwrap();
+        let mut context = Context::with_single_instance(this);
+        run(&format!("var n; n = 0; {} return n;", source), &mut context, &list).unwrap();
+        assert_eq!((context.this, context.other), (this, this));
+        context.return_value
+    }
+
+    /// The tree-walking interpreter the Vm replaced, cut down to what can run without a game,
+    /// so the two can be checked against each other.
+    struct TreeWalker<'a> {
+        instance_list: &'a InstanceList,
+    }
+
+    impl TreeWalker<'_> {
+        fn execute(&self, instructions: &[Instruction], context: &mut Context) -> gml::Result<ReturnType> {
+            for instruction in instructions.iter() {
+                match self.exec_instruction(instruction, context)? {
+                    ReturnType::Normal => (),
+                    r => return Ok(r),
                 }
-            },
+            }
+            Ok(ReturnType::Normal)
+        }
+
+        fn exec_instruction(&self, instruction: &Instruction, context: &mut Context) -> gml::Result<ReturnType> {
+            match instruction {
+                Instruction::SetField { accessor, value } => {
+                    let local = matches!(accessor.owner, InstanceIdentifier::Local);
+                    assert!(local, "only locals can be set without a game");
+                    let array_index = self.get_array_index(&accessor.array, context)?;
+                    let value = self.eval(value, context)?;
+                    context.return_value = value.clone();
+                    if let Some(field) = context.locals.fields.get_mut(&accessor.index) {
+                        field.set(array_index, value)
+                    } else {
+                        context.locals.fields.insert(accessor.index, Field::new(array_index, value));
+                    }
+                },
+                Instruction::EvalExpression { node: value } | Instruction::SetReturnValue { value } => {
+                    context.return_value = self.eval(value, context)?;
+                },
+                Instruction::IfElse { cond, if_body, else_body } => {
+                    let return_type = if self.eval(cond, context)?.is_truthy() {
+                        self.execute(if_body, context)
+                    } else {
+                        self.execute(else_body, context)
+                    }?;
+                    if return_type != ReturnType::Normal {
+                        return Ok(return_type)
+                    }
+                },
+                Instruction::LoopUntil { cond, body } => loop {
+                    match self.execute(body, context)? {
+                        ReturnType::Normal => (),
+                        ReturnType::Continue => continue,
+                        ReturnType::Break => break,
+                        ReturnType::Exit => return Ok(ReturnType::Exit),
+                    }
+                    if self.eval(cond, context)?.is_truthy() {
+                        break
+                    }
+                },
+                Instruction::LoopWhile { cond, body } => {
+                    while self.eval(cond, context)?.is_truthy() {
+                        match self.execute(body, context)? {
+                            ReturnType::Normal | ReturnType::Continue => (),
+                            ReturnType::Break => break,
+                            ReturnType::Exit => return Ok(ReturnType::Exit),
+                        }
+                    }
+                },
+                Instruction::LoopFor { cond, body, step } => {
+                    while self.eval(cond, context)?.is_truthy() {
+                        match self.execute(body, context)? {
+                            ReturnType::Normal | ReturnType::Continue => {
+                                self.execute(step, context)?;
+                            },
+                            ReturnType::Break => break,
+                            ReturnType::Exit => return Ok(ReturnType::Exit),
+                        }
+                    }
+                },
+                Instruction::Return { return_type } => return Ok(*return_type),
+                Instruction::Repeat { count, body } => {
+                    for _ in 0..self.eval(count, context)?.round() {
+                        match self.execute(body, context)? {
+                            ReturnType::Normal | ReturnType::Continue => (),
+                            ReturnType::Break => break,
+                            ReturnType::Exit => return Ok(ReturnType::Exit),
+                        }
+                    }
+                },
+                Instruction::Switch { input, cases, default, body } => {
+                    let input = self.eval(input, context)?;
+                    for (cond, start) in cases.iter() {
+                        if self.eval(cond, context)?.almost_equals(&input) {
+                            return Ok(match self.execute(&body[*start..], context)? {
+                                ReturnType::Break => ReturnType::Normal,
+                                x => x,
+                            })
+                        }
+                    }
+                    if let Some(start) = default {
+                        return Ok(match self.execute(&body[*start..], context)? {
+                            ReturnType::Break => ReturnType::Normal,
+                            x => x,
+                        })
+                    }
+                },
+                Instruction::With { target, body } => {
+                    let (old_this, old_other) = (context.this, context.other);
+                    let target = i32::from(self.eval(target, context)?);
+                    context.other = context.this;
+                    let mut instances = Vec::new();
+                    match target {
+                        gml::SELF | gml::UNSPECIFIED => instances.push(old_this),
+                        gml::OTHER => instances.push(old_other),
+                        gml::ALL => {
+                            let mut iter = self.instance_list.iter_by_drawing();
+                            while let Some(instance) = iter.next(self.instance_list) {
+                                instances.push(instance);
+                            }
+                        },
+                        i if i < 0 => (),
+                        i if i < 100_000 => {
+                            let mut iter = self.instance_list.iter_by_identity(i);
+                            while let Some(instance) = iter.next(self.instance_list) {
+                                instances.push(instance);
+                            }
+                        },
+                        i => instances.extend(self.instance_list.get_by_instid(i)),
+                    }
+                    for instance in instances {
+                        context.this = instance;
+                        match self.execute(body, context)? {
+                            ReturnType::Normal | ReturnType::Continue => (),
+                            ReturnType::Break => break,
+                            ReturnType::Exit => {
+                                context.this = old_this;
+                                context.other = old_other;
+                                return Ok(ReturnType::Exit)
+                            },
+                        }
+                    }
+                    context.this = old_this;
+                    context.other = old_other;
+                },
+                Instruction::RuntimeError { error } => return Err(error.clone()),
+                Instruction::Line { .. } => (),
+                _ => panic!("{:?} needs a game to run", instruction),
+            }
+            Ok(ReturnType::Normal)
+        }
+
+        fn eval(&self, node: &Node, context: &mut Context) -> gml::Result<Value> {
+            match node {
+                Node::Literal { value } => Ok(value.clone()),
+                Node::Field { accessor: FieldAccessor { index, array, owner: InstanceIdentifier::Local } } => {
+                    let array_index = self.get_array_index(array, context)?;
+                    let value = context.locals.fields.get(index).and_then(|x| x.get(array_index));
+                    Ok(value.expect("uninitialized locals need a game to report them"))
+                },
+                Node::ValueFunction { args, function } => {
+                    let mut arg_values: [Value; 16] = Default::default();
+                    for (src, dest) in args.iter().zip(arg_values.iter_mut()) {
+                        *dest = self.eval(src, context)?;
+                    }
+                    function.0(&arg_values[..args.len()])
+                },
+                Node::Binary { left, right, operator, type_unsafe } => {
+                    let left = self.eval(left, context)?;
+                    match operator.call(left.clone(), self.eval(right, context)?) {
+                        res @ Ok(_) => res,
+                        Err(_) if *type_unsafe => Ok(left),
+                        res => res,
+                    }
+                },
+                Node::Unary { child, operator } => operator.call(self.eval(child, context)?),
+                Node::RuntimeError { error } => Err(error.clone()),
+                _ => panic!("{:?} needs a game to run", node),
+            }
+        }
+
+        fn get_array_index(&self, accessor: &ArrayAccessor, context: &mut Context) -> gml::Result<u32> {
+            let check = |index: i32| {
+                if index < 0 || index >= 32000 { Err(Error::InvalidArrayIndex(index)) } else { Ok(index as u32) }
+            };
+            match accessor {
+                ArrayAccessor::None => Ok(0),
+                ArrayAccessor::Single(node) => check(self.eval(node, context)?.round()),
+                ArrayAccessor::Double(node1, node2) => {
+                    let index1 = self.eval(node1, context)?.round();
+                    let index2 = self.eval(node2, context)?.round();
+                    Ok(check(index1)? * 32000 + check(index2)?)
+                },
+            }
+        }
+    }
+
+    /// Runs some GML on both the Vm and the tree-walking interpreter with the instances above,
+    /// and checks that they end up the same.
+    fn same(source: &str) {
+        let list = instances();
+        let this = list.iter_by_drawing().next(&list).unwrap();
+        let instructions = Compiler::new().compile_instructions(source.as_bytes()).unwrap();
+        let mut expected_context = Context::with_single_instance(this);
+        let expected = TreeWalker { instance_list: &list }.execute(&instructions, &mut expected_context);
+        let mut context = Context::with_single_instance(this);
+        let result = run(source, &mut context, &list);
+        assert_eq!(format!("{:?}", result), format!("{:?}", expected), "{}", source);
+        assert_eq!(context.return_value, expected_context.return_value, "{}", source);
+        assert_eq!((context.this, context.other), (expected_context.this, expected_context.other), "{}", source);
+    }
+
+    #[test]
+    fn precedence() {
+        let vars = "var a, b, c; a = 2; b = 3; c = 4;";
+        assert_eq!(result(&format!("{} return a + b * c;", vars)), Value::from(14));
+        assert_eq!(result(&format!("{} return (a + b) * c;", vars)), Value::from(20));
+        assert_eq!(result(&format!("{} return c - b - a;", vars)), Value::from(-1));
+        assert_eq!(result(&format!("{} return a * b mod c;", vars)), Value::from(2));
+        assert_eq!(result(&format!("{} return a + b << 1;", vars)), Value::from(10));
+        // GM8 gives all the bitwise operators the same precedence
+        assert_eq!(result(&format!("{} return a | b & c;", vars)), Value::from(0));
+        assert_eq!(result(&format!("{} return a < b == 1;", vars)), Value::from(1));
+        // and the boolean ones too, so this is (a == 3 || b == 3) && c == 3
+        assert_eq!(result(&format!("{} return a == 3 || b == 3 && c == 3;", vars)), Value::from(0));
+        assert_eq!(result(&format!("{} return a == 2 && b == 2 || c == 4;", vars)), Value::from(1));
+    }
+
+    #[test]
+    fn no_short_circuit() {
+        // Both sides are always evaluated, so the bad right-hand side fails even when the left decides it
+        assert!(fails(r#"var a, s; a = 0; s = "s"; return a != 0 && s + 1;"#));
+        assert!(fails(r#"var a, s; a = 1; s = "s"; return a == 1 || s + 1;"#));
+        assert_eq!(result("var a, b; a = 0; b = 1; return a && b / a;"), Value::from(0));
+    }
+
+    #[test]
+    fn real_string_comparison() {
+        let vars = r#"var a, s; a = 1; s = "1";"#;
+        assert!(fails(&format!("{} return a == s;", vars)));
+        assert!(fails(&format!("{} return s < a;", vars)));
+        assert_eq!(result(&format!(r#"{} return s == "1";"#, vars)), Value::from(1));
+        assert_eq!(result(&format!(r#"{} return s < "2";"#, vars)), Value::from(1));
+        // Strings are never truthy
+        assert_eq!(result(&format!("{} if (s) return 1; else return 2;", vars)), Value::from(2));
+        // switch doesn't complain about mismatched types, it just doesn't match them
+        assert_eq!(
+            result(&format!("{} switch (s) {{ case a: return 1; case \"1\": return 2; }} return 3;", vars)),
+            Value::from(2),
+        );
+        assert_eq!(result(&format!("{} switch (s) {{ case a: return 1; }} return 3;", vars)), Value::from(3));
+        // += ignores errors in its + and leaves the variable alone
+        assert_eq!(result(&format!("{} a += s; return a;", vars)), Value::from(1));
+    }
+
+    #[test]
+    fn loops() {
+        let result = |source: &str| result(&format!("var i, n; n = 0; {}", source));
+        assert_eq!(
+            result("for (i = 0; i < 10; i += 1) { if (i == 5) break; if (i mod 2 == 0) continue; n += i; } return n;"),
+            Value::from(4),
+        );
+        assert_eq!(
+            result("i = 0; while (i < 9) { i += 1; if (i mod 3 == 0) continue; n += 1; if (n == 4) break; } return i;"),
+            Value::from(5),
+        );
+        // continue goes back to the start of a do-until without checking the condition
+        assert_eq!(result("i = 0; do { i += 1; if (i < 10) continue; } until (true); return i;"), Value::from(10));
+        assert_eq!(result("repeat (2.6) n += 1; return n;"), Value::from(3));
+        assert_eq!(result("repeat (4) { n += 1; if (n == 3) break; } return n;"), Value::from(3));
+        assert_eq!(result("repeat (4) { n += 1; continue; n += 10; } return n;"), Value::from(4));
+        assert_eq!(result("repeat (3) repeat (3) { n += 1; break; } return n;"), Value::from(3));
+        // break leaves a switch, but continue goes to the loop around it
+        assert_eq!(
+            result("for (i = 0; i < 4; i += 1) { switch (i) { case 1: continue; case 2: break; } n += 1; } return n;"),
+            Value::from(3),
+        );
+    }
+
+    #[test]
+    fn exit() {
+        let mut context = Context::default();
+        let source = "var n; n = 0; repeat (5) { n += 1; if (n == 2) return n * 10; } return -1;";
+        assert_eq!(run(source, &mut context, &InstanceList::new()).unwrap(), ReturnType::Exit);
+        assert_eq!(context.return_value, Value::from(20));
+
+        let source = "var n; n = 0; while (true) { n += 1; if (n == 3) exit; }";
+        assert_eq!(run(source, &mut context, &InstanceList::new()).unwrap(), ReturnType::Exit);
+        assert_eq!(context.return_value, Value::from(3));
+
+        // A break outside of any loop stops the script too
+        let source = "var n; n = 1; break; n = 2;";
+        assert_eq!(run(source, &mut context, &InstanceList::new()).unwrap(), ReturnType::Break);
+        assert_eq!(context.return_value, Value::from(1));
+    }
+
+    #[test]
+    fn with() {
+        assert_eq!(count_with("with (all) n += 1;"), Value::from(3));
+        assert_eq!(count_with("with (3) n += 1;"), Value::from(3));
+        assert_eq!(count_with("with (4) n += 1;"), Value::from(0));
+        assert_eq!(count_with("with (100002) n += 1;"), Value::from(1));
+        assert_eq!(count_with("with (100004) n += 1;"), Value::from(0));
+        assert_eq!(count_with("with (self) n += 1;"), Value::from(1));
+        assert_eq!(count_with("with (other) n += 1;"), Value::from(1));
+        assert_eq!(count_with("with (noone) n += 1;"), Value::from(0));
+        assert_eq!(count_with("with (all) { n += 1; if (n == 2) break; }"), Value::from(2));
+        assert_eq!(count_with("with (all) { n += 1; continue; n += 10; }"), Value::from(3));
+        assert_eq!(count_with("with (self) { n += 1; continue; n += 10; }"), Value::from(1));
+        assert_eq!(count_with("with (all) with (all) n += 1;"), Value::from(9));
+        assert_eq!(count_with("repeat (2) with (all) { n += 1; break; }"), Value::from(2));
+
+        // self and other are put back when leaving a with early
+        let list = instances();
+        let this = list.iter_by_drawing().next(&list).unwrap();
+        let mut context = Context::with_single_instance(this);
+        let source = "var n; n = 0; with (all) with (other) { n += 1; if (n == 2) exit; }";
+        assert_eq!(run(source, &mut context, &list).unwrap(), ReturnType::Exit);
+        assert_eq!((context.this, context.other), (this, this));
+    }
+    #[test]
+    fn matches_tree_walker() {
+        let vars = r#"var a, b, c, s, t; a = 2; b = 3.5; c = -4; s = "1"; t = "two";"#;
+        for expr in [
+            "a + b * c",
+            "a / c - b div a mod c",
+            "a << 3 >> 1 | b & c ^ 5",
+            "!a || b xor c && -a < ~c",
+            "s + t",
+            "s < t",
+            "s == a",
+            "t - 1",
+            "a / 0",
+            "color_get_red(make_color_rgb(a * 100, b, c)) + make_color_rgb(s, 0, 0)",
+        ] {
+            same(&format!("{} return {};", vars, expr));
+        }
+        same(&format!("{} a += s; b *= 2; c -= t; return a + b + c;", vars));
+
+        let loops = "var i, n; n = 0;";
+        for source in [
+            "for (i = 0; i < 10; i += 1) { if (i == 5) break; if (i mod 2 == 0) continue; n += i; }",
+            "i = 0; while (i < 9) { i += 1; if (i mod 3 == 0) continue; n += 1; if (n == 4) break; }",
+            "i = 0; do { i += 1; if (i < 10) continue; } until (true);",
+            "repeat (2.6) n += 1; repeat (-3) n += 10;",
+            "repeat (3) repeat (3) { n += 1; break; }",
+            "for (i = 0; i < 4; i += 1) { switch (i) { case 1: continue; case 2: break; } n += 1; }",
+            "for (i = 0; i < 4; i += 1) switch (i) { case 0: n += 1; case 1: n += 10; break; default: n += 100; }",
+            "repeat (5) { n += 1; if (n == 2) exit; }",
+            "n = 1; break; n = 2;",
+        ] {
+            same(&format!("{} {} return n;", loops, source));
+        }
+
+        for source in [
+            "with (all) n += 1;",
+            "with (3) { n += 1; if (n == 2) break; }",
+            "with (100002) n += 1; with (100004) n += 1; with (noone) n += 1;",
+            "with (all) with (other) { n += 1; if (n == 2) exit; }",
+            "with (all) with (all) n += 1;",
+            "with (all) { n += 1; n += "s"; }",
+            "with (all) n = n - "s";",
+        ] {
+            same(&format!("var n; n = 0; {} return n;", source));
+        }
+    }
+
+    #[test]
+    fn arrays_match_tree_walker() {
+        let arrays = "var a, n; n = 0; a = 1;";
+        for source in [
+            "a[3] = 4; a[1, 2] = 5; return a[3] + a[1, 2] + a;",
+            "a[2.6] = 7; return a[3];",
+            "a[31999] = 1; a[1, 31999] = 2; return a[31999] + a[1, 31999];",
+            "return a[-1];",
+            "return a[32000, 0];",
+            // The index is checked before the value is evaluated, so a bad index is what gets reported
+            "a[-1] = "s" + 1;",
+            "a[32000] = 1 / "s";",
+            "a[0, 32000] = -"s";",
+            "a[-1, 0] = 1; n = 1;",
+            "a["s" + 1] = 1;",
+            "a[0] = "s" + 1;",
+            "a[1] = 1; a[1] += "s"; return a[1];",
+        ] {
+            same(&format!("{} {}", arrays, source));
+        }
+    }
+    #[test]
+    fn folding_matches_runtime() {
+        // Each operation runs once on literals, which get folded when compiling, and once on locals, which don't
+        for (lhs, operator, rhs) in [
+            ("7", "div", "2"),
+            ("-7", "div", "2"),
+            ("7.5", "mod", "2"),
+            ("-7", "mod", "2"),
+            ("2.5", "|", "0"),
+            ("3.5", "^", "1.5"),
+            ("1.4", "<<", "2.6"),
+            ("1", "/", "3"),
+            ("1", "/", "0"),
+            ("1", "div", "0"),
+            ("1", "mod", "0"),
+            ("0.4", "&&", "1"),
+            ("2", "<", "2.5"),
+            ("3", "*", r#""ab""#),
+            (r#""ab""#, "+", r#""c""#),
+            (r#""a""#, "+", "1"),
+            ("1", "-", r#""a""#),
+            (r#""a""#, "<", "1"),
+            (r#""a""#, "div", r#""b""#),
+        ] {
+            let literals = format!("return {} {} {};", lhs, operator, rhs);
+            let program = Compiler::new().compile(literals.as_bytes()).unwrap();
+            assert!(!program.ops.iter().any(|op| matches!(op, Op::Binary { .. })), "{} wasn't folded", literals);
+            let locals = format!("var a, b; a = {}; b = {}; return a {} b;", lhs, rhs, operator);
+            let (mut folded, mut unfolded) = (Context::default(), Context::default());
+            assert_eq!(
+                format!("{:?}", run(&literals, &mut folded, &InstanceList::new())),
+                format!("{:?}", run(&locals, &mut unfolded, &InstanceList::new())),
+                "{}",
+                literals,
+            );
+            assert_eq!(format!("{:?}", folded.return_value), format!("{:?}", unfolded.return_value), "{}", literals);
         }
     }
 }
@@ -0,0 +1,259 @@
+use crate::{
+    game::{Game, GetAsset},
+    gml::{self, ev},
+};
+use gml_parser::lexer::Position;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// A piece of GML code which can appear in a call stack.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Source {
+    /// A script, by name
+    Script(String),
+    /// A drag-n-drop action, by its index in the action list
+    Action(usize),
+    /// An object's event, owning the actions below it
+    Event { object: String, event: String },
+    /// A timeline moment, owning the actions below it
+    Timeline { timeline: String, moment: i32 },
+    /// The creation code of a room
+    RoomCreation(String),
+    /// The creation code of an instance placed in a room
+    InstanceCreation(i32),
+    /// Code passed to execute_string()
+    ExecuteString,
+    /// The condition of a trigger, by name
+    Trigger(String),
+    /// The expression giving the value of a user-defined constant, by name
+    Constant(String),
+}
+
+/// One level of a GML call stack, along with the line of code that was being executed there if there was one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Frame {
+    pub source: Source,
+    pub pos: Option<Position>,
+}
+
+/// A runtime error along with where it happened.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trace {
+    pub error: gml::Error,
+
+    /// Position of the line that errored, if no frame has claimed it yet
+    pub pos: Option<Position>,
+
+    /// The call stack, innermost frame first
+    pub frames: Vec<Frame>,
+}
+
+impl gml::Error {
+    /// Notes the position of the line of code this error happened on, unless it's already known.
+    pub fn at(self, pos: Position) -> Self {
+        match self {
+            Self::Traced(mut trace) => {
+                trace.pos.get_or_insert(pos);
+                Self::Traced(trace)
+            },
+            error => Self::Traced(Box::new(Trace { error, pos: Some(pos), frames: Vec::new() })),
+        }
+    }
+
+    /// Adds a frame to this error's call stack. The frame takes the position of the line which errored inside it.
+    pub fn within(self, source: Source) -> Self {
+        match self {
+            Self::Traced(mut trace) => {
+                let pos = trace.pos.take();
+                trace.frames.push(Frame { source, pos });
+                Self::Traced(trace)
+            },
+            error => Self::Traced(Box::new(Trace { error, pos: None, frames: vec![Frame { source, pos: None }] })),
+        }
+    }
+
+    /// Formats this error the same way GM8's error dialog would.
+    pub fn dialog_text(&self) -> String {
+        let trace = match self {
+            Self::Traced(trace) => trace,
+            error => return format!("___________________________________________\nERROR in\n{}", error),
+        };
+
+        let mut text = String::from("___________________________________________\nERROR in\n");
+        let mut frames = trace.frames.iter().rev().peekable();
+        match frames.peek().copied().map(|x| &x.source) {
+            Some(Source::Event { object, event }) => {
+                frames.next();
+                if let Some(Frame { source: Source::Action(index), .. }) = frames.peek() {
+                    text += &format!("action number {}\n", index + 1);
+                }
+                text += &format!("of {}\nfor object {}:\n", event, object);
+            },
+            Some(Source::Timeline { timeline, moment }) => {
+                frames.next();
+                if let Some(Frame { source: Source::Action(index), .. }) = frames.peek() {
+                    text += &format!("action number {}\n", index + 1);
+                }
+                text += &format!("at moment {}\nof timeline {}:\n", moment, timeline);
+            },
+            Some(Source::RoomCreation(room)) => text += &format!("creation code of room {}:\n", room),
+            Some(Source::InstanceCreation(id)) => text += &format!("creation code of instance {}:\n", id),
+            _ => text += "code:\n",
+        }
+        text.push('\n');
+
+        let innermost = trace.frames.first();
+        if let Some(Frame { source: Source::Script(name), .. }) = innermost {
+            text += &format!("In script {}:\n", name);
+        }
+        match innermost.and_then(|x| x.pos).or(trace.pos) {
+            Some(pos) => {
+                text += &format!("Error in code at line {}:\n\nat position {}: {}\n", pos.line, pos.column, trace.error)
+            },
+            None => text += &format!("{}\n", trace.error),
+        }
+        text
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Script(name) => write!(f, "script {}", name),
+            Self::Action(index) => write!(f, "action number {}", index + 1),
+            Self::Event { object, event } => write!(f, "{} for object {}", event, object),
+            Self::Timeline { timeline, moment } => write!(f, "moment {} of timeline {}", moment, timeline),
+            Self::RoomCreation(room) => write!(f, "creation code of room {}", room),
+            Self::InstanceCreation(id) => write!(f, "creation code of instance {}", id),
+            Self::ExecuteString => write!(f, "execute_string"),
+            Self::Trigger(trigger) => write!(f, "condition of trigger {}", trigger),
+            Self::Constant(constant) => write!(f, "value of constant {}", constant),
+        }
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(pos) = self.pos {
+            write!(f, "\n    at line {}, position {}", pos.line, pos.column)?;
+        }
+        for frame in &self.frames {
+            write!(f, "\n    in {}", frame.source)?;
+            if let Some(pos) = frame.pos {
+                write!(f, " at line {}, position {}", pos.line, pos.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Game {
+    /// Makes a call stack frame for a script.
+    pub fn script_source(&self, script_id: usize) -> Source {
+        match self.assets.scripts.get(script_id).and_then(Option::as_ref) {
+            Some(script) => Source::Script(self.decode_str(script.name.as_ref()).into_owned()),
+            None => Source::Script(format!("<{}>", script_id)),
+        }
+    }
+
+    /// Makes a call stack frame for a timeline moment.
+    pub fn timeline_source(&self, timeline_id: i32, moment: i32) -> Source {
+        let timeline = match self.assets.timelines.get_asset(timeline_id) {
+            Some(timeline) => self.decode_str(timeline.name.as_ref()).into_owned(),
+            None => format!("<{}>", timeline_id),
+        };
+        Source::Timeline { timeline, moment }
+    }
+
+    /// Makes a call stack frame for an object's event, using the names GM8 shows in its error dialog.
+    pub fn event_source(&self, object_id: i32, event_type: usize, event_number: u32) -> Source {
+        let object = match self.assets.objects.get_asset(object_id) {
+            Some(object) => self.decode_str(object.name.as_ref()).into_owned(),
+            None => format!("<{}>", object_id),
+        };
+        let event = event_name(
+            event_type,
+            event_number,
+            |id| self.assets.objects.get_asset(id).map(|x| self.decode_str(x.name.as_ref()).into_owned()),
+            |id| self.assets.triggers.get_asset(id).map(|x| self.decode_str(x.name.as_ref()).into_owned()),
+        );
+        Source::Event { object, event }
+    }
+}
+
+/// Names an event the way GM8 does in its error dialog. Objects and triggers are named by looking up their IDs.
+pub fn event_name(
+    event_type: usize,
+    event_number: u32,
+    object_name: impl Fn(i32) -> Option<String>,
+    trigger_name: impl Fn(i32) -> Option<String>,
+) -> String {
+    match event_type {
+        ev::CREATE => "Create Event".into(),
+        ev::DESTROY => "Destroy Event".into(),
+        ev::ALARMS => format!("Alarm Event for alarm {}", event_number),
+        ev::STEP => match event_number {
+            1 => "Begin Step Event".into(),
+            2 => "End Step Event".into(),
+            _ => "Step Event".into(),
+        },
+        ev::COLLISION => match object_name(event_number as i32) {
+            Some(target) => format!("Collision Event with object {}", target),
+            None => format!("Collision Event with object <{}>", event_number),
+        },
+        ev::KEYBOARD => format!("Keyboard Event for key {}", event_number),
+        ev::MOUSE => format!("Mouse Event for {}", mouse_event_name(event_number)),
+        ev::OTHER => format!("Other Event: {}", other_event_name(event_number)),
+        ev::DRAW => "Draw Event".into(),
+        ev::KEYPRESS => format!("Key Press Event for key {}", event_number),
+        ev::KEYRELEASE => format!("Key Release Event for key {}", event_number),
+        ev::TRIGGER => match trigger_name(event_number as i32) {
+            Some(trigger) => format!("Trigger Event: {}", trigger),
+            None => format!("Trigger Event {}", event_number),
+        },
+        _ => format!("Event {} {}", event_type, event_number),
+    }
+}
+
+fn mouse_event_name(event_number: u32) -> String {
+    let button = |n| match n % 3 {
+        0 => "Left",
+        1 => "Right",
+        _ => "Middle",
+    };
+    match event_number {
+        0..=2 => format!("{} Button", button(event_number)),
+        3 => "No Button".into(),
+        4..=6 => format!("{} Pressed", button(event_number - 4)),
+        7..=9 => format!("{} Released", button(event_number - 7)),
+        10 => "Mouse Enter".into(),
+        11 => "Mouse Leave".into(),
+        50..=52 => format!("Glob {} Button", button(event_number - 50)),
+        53..=55 => format!("Glob {} Pressed", button(event_number - 53)),
+        56..=58 => format!("Glob {} Released", button(event_number - 56)),
+        60 => "Mouse Wheel Up".into(),
+        61 => "Mouse Wheel Down".into(),
+        n => format!("event {}", n),
+    }
+}
+
+fn other_event_name(event_number: u32) -> String {
+    match event_number {
+        0 => "Outside Room".into(),
+        1 => "Intersect Boundary".into(),
+        2 => "Game Start".into(),
+        3 => "Game End".into(),
+        4 => "Room Start".into(),
+        5 => "Room End".into(),
+        6 => "No More Lives".into(),
+        7 => "Animation End".into(),
+        8 => "End of Path".into(),
+        9 => "No More Health".into(),
+        10..=25 => format!("User Defined {}", event_number - 10),
+        30 => "Close Button".into(),
+        40..=47 => format!("Outside View {}", event_number - 40),
+        50..=57 => format!("Boundary View {}", event_number - 50),
+        n => format!("event {}", n),
+    }
+}
@@ -56,6 +56,10 @@ impl<T> HandleList<T> {
     pub fn put(&mut self, handle: T) -> i32 {
         self.add(handle).unwrap()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().flatten()
+    }
 }
 
 impl<T, const LEN: usize> HandleArray<T, LEN> {
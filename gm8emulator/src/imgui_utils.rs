@@ -1,6 +1,9 @@
-use crate::types::Colour;
+use crate::{
+    render::{atlas::AtlasRef, PrimitiveType, Renderer},
+    types::Colour,
+};
 use clipboard::{ClipboardContext, ClipboardProvider};
-use imgui::{self, ImColor32, TableColumnSetup, WindowToken};
+use imgui::{self, internal::RawWrapper, DrawCmd, ImColor32, TableColumnSetup, WindowToken};
 
 #[derive(Clone, Copy)]
 pub struct Vec2<T>(pub T, pub T);
@@ -375,3 +378,52 @@ impl imgui::ClipboardBackend for EmuClipboardProvider {
         }
     }
 }
+
+/// Draws everything imgui has rendered this frame using the game's renderer
+pub fn render_draw_data(renderer: &mut Renderer, draw_data: &imgui::DrawData) {
+    for draw_list in draw_data.draw_lists() {
+        //let draw_list: &DrawList = draw_list;
+        let vertex_buffer = draw_list.vtx_buffer();
+        let index_buffer = draw_list.idx_buffer();
+        for cmd in draw_list.commands() {
+            match cmd {
+                DrawCmd::Elements { count, cmd_params } => {
+                    // TODO: don't use the primitive builder for this, it allocates a lot and
+                    // also doesn't do instanced drawing I think?
+                    renderer.reset_primitive_2d(
+                        PrimitiveType::TriList,
+                        if cmd_params.texture_id.id() == 0 {
+                            None
+                        } else {
+                            Some(AtlasRef(cmd_params.texture_id.id() as i32))
+                        },
+                    );
+
+                    for i in 0..count {
+                        let vert: imgui::DrawVert =
+                            vertex_buffer[cmd_params.vtx_offset + usize::from(index_buffer[i + cmd_params.idx_offset])];
+                        renderer.vertex_2d(
+                            f64::from(vert.pos[0]) - 0.5,
+                            f64::from(vert.pos[1]) - 0.5,
+                            vert.uv[0].into(),
+                            vert.uv[1].into(),
+                            i32::from(vert.col[0]) | (i32::from(vert.col[1]) << 8) | (i32::from(vert.col[2]) << 16),
+                            f64::from(vert.col[3]) / 255.0,
+                        );
+                    }
+
+                    let clip_x = cmd_params.clip_rect[0] as i32;
+                    let clip_y = cmd_params.clip_rect[1] as i32;
+                    let clip_w = (cmd_params.clip_rect[2] - cmd_params.clip_rect[0]) as i32 + 1;
+                    let clip_h = (cmd_params.clip_rect[3] - cmd_params.clip_rect[1]) as i32 + 1;
+                    renderer.set_view(clip_x, clip_y, clip_w, clip_h, 0.0, clip_x, clip_y, clip_w, clip_h);
+                    renderer.draw_primitive_2d();
+                },
+                DrawCmd::RawCallback { callback, raw_cmd } => {
+                    unsafe { callback(draw_list.raw(), raw_cmd) };
+                },
+                DrawCmd::ResetRenderState => {},
+            }
+        }
+    }
+}
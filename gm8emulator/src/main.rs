@@ -14,26 +14,50 @@ mod tile;
 mod types;
 mod util;
 
+use encoding_rs::Encoding;
 use game::{
+    encoding,
+    profiler::Profiler,
+    recording::ProjectConfig,
+    replay::{
+        self,
+        text::{key_name, parse_key},
+    },
     savestate::{self, SaveState},
-    Game, GameClock, PlayType, Replay,
+    search::{Outcome, Search, Settings},
+    shell, Game, GameClock, PlayType, Replay,
 };
 use std::{
     env, fs,
     path::{Path, PathBuf},
     process,
+    sync::{
+        atomic::{self, AtomicUsize},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 const EXIT_SUCCESS: i32 = 0;
 const EXIT_FAILURE: i32 = 1;
 
+fn program_name(argv0: &str) -> &str {
+    match Path::new(argv0).file_name() {
+        Some(file) => file.to_str().unwrap_or(argv0),
+        None => argv0,
+    }
+}
+
 fn help(argv0: &str, opts: getopts::Options) {
+    let name = program_name(argv0);
     print!(
         "{}",
-        opts.usage(&format!("Usage: {} FILE [options]", match Path::new(argv0).file_name() {
-            Some(file) => file.to_str().unwrap_or(argv0),
-            None => argv0,
-        }))
+        opts.usage(&format!(
+            "Usage: {} FILE [options]\n       {} lint FILE [options]\n       {} compat FILE [options]\n       \
+            {} search FILE [options]",
+            name, name, name, name
+        ))
     );
 }
 
@@ -44,6 +68,12 @@ fn main() {
 fn xmain() -> i32 {
     let args: Vec<String> = env::args().collect();
     let process = args[0].clone();
+    match args.get(1).map(String::as_str) {
+        Some("lint") => return lint(&process, &args[2..]),
+        Some("compat") => return compat(&process, &args[2..]),
+        Some("search") => return search(&process, &args[2..]),
+        _ => (),
+    }
 
     let mut opts = getopts::Options::new();
     opts.optflag("h", "help", "prints this help message");
@@ -56,6 +86,10 @@ fn xmain() -> i32 {
     opts.optopt("f", "replay-file", "path to savestate file to replay", "FILE");
     opts.optopt("o", "output-file", "output savestate name in replay mode", "FILE.bin");
     opts.optmulti("a", "game-arg", "argument to pass to the game", "ARG");
+    opts.optopt("x", "exec-policy", "what to do when the game runs programs: allow, deny or emulate", "POLICY");
+    opts.optopt("e", "encoding", "text encoding of GM8.0 games, e.g. windows-1251 (guessed if not given)", "NAME");
+    opts.optopt("", "profile", "time the game's GML and write a report to FILE when it exits", "FILE");
+    opts.optflag("", "ignore-checksum", "play a .gmtas or project made on a different version of the game");
     opts.optflagopt("p", "start-save", "Either loads the savestate specified after this parameter or starts at the first frame. If a .gmtas is specified by -f this will start the replay from this savestate instead", "savestate");
 
     let matches = match opts.parse(&args[1..]) {
@@ -92,9 +126,28 @@ fn xmain() -> i32 {
         .unwrap_or(0);
     let frame_limiter = !matches.opt_present("l");
     let verbose = matches.opt_present("v");
+    let ignore_checksum = matches.opt_present("ignore-checksum");
     let output_bin = matches.opt_str("o").map(PathBuf::from);
     let pause = matches.opt_present("p");
     let start_save_path = matches.opt_str("p").map(PathBuf::from);
+    let shell_policy = match matches.opt_str("x").map(|policy| policy.parse::<shell::Policy>()).transpose() {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("invalid policy for -x: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let encoding_arg = match matches.opt_str("e") {
+        Some(name) => match Encoding::for_label(name.as_bytes()) {
+            Some(encoding) => Some(encoding),
+            None => {
+                eprintln!("unknown encoding for -e: {}", name);
+                return EXIT_FAILURE
+            },
+        },
+        None => None,
+    };
+    let profile_path = matches.opt_str("profile").map(PathBuf::from);
     let project_path = matches.opt_str("n").map(|name| {
         let mut p = env::current_dir().expect("std::env::current_dir() failed");
         p.push("projects");
@@ -136,27 +189,35 @@ fn xmain() -> i32 {
             })
     });
     let can_clear_temp_dir = temp_dir.is_none();
-    let replay = match matches
+    let (replay, metadata) = match matches
         .opt_str("f")
         .map(|filename| {
             let filepath = PathBuf::from(&filename);
             match filepath.extension().and_then(|x| x.to_str()) {
-                Some("bin") => match SaveState::from_file(&filepath, &mut savestate::Buffer::new()) {
-                    Ok(state) => Ok(state.into_replay()),
+                // Only the replay is needed, which can still be read if the rest of the savestate can't be
+                Some("bin") => match SaveState::replay_from_file(&filepath, &mut savestate::Buffer::new()) {
+                    Ok(replay) => Ok((replay, None)),
                     Err(e) => Err(format!("couldn't load {:?}: {:?}", filepath, e)),
                 },
 
-                Some("gmtas") => match Replay::from_file(&filepath) {
+                Some("gmtas") => match Replay::from_file_with_metadata(&filepath) {
                     Ok(replay) => Ok(replay),
                     Err(e) => Err(format!("couldn't load {:?}: {:?}", filepath, e)),
                 },
 
-                _ => Err("unknown filetype for -f, expected '.bin' or '.gmtas'".into()),
+                Some("gmtxt") => match Replay::from_text_file(&filepath) {
+                    Ok(replay) => Ok((replay, None)),
+                    Err(replay::ReadError::ParseErr(e)) => Err(format!("couldn't load {:?}: {}", filepath, e)),
+                    Err(e) => Err(format!("couldn't load {:?}: {:?}", filepath, e)),
+                },
+
+                _ => Err("unknown filetype for -f, expected '.bin', '.gmtas' or '.gmtxt'".into()),
             }
         })
         .transpose()
     {
-        Ok(r) => r,
+        Ok(Some((replay, metadata))) => (Some(replay), metadata),
+        Ok(None) => (None, None),
         Err(e) => {
             eprintln!("{}", e);
             return EXIT_FAILURE
@@ -212,6 +273,24 @@ fn xmain() -> i32 {
         },
     };
 
+    // In record mode, the project's replay is checked instead of anything given with -f
+    let game_hash = game_hash(&file, &assets);
+    let project_replay =
+        project_path.as_ref().and_then(|path| match Replay::from_file_with_metadata(&path.join("backup.gmtas")) {
+            Ok((replay, Some(metadata))) => Some((replay, metadata)),
+            _ => None,
+        });
+    let checked = if project_path.is_some() {
+        project_replay.as_ref().map(|(replay, metadata)| (replay, metadata))
+    } else {
+        replay.as_ref().zip(metadata.as_ref())
+    };
+    if let Some((replay, metadata)) = checked {
+        if !check_replay(replay, metadata, game_hash, ignore_checksum, verbose) {
+            return EXIT_FAILURE
+        }
+    }
+
     let absolute_path = match file_path.canonicalize() {
         Ok(p) => p,
         Err(e) => {
@@ -220,7 +299,17 @@ fn xmain() -> i32 {
         },
     };
 
-    let encoding = encoding_rs::SHIFT_JIS; // TODO: argument
+    // An encoding given on the command line takes priority, then whatever the project used last time
+    let encoding = match encoding_arg.or_else(|| project_path.as_deref().and_then(ProjectConfig::saved_encoding)) {
+        Some(encoding) => encoding,
+        None => match assets.version {
+            gm8exe::GameVersion::GameMaker8_0 => encoding::detect(&assets),
+            gm8exe::GameVersion::GameMaker8_1 => encoding_rs::SHIFT_JIS, // only used for fonts without a charset
+        },
+    };
+    if verbose {
+        println!("using text encoding {}", encoding.name());
+    }
 
     let play_type = if project_path.is_some() {
         PlayType::Record
@@ -247,6 +336,14 @@ fn xmain() -> i32 {
         },
     };
 
+    components.game_hash = game_hash;
+    if let Some(policy) = shell_policy {
+        components.shell_policy = policy;
+    }
+    if let Some(path) = profile_path {
+        components.profiler = Some(Box::new(Profiler::new(Some(path))));
+    }
+
     let time_now = GameClock::SpoofedNanos(gml::datetime::now_as_nanos());
 
     if let Err(err) = if let Some(path) = project_path {
@@ -281,8 +378,369 @@ fn xmain() -> i32 {
         result
     } {
         println!("Runtime error: {}", err);
+        if let Some(err) = err.downcast_ref::<gml::Error>() {
+            println!("{}", err.dialog_text());
+        }
         EXIT_FAILURE
     } else {
         EXIT_SUCCESS
     }
 }
+
+/// Prints what's known about a replay, and warns about anything which could make it desync on this game.
+/// If it was recorded on a different version of the game, this fails unless `ignore_checksum` is set.
+fn check_replay(
+    replay: &Replay,
+    metadata: &replay::Metadata,
+    game_hash: u32,
+    ignore_checksum: bool,
+    verbose: bool,
+) -> bool {
+    if verbose {
+        println!(
+            "replay by {}, {} frames, {} rerecords, made with OpenGMK {}",
+            if metadata.author.is_empty() { "unknown" } else { metadata.author.as_str() },
+            metadata.frame_count,
+            metadata.rerecords,
+            metadata.emulator_version,
+        );
+        if !metadata.comments.is_empty() {
+            println!("{}", metadata.comments);
+        }
+    }
+    if metadata.game_hash != 0 && metadata.game_hash != game_hash {
+        if ignore_checksum {
+            eprintln!("warning: this replay was recorded on a different version of the game, so it may desync");
+        } else {
+            eprintln!(
+                "this replay was recorded on a different version of the game (checksum {:08x}, not {:08x}), \
+                so it will most likely desync. Use --ignore-checksum to play it anyway.",
+                metadata.game_hash, game_hash,
+            );
+            return false
+        }
+    }
+    if metadata.emulator_version != replay::EMULATOR_VERSION {
+        eprintln!(
+            "warning: this replay was made with OpenGMK {}, but this is {}, so it may desync",
+            metadata.emulator_version,
+            replay::EMULATOR_VERSION,
+        );
+    }
+    if metadata.frame_count != replay.frame_count() {
+        eprintln!(
+            "warning: this replay should have {} frames, but it has {}, so it may be damaged",
+            metadata.frame_count,
+            replay.frame_count(),
+        );
+    }
+    true
+}
+
+/// Checksums a game's data. from_exe decrypts that in place, so this is the same however the exe was packed.
+fn game_hash(exe: &[u8], assets: &gm8exe::GameAssets) -> u32 {
+    crc32fast::hash(exe.get(assets.data_range.clone()).unwrap_or_default())
+}
+
+/// Parses the arguments of a subcommand which looks at a game without running it, then loads that game's assets
+/// and works out its checksum. Its text encoding is picked the same way as when running, except that saved projects
+/// aren't looked at. If there's nothing to do after this, because it failed or only printed help, it gives the code to exit with.
+fn open_game(
+    process: &str,
+    subcommand: &str,
+    args: &[String],
+    mut opts: getopts::Options,
+) -> Result<(getopts::Matches, gm8exe::GameAssets, &'static Encoding, u32), i32> {
+    opts.optflag("h", "help", "prints this help message");
+    opts.optopt("e", "encoding", "text encoding of GM8.0 games, e.g. windows-1251 (guessed if not given)", "NAME");
+    let matches = opts.parse(args).map_err(|fail| {
+        eprintln!("{}", fail);
+        EXIT_FAILURE
+    })?;
+    let input = match matches.free.as_slice() {
+        _ if matches.opt_present("h") => {
+            print!("{}", opts.usage(&format!("Usage: {} {} FILE [options]", program_name(process), subcommand)));
+            return Err(EXIT_SUCCESS)
+        },
+        [input] => input,
+        [] => {
+            eprintln!("no input file");
+            return Err(EXIT_FAILURE)
+        },
+        [_, extra, ..] => {
+            eprintln!("unexpected second input {}", extra);
+            return Err(EXIT_FAILURE)
+        },
+    };
+    let encoding_arg = match matches.opt_str("e") {
+        Some(name) => match Encoding::for_label(name.as_bytes()) {
+            Some(encoding) => Some(encoding),
+            None => {
+                eprintln!("unknown encoding for -e: {}", name);
+                return Err(EXIT_FAILURE)
+            },
+        },
+        None => None,
+    };
+
+    let mut file = fs::read(input).map_err(|err| {
+        eprintln!("failed to open '{}': {}", input, err);
+        EXIT_FAILURE
+    })?;
+    let assets = gm8exe::reader::from_exe(&mut file, None::<fn(&str)>, false, true).map_err(|err| {
+        eprintln!("failed to load '{}' - {}", input, err);
+        EXIT_FAILURE
+    })?;
+    let encoding = match assets.version {
+        gm8exe::GameVersion::GameMaker8_0 => encoding_arg.unwrap_or_else(|| encoding::detect(&assets)),
+        gm8exe::GameVersion::GameMaker8_1 => encoding_rs::UTF_8,
+    };
+    let game_hash = game_hash(&file, &assets);
+    Ok((matches, assets, encoding, game_hash))
+}
+
+fn lint(process: &str, args: &[String]) -> i32 {
+    let (_, assets, encoding, _) = match open_game(process, "lint", args, getopts::Options::new()) {
+        Ok(game) => game,
+        Err(code) => return code,
+    };
+    let diagnostics = gml::lint::lint(&assets, encoding);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    println!("{} problem(s) found", diagnostics.len());
+    EXIT_SUCCESS
+}
+
+fn compat(process: &str, args: &[String]) -> i32 {
+    let mut opts = getopts::Options::new();
+    opts.optflag("v", "verbose", "lists where everything is used, not just what isn't implemented");
+    let (matches, assets, encoding, _) = match open_game(process, "compat", args, opts) {
+        Ok(game) => game,
+        Err(code) => return code,
+    };
+    let report = gml::compat::report(&assets, encoding);
+    if matches.opt_present("v") {
+        print!("{:#}", report);
+    } else {
+        print!("{}", report);
+    }
+    EXIT_SUCCESS
+}
+
+fn search(process: &str, args: &[String]) -> i32 {
+    let mut opts = getopts::Options::new();
+    opts.optopt("p", "start-save", "savestate to search from", "FILE.bin");
+    opts.optopt("k", "keys", "comma-separated keys which may be held, e.g. vk_left,vk_shift,Z", "KEYS");
+    opts.optopt("w", "frames", "the most frames to run each candidate for", "N");
+    opts.optopt("", "hold", "how many frames in a row the same keys are held for (default 1)", "N");
+    opts.optopt("r", "rng", "the most RNG increments to try on the first frame (default 0)", "N");
+    opts.optopt("s", "score", "GML expression to score candidates by, highest first, e.g. obj_player.x", "EXPR");
+    opts.optflag("m", "minimise", "lower scores are better");
+    opts.optopt("u", "until", "GML expression which stops a candidate once it's true", "EXPR");
+    opts.optopt("b", "best", "how many of the best candidates to report (default 5)", "N");
+    opts.optopt("j", "threads", "how many games to run at once (default one per core)", "N");
+    opts.optopt("o", "output-dir", "folder to save the best candidates' replays to as .gmtas files", "DIR");
+    let (matches, assets, encoding, game_hash) = match open_game(process, "search", args, opts) {
+        Ok(game) => game,
+        Err(code) => return code,
+    };
+
+    fn number(matches: &getopts::Matches, name: &str, default: Option<usize>) -> Result<usize, String> {
+        match matches.opt_str(name) {
+            Some(n) => n.parse().map_err(|e| format!("invalid number for --{}: {}", name, e)),
+            None => default.ok_or_else(|| format!("missing option --{}", name)),
+        }
+    }
+    let settings = (|| -> Result<Settings, String> {
+        let keys = matches
+            .opt_str("k")
+            .ok_or("missing option --keys")?
+            .split(',')
+            .map(|name| parse_key(name.trim()).ok_or_else(|| format!("unknown key for --keys: {}", name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let settings = Settings {
+            keys,
+            frames: number(&matches, "frames", None)?,
+            hold: number(&matches, "hold", Some(1))?.max(1),
+            max_rng: number(&matches, "rng", Some(0))?.try_into().map_err(|_| "too many RNG increments for --rng")?,
+            score: matches.opt_str("s").ok_or("missing option --score")?,
+            minimise: matches.opt_present("m"),
+            until: matches.opt_str("u"),
+        };
+        match settings.candidate_count() {
+            Some(_) => Ok(settings),
+            None => Err("too many candidates to search, try fewer keys or frames, or a longer --hold".into()),
+        }
+    })();
+    let (settings, best, threads) = match (
+        settings,
+        number(&matches, "best", Some(5)),
+        number(&matches, "threads", Some(thread::available_parallelism().map_or(1, |n| n.get()))),
+    ) {
+        (Ok(settings), Ok(best), Ok(threads)) => (settings, best, threads.max(1)),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("{}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let state_path = match matches.opt_str("p") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("missing option --start-save");
+            return EXIT_FAILURE
+        },
+    };
+    let output_dir = matches.opt_str("o").map(PathBuf::from);
+    let input = &matches.free[0];
+    let file_path = match Path::new(input).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Failed to resolve game path: {}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let exe = match fs::read(&file_path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("failed to open '{}': {}", input, err);
+            return EXIT_FAILURE
+        },
+    };
+
+    // Sets up a game to run candidates in. Each one gets its own temp folder, so they don't get in each other's way.
+    // They run in record mode, like the savestate was made in, so events such as dialogs get recorded.
+    let launch = |assets: gm8exe::GameAssets| -> Result<(Game, Search), String> {
+        let mut game =
+            Game::launch(assets, file_path.clone(), vec![input.clone()], None, encoding, false, 0, PlayType::Record)
+                .map_err(|e| format!("Failed to launch game: {}", e))?;
+        let state = SaveState::from_file(&state_path, &mut savestate::Buffer::new())
+            .map_err(|e| format!("couldn't load {:?}: {:?}", state_path, e))?;
+        let search = Search::new(&mut game, settings.clone(), state)?;
+        Ok((game, search))
+    };
+
+    // The main thread's game checks that everything's set up properly, then replays the best candidates at the end
+    let (mut game, main_search) = match launch(assets) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILURE
+        },
+    };
+    let count = settings.candidate_count().unwrap_or(0);
+    println!("Searching {} candidates on {} thread(s)", count, threads);
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel::<Result<Outcome, String>>();
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    let mut errors = 0;
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let (sender, next, exe, launch) = (sender.clone(), &next, &exe, &launch);
+            scope.spawn(move || {
+                let mut exe = exe.clone();
+                let setup = gm8exe::reader::from_exe(&mut exe, None::<fn(&str)>, false, false)
+                    .map_err(|e| format!("failed to load '{}' - {}", input, e))
+                    .and_then(launch);
+                match setup {
+                    Ok((mut game, search)) => {
+                        loop {
+                            let index = next.fetch_add(1, atomic::Ordering::Relaxed);
+                            if index >= count {
+                                break
+                            }
+                            if sender.send(search.run(&mut game, index).map(|(outcome, _)| outcome)).is_err() {
+                                break
+                            }
+                        }
+                        remove_temp_dir(&game);
+                    },
+                    Err(e) => eprintln!("{}", e),
+                }
+            });
+        }
+        drop(sender);
+
+        let mut last_report = Instant::now();
+        for (done, result) in receiver.iter().enumerate() {
+            match result {
+                Ok(outcome) => {
+                    let position = outcomes.partition_point(|o| o.rank(&outcome, settings.minimise).is_le());
+                    if position < best {
+                        outcomes.insert(position, outcome);
+                        outcomes.truncate(best);
+                    }
+                },
+                Err(e) => {
+                    if errors == 0 {
+                        eprintln!("{}", e);
+                    }
+                    errors += 1;
+                },
+            }
+            if last_report.elapsed() >= Duration::from_secs(1) {
+                last_report = Instant::now();
+                match outcomes.first() {
+                    Some(outcome) => println!("{}/{} tried, best score so far {}", done + 1, count, outcome.score),
+                    None => println!("{}/{} tried", done + 1, count),
+                }
+            }
+        }
+    });
+    if errors > 0 {
+        println!("{} candidate(s) crashed or couldn't be scored", errors);
+    }
+
+    let base = main_search.state().clone().into_replay();
+    let metadata = replay::Metadata { game_hash, ..Default::default() };
+    for (rank, outcome) in outcomes.iter().enumerate() {
+        match outcome.success {
+            Some(frames) => println!("#{}: score {}, done after {} frame(s)", rank + 1, outcome.score, frames),
+            None => println!("#{}: score {}", rank + 1, outcome.score),
+        }
+        let frames = match main_search.run(&mut game, outcome.index) {
+            Ok((_, frames)) => frames,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue
+            },
+        };
+        for (i, frame) in frames.iter().enumerate() {
+            let mut changes = frame
+                .inputs
+                .iter()
+                .map(|input| match input {
+                    replay::Input::KeyPress(key) => format!("+{}", key_name(*key)),
+                    replay::Input::KeyRelease(key) => format!("-{}", key_name(*key)),
+                    input => format!("{:?}", input),
+                })
+                .collect::<Vec<_>>();
+            if let Some(replay::FrameRng::Increment(n)) = frame.new_seed {
+                changes.push(format!("rng +{}", n));
+            }
+            if !changes.is_empty() {
+                println!("    frame {}: {}", base.frame_count() + i, changes.join(" "));
+            }
+        }
+        if let Some(dir) = &output_dir {
+            let mut replay = base.clone();
+            for frame in frames {
+                *replay.new_frame() = frame;
+            }
+            let path = dir.join(format!("search-{}.gmtas", rank + 1));
+            let result = fs::create_dir_all(dir)
+                .map_err(replay::WriteError::IOErr)
+                .and_then(|_| replay.to_file(&path, &metadata));
+            if let Err(e) = result {
+                eprintln!("couldn't save {:?}: {:?}", path, e);
+            }
+        }
+    }
+    remove_temp_dir(&game);
+    EXIT_SUCCESS
+}
+
+fn remove_temp_dir(game: &Game) {
+    fs::remove_dir_all(game.decode_str(game.temp_directory.as_ref()).into_owned()).ok();
+}
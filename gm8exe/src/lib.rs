@@ -30,6 +30,7 @@ mod colour;
 
 use crate::asset::*;
 use settings::{GameHelpDialog, Settings};
+use std::ops::Range;
 
 pub type AssetList<T> = Vec<Option<Box<T>>>;
 
@@ -60,6 +61,10 @@ pub struct GameAssets {
     pub settings: Settings,
     pub game_id: u32,
     pub guid: [u32; 4],
+
+    /// Where the game data was in the exe. Reading it decrypts it in place, so this part can be hashed afterwards
+    /// to identify the game no matter how its exe was packed.
+    pub data_range: Range<usize>,
 }
 
 #[derive(Copy, Clone, Debug)]
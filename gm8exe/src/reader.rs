@@ -157,6 +157,7 @@ where
 
     // Identify the game version in use and locate the gamedata header
     let game_ver = gamedata::find(&mut exe, logger, upx_data)?;
+    let data_start = exe.position() as usize;
 
     // little helper thing
     macro_rules! assert_ver {
@@ -724,6 +725,7 @@ where
 
         room_order
     };
+    let data_range = data_start..exe.position() as usize;
 
     Ok(GameAssets {
         extensions,
@@ -752,5 +754,7 @@ where
         settings,
         game_id,
         guid,
+
+        data_range,
     })
 }
@@ -1,19 +1,26 @@
 use crate::{
-    lexer::Lexer,
+    lexer::{Lexer, Position, Span, SpannedToken},
     token::{Keyword, Operator, Separator, Token},
 };
 
 use std::{
     error, fmt,
-    iter::{IntoIterator, Peekable},
+    iter::IntoIterator,
     ops::{Deref, DerefMut},
 };
 
 #[derive(Debug, PartialEq)]
 pub struct AST<'a>(Vec<Expr<'a>>);
 
+/// An expression, tagged with the bytes it covers in the source.
 #[derive(Debug, PartialEq)]
-pub enum Expr<'a> {
+pub struct Expr<'a> {
+    pub kind: ExprKind<'a>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExprKind<'a> {
     LiteralIdentifier(&'a [u8]),
     LiteralReal(f64),
     LiteralString(&'a [u8]),
@@ -40,6 +47,8 @@ pub enum Expr<'a> {
     Break,
     Exit,
     Return(Box<Expr<'a>>),
+
+    Line(Box<LineExpr<'a>>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -117,38 +126,62 @@ pub struct WhileExpr<'a> {
     pub body: Expr<'a>,
 }
 
+/// A single line of code, tagged with where it is in the source.
+/// The span of the Line expression covers everything in the line, including any trailing semicolons.
+#[derive(Debug, PartialEq)]
+pub struct LineExpr<'a> {
+    pub pos: Position,
+    pub expr: Expr<'a>,
+}
+
 #[derive(Debug)]
 pub struct Error {
     pub message: String,
+
+    /// Where the token that caused the error is. Always set on errors returned by AST functions.
+    pub pos: Option<Position>,
+    pub span: Option<Span>,
 }
 
 impl Error {
     pub fn new(message: String) -> Self {
-        Error { message }
+        Error { message, pos: None, span: None }
+    }
+
+    /// Tags an error with the position of the token the lexer is on.
+    fn at(self, lex: &Lexer) -> Self {
+        Error { pos: Some(lex.pos()), span: Some(lex.span()), ..self }
+    }
+}
+
+impl<'a> From<ExprKind<'a>> for Expr<'a> {
+    /// Wraps an expression with an empty span, for ASTs which weren't parsed from source code.
+    fn from(kind: ExprKind<'a>) -> Self {
+        Expr { kind, span: Span::default() }
     }
 }
 
 impl<'a> fmt::Display for Expr<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Expr::LiteralIdentifier(id) => write!(f, "{}", String::from_utf8_lossy(id)),
-            Expr::LiteralReal(r) => write!(f, "{}", r),
-            Expr::LiteralString(s) => write!(f, "\"{}\"", String::from_utf8_lossy(s)),
+        match &self.kind {
+            ExprKind::LiteralIdentifier(id) => write!(f, "{}", String::from_utf8_lossy(id)),
+            ExprKind::LiteralReal(r) => write!(f, "{}", r),
+            ExprKind::LiteralString(s) => write!(f, "\"{}\"", String::from_utf8_lossy(s)),
 
-            Expr::Unary(unary) => write!(f, "({} {})", unary.op, unary.child),
-            Expr::Binary(binary) => write!(f, "({} {} {})", binary.op, binary.left, binary.right),
+            ExprKind::Unary(unary) => write!(f, "({} {})", unary.op, unary.child),
+            ExprKind::Binary(binary) => write!(f, "({} {} {})", binary.op, binary.left, binary.right),
 
-            Expr::DoUntil(dountil) => write!(f, "(do {} until {})", dountil.body, dountil.cond),
-            Expr::For(for_ex) => {
+            ExprKind::DoUntil(dountil) => write!(f, "(do {} until {})", dountil.body, dountil.cond),
+            ExprKind::For(for_ex) => {
                 write!(f, "(for ({}, {}, {}) {})", for_ex.start, for_ex.cond, for_ex.step, for_ex.body)
             },
-            Expr::Function(call) => write!(
+            ExprKind::Function(call) => write!(
                 f,
                 "(@{} {})",
                 String::from_utf8_lossy(call.name),
                 call.params.iter().fold(String::new(), |acc, fnname| acc + &format!("{} ", fnname)).trim_end()
             ),
-            Expr::Group(group) => write!(
+            ExprKind::Group(group) => write!(
                 f,
                 "<{}>",
                 group
@@ -156,13 +189,13 @@ impl<'a> fmt::Display for Expr<'a> {
                     .fold(String::new(), |acc, expr| acc + &format!("{}, ", expr))
                     .trim_end_matches(|ch| ch == ' ' || ch == ',')
             ),
-            Expr::If(if_ex) => match if_ex.else_body {
+            ExprKind::If(if_ex) => match if_ex.else_body {
                 Some(ref els) => write!(f, "(if {} {} {})", if_ex.cond, if_ex.body, els),
                 None => write!(f, "(if {} {})", if_ex.cond, if_ex.body),
             },
-            Expr::Repeat(repeat) => write!(f, "(repeat {} {})", repeat.count, repeat.body),
-            Expr::Switch(switch) => write!(f, "(switch {} {})", switch.input, switch.body),
-            Expr::Var(var) => write!(
+            ExprKind::Repeat(repeat) => write!(f, "(repeat {} {})", repeat.count, repeat.body),
+            ExprKind::Switch(switch) => write!(f, "(switch {} {})", switch.input, switch.body),
+            ExprKind::Var(var) => write!(
                 f,
                 "(var {})",
                 var.vars
@@ -170,7 +203,7 @@ impl<'a> fmt::Display for Expr<'a> {
                     .fold(String::new(), |acc, varname| acc + &format!("{} ", String::from_utf8_lossy(varname)))
                     .trim_end()
             ),
-            Expr::GlobalVar(var) => write!(
+            ExprKind::GlobalVar(var) => write!(
                 f,
                 "(globalvar {})",
                 var.vars
@@ -178,16 +211,18 @@ impl<'a> fmt::Display for Expr<'a> {
                     .fold(String::new(), |acc, varname| acc + &format!("{} ", String::from_utf8_lossy(varname)))
                     .trim_end()
             ),
-            Expr::With(with) => write!(f, "(with {} {})", with.target, with.body),
-            Expr::While(while_ex) => write!(f, "(while {} {})", while_ex.cond, while_ex.body),
+            ExprKind::With(with) => write!(f, "(with {} {})", with.target, with.body),
+            ExprKind::While(while_ex) => write!(f, "(while {} {})", while_ex.cond, while_ex.body),
 
-            Expr::Case(e) => write!(f, "(case {})", e),
-            Expr::Default => write!(f, "(default)"),
+            ExprKind::Case(e) => write!(f, "(case {})", e),
+            ExprKind::Default => write!(f, "(default)"),
 
-            Expr::Continue => write!(f, "(continue)"),
-            Expr::Break => write!(f, "(break)"),
-            Expr::Exit => write!(f, "(exit)"),
-            Expr::Return(e) => write!(f, "(return {})", e),
+            ExprKind::Continue => write!(f, "(continue)"),
+            ExprKind::Break => write!(f, "(break)"),
+            ExprKind::Exit => write!(f, "(exit)"),
+            ExprKind::Return(e) => write!(f, "(return {})", e),
+
+            ExprKind::Line(line) => write!(f, "{}", line.expr),
         }
     }
 }
@@ -195,7 +230,10 @@ impl<'a> fmt::Display for Expr<'a> {
 impl error::Error for Error {}
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self.pos {
+            Some(pos) => write!(f, "{} at line {}, position {}", self.message, pos.line, pos.column),
+            None => write!(f, "{}", self.message),
+        }
     }
 }
 
@@ -251,7 +289,7 @@ impl<'a> IntoIterator for AST<'a> {
 
 impl<'a> AST<'a> {
     pub fn new(source: &'a [u8]) -> Result<Self, Error> {
-        let mut lex = Lexer::new(source).peekable();
+        let mut lex = Lexer::new(source);
         let mut expressions = Vec::new();
 
         loop {
@@ -259,41 +297,110 @@ impl<'a> AST<'a> {
             match AST::read_line(&mut lex) {
                 Ok(Some(expr)) => expressions.push(expr),
                 Ok(None) => break,
-                Err(e) => return Err(e),
+                Err(e) => return Err(e.at(&lex)),
             }
         }
 
         Ok(Self(expressions))
     }
 
+    /// Parses as much of some code as possible, rather than stopping at the first syntax error.
+    /// Lines with errors in them are skipped, and every error found is returned alongside whatever could be parsed.
+    pub fn with_errors(source: &'a [u8]) -> (Self, Vec<Error>) {
+        let mut lex = Lexer::new(source);
+        let mut expressions = Vec::new();
+        let mut errors = Vec::new();
+
+        loop {
+            // After an error, any leftover closing braces most likely belong to a block the error was inside
+            if !errors.is_empty() && lex.peek_token() == Some(&Token::Separator(Separator::BraceRight)) {
+                lex.next();
+                continue
+            }
+            match AST::read_line(&mut lex) {
+                Ok(Some(expr)) => expressions.push(expr),
+                Ok(None) => break,
+                Err(e) => {
+                    let line = lex.line();
+                    errors.push(e.at(&lex));
+                    AST::skip_line(&mut lex, line);
+                },
+            }
+        }
+
+        (Self(expressions), errors)
+    }
+
     pub fn expression(source: &'a [u8]) -> Result<Expr<'a>, Error> {
-        let mut lex = Lexer::new(source).peekable();
-        if lex.peek().is_some() { AST::read_binary_tree(&mut lex, None, false) } else { Ok(Expr::LiteralReal(0.0)) }
+        let mut lex = Lexer::new(source);
+        if lex.peek_token().is_some() {
+            AST::read_binary_tree(&mut lex, None, false).map_err(|e| e.at(&lex))
+        } else {
+            Ok(ExprKind::LiteralReal(0.0).into())
+        }
+    }
+
+    /// Skips the rest of a line with an error in it. It's assumed to end at the next semicolon or brace,
+    /// or at whatever looks like the start of a statement on a later line.
+    fn skip_line(lex: &mut Lexer<'a>, line: usize) {
+        while let Some(&token) = lex.peek_token() {
+            match token {
+                Token::Separator(Separator::Semicolon) => {
+                    lex.next();
+                    break
+                },
+                Token::Separator(Separator::BraceLeft | Separator::BraceRight) => break,
+                Token::Identifier(_)
+                | Token::Separator(Separator::ParenLeft)
+                | Token::Keyword(
+                    Keyword::Var
+                    | Keyword::GlobalVar
+                    | Keyword::If
+                    | Keyword::With
+                    | Keyword::Repeat
+                    | Keyword::Do
+                    | Keyword::While
+                    | Keyword::For
+                    | Keyword::Switch
+                    | Keyword::Case
+                    | Keyword::Default
+                    | Keyword::Break
+                    | Keyword::Continue
+                    | Keyword::Return
+                    | Keyword::Exit,
+                ) if lex.line() > line => break,
+                _ => {
+                    lex.next();
+                },
+            }
+        }
     }
 
-    fn read_line(lex: &mut Peekable<Lexer<'a>>) -> Result<Option<Expr<'a>>, Error> {
+    fn read_line(lex: &mut Lexer<'a>) -> Result<Option<Expr<'a>>, Error> {
         let token = loop {
             match lex.next() {
-                Some(Token::Separator(Separator::Semicolon)) => continue,
+                Some(SpannedToken { token: Token::Separator(Separator::Semicolon), .. }) => continue,
                 Some(t) => break t,
                 None => return Ok(None), // EOF
             }
         };
+        let pos = lex.pos();
+        let start = token.span.start;
 
         // Use token type to determine what logic we should apply here
-        let ret = match token {
+        let ret = match token.token {
             Token::Keyword(key) => {
-                match key {
+                let kind = match key {
                     Keyword::Var | Keyword::GlobalVar => {
                         // Read var identifiers
-                        if let Some(&Token::Identifier(id)) = lex.peek() {
+                        if let Some(&Token::Identifier(id)) = lex.peek_token() {
                             lex.next();
                             let mut vars = vec![id];
 
                             loop {
                                 let mut peek_lex = lex.clone();
                                 // Check next token
-                                match peek_lex.next() {
+                                match peek_lex.next_token() {
                                     // If next token is a comma, skip it and expect another identifier after it
                                     Some(Token::Separator(Separator::Comma)) => {
                                         lex.next();
@@ -303,7 +410,7 @@ impl<'a> AST<'a> {
                                     Some(Token::Identifier(_)) => {
                                         // ...but if the token after that is '(' or `.`, then it's actually the start
                                         // of the next line, so stop reading var names here
-                                        let next = peek_lex.next();
+                                        let next = peek_lex.next_token();
                                         if matches!(
                                             next,
                                             Some(Token::Separator(Separator::ParenLeft))
@@ -319,7 +426,7 @@ impl<'a> AST<'a> {
 
                                 // Read one identifier and store it as a var name
                                 // Alternatively, break if the next token is not a Token::Identifier
-                                if let Some(Token::Identifier(id)) = lex.peek() {
+                                if let Some(Token::Identifier(id)) = lex.peek_token() {
                                     vars.push(id);
                                     lex.next();
                                 } else {
@@ -328,17 +435,15 @@ impl<'a> AST<'a> {
                             }
 
                             match key {
-                                Keyword::Var => Ok(Some(Expr::Var(Box::new(VarExpr { vars })))),
-                                Keyword::GlobalVar => Ok(Some(Expr::GlobalVar(Box::new(GlobalVarExpr { vars })))),
+                                Keyword::Var => ExprKind::Var(Box::new(VarExpr { vars })),
+                                Keyword::GlobalVar => ExprKind::GlobalVar(Box::new(GlobalVarExpr { vars })),
                                 _ => unreachable!(),
                             }
                         } else {
                             // This doesn't do anything in GML. We could probably make it a NOP.
                             match key {
-                                Keyword::Var => Ok(Some(Expr::Var(Box::new(VarExpr { vars: vec![] })))),
-                                Keyword::GlobalVar => {
-                                    Ok(Some(Expr::GlobalVar(Box::new(GlobalVarExpr { vars: vec![] }))))
-                                },
+                                Keyword::Var => ExprKind::Var(Box::new(VarExpr { vars: vec![] })),
+                                Keyword::GlobalVar => ExprKind::GlobalVar(Box::new(GlobalVarExpr { vars: vec![] })),
                                 _ => unreachable!(),
                             }
                         }
@@ -347,19 +452,19 @@ impl<'a> AST<'a> {
                     Keyword::Do => {
                         let body = AST::read_group(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'do' keyword".to_string()))?;
-                        expect_token!(lex.next(), Keyword(Keyword::Until));
+                        expect_token!(lex.next_token(), Keyword(Keyword::Until));
                         let cond = AST::read_binary_tree(lex, None, false)?;
-                        Ok(Some(Expr::DoUntil(Box::new(DoUntilExpr { cond, body }))))
+                        ExprKind::DoUntil(Box::new(DoUntilExpr { cond, body }))
                     },
 
                     Keyword::If => {
                         let cond = AST::read_binary_tree(lex, None, false)?;
-                        if lex.peek() == Some(&Token::Separator(Separator::Then)) {
+                        if lex.peek_token() == Some(&Token::Separator(Separator::Then)) {
                             lex.next();
                         }
                         let body = AST::read_group(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'if' condition".to_string()))?;
-                        let else_body = if lex.peek() == Some(&Token::Keyword(Keyword::Else)) {
+                        let else_body = if lex.peek_token() == Some(&Token::Keyword(Keyword::Else)) {
                             lex.next(); // consume 'else'
                             Some(
                                 AST::read_group(lex)?
@@ -368,95 +473,96 @@ impl<'a> AST<'a> {
                         } else {
                             None
                         };
-                        Ok(Some(Expr::If(Box::new(IfExpr { cond, body, else_body }))))
+                        ExprKind::If(Box::new(IfExpr { cond, body, else_body }))
                     },
 
                     Keyword::For => {
-                        expect_token!(lex.next(), Separator(Separator::ParenLeft));
+                        expect_token!(lex.next_token(), Separator(Separator::ParenLeft));
                         let start = AST::read_line(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF during 'for' params".to_string()))?;
-                        if lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
+                        if lex.peek_token() == Some(&Token::Separator(Separator::Semicolon)) {
                             lex.next();
                         }
                         let cond = AST::read_binary_tree(lex, None, false)?;
-                        if lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
+                        if lex.peek_token() == Some(&Token::Separator(Separator::Semicolon)) {
                             lex.next();
                         }
                         let step = AST::read_line(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF during 'for' params".to_string()))?;
-                        while lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
+                        while lex.peek_token() == Some(&Token::Separator(Separator::Semicolon)) {
                             lex.next();
                         }
-                        expect_token!(lex.next(), Separator(Separator::ParenRight));
+                        expect_token!(lex.next_token(), Separator(Separator::ParenRight));
                         let body = AST::read_group(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'for' params".to_string()))?;
-                        Ok(Some(Expr::For(Box::new(ForExpr { start, cond, step, body }))))
+                        ExprKind::For(Box::new(ForExpr { start, cond, step, body }))
                     },
 
                     Keyword::Repeat => {
                         let count = AST::read_binary_tree(lex, None, false)?;
                         let body = AST::read_group(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'repeat' condition".to_string()))?;
-                        Ok(Some(Expr::Repeat(Box::new(RepeatExpr { count, body }))))
+                        ExprKind::Repeat(Box::new(RepeatExpr { count, body }))
                     },
 
                     Keyword::Switch => {
                         let input = AST::read_binary_tree(lex, None, false)?;
                         let body = AST::read_line(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'switch' condition".to_string()))?;
-                        Ok(Some(Expr::Switch(Box::new(SwitchExpr { input, body }))))
+                        ExprKind::Switch(Box::new(SwitchExpr { input, body }))
                     },
 
                     Keyword::With => {
                         let target = AST::read_binary_tree(lex, None, false)?;
-                        if lex.peek() == Some(&Token::Keyword(Keyword::Do)) {
+                        if lex.peek_token() == Some(&Token::Keyword(Keyword::Do)) {
                             lex.next();
                         }
                         let body = AST::read_group(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'with' condition".to_string()))?;
-                        Ok(Some(Expr::With(Box::new(WithExpr { target, body }))))
+                        ExprKind::With(Box::new(WithExpr { target, body }))
                     },
 
                     Keyword::While => {
                         let cond = AST::read_binary_tree(lex, None, false)?;
-                        if lex.peek() == Some(&Token::Keyword(Keyword::Do)) {
+                        if lex.peek_token() == Some(&Token::Keyword(Keyword::Do)) {
                             lex.next();
                         }
                         let body = AST::read_group(lex)?
                             .ok_or_else(|| Error::new("Unexpected EOF after 'while' condition".to_string()))?;
-                        Ok(Some(Expr::While(Box::new(WhileExpr { cond, body }))))
+                        ExprKind::While(Box::new(WhileExpr { cond, body }))
                     },
 
                     Keyword::Case => {
                         let expr = AST::read_binary_tree(lex, None, false)?;
-                        expect_token!(lex.next(), Separator(Separator::Colon));
-                        Ok(Some(Expr::Case(Box::new(expr))))
+                        expect_token!(lex.next_token(), Separator(Separator::Colon));
+                        ExprKind::Case(Box::new(expr))
                     },
 
                     Keyword::Default => {
-                        expect_token!(lex.next(), Separator(Separator::Colon));
-                        Ok(Some(Expr::Default))
+                        expect_token!(lex.next_token(), Separator(Separator::Colon));
+                        ExprKind::Default
                     },
 
-                    Keyword::Break => Ok(Some(Expr::Break)),
+                    Keyword::Break => ExprKind::Break,
 
-                    Keyword::Continue => Ok(Some(Expr::Continue)),
+                    Keyword::Continue => ExprKind::Continue,
 
-                    Keyword::Exit => Ok(Some(Expr::Exit)),
+                    Keyword::Exit => ExprKind::Exit,
 
                     Keyword::Return => {
                         let val = AST::read_binary_tree(lex, None, false)?;
-                        Ok(Some(Expr::Return(Box::new(val))))
+                        ExprKind::Return(Box::new(val))
                     },
 
                     _ => return Err(Error::new(format!("Invalid Keyword at beginning of expression: {:?}", key))),
-                }
+                };
+                Ok(Some(AST::spanned(lex, start, kind)))
             },
 
             Token::Identifier(id) => {
                 // An expression starting with an identifier may be either an assignment or script/function.
                 // This is determined by what type of token immediately follows it.
-                let next_token = match lex.peek() {
+                let next_token = match lex.peek_token() {
                     Some(t) => t,
                     None => {
                         return Err(Error::new(format!("Stray identifier at EOF: {:?}", String::from_utf8_lossy(id))))
@@ -464,7 +570,7 @@ impl<'a> AST<'a> {
                 };
                 match next_token {
                     Token::Separator(ref sep) if *sep == Separator::ParenLeft => {
-                        Ok(Some(AST::read_function_call(lex, id)?))
+                        Ok(Some(AST::read_function_call(lex, id, start)?))
                     },
                     _ => Ok(Some(AST::read_binary_tree(lex, Some(token), true)?)),
                 }
@@ -476,10 +582,10 @@ impl<'a> AST<'a> {
                     Separator::BraceLeft => {
                         let mut inner_expressions = Vec::new();
                         loop {
-                            match lex.peek() {
+                            match lex.peek_token() {
                                 Some(Token::Separator(Separator::BraceRight)) => {
                                     lex.next();
-                                    break Ok(Some(Expr::Group(inner_expressions)))
+                                    break Ok(Some(AST::spanned(lex, start, ExprKind::Group(inner_expressions))))
                                 },
                                 _ => match AST::read_line(lex) {
                                     Ok(Some(e)) => inner_expressions.push(e),
@@ -492,8 +598,7 @@ impl<'a> AST<'a> {
 
                     // An assignment may start with an open-parenthesis, eg: (1).x = 400;
                     Separator::ParenLeft => {
-                        let binary_tree =
-                            AST::read_binary_tree(lex, Some(Token::Separator(Separator::ParenLeft)), true)?;
+                        let binary_tree = AST::read_binary_tree(lex, Some(token), true)?;
                         Ok(Some(binary_tree))
                     },
 
@@ -506,20 +611,27 @@ impl<'a> AST<'a> {
         };
 
         // skip over trailing semicolons
-        while lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
+        while lex.peek_token() == Some(&Token::Separator(Separator::Semicolon)) {
             lex.next();
         }
-
-        ret
+        let span = Span { start, end: lex.consumed_end() };
+
+        // Tag lines of code with their position, but not blocks or labels, which are never executed by themselves
+        ret.map(|expr| {
+            expr.map(|expr| match expr.kind {
+                ExprKind::Group(_) | ExprKind::Case(_) | ExprKind::Default => expr,
+                _ => Expr { kind: ExprKind::Line(Box::new(LineExpr { pos, expr })), span },
+            })
+        })
     }
 
-    fn read_group(lex: &mut Peekable<Lexer<'a>>) -> Result<Option<Expr<'a>>, Error> {
+    fn read_group(lex: &mut Lexer<'a>) -> Result<Option<Expr<'a>>, Error> {
         match lex.peek() {
-            Some(Token::Separator(Separator::Semicolon)) => {
-                while lex.peek() == Some(&Token::Separator(Separator::Semicolon)) {
+            Some(&SpannedToken { token: Token::Separator(Separator::Semicolon), span }) => {
+                while lex.peek_token() == Some(&Token::Separator(Separator::Semicolon)) {
                     lex.next();
                 }
-                Ok(Some(Expr::Group(vec![])))
+                Ok(Some(AST::spanned(lex, span.start, ExprKind::Group(vec![]))))
             },
             Some(_) => Self::read_line(lex),
             None => Ok(None),
@@ -527,9 +639,9 @@ impl<'a> AST<'a> {
     }
 
     fn read_binary_tree(
-        lex: &mut Peekable<Lexer<'a>>,
-        first_token: Option<Token<'a>>, // Sometimes we've already parsed the first token, so it should be put here.
-        expect_assignment: bool,        // Do we expect the first op to be an assignment?
+        lex: &mut Lexer<'a>,
+        first_token: Option<SpannedToken<'a>>, // Sometimes we've already parsed the first token, so it should be put here.
+        expect_assignment: bool,               // Do we expect the first op to be an assignment?
     ) -> Result<Expr<'a>, Error> {
         let (val, op) = AST::read_binary_tree_recursive(lex, first_token, expect_assignment, 0)?;
         if let Some(stray_op) = op {
@@ -540,17 +652,17 @@ impl<'a> AST<'a> {
     }
 
     fn read_binary_tree_recursive(
-        lex: &mut Peekable<Lexer<'a>>,
-        first_token: Option<Token<'a>>, // Sometimes we've already parsed the first token, so it should be put here.
-        expect_assignment: bool,        // Do we expect the first op to be an assignment?
-        lowest_prec: u8,                // We are not allowed to go below this operator precedence in this tree.
-                                        // If we do, we'll return the next op.
+        lex: &mut Lexer<'a>,
+        first_token: Option<SpannedToken<'a>>, // Sometimes we've already parsed the first token, so it should be put here.
+        expect_assignment: bool,               // Do we expect the first op to be an assignment?
+        lowest_prec: u8,                       // We are not allowed to go below this operator precedence in this tree.
+                                               // If we do, we'll return the next op.
     ) -> Result<(Expr<'a>, Option<Operator>), Error> {
         // Get the first expression before any operators
         let mut lhs = AST::read_btree_expression(lex, first_token)?;
 
         // Check if the next token is an operator
-        let next_token = lex.peek();
+        let next_token = lex.peek_token();
         match next_token {
             Some(Token::Operator(op)) => {
                 // '=' can be either an assignment or equality check (==) in GML.
@@ -583,14 +695,11 @@ impl<'a> AST<'a> {
                             if let Some(next_prec) = AST::get_op_precedence(&next_op) {
                                 if next_prec < lowest_prec {
                                     // This next op is lower than we're allowed to go, so we must return it
-                                    break Ok((
-                                        Expr::Binary(Box::new(BinaryExpr { op, left: lhs, right: rhs })),
-                                        Some(next_op),
-                                    ))
+                                    break Ok((AST::binary(op, lhs, rhs), Some(next_op)))
                                 } else {
                                     // Update LHS by sticking RHS onto it,
                                     // set op to the new operator, and go round again.
-                                    lhs = Expr::Binary(Box::new(BinaryExpr { op, left: lhs, right: rhs }));
+                                    lhs = AST::binary(op, lhs, rhs);
                                     op = next_op;
                                 }
                             } else {
@@ -602,7 +711,7 @@ impl<'a> AST<'a> {
                             }
                         } else {
                             // No more operators so let's put our lhs and rhs together.
-                            break Ok((Expr::Binary(Box::new(BinaryExpr { op, left: lhs, right: rhs })), None))
+                            break Ok((AST::binary(op, lhs, rhs), None))
                         }
                     } else {
                         // this op is invalid if assignment not expected, OR if it's a unary operator
@@ -615,7 +724,7 @@ impl<'a> AST<'a> {
                             break if let Some(op) = stray_op {
                                 Err(Error::new(format!("Stray operator {:?} in expression", op)))
                             } else {
-                                Ok((Expr::Binary(Box::new(BinaryExpr { op, left: lhs, right: rhs })), None))
+                                Ok((AST::binary(op, lhs, rhs), None))
                             }
                         }
                     }
@@ -631,55 +740,61 @@ impl<'a> AST<'a> {
         }
     }
 
-    fn read_btree_expression(lex: &mut Peekable<Lexer<'a>>, first_token: Option<Token<'a>>) -> Result<Expr<'a>, Error> {
+    fn read_btree_expression(lex: &mut Lexer<'a>, first_token: Option<SpannedToken<'a>>) -> Result<Expr<'a>, Error> {
         // Get first token and match it
-        let mut lhs = match if first_token.is_some() { first_token } else { lex.next() } {
-            Some(Token::Separator(ref sep)) if *sep == Separator::ParenLeft => {
+        let first = match first_token.or_else(|| lex.next()) {
+            Some(token) => token,
+            None => return Err(Error::new("Found EOF unexpectedly while reading binary tree".to_string())),
+        };
+        let start = first.span.start;
+        let mut lhs = match first.token {
+            Token::Separator(Separator::ParenLeft) => {
                 let binary_tree = AST::read_binary_tree(lex, None, false)?;
-                if lex.next() != Some(Token::Separator(Separator::ParenRight)) {
+                if lex.next_token() != Some(Token::Separator(Separator::ParenRight)) {
                     return Err(Error::new("Unclosed parenthesis in binary tree".to_string()))
                 } else {
-                    binary_tree
+                    // There's no node for parentheses, so the expression inside them covers them instead
+                    Expr { span: Span { start, end: lex.consumed_end() }, ..binary_tree }
                 }
             },
-            Some(Token::Operator(op)) => {
+            Token::Operator(op) => {
                 if op == Operator::Add || op == Operator::Subtract || op == Operator::Not || op == Operator::Complement
                 {
-                    Expr::Unary(Box::new(UnaryExpr { op, child: AST::read_btree_expression(lex, None)? }))
+                    let child = AST::read_btree_expression(lex, None)?;
+                    AST::spanned(lex, start, ExprKind::Unary(Box::new(UnaryExpr { op, child })))
                 } else {
                     return Err(Error::new(format!("Invalid unary operator {:?} in expression", op)))
                 }
             },
-            Some(Token::Identifier(t)) => {
-                if lex.peek() == Some(&Token::Separator(Separator::ParenLeft)) {
-                    AST::read_function_call(lex, t)?
+            Token::Identifier(t) => {
+                if lex.peek_token() == Some(&Token::Separator(Separator::ParenLeft)) {
+                    AST::read_function_call(lex, t, start)?
                 } else {
-                    Expr::LiteralIdentifier(t)
+                    Expr { kind: ExprKind::LiteralIdentifier(t), span: first.span }
                 }
             },
 
-            Some(Token::Real(t)) => Expr::LiteralReal(t),
-            Some(Token::String(t)) => Expr::LiteralString(t),
-            Some(t) => return Err(Error::new(format!("Invalid token while scanning binary tree: {:?}", t))),
-            None => return Err(Error::new("Found EOF unexpectedly while reading binary tree".to_string())),
+            Token::Real(t) => Expr { kind: ExprKind::LiteralReal(t), span: first.span },
+            Token::String(t) => Expr { kind: ExprKind::LiteralString(t), span: first.span },
+            t => return Err(Error::new(format!("Invalid token while scanning binary tree: {:?}", t))),
         };
 
         // Do we need to amend this LHS at all?
         loop {
             match lex.peek() {
-                Some(Token::Separator(ref sep)) if *sep == Separator::BracketLeft => {
+                Some(&SpannedToken { token: Token::Separator(Separator::BracketLeft), span }) => {
                     lex.next();
                     let mut dimensions = Vec::new();
-                    if lex.peek() == Some(&Token::Separator(Separator::BracketRight)) {
+                    if lex.peek_token() == Some(&Token::Separator(Separator::BracketRight)) {
                         lex.next();
                     } else {
                         loop {
                             let dim = AST::read_binary_tree(lex, None, false)?;
                             dimensions.push(dim);
-                            match lex.next() {
+                            match lex.next_token() {
                                 Some(Token::Separator(Separator::BracketRight)) => break,
                                 Some(Token::Separator(Separator::Comma)) => {
-                                    if lex.peek() == Some(&Token::Separator(Separator::BracketRight)) {
+                                    if lex.peek_token() == Some(&Token::Separator(Separator::BracketRight)) {
                                         lex.next();
                                         break
                                     }
@@ -695,22 +810,17 @@ impl<'a> AST<'a> {
                             }
                         }
                     }
-                    lhs = Expr::Binary(Box::new(BinaryExpr {
-                        op: Operator::Index,
-                        left: lhs,
-                        right: Expr::Group(dimensions),
-                    }));
+                    let dimensions = AST::spanned(lex, span.start, ExprKind::Group(dimensions));
+                    lhs = AST::binary(Operator::Index, lhs, dimensions);
                 },
 
-                Some(Token::Separator(ref sep)) if *sep == Separator::Period => {
+                Some(SpannedToken { token: Token::Separator(Separator::Period), .. }) => {
                     lex.next();
                     lhs = match lex.next() {
-                        Some(Token::Identifier(id)) => Expr::Binary(Box::new(BinaryExpr {
-                            op: Operator::Deref,
-                            left: lhs,
-                            right: Expr::LiteralIdentifier(id),
-                        })),
-                        Some(t) => return Err(Error::new(format!("Unexpected token {:?} following deref", t))),
+                        Some(SpannedToken { token: Token::Identifier(id), span }) => {
+                            AST::binary(Operator::Deref, lhs, Expr { kind: ExprKind::LiteralIdentifier(id), span })
+                        },
+                        Some(t) => return Err(Error::new(format!("Unexpected token {:?} following deref", t.token))),
                         None => return Err(Error::new("Found EOF unexpectedly while reading binary tree".to_string())),
                     }
                 },
@@ -721,20 +831,20 @@ impl<'a> AST<'a> {
         Ok(lhs)
     }
 
-    fn read_function_call(lex: &mut Peekable<Lexer<'a>>, function_name: &'a [u8]) -> Result<Expr<'a>, Error> {
-        expect_token!(lex.next(), Separator(Separator::ParenLeft));
+    fn read_function_call(lex: &mut Lexer<'a>, function_name: &'a [u8], start: usize) -> Result<Expr<'a>, Error> {
+        expect_token!(lex.next_token(), Separator(Separator::ParenLeft));
 
         let mut params = Vec::new();
-        if lex.peek() == Some(&Token::Separator(Separator::ParenRight)) {
+        if lex.peek_token() == Some(&Token::Separator(Separator::ParenRight)) {
             lex.next();
         } else {
             loop {
                 let param = AST::read_binary_tree(lex, None, false)?;
                 params.push(param);
-                match lex.next() {
+                match lex.next_token() {
                     Some(Token::Separator(Separator::ParenRight)) => break,
                     Some(Token::Separator(Separator::Comma)) => {
-                        if lex.peek() == Some(&Token::Separator(Separator::ParenRight)) {
+                        if lex.peek_token() == Some(&Token::Separator(Separator::ParenRight)) {
                             lex.next();
                             break
                         }
@@ -744,10 +854,21 @@ impl<'a> AST<'a> {
                 }
             }
         }
-        Ok(Expr::Function(Box::new(FunctionExpr { name: function_name, params })))
+        Ok(AST::spanned(lex, start, ExprKind::Function(Box::new(FunctionExpr { name: function_name, params }))))
+    }
+
+    /// Tags an expression with the bytes from `start` to the end of the last token consumed.
+    fn spanned(lex: &Lexer, start: usize, kind: ExprKind<'a>) -> Expr<'a> {
+        Expr { kind, span: Span { start, end: lex.consumed_end() } }
+    }
+
+    /// Joins two expressions with a binary operator, covering both of them.
+    fn binary(op: Operator, left: Expr<'a>, right: Expr<'a>) -> Expr<'a> {
+        let span = Span { start: left.span.start, end: right.span.end };
+        Expr { kind: ExprKind::Binary(Box::new(BinaryExpr { op, left, right })), span }
     }
 
-    fn get_op_precedence(op: &Operator) -> Option<u8> {
+    pub(crate) fn get_op_precedence(op: &Operator) -> Option<u8> {
         match op {
             Operator::Add => Some(4),
             Operator::Subtract => Some(4),
@@ -786,21 +907,210 @@ impl<'a> AST<'a> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
     /// Helper function for all the AST testcases.
+    /// Line positions are stripped out, as they're tested separately.
     fn assert_ast(input: &str, expected_output: Option<Vec<Expr>>) {
         match AST::new(input.as_bytes()) {
             Ok(ast) => {
                 if let Some(e) = expected_output {
-                    assert_eq!(*ast, e);
+                    assert_eq!(ast.0.into_iter().map(strip_lines).collect::<Vec<_>>(), e);
                 }
             },
             Err(e) => panic!("AST test encountered error: '{}' for input: {}", e, input),
         }
     }
 
+    /// Removes every Line wrapper and span from an expression tree.
+    pub(crate) fn strip_lines(expr: Expr) -> Expr {
+        let kind = match expr.kind {
+            ExprKind::Unary(mut unary) => {
+                unary.child = strip_lines(unary.child);
+                ExprKind::Unary(unary)
+            },
+            ExprKind::Binary(mut binary) => {
+                binary.left = strip_lines(binary.left);
+                binary.right = strip_lines(binary.right);
+                ExprKind::Binary(binary)
+            },
+            ExprKind::DoUntil(mut dountil) => {
+                dountil.cond = strip_lines(dountil.cond);
+                dountil.body = strip_lines(dountil.body);
+                ExprKind::DoUntil(dountil)
+            },
+            ExprKind::For(mut for_ex) => {
+                for_ex.start = strip_lines(for_ex.start);
+                for_ex.cond = strip_lines(for_ex.cond);
+                for_ex.step = strip_lines(for_ex.step);
+                for_ex.body = strip_lines(for_ex.body);
+                ExprKind::For(for_ex)
+            },
+            ExprKind::Function(mut call) => {
+                call.params = call.params.into_iter().map(strip_lines).collect();
+                ExprKind::Function(call)
+            },
+            ExprKind::Group(group) => ExprKind::Group(group.into_iter().map(strip_lines).collect()),
+            ExprKind::If(mut if_ex) => {
+                if_ex.cond = strip_lines(if_ex.cond);
+                if_ex.body = strip_lines(if_ex.body);
+                if_ex.else_body = if_ex.else_body.map(strip_lines);
+                ExprKind::If(if_ex)
+            },
+            ExprKind::Repeat(mut repeat) => {
+                repeat.count = strip_lines(repeat.count);
+                repeat.body = strip_lines(repeat.body);
+                ExprKind::Repeat(repeat)
+            },
+            ExprKind::Switch(mut switch) => {
+                switch.input = strip_lines(switch.input);
+                switch.body = strip_lines(switch.body);
+                ExprKind::Switch(switch)
+            },
+            ExprKind::With(mut with) => {
+                with.target = strip_lines(with.target);
+                with.body = strip_lines(with.body);
+                ExprKind::With(with)
+            },
+            ExprKind::While(mut while_ex) => {
+                while_ex.cond = strip_lines(while_ex.cond);
+                while_ex.body = strip_lines(while_ex.body);
+                ExprKind::While(while_ex)
+            },
+            ExprKind::Case(value) => ExprKind::Case(Box::new(strip_lines(*value))),
+            ExprKind::Return(value) => ExprKind::Return(Box::new(strip_lines(*value))),
+            ExprKind::Line(line) => return strip_lines(line.expr),
+            kind => kind,
+        };
+        kind.into()
+    }
+
+    /// Returns the source code an expression covers.
+    fn source_of<'s>(source: &'s [u8], expr: &Expr) -> &'s str {
+        std::str::from_utf8(&source[expr.span.start..expr.span.end]).unwrap()
+    }
+
+    #[test]
+    fn line_positions() {
+        let ast = AST::new(b"a = 1;\n// comment\nif b {\n    c()\n} /* multi\nline */ exit").unwrap();
+        let positions = ast
+            .iter()
+            .map(|expr| match &expr.kind {
+                ExprKind::Line(line) => line.pos,
+                _ => panic!("expected a line, got {}", expr),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![Position { line: 1, column: 1 }, Position { line: 3, column: 1 }, Position { line: 6, column: 9 },]
+        );
+        match &ast[1].kind {
+            ExprKind::Line(line) => match &line.expr.kind {
+                ExprKind::If(if_ex) => match &if_ex.body.kind {
+                    ExprKind::Group(group) => match &group[0].kind {
+                        ExprKind::Line(line) => assert_eq!(line.pos, Position { line: 4, column: 5 }),
+                        _ => panic!("expected a line inside the if body"),
+                    },
+                    _ => panic!("expected a group as the if body"),
+                },
+                _ => panic!("expected an if statement"),
+            },
+            _ => panic!("expected a line"),
+        }
+    }
+
+    #[test]
+    fn line_spans() {
+        let source = b"a = 1;;\nif b {\n    c()\n} // comment\nexit";
+        let ast = AST::new(source).unwrap();
+        let lines = ast.iter().map(|expr| source_of(source, expr)).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["a = 1;;", "if b {\n    c()\n}", "exit"]);
+        let statements = ast
+            .iter()
+            .map(|expr| match &expr.kind {
+                ExprKind::Line(line) => source_of(source, &line.expr),
+                _ => panic!("expected a line, got {}", expr),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(statements, vec!["a = 1", "if b {\n    c()\n}", "exit"]);
+    }
+
+    #[test]
+    fn token_spans() {
+        let source = b"if a<>$1F // comment\n{ b.c = \"str\" }";
+        let tokens = Lexer::new(source).map(|t| &source[t.span.start..t.span.end]).collect::<Vec<_>>();
+        assert_eq!(tokens, vec![&b"if"[..], b"a", b"<>", b"$1F", b"{", b"b", b".", b"c", b"=", b"\"str\"", b"}"]);
+    }
+
+    #[test]
+    fn expression_spans() {
+        let source = b"a[1, 2].b = -(c + d) * f(x)";
+        fn binary<'e, 'a>(expr: &'e Expr<'a>) -> &'e BinaryExpr<'a> {
+            match &expr.kind {
+                ExprKind::Binary(binary) => binary,
+                _ => panic!("expected a binary expression, got {}", expr),
+            }
+        }
+        let ast = AST::new(source).unwrap();
+        let assign = match &ast[0].kind {
+            ExprKind::Line(line) => binary(&line.expr),
+            _ => panic!("expected a line"),
+        };
+        let deref = binary(&assign.left);
+        let index = binary(&deref.left);
+        let multiply = binary(&assign.right);
+        let spans = [&assign.left, &deref.left, &index.left, &index.right, &deref.right, &assign.right, &multiply.left]
+            .into_iter()
+            .map(|expr| source_of(source, expr))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec!["a[1, 2].b", "a[1, 2]", "a", "[1, 2]", "b", "-(c + d) * f(x)", "-(c + d)"]);
+        match &multiply.left.kind {
+            ExprKind::Unary(unary) => assert_eq!(source_of(source, &unary.child), "(c + d)"),
+            _ => panic!("expected a unary expression"),
+        }
+        match &multiply.right.kind {
+            ExprKind::Function(call) => {
+                assert_eq!(source_of(source, &multiply.right), "f(x)");
+                assert_eq!(source_of(source, &call.params[0]), "x");
+            },
+            _ => panic!("expected a function call"),
+        }
+        let expr = AST::expression(b" 1 + 2 ").unwrap();
+        assert_eq!(expr.span, Span { start: 1, end: 6 });
+    }
+
+    #[test]
+    fn error_positions() {
+        let error = AST::new(b"a = 1\nb = )").unwrap_err();
+        assert_eq!(error.pos, Some(Position { line: 2, column: 5 }));
+        assert_eq!(error.span, Some(Span { start: 10, end: 11 }));
+        assert!(error.to_string().ends_with(" at line 2, position 5"));
+    }
+
+    #[test]
+    fn error_recovery() {
+        let (ast, errors) = AST::with_errors(b"a = ); b = 1\nc = )\nd = 2\n}");
+        assert_eq!(errors.iter().map(|e| e.pos.unwrap().line).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(
+            ast.0.into_iter().map(strip_lines).collect::<Vec<_>>(),
+            vec![
+                ExprKind::Binary(Box::new(BinaryExpr {
+                    op: Operator::Assign,
+                    left: ExprKind::LiteralIdentifier(b"b").into(),
+                    right: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+                ExprKind::Binary(Box::new(BinaryExpr {
+                    op: Operator::Assign,
+                    left: ExprKind::LiteralIdentifier(b"d").into(),
+                    right: ExprKind::LiteralReal(2.0).into(),
+                }))
+                .into(),
+            ]
+        );
+    }
+
     #[test]
     fn nothing() {
         // Empty string
@@ -812,11 +1122,12 @@ mod tests {
         assert_ast(
             // Simple assignment - Assign
             "a = 1",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::LiteralReal(1.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::LiteralReal(1.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -825,11 +1136,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignAdd
             "b += 2",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignAdd,
-                left: Expr::LiteralIdentifier(b"b"),
-                right: Expr::LiteralReal(2.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"b").into(),
+                right: ExprKind::LiteralReal(2.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -838,11 +1150,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignSubtract
             "c -= 3",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignSubtract,
-                left: Expr::LiteralIdentifier(b"c"),
-                right: Expr::LiteralReal(3.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"c").into(),
+                right: ExprKind::LiteralReal(3.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -851,11 +1164,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignMultiply
             "d *= 4",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignMultiply,
-                left: Expr::LiteralIdentifier(b"d"),
-                right: Expr::LiteralReal(4.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"d").into(),
+                right: ExprKind::LiteralReal(4.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -864,11 +1178,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignDivide
             "e /= 5",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignDivide,
-                left: Expr::LiteralIdentifier(b"e"),
-                right: Expr::LiteralReal(5.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"e").into(),
+                right: ExprKind::LiteralReal(5.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -877,11 +1192,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignBinaryAnd
             "f &= 6",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignBitwiseAnd,
-                left: Expr::LiteralIdentifier(b"f"),
-                right: Expr::LiteralReal(6.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"f").into(),
+                right: ExprKind::LiteralReal(6.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -890,11 +1206,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignBinaryOr
             "g |= 7",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignBitwiseOr,
-                left: Expr::LiteralIdentifier(b"g"),
-                right: Expr::LiteralReal(7.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"g").into(),
+                right: ExprKind::LiteralReal(7.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -903,11 +1220,12 @@ mod tests {
         assert_ast(
             // Simple assignment - AssignBinaryXor
             "h ^= 8",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignBitwiseXor,
-                left: Expr::LiteralIdentifier(b"h"),
-                right: Expr::LiteralReal(8.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"h").into(),
+                right: ExprKind::LiteralReal(8.0).into(),
+            }))
+            .into()]),
         )
     }
 
@@ -938,19 +1256,22 @@ mod tests {
         assert_ast(
             // Assignment with deref and index on lhs
             "a.b[c] += d;",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::AssignAdd,
-                left: Expr::Binary(Box::new(BinaryExpr {
+                left: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Index,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Deref,
-                        left: Expr::LiteralIdentifier(b"a"),
-                        right: Expr::LiteralIdentifier(b"b"),
-                    })),
-                    right: Expr::Group(vec![Expr::LiteralIdentifier(b"c")]),
-                })),
-                right: Expr::LiteralIdentifier(b"d"),
-            }))]),
+                        left: ExprKind::LiteralIdentifier(b"a").into(),
+                        right: ExprKind::LiteralIdentifier(b"b").into(),
+                    }))
+                    .into(),
+                    right: ExprKind::Group(vec![ExprKind::LiteralIdentifier(b"c").into()]).into(),
+                }))
+                .into(),
+                right: ExprKind::LiteralIdentifier(b"d").into(),
+            }))
+            .into()]),
         );
     }
 
@@ -959,39 +1280,55 @@ mod tests {
         assert_ast(
             // Arbitrary chains of deref, 1- and 2-dimension index ops on both lhs and rhs
             "a.b[c].d.e[f,g]=h[i,j].k",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::Binary(Box::new(BinaryExpr {
+                left: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Index,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Deref,
-                        left: Expr::Binary(Box::new(BinaryExpr {
+                        left: ExprKind::Binary(Box::new(BinaryExpr {
                             op: Operator::Deref,
-                            left: Expr::Binary(Box::new(BinaryExpr {
+                            left: ExprKind::Binary(Box::new(BinaryExpr {
                                 op: Operator::Index,
-                                left: Expr::Binary(Box::new(BinaryExpr {
+                                left: ExprKind::Binary(Box::new(BinaryExpr {
                                     op: Operator::Deref,
-                                    left: Expr::LiteralIdentifier(b"a"),
-                                    right: Expr::LiteralIdentifier(b"b"),
-                                })),
-                                right: Expr::Group(vec![Expr::LiteralIdentifier(b"c")]),
-                            })),
-                            right: Expr::LiteralIdentifier(b"d"),
-                        })),
-                        right: Expr::LiteralIdentifier(b"e"),
-                    })),
-                    right: Expr::Group(vec![Expr::LiteralIdentifier(b"f"), Expr::LiteralIdentifier(b"g")]),
-                })),
-                right: Expr::Binary(Box::new(BinaryExpr {
+                                    left: ExprKind::LiteralIdentifier(b"a").into(),
+                                    right: ExprKind::LiteralIdentifier(b"b").into(),
+                                }))
+                                .into(),
+                                right: ExprKind::Group(vec![ExprKind::LiteralIdentifier(b"c").into()]).into(),
+                            }))
+                            .into(),
+                            right: ExprKind::LiteralIdentifier(b"d").into(),
+                        }))
+                        .into(),
+                        right: ExprKind::LiteralIdentifier(b"e").into(),
+                    }))
+                    .into(),
+                    right: ExprKind::Group(vec![
+                        ExprKind::LiteralIdentifier(b"f").into(),
+                        ExprKind::LiteralIdentifier(b"g").into(),
+                    ])
+                    .into(),
+                }))
+                .into(),
+                right: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Deref,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Index,
-                        left: Expr::LiteralIdentifier(b"h"),
-                        right: Expr::Group(vec![Expr::LiteralIdentifier(b"i"), Expr::LiteralIdentifier(b"j")]),
-                    })),
-                    right: Expr::LiteralIdentifier(b"k"),
-                })),
-            }))]),
+                        left: ExprKind::LiteralIdentifier(b"h").into(),
+                        right: ExprKind::Group(vec![
+                            ExprKind::LiteralIdentifier(b"i").into(),
+                            ExprKind::LiteralIdentifier(b"j").into(),
+                        ])
+                        .into(),
+                    }))
+                    .into(),
+                    right: ExprKind::LiteralIdentifier(b"k").into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         );
     }
 
@@ -1000,19 +1337,22 @@ mod tests {
         assert_ast(
             // Assignment whose LHS is an expression-deref
             "(a + 1).x = 400;",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::Binary(Box::new(BinaryExpr {
+                left: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Deref,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Add,
-                        left: Expr::LiteralIdentifier(b"a"),
-                        right: Expr::LiteralReal(1.0),
-                    })),
-                    right: Expr::LiteralIdentifier(b"x"),
-                })),
-                right: Expr::LiteralReal(400.0),
-            }))]),
+                        left: ExprKind::LiteralIdentifier(b"a").into(),
+                        right: ExprKind::LiteralReal(1.0).into(),
+                    }))
+                    .into(),
+                    right: ExprKind::LiteralIdentifier(b"x").into(),
+                }))
+                .into(),
+                right: ExprKind::LiteralReal(400.0).into(),
+            }))
+            .into()]),
         );
     }
 
@@ -1021,15 +1361,17 @@ mod tests {
         assert_ast(
             // Differentiation between usages of '=' - simple
             "a=b=c",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Binary(Box::new(BinaryExpr {
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Equal,
-                    left: Expr::LiteralIdentifier(b"b"),
-                    right: Expr::LiteralIdentifier(b"c"),
-                })),
-            }))]),
+                    left: ExprKind::LiteralIdentifier(b"b").into(),
+                    right: ExprKind::LiteralIdentifier(b"c").into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         );
     }
 
@@ -1038,39 +1380,49 @@ mod tests {
         assert_ast(
             // Differentiation between usages of '=' - complex
             "(a=b).c[d=e]=f[g=h]=i",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::Binary(Box::new(BinaryExpr {
+                left: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Index,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Deref,
-                        left: Expr::Binary(Box::new(BinaryExpr {
+                        left: ExprKind::Binary(Box::new(BinaryExpr {
                             op: Operator::Equal,
-                            left: Expr::LiteralIdentifier(b"a"),
-                            right: Expr::LiteralIdentifier(b"b"),
-                        })),
-                        right: Expr::LiteralIdentifier(b"c"),
-                    })),
-                    right: Expr::Group(vec![Expr::Binary(Box::new(BinaryExpr {
+                            left: ExprKind::LiteralIdentifier(b"a").into(),
+                            right: ExprKind::LiteralIdentifier(b"b").into(),
+                        }))
+                        .into(),
+                        right: ExprKind::LiteralIdentifier(b"c").into(),
+                    }))
+                    .into(),
+                    right: ExprKind::Group(vec![ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Equal,
-                        left: Expr::LiteralIdentifier(b"d"),
-                        right: Expr::LiteralIdentifier(b"e"),
-                    }))]),
-                })),
-                right: Expr::Binary(Box::new(BinaryExpr {
+                        left: ExprKind::LiteralIdentifier(b"d").into(),
+                        right: ExprKind::LiteralIdentifier(b"e").into(),
+                    }))
+                    .into()])
+                    .into(),
+                }))
+                .into(),
+                right: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Equal,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Index,
-                        left: Expr::LiteralIdentifier(b"f"),
-                        right: Expr::Group(vec![Expr::Binary(Box::new(BinaryExpr {
+                        left: ExprKind::LiteralIdentifier(b"f").into(),
+                        right: ExprKind::Group(vec![ExprKind::Binary(Box::new(BinaryExpr {
                             op: Operator::Equal,
-                            left: Expr::LiteralIdentifier(b"g"),
-                            right: Expr::LiteralIdentifier(b"h"),
-                        }))]),
-                    })),
-                    right: Expr::LiteralIdentifier(b"i"),
-                })),
-            }))]),
+                            left: ExprKind::LiteralIdentifier(b"g").into(),
+                            right: ExprKind::LiteralIdentifier(b"h").into(),
+                        }))
+                        .into()])
+                        .into(),
+                    }))
+                    .into(),
+                    right: ExprKind::LiteralIdentifier(b"i").into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         );
     }
 
@@ -1086,11 +1438,16 @@ mod tests {
         assert_ast(
             // Binary tree format - unary operator - positive
             "a=+1",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Unary(Box::new(UnaryExpr { op: Operator::Add, child: Expr::LiteralReal(1.0) })),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Unary(Box::new(UnaryExpr {
+                    op: Operator::Add,
+                    child: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1099,11 +1456,16 @@ mod tests {
         assert_ast(
             // Binary tree format - unary operator - negative
             "a=-1",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Unary(Box::new(UnaryExpr { op: Operator::Subtract, child: Expr::LiteralReal(1.0) })),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Unary(Box::new(UnaryExpr {
+                    op: Operator::Subtract,
+                    child: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1112,11 +1474,16 @@ mod tests {
         assert_ast(
             // Binary tree format - unary operator - complement
             "a=~1",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Unary(Box::new(UnaryExpr { op: Operator::Complement, child: Expr::LiteralReal(1.0) })),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Unary(Box::new(UnaryExpr {
+                    op: Operator::Complement,
+                    child: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1125,11 +1492,16 @@ mod tests {
         assert_ast(
             // Binary tree format - unary operator - negative
             "a=!1",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Unary(Box::new(UnaryExpr { op: Operator::Not, child: Expr::LiteralReal(1.0) })),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Unary(Box::new(UnaryExpr {
+                    op: Operator::Not,
+                    child: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1138,43 +1510,58 @@ mod tests {
         assert_ast(
             // Binary tree format - unary operators - syntax parse test
             "a = 1+!~-b.c[+d]-2--3", // (- (- (+ 1 2) 3) 4)
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Binary(Box::new(BinaryExpr {
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Subtract,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Subtract,
-                        left: Expr::Binary(Box::new(BinaryExpr {
+                        left: ExprKind::Binary(Box::new(BinaryExpr {
                             op: Operator::Add,
-                            left: Expr::LiteralReal(1.0),
-                            right: Expr::Unary(Box::new(UnaryExpr {
+                            left: ExprKind::LiteralReal(1.0).into(),
+                            right: ExprKind::Unary(Box::new(UnaryExpr {
                                 op: Operator::Not,
-                                child: Expr::Unary(Box::new(UnaryExpr {
+                                child: ExprKind::Unary(Box::new(UnaryExpr {
                                     op: Operator::Complement,
-                                    child: Expr::Unary(Box::new(UnaryExpr {
+                                    child: ExprKind::Unary(Box::new(UnaryExpr {
                                         op: Operator::Subtract,
-                                        child: Expr::Binary(Box::new(BinaryExpr {
+                                        child: ExprKind::Binary(Box::new(BinaryExpr {
                                             op: Operator::Index,
-                                            left: Expr::Binary(Box::new(BinaryExpr {
+                                            left: ExprKind::Binary(Box::new(BinaryExpr {
                                                 op: Operator::Deref,
-                                                left: Expr::LiteralIdentifier(b"b"),
-                                                right: Expr::LiteralIdentifier(b"c"),
-                                            })),
-                                            right: Expr::Group(vec![Expr::Unary(Box::new(UnaryExpr {
+                                                left: ExprKind::LiteralIdentifier(b"b").into(),
+                                                right: ExprKind::LiteralIdentifier(b"c").into(),
+                                            }))
+                                            .into(),
+                                            right: ExprKind::Group(vec![ExprKind::Unary(Box::new(UnaryExpr {
                                                 op: Operator::Add,
-                                                child: Expr::LiteralIdentifier(b"d"),
-                                            }))]),
-                                        })),
-                                    })),
-                                })),
-                            })),
-                        })),
-                        right: Expr::LiteralReal(2.0),
-                    })),
-                    right: Expr::Unary(Box::new(UnaryExpr { op: Operator::Subtract, child: Expr::LiteralReal(3.0) })),
-                })),
-            }))]),
+                                                child: ExprKind::LiteralIdentifier(b"d").into(),
+                                            }))
+                                            .into()])
+                                            .into(),
+                                        }))
+                                        .into(),
+                                    }))
+                                    .into(),
+                                }))
+                                .into(),
+                            }))
+                            .into(),
+                        }))
+                        .into(),
+                        right: ExprKind::LiteralReal(2.0).into(),
+                    }))
+                    .into(),
+                    right: ExprKind::Unary(Box::new(UnaryExpr {
+                        op: Operator::Subtract,
+                        child: ExprKind::LiteralReal(3.0).into(),
+                    }))
+                    .into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1183,18 +1570,21 @@ mod tests {
         assert_ast(
             // Unary operator applied to sub-tree
             "a = ~(b + 1)",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::Unary(Box::new(UnaryExpr {
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::Unary(Box::new(UnaryExpr {
                     op: Operator::Complement,
-                    child: Expr::Binary(Box::new(BinaryExpr {
+                    child: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Add,
-                        left: Expr::LiteralIdentifier(b"b"),
-                        right: Expr::LiteralReal(1.0),
-                    })),
-                })),
-            }))]),
+                        left: ExprKind::LiteralIdentifier(b"b").into(),
+                        right: ExprKind::LiteralReal(1.0).into(),
+                    }))
+                    .into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1203,14 +1593,23 @@ mod tests {
         assert_ast(
             // Function call syntax
             "instance_create(random(800), random(608,), apple);",
-            Some(vec![Expr::Function(Box::new(FunctionExpr {
+            Some(vec![ExprKind::Function(Box::new(FunctionExpr {
                 name: b"instance_create",
                 params: vec![
-                    Expr::Function(Box::new(FunctionExpr { name: b"random", params: vec![Expr::LiteralReal(800.0)] })),
-                    Expr::Function(Box::new(FunctionExpr { name: b"random", params: vec![Expr::LiteralReal(608.0)] })),
-                    Expr::LiteralIdentifier(b"apple"),
+                    ExprKind::Function(Box::new(FunctionExpr {
+                        name: b"random",
+                        params: vec![ExprKind::LiteralReal(800.0).into()],
+                    }))
+                    .into(),
+                    ExprKind::Function(Box::new(FunctionExpr {
+                        name: b"random",
+                        params: vec![ExprKind::LiteralReal(608.0).into()],
+                    }))
+                    .into(),
+                    ExprKind::LiteralIdentifier(b"apple").into(),
                 ],
-            }))]),
+            }))
+            .into()]),
         )
     }
 
@@ -1219,35 +1618,42 @@ mod tests {
         assert_ast(
             // For-loop syntax - standard
             "for(i = 0; i < 10; i += 1) { a = 1; b = c;}",
-            Some(vec![Expr::For(Box::new(ForExpr {
-                start: Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::For(Box::new(ForExpr {
+                start: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(0.0),
-                })),
-                cond: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(0.0).into(),
+                }))
+                .into(),
+                cond: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::LessThan,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(10.0),
-                })),
-                step: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(10.0).into(),
+                }))
+                .into(),
+                step: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::AssignAdd,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(1.0),
-                })),
-                body: Expr::Group(vec![
-                    Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+                body: ExprKind::Group(vec![
+                    ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Assign,
-                        left: Expr::LiteralIdentifier(b"a"),
-                        right: Expr::LiteralReal(1.0),
-                    })),
-                    Expr::Binary(Box::new(BinaryExpr {
+                        left: ExprKind::LiteralIdentifier(b"a").into(),
+                        right: ExprKind::LiteralReal(1.0).into(),
+                    }))
+                    .into(),
+                    ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Assign,
-                        left: Expr::LiteralIdentifier(b"b"),
-                        right: Expr::LiteralIdentifier(b"c"),
-                    })),
-                ]),
-            }))]),
+                        left: ExprKind::LiteralIdentifier(b"b").into(),
+                        right: ExprKind::LiteralIdentifier(b"c").into(),
+                    }))
+                    .into(),
+                ])
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1256,28 +1662,33 @@ mod tests {
         assert_ast(
             // For-loop syntax - no separators
             "for(i=0 i<10 i+=1) c=3",
-            Some(vec![Expr::For(Box::new(ForExpr {
-                start: Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::For(Box::new(ForExpr {
+                start: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(0.0),
-                })),
-                cond: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(0.0).into(),
+                }))
+                .into(),
+                cond: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::LessThan,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(10.0),
-                })),
-                step: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(10.0).into(),
+                }))
+                .into(),
+                step: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::AssignAdd,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(1.0),
-                })),
-                body: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+                body: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::LiteralIdentifier(b"c"),
-                    right: Expr::LiteralReal(3.0),
-                })),
-            }))]),
+                    left: ExprKind::LiteralIdentifier(b"c").into(),
+                    right: ExprKind::LiteralReal(3.0).into(),
+                }))
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1286,28 +1697,34 @@ mod tests {
         assert_ast(
             // For-loop syntax - arbitrary semicolons
             "for(i=0; i<10 i+=1; ;) {d=4}",
-            Some(vec![Expr::For(Box::new(ForExpr {
-                start: Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::For(Box::new(ForExpr {
+                start: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(0.0),
-                })),
-                cond: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(0.0).into(),
+                }))
+                .into(),
+                cond: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::LessThan,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(10.0),
-                })),
-                step: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(10.0).into(),
+                }))
+                .into(),
+                step: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::AssignAdd,
-                    left: Expr::LiteralIdentifier(b"i"),
-                    right: Expr::LiteralReal(1.0),
-                })),
-                body: Expr::Group(vec![Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"i").into(),
+                    right: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+                body: ExprKind::Group(vec![ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::LiteralIdentifier(b"d"),
-                    right: Expr::LiteralReal(4.0),
-                }))]),
-            }))]),
+                    left: ExprKind::LiteralIdentifier(b"d").into(),
+                    right: ExprKind::LiteralReal(4.0).into(),
+                }))
+                .into()])
+                .into(),
+            }))
+            .into()]),
         )
     }
 
@@ -1315,11 +1732,12 @@ mod tests {
     fn pascal_init_assign() {
         assert_ast(
             "a := 1",
-            Some(vec![Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Assign,
-                left: Expr::LiteralIdentifier(b"a"),
-                right: Expr::LiteralReal(1.0),
-            }))]),
+                left: ExprKind::LiteralIdentifier(b"a").into(),
+                right: ExprKind::LiteralReal(1.0).into(),
+            }))
+            .into()]),
         );
     }
 
@@ -1336,31 +1754,41 @@ mod tests {
                 a = 4;
             end
             ",
-            Some(vec![Expr::If(Box::new(IfExpr {
-                cond: Expr::Binary(Box::new(BinaryExpr {
+            Some(vec![ExprKind::If(Box::new(IfExpr {
+                cond: ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Equal,
-                    left: Expr::LiteralIdentifier(b"a"),
-                    right: Expr::LiteralReal(1.0),
-                })),
-                body: Expr::Group(vec![Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::LiteralIdentifier(b"a").into(),
+                    right: ExprKind::LiteralReal(1.0).into(),
+                }))
+                .into(),
+                body: ExprKind::Group(vec![ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::LiteralIdentifier(b"a"),
-                    right: Expr::LiteralReal(2.0),
-                }))]),
-                else_body: Some(Expr::If(Box::new(IfExpr {
-                    cond: Expr::Binary(Box::new(BinaryExpr {
-                        op: Operator::Equal,
-                        left: Expr::LiteralIdentifier(b"a"),
-                        right: Expr::LiteralReal(2.0),
-                    })),
-                    body: Expr::Group(vec![Expr::Binary(Box::new(BinaryExpr {
-                        op: Operator::Assign,
-                        left: Expr::LiteralIdentifier(b"a"),
-                        right: Expr::LiteralReal(4.0),
-                    }))]),
-                    else_body: None,
-                }))),
-            }))]),
+                    left: ExprKind::LiteralIdentifier(b"a").into(),
+                    right: ExprKind::LiteralReal(2.0).into(),
+                }))
+                .into()])
+                .into(),
+                else_body: Some(
+                    ExprKind::If(Box::new(IfExpr {
+                        cond: ExprKind::Binary(Box::new(BinaryExpr {
+                            op: Operator::Equal,
+                            left: ExprKind::LiteralIdentifier(b"a").into(),
+                            right: ExprKind::LiteralReal(2.0).into(),
+                        }))
+                        .into(),
+                        body: ExprKind::Group(vec![ExprKind::Binary(Box::new(BinaryExpr {
+                            op: Operator::Assign,
+                            left: ExprKind::LiteralIdentifier(b"a").into(),
+                            right: ExprKind::LiteralReal(4.0).into(),
+                        }))
+                        .into()])
+                        .into(),
+                        else_body: None,
+                    }))
+                    .into(),
+                ),
+            }))
+            .into()]),
         );
     }
 
@@ -1370,8 +1798,8 @@ mod tests {
             // var syntax - basic constructions
             "var a; var b, c",
             Some(vec![
-                Expr::Var(Box::new(VarExpr { vars: vec![b"a"] })),
-                Expr::Var(Box::new(VarExpr { vars: vec![b"b", b"c"] })),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![b"a"] })).into(),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![b"b", b"c"] })).into(),
             ]),
         )
     }
@@ -1382,10 +1810,10 @@ mod tests {
             // var syntax - unusual valid constructions
             "var; var a,b,; var c,var",
             Some(vec![
-                Expr::Var(Box::new(VarExpr { vars: vec![] })),
-                Expr::Var(Box::new(VarExpr { vars: vec![b"a", b"b"] })),
-                Expr::Var(Box::new(VarExpr { vars: vec![b"c"] })),
-                Expr::Var(Box::new(VarExpr { vars: vec![] })),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![] })).into(),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![b"a", b"b"] })).into(),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![b"c"] })).into(),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![] })).into(),
             ]),
         )
     }
@@ -1403,8 +1831,8 @@ mod tests {
         assert_ast(
             "var a instance_create instance_destroy ()",
             Some(vec![
-                Expr::Var(Box::new(VarExpr { vars: vec![b"a", b"instance_create"] })),
-                Expr::Function(Box::new(FunctionExpr { name: b"instance_destroy", params: vec![] })),
+                ExprKind::Var(Box::new(VarExpr { vars: vec![b"a", b"instance_create"] })).into(),
+                ExprKind::Function(Box::new(FunctionExpr { name: b"instance_destroy", params: vec![] })).into(),
             ]),
         )
     }
@@ -1415,16 +1843,18 @@ mod tests {
         assert_ast(
             "var a b global.g = 0",
             Some(vec![
-                Expr::Var(Box::new(VarExpr { vars: vec![b"a", b"b"] })),
-                Expr::Binary(Box::new(BinaryExpr {
+                ExprKind::Var(Box::new(VarExpr { vars: vec![b"a", b"b"] })).into(),
+                ExprKind::Binary(Box::new(BinaryExpr {
                     op: Operator::Assign,
-                    left: Expr::Binary(Box::new(BinaryExpr {
+                    left: ExprKind::Binary(Box::new(BinaryExpr {
                         op: Operator::Deref,
-                        left: Expr::LiteralIdentifier(b"global"),
-                        right: Expr::LiteralIdentifier(b"g"),
-                    })),
-                    right: Expr::LiteralReal(0.0),
-                })),
+                        left: ExprKind::LiteralIdentifier(b"global").into(),
+                        right: ExprKind::LiteralIdentifier(b"g").into(),
+                    }))
+                    .into(),
+                    right: ExprKind::LiteralReal(0.0).into(),
+                }))
+                .into(),
             ]),
         )
     }
@@ -1432,31 +1862,36 @@ mod tests {
     #[test]
     fn expression_literal_real() {
         // expression - single literal real
-        assert_eq!(AST::expression(b"1").unwrap(), Expr::LiteralReal(1.0));
+        assert_eq!(strip_lines(AST::expression(b"1").unwrap()), ExprKind::LiteralReal(1.0).into());
     }
 
     #[test]
     fn expression_literal_identifier() {
         // expression - literal identifier
-        assert_eq!(AST::expression(b"a").unwrap(), Expr::LiteralIdentifier(b"a"));
+        assert_eq!(strip_lines(AST::expression(b"a").unwrap()), ExprKind::LiteralIdentifier(b"a").into());
     }
 
     #[test]
     fn expression_with_operators() {
         // expression - unary and binary operators
         assert_eq!(
-            AST::expression(b"1 * -2").unwrap(),
-            Expr::Binary(Box::new(BinaryExpr {
+            strip_lines(AST::expression(b"1 * -2").unwrap()),
+            ExprKind::Binary(Box::new(BinaryExpr {
                 op: Operator::Multiply,
-                left: Expr::LiteralReal(1.0),
-                right: Expr::Unary(Box::new(UnaryExpr { op: Operator::Subtract, child: Expr::LiteralReal(2.0) })),
+                left: ExprKind::LiteralReal(1.0).into(),
+                right: ExprKind::Unary(Box::new(UnaryExpr {
+                    op: Operator::Subtract,
+                    child: ExprKind::LiteralReal(2.0).into()
+                }))
+                .into(),
             }))
+            .into()
         );
     }
 
     #[test]
     fn expression_with_overrun() {
         // expression with extra code after it - extra code should be dropped
-        assert_eq!(AST::expression(b"0; a=1; game_end()").unwrap(), Expr::LiteralReal(0.0));
+        assert_eq!(strip_lines(AST::expression(b"0; a=1; game_end()").unwrap()), ExprKind::LiteralReal(0.0).into());
     }
 }
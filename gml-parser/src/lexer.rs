@@ -1,5 +1,8 @@
 use crate::token::{Keyword, Operator, Separator, Token};
 
+#[cfg(feature = "runner-serde-derives")]
+use serde::{Deserialize, Serialize};
+
 use std::{
     iter::{Copied, Enumerate, Peekable},
     slice,
@@ -7,12 +10,64 @@ use std::{
     str, u64,
 };
 
+/// A place in GML source code. Both the line and the column start at 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "runner-serde-derives", derive(Serialize, Deserialize))]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A range of bytes in GML source code, from `start` up to but not including `end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "runner-serde-derives", derive(Serialize, Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A comment in GML source code. These are normally skipped over, but a Lexer can be asked to keep them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Comment<'a> {
+    /// Where the comment's opening `//` or `/*` is.
+    pub pos: Position,
+    pub span: Span,
+
+    /// The whole comment, including the `//` or `/* */`.
+    pub text: &'a [u8],
+}
+
+/// A token read from GML source code, along with the bytes it covers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
+    pub span: Span,
+}
+
 #[derive(Clone)]
 pub struct Lexer<'a> {
     /// GML source code to return references to.
     src: &'a [u8],
 
-    line_hint: usize,
+    /// Byte offsets where the most recently read token starts and ends.
+    token_start: usize,
+    token_end: usize,
+
+    /// Byte offset where the most recently consumed token ends, ignoring any token that's only been peeked at.
+    consumed_end: usize,
+
+    /// Line number at `line_start`, and the byte offset where that line starts.
+    line: usize,
+    line_start: usize,
+
+    /// How far into the source newlines have been counted.
+    counted: usize,
+
+    /// Token read ahead of time by peek(), if any.
+    peeked: Option<Option<SpannedToken<'a>>>,
+
+    /// Comments skipped over so far, if they're being kept.
+    comments: Option<Vec<Comment<'a>>>,
 
     /// Iterator over the source code as raw bytes.
     iter: Peekable<Enumerate<Copied<slice::Iter<'a, u8>>>>,
@@ -21,38 +76,133 @@ pub struct Lexer<'a> {
 impl<'a> Lexer<'a> {
     /// Creates a new Lexer over GML source code.
     pub fn new(src: &'a [u8]) -> Self {
-        Lexer { src, line_hint: 1, iter: src.iter().copied().enumerate().peekable() }
+        Lexer {
+            src,
+            token_start: 0,
+            token_end: 0,
+            consumed_end: 0,
+            line: 1,
+            line_start: 0,
+            counted: 0,
+            peeked: None,
+            comments: None,
+            iter: src.iter().copied().enumerate().peekable(),
+        }
+    }
+
+    /// Creates a new Lexer which keeps the comments it skips over, so they can be collected with take_comments().
+    pub fn with_comments(src: &'a [u8]) -> Self {
+        Lexer { comments: Some(Vec::new()), ..Self::new(src) }
+    }
+
+    /// Returns the comments skipped over since this was last called.
+    /// This is always empty if the Lexer wasn't created with with_comments().
+    pub fn take_comments(&mut self) -> Vec<Comment<'a>> {
+        self.comments.as_mut().map(std::mem::take).unwrap_or_default()
     }
 
-    /// Returns the current line number in the source code.
+    /// Returns the line number of the most recently read token.
     pub fn line(&self) -> usize {
-        self.line_hint
+        self.line
+    }
+
+    /// Returns where the most recently read token starts in the source code.
+    pub fn pos(&self) -> Position {
+        Position { line: self.line, column: self.token_start - self.line_start + 1 }
+    }
+
+    /// Returns the bytes the most recently read token covers in the source code.
+    pub fn span(&self) -> Span {
+        Span { start: self.token_start, end: self.token_end }
+    }
+
+    /// Returns where the most recently consumed token ends in the source code.
+    /// Unlike span(), this isn't affected by peek().
+    pub fn consumed_end(&self) -> usize {
+        self.consumed_end
+    }
+
+    /// Returns the next token without consuming it.
+    pub fn peek(&mut self) -> Option<&SpannedToken<'a>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read_spanned());
+        }
+        self.peeked.as_ref().and_then(Option::as_ref)
+    }
+
+    /// Like next(), but without the span.
+    pub fn next_token(&mut self) -> Option<Token<'a>> {
+        self.next().map(|t| t.token)
+    }
+
+    /// Like peek(), but without the span.
+    pub fn peek_token(&mut self) -> Option<&Token<'a>> {
+        self.peek().map(|t| &t.token)
     }
 
     /// Fast-forwards the internal iterator to the next token, skipping over whitespace.
-    /// Returns how many lines (LF) were skipped in the process.
-    fn fast_forward(&mut self) -> usize {
-        let mut lines_skipped: usize = 0;
-        loop {
-            match self.iter.peek() {
-                Some(&(_, ch)) if ch <= b' ' => {
-                    if ch == b'\n' {
-                        lines_skipped += 1;
-                    }
-                    self.iter.next();
-                },
-                _ => break lines_skipped,
+    fn fast_forward(&mut self) {
+        while let Some(&(_, ch)) = self.iter.peek() {
+            if ch > b' ' {
+                break
             }
+            self.iter.next();
+        }
+    }
+
+    /// Marks the token starting at the given offset as the current one, counting any lines passed on the way.
+    fn start_token(&mut self, offset: usize) {
+        for (i, &ch) in self.src[self.counted..offset].iter().enumerate() {
+            if ch == b'\n' {
+                self.line += 1;
+                self.line_start = self.counted + i + 1;
+            }
+        }
+        self.counted = offset;
+        self.token_start = offset;
+    }
+
+    /// Keeps the comment which started at the current token's position and ends where the iterator is, if needed.
+    fn end_comment(&mut self) {
+        if self.comments.is_some() {
+            let pos = self.pos();
+            let span = Span { start: self.token_start, end: self.offset() };
+            let text = &self.src[span.start..span.end];
+            self.comments.as_mut().unwrap().push(Comment { pos, span, text });
         }
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token<'a>;
+    type Item = SpannedToken<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let token = match self.peeked.take() {
+            Some(token) => token,
+            None => self.read_spanned(),
+        };
+        self.consumed_end = self.token_end;
+        token
+    }
+}
+
+impl<'a> Lexer<'a> {
+    /// Reads the next token from the source code, and records where it ends.
+    fn read_spanned(&mut self) -> Option<SpannedToken<'a>> {
+        let token = self.read_token();
+        self.token_end = self.offset();
+        token.map(|token| SpannedToken { token, span: self.span() })
+    }
+
+    /// Returns the byte offset the iterator is at.
+    fn offset(&mut self) -> usize {
+        self.iter.peek().map(|&(i, _)| i).unwrap_or(self.src.len())
+    }
+
+    /// Reads the next token from the source code.
+    fn read_token(&mut self) -> Option<Token<'a>> {
         // locate next token
-        self.line_hint = self.fast_forward();
+        self.fast_forward();
 
         /// Helper function to reconstruct our byte slices to a string easily.
         /// This is fine since we operate on something that is a &str in a first place,
@@ -63,6 +213,7 @@ impl<'a> Iterator for Lexer<'a> {
         }
 
         let head = *self.iter.peek()?;
+        self.start_token(head.0);
 
         #[allow(clippy::match_overlapping_arm)] // quotes overlap with the catch-all ASCII
         Some(match head.1 {
@@ -288,7 +439,8 @@ impl<'a> Iterator for Lexer<'a> {
                                         },
                                     }
                                 }
-                                return self.next()
+                                self.end_comment();
+                                return self.read_token()
                             },
 
                             _ => return Some(Token::Operator(op)),
@@ -338,7 +490,8 @@ impl<'a> Iterator for Lexer<'a> {
                                 },
                             }
                         }
-                        return self.next()
+                        self.end_comment();
+                        return self.read_token()
                     } else if op == Operator::LessThan && ch2 == b'>' {
                         // <> is the same as != (let's call it a diamond)
 
@@ -0,0 +1,690 @@
+use crate::{
+    ast::{self, Expr, ExprKind, AST},
+    lexer::{Comment, Lexer, Position},
+    token::{Operator, Token},
+};
+
+/// Where to put the opening brace of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BraceStyle {
+    /// At the end of the line which starts the block: `if (a) {`
+    SameLine,
+
+    /// On a line of its own, lined up with the closing brace
+    NextLine,
+}
+
+/// How printed code should be laid out.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// What to indent code with, once for each level of nesting
+    pub indent: String,
+
+    pub brace_style: BraceStyle,
+
+    /// What to end lines with
+    pub newline: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { indent: "    ".into(), brace_style: BraceStyle::SameLine, newline: "\r\n".into() }
+    }
+}
+
+/// What an expression is used for, so a Rewrite can tell where it's being asked about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Place {
+    /// Anything which is evaluated normally
+    Value,
+
+    /// The field name on the right of a `.`
+    Field,
+
+    /// The instance on the left of a `.`
+    DerefTarget,
+
+    /// The instance in `with (...)`
+    WithTarget,
+}
+
+/// Lets some parts of the code be printed differently, for example to rename things.
+///
+/// Anything returned is printed as it is, and treated as a single operand,
+/// so it should have its own parentheses if it needs them.
+pub trait Rewrite {
+    /// Returns the code to print in place of an expression, or None to print it normally.
+    fn expression(&mut self, _expr: &Expr, _place: Place) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Returns the name to print in place of a function's name when it's called.
+    fn function(&mut self, _name: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Returns the name to print in place of a variable declared with `var` or `globalvar`.
+    fn declaration(&mut self, _name: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Prints everything as it is.
+impl Rewrite for () {}
+
+/// Pretty-prints some GML code, keeping its comments.
+pub fn format(source: &[u8], options: &Options, rewrite: &mut dyn Rewrite) -> Result<Vec<u8>, ast::Error> {
+    let ast = AST::new(source)?;
+    Ok(print_statements(&ast, options, rewrite, comments(source)))
+}
+
+/// Pretty-prints a GML expression, such as an action argument, keeping its comments.
+pub fn format_expression(source: &[u8], options: &Options, rewrite: &mut dyn Rewrite) -> Result<Vec<u8>, ast::Error> {
+    let expr = AST::expression(source)?;
+    Ok(print_operand(&expr, options, rewrite, comments(source)))
+}
+
+/// Prints a parsed piece of GML back to code. Parsing the output gives back the same AST, apart from line positions.
+pub fn print(ast: &[Expr], options: &Options) -> Vec<u8> {
+    print_statements(ast, options, &mut (), Vec::new())
+}
+
+/// Prints a parsed GML expression back to code.
+pub fn print_expression(expr: &Expr, options: &Options) -> Vec<u8> {
+    print_operand(expr, options, &mut (), Vec::new())
+}
+
+fn print_statements(ast: &[Expr], options: &Options, rewrite: &mut dyn Rewrite, comments: Vec<Comment>) -> Vec<u8> {
+    let mut printer = Printer::new(options, rewrite, comments, ast);
+    printer.statements(ast);
+    printer.flush_comments();
+    printer.output
+}
+
+fn print_operand(expr: &Expr, options: &Options, rewrite: &mut dyn Rewrite, comments: Vec<Comment>) -> Vec<u8> {
+    let mut printer = Printer::new(options, rewrite, comments, &[]);
+    printer.operand(expr, Place::Value, false);
+    // There's nowhere else for comments to go in a single expression, so they all go after it
+    while let Some(comment) = printer.comments.pop() {
+        printer.output.push(b' ');
+        printer.comment(comment);
+    }
+    printer.output
+}
+
+/// Finds every comment in some GML code.
+fn comments(source: &[u8]) -> Vec<Comment<'_>> {
+    let mut lex = Lexer::with_comments(source);
+    while lex.next().is_some() {}
+    lex.take_comments()
+}
+
+struct Printer<'o, 'r, 'c> {
+    options: &'o Options,
+    rewrite: &'r mut dyn Rewrite,
+    output: Vec<u8>,
+    indent: usize,
+
+    /// Comments which haven't been printed yet, in reverse order so the next one can be popped off
+    comments: Vec<Comment<'c>>,
+
+    /// Where every line of code starts, in order, for telling which comments belong at the end of a line
+    lines: Vec<Position>,
+}
+
+impl<'o, 'r, 'c> Printer<'o, 'r, 'c> {
+    fn new(options: &'o Options, rewrite: &'r mut dyn Rewrite, mut comments: Vec<Comment<'c>>, ast: &[Expr]) -> Self {
+        let mut lines = Vec::new();
+        ast.iter().for_each(|x| line_positions(x, &mut lines));
+        lines.sort_unstable();
+        comments.reverse();
+        Self { options, rewrite, output: Vec::new(), indent: 0, comments, lines }
+    }
+
+    /// Writes a list of statements, each on their own line, with switch labels outdented.
+    fn statements(&mut self, exprs: &[Expr]) {
+        let mut in_case = false;
+        for expr in exprs {
+            if matches!(expr.kind, ExprKind::Case(_) | ExprKind::Default) {
+                if in_case {
+                    self.indent -= 1;
+                }
+                in_case = true;
+                self.statement(expr);
+                self.indent += 1;
+            } else {
+                self.statement(expr);
+            }
+        }
+        if in_case {
+            self.indent -= 1;
+        }
+    }
+
+    /// Writes a statement on a new line, along with any comments that came before it or after it on the same line.
+    fn statement(&mut self, expr: &Expr) {
+        let pos = match &expr.kind {
+            ExprKind::Line(line) => {
+                self.comments_before(line.pos);
+                Some(line.pos)
+            },
+            _ => None,
+        };
+        self.write_indent();
+        if self.simple_statement(expr) {
+            self.output.push(b';');
+            if let Some(pos) = pos {
+                self.trailing_comments(pos);
+            }
+        } else {
+            self.compound_statement(expr);
+        }
+        self.end_line();
+    }
+
+    /// Writes a statement which fits on one line, without its semicolon. Returns false if it isn't one.
+    fn simple_statement(&mut self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Line(line) => return self.simple_statement(&line.expr),
+            ExprKind::Binary(binary) if !matches!(binary.op, Operator::Deref | Operator::Index) => {
+                // The line has to start with an identifier that isn't a function call, or an opening parenthesis
+                let base = chain_base(&binary.left);
+                let wrap = match base.kind {
+                    ExprKind::LiteralIdentifier(_) => false,
+                    ExprKind::Function(_) => true,
+                    _ => std::ptr::eq(base, &binary.left),
+                };
+                self.operand(&binary.left, Place::Value, wrap);
+                self.write_operator(binary.op);
+                self.operand(&binary.right, Place::Value, false);
+            },
+            ExprKind::Var(var) => self.declaration(b"var", &var.vars),
+            ExprKind::GlobalVar(var) => self.declaration(b"globalvar", &var.vars),
+            ExprKind::Continue => self.output.extend_from_slice(b"continue"),
+            ExprKind::Break => self.output.extend_from_slice(b"break"),
+            ExprKind::Exit => self.output.extend_from_slice(b"exit"),
+            ExprKind::Return(value) => {
+                self.output.extend_from_slice(b"return ");
+                self.operand(value, Place::Value, false);
+            },
+            ExprKind::DoUntil(_)
+            | ExprKind::For(_)
+            | ExprKind::Group(_)
+            | ExprKind::If(_)
+            | ExprKind::Repeat(_)
+            | ExprKind::Switch(_)
+            | ExprKind::With(_)
+            | ExprKind::While(_)
+            | ExprKind::Case(_)
+            | ExprKind::Default => return false,
+            _ => self.operand(expr, Place::Value, false),
+        }
+        true
+    }
+
+    /// Writes a statement which may span several lines, such as an if statement or a loop.
+    fn compound_statement(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Line(line) => self.compound_statement(&line.expr),
+            ExprKind::DoUntil(do_until) => {
+                self.output.extend_from_slice(b"do");
+                self.body(&do_until.body);
+                self.continue_after(&do_until.body);
+                self.output.extend_from_slice(b"until ");
+                self.condition(&do_until.cond, Place::Value);
+                self.output.push(b';');
+            },
+            ExprKind::For(for_ex) => {
+                self.output.extend_from_slice(b"for (");
+                self.inline_statement(&for_ex.start);
+                self.output.extend_from_slice(b"; ");
+                self.operand(&for_ex.cond, Place::Value, false);
+                self.output.extend_from_slice(b"; ");
+                self.inline_statement(&for_ex.step);
+                self.output.push(b')');
+                self.body(&for_ex.body);
+            },
+            ExprKind::Group(group) => {
+                self.output.push(b'{');
+                self.block(group);
+            },
+            ExprKind::If(if_ex) => {
+                self.output.extend_from_slice(b"if ");
+                self.condition(&if_ex.cond, Place::Value);
+                self.body(&if_ex.body);
+                if let Some(else_body) = &if_ex.else_body {
+                    self.continue_after(&if_ex.body);
+                    self.output.extend_from_slice(b"else");
+                    match &else_body.kind {
+                        ExprKind::Line(line) if matches!(line.expr.kind, ExprKind::If(_)) => {
+                            self.output.push(b' ');
+                            self.compound_statement(&line.expr);
+                        },
+                        _ => self.body(else_body),
+                    }
+                }
+            },
+            ExprKind::Repeat(repeat) => {
+                self.output.extend_from_slice(b"repeat ");
+                self.condition(&repeat.count, Place::Value);
+                self.body(&repeat.body);
+            },
+            ExprKind::Switch(switch) => {
+                self.output.extend_from_slice(b"switch ");
+                self.condition(&switch.input, Place::Value);
+                self.body(&switch.body);
+            },
+            ExprKind::With(with) => {
+                self.output.extend_from_slice(b"with ");
+                self.condition(&with.target, Place::WithTarget);
+                self.body(&with.body);
+            },
+            ExprKind::While(while_ex) => {
+                self.output.extend_from_slice(b"while ");
+                self.condition(&while_ex.cond, Place::Value);
+                self.body(&while_ex.body);
+            },
+            ExprKind::Case(value) => {
+                self.output.extend_from_slice(b"case ");
+                self.operand(value, Place::Value, false);
+                self.output.push(b':');
+            },
+            ExprKind::Default => self.output.extend_from_slice(b"default:"),
+            _ => {
+                self.simple_statement(expr);
+                self.output.push(b';');
+            },
+        }
+    }
+
+    /// Writes a statement inside a for loop's parentheses.
+    fn inline_statement(&mut self, expr: &Expr) {
+        if !self.simple_statement(expr) {
+            self.compound_statement(expr);
+        }
+    }
+
+    /// Writes the body of an if statement or loop, after the part that's already been written.
+    /// Blocks are left open after the closing brace, and anything else ends its line.
+    fn body(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Group(group) => {
+                match self.options.brace_style {
+                    BraceStyle::SameLine => self.output.push(b' '),
+                    BraceStyle::NextLine => {
+                        self.end_line();
+                        self.write_indent();
+                    },
+                }
+                self.output.push(b'{');
+                self.block(group);
+            },
+            _ => {
+                self.end_line();
+                self.indent += 1;
+                self.statement(expr);
+                self.indent -= 1;
+            },
+        }
+    }
+
+    /// Gets ready to write the `else` or `until` which follows a body.
+    fn continue_after(&mut self, body: &Expr) {
+        if matches!(body.kind, ExprKind::Group(_)) && self.options.brace_style == BraceStyle::SameLine {
+            self.output.push(b' ');
+        } else {
+            self.end_line();
+            self.write_indent();
+        }
+    }
+
+    /// Writes the contents of a block and its closing brace, after the opening brace has been written.
+    fn block(&mut self, group: &[Expr]) {
+        if !group.is_empty() {
+            self.end_line();
+            self.indent += 1;
+            self.statements(group);
+            self.indent -= 1;
+            self.write_indent();
+        }
+        self.output.push(b'}');
+    }
+
+    fn declaration(&mut self, keyword: &[u8], names: &[&[u8]]) {
+        self.output.extend_from_slice(keyword);
+        for (i, name) in names.iter().enumerate() {
+            self.output.extend_from_slice(if i == 0 { b" " } else { b", " });
+            match self.rewrite.declaration(name) {
+                Some(name) => self.output.extend_from_slice(&name),
+                None => self.output.extend_from_slice(name),
+            }
+        }
+    }
+
+    /// Writes the condition of an if statement or loop, in parentheses.
+    fn condition(&mut self, expr: &Expr, place: Place) {
+        self.output.push(b'(');
+        self.operand(expr, place, false);
+        self.output.push(b')');
+    }
+
+    /// Writes an expression, in parentheses if `wrap` is set, unless it gets rewritten.
+    fn operand(&mut self, expr: &Expr, place: Place, wrap: bool) {
+        if let Some(code) = self.rewrite.expression(expr, place) {
+            self.output.extend_from_slice(&code);
+        } else if wrap {
+            self.output.push(b'(');
+            self.expression(expr);
+            self.output.push(b')');
+        } else {
+            self.expression(expr);
+        }
+    }
+
+    /// Writes an expression, with only the parentheses needed for it to be parsed the same way.
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::LiteralIdentifier(name) => self.output.extend_from_slice(name),
+            ExprKind::LiteralReal(real) => self.output.extend_from_slice(format_real(*real).as_bytes()),
+            ExprKind::LiteralString(string) => {
+                let quote = if string.contains(&b'"') { b'\'' } else { b'"' };
+                self.output.push(quote);
+                self.output.extend_from_slice(string);
+                self.output.push(quote);
+            },
+            ExprKind::Unary(unary) => {
+                self.output.extend_from_slice(unary.op.to_string().as_bytes());
+                let wrap = matches!(&unary.child.kind, ExprKind::Binary(b) if !matches!(b.op, Operator::Deref | Operator::Index));
+                self.operand(&unary.child, Place::Value, wrap);
+            },
+            ExprKind::Binary(binary) => match binary.op {
+                Operator::Deref => {
+                    self.operand(&binary.left, Place::DerefTarget, !is_postfix_base(&binary.left));
+                    self.output.push(b'.');
+                    self.operand(&binary.right, Place::Field, false);
+                },
+                Operator::Index => {
+                    self.operand(&binary.left, Place::Value, !is_postfix_base(&binary.left));
+                    self.output.push(b'[');
+                    match &binary.right.kind {
+                        ExprKind::Group(dimensions) => self.list(dimensions),
+                        _ => self.operand(&binary.right, Place::Value, false),
+                    }
+                    self.output.push(b']');
+                },
+                op => {
+                    // Operators of the same precedence are read left to right, so only the right side needs
+                    // parentheses around them
+                    let precedence = AST::get_op_precedence(&op);
+                    let left_wrap = binary_precedence(&binary.left).map_or(false, |x| Some(x) < precedence);
+                    let right_wrap = binary_precedence(&binary.right).map_or(false, |x| Some(x) <= precedence);
+                    self.operand(&binary.left, Place::Value, left_wrap);
+                    self.write_operator(op);
+                    self.operand(&binary.right, Place::Value, right_wrap);
+                },
+            },
+            ExprKind::Function(call) => {
+                match self.rewrite.function(call.name) {
+                    Some(name) => self.output.extend_from_slice(&name),
+                    None => self.output.extend_from_slice(call.name),
+                }
+                self.output.push(b'(');
+                self.list(&call.params);
+                self.output.push(b')');
+            },
+            ExprKind::Group(group) => self.list(group),
+            ExprKind::Line(line) => self.expression(&line.expr),
+            _ => self.inline_statement(expr),
+        }
+    }
+
+    /// Writes a comma-separated list of expressions.
+    fn list(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i != 0 {
+                self.output.extend_from_slice(b", ");
+            }
+            self.operand(expr, Place::Value, false);
+        }
+    }
+
+    fn write_operator(&mut self, op: Operator) {
+        self.output.push(b' ');
+        self.output.extend_from_slice(op.to_string().as_bytes());
+        self.output.push(b' ');
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.output.extend_from_slice(self.options.indent.as_bytes());
+        }
+    }
+
+    /// Starts a new line, unless the current one is empty.
+    fn end_line(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with(self.options.newline.as_bytes()) {
+            self.output.extend_from_slice(self.options.newline.as_bytes());
+        }
+    }
+
+    fn comment(&mut self, comment: Comment) {
+        self.output.extend_from_slice(comment.text);
+        if comment.text.starts_with(b"//") {
+            self.output.extend_from_slice(self.options.newline.as_bytes());
+        }
+    }
+
+    /// Writes every comment that comes before a position, each on its own line.
+    fn comments_before(&mut self, pos: Position) {
+        while self.comments.last().map_or(false, |x| x.pos < pos) {
+            let comment = self.comments.pop().unwrap();
+            self.end_line();
+            self.write_indent();
+            self.comment(comment);
+            self.end_line();
+        }
+    }
+
+    /// Writes any comments after a line of code, up until the next line of code, as long as they're on the same line.
+    fn trailing_comments(&mut self, pos: Position) {
+        let next = self.lines.iter().find(|x| **x > pos).copied();
+        while let Some(&comment) = self.comments.last() {
+            if comment.pos.line != pos.line || next.map_or(false, |x| comment.pos > x) {
+                break
+            }
+            self.comments.pop();
+            self.output.push(b' ');
+            self.comment(comment);
+        }
+    }
+
+    /// Writes all the comments which haven't been written yet, such as ones at the end of the code.
+    fn flush_comments(&mut self) {
+        while let Some(comment) = self.comments.pop() {
+            self.end_line();
+            self.write_indent();
+            self.comment(comment);
+        }
+        self.end_line();
+    }
+}
+
+/// Finds where every line of code in an expression starts.
+fn line_positions(expr: &Expr, out: &mut Vec<Position>) {
+    let mut visit = |expr: &Expr| line_positions(expr, out);
+    match &expr.kind {
+        ExprKind::Line(line) => {
+            out.push(line.pos);
+            line_positions(&line.expr, out);
+        },
+        ExprKind::DoUntil(do_until) => visit(&do_until.body),
+        ExprKind::For(for_ex) => {
+            visit(&for_ex.start);
+            visit(&for_ex.step);
+            visit(&for_ex.body);
+        },
+        ExprKind::Group(group) => group.iter().for_each(visit),
+        ExprKind::If(if_ex) => {
+            visit(&if_ex.body);
+            if_ex.else_body.iter().for_each(visit);
+        },
+        ExprKind::Repeat(repeat) => visit(&repeat.body),
+        ExprKind::Switch(switch) => visit(&switch.body),
+        ExprKind::With(with) => visit(&with.body),
+        ExprKind::While(while_ex) => visit(&while_ex.body),
+        _ => (),
+    }
+}
+
+/// Follows the left side of `.` and `[]` operators to find what an expression like `a.b[c].d` starts with.
+fn chain_base<'e, 'a>(expr: &'e Expr<'a>) -> &'e Expr<'a> {
+    match &expr.kind {
+        ExprKind::Binary(binary) if matches!(binary.op, Operator::Deref | Operator::Index) => chain_base(&binary.left),
+        _ => expr,
+    }
+}
+
+/// Whether an expression can have `.` or `[]` put straight after it without parentheses.
+fn is_postfix_base(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::LiteralIdentifier(_) | ExprKind::Function(_) => true,
+        ExprKind::Binary(binary) => matches!(binary.op, Operator::Deref | Operator::Index),
+        _ => false,
+    }
+}
+
+/// Returns the precedence of an expression's operator, if it's an infix operator that might need parentheses.
+/// Assignments count as the lowest precedence, since they can't be parsed as an operand at all.
+fn binary_precedence(expr: &Expr) -> Option<u8> {
+    match &expr.kind {
+        ExprKind::Binary(binary) if !matches!(binary.op, Operator::Deref | Operator::Index) => {
+            Some(AST::get_op_precedence(&binary.op).unwrap_or(0))
+        },
+        _ => None,
+    }
+}
+
+/// Formats a real so that the lexer reads it back as exactly the same number, if that's possible.
+fn format_real(real: f64) -> String {
+    let reads_back = |s: &str| matches!(Lexer::new(s.as_bytes()).next_token(), Some(Token::Real(x)) if x == real);
+    let shortest = real.to_string();
+    if reads_back(&shortest) {
+        return shortest
+    }
+    // The lexer divides by 10 once per decimal place, which isn't always exact, so try some other lengths
+    (0..=20).map(|places| format!("{:.*}", places, real)).find(|s| reads_back(s)).unwrap_or(shortest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::tests::strip_lines;
+
+    /// Checks that some code's AST survives being printed and parsed again, and that printing it is stable.
+    fn assert_round_trip(input: &str) {
+        let options = Options::default();
+        let ast = AST::new(input.as_bytes()).unwrap();
+        let printed = print(&ast, &options);
+        let reparsed = AST::new(&printed)
+            .unwrap_or_else(|e| panic!("printed code didn't parse: {}\n{}", e, String::from_utf8_lossy(&printed)));
+        assert_eq!(print(&reparsed, &options), printed);
+        assert_eq!(
+            ast.into_iter().map(strip_lines).collect::<Vec<_>>(),
+            reparsed.into_iter().map(strip_lines).collect::<Vec<_>>(),
+            "for printed code:\n{}",
+            String::from_utf8_lossy(&printed),
+        );
+    }
+
+    fn assert_format(input: &str, options: &Options, expected: &str) {
+        let output = format(input.as_bytes(), options, &mut ()).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output), expected);
+    }
+
+    #[test]
+    fn round_trip_expressions() {
+        assert_round_trip("a = 1 + 2 * 3 - (4 - 5) / (6 div 7) mod 8");
+        assert_round_trip("a = (1 + 2) * 3; b = 1 - (2 + 3); c = (a < b) == (b > c) && !d || e ^^ f");
+        assert_round_trip("a = -(b + c) * ~d; e = !f.g[1, 2]; h = (-i).j; k = - -l");
+        assert_round_trip("a = x << 2 | y >> 1 & z ^ w; b = a <> c and d or not e xor f");
+        assert_round_trip("a = 'say \"hi\"' + \"it's\"; b = $ff + 0.1 + 1.23 + 4.56789 + 123456789.5 + .5");
+        assert_round_trip("a.b.c = 1; a[0].b = 2; a[1, 2][3] = 4; (5).x = 6; (a + b).y = 7; (f().z) = 8");
+        assert_round_trip("a = f(g(1, 2), h()).i[j()]; b = c[]; d := e; f += 1; g -= 1; h *= 2; i /= 2");
+        assert_round_trip("j &= 1; k |= 2; l ^= 3");
+    }
+
+    #[test]
+    fn round_trip_statements() {
+        assert_round_trip("var a, b; var; globalvar c; a = 1 b = 2 exit; return a + b");
+        assert_round_trip("if a b = 1 else if c d = 2 else { e = 3 f = 4 }");
+        assert_round_trip("if (a) then { } else ;");
+        assert_round_trip("if a if b c() else d()");
+        assert_round_trip("while a > 0 do a -= 1; while b { b -= 1; continue; break }");
+        assert_round_trip("repeat 5 { x += 1 } repeat (y) z()");
+        assert_round_trip("do a += 1 until a > 10; do { b() } until (c)");
+        assert_round_trip("for (i = 0; i < 10; i += 1) { a[i] = i }");
+        assert_round_trip("with other x = 1 with (obj_player) do { y = 2 } with (-(1 + 2)) z = 3");
+        assert_round_trip("switch a { case 1: b = 2 break case \"c\": default: d() } switch (e) {}");
+        assert_round_trip("{ a = 1 { b = 2 } } begin c = 3 end");
+    }
+
+    #[test]
+    fn reals() {
+        for real in [0.0, 1.0, 0.1, 1.23, 4.56789, 0.3333333333333333, 123456789.98765433, 1e20, 3.0e-5] {
+            let printed = format_real(real);
+            assert!(
+                matches!(Lexer::new(printed.as_bytes()).next_token(), Some(Token::Real(x)) if x == real),
+                "{}",
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let source = "if a {b=1;c()}else{ d=2 } switch x{case 1:y=2;break;default:exit}";
+        assert_format(
+            source,
+            &Options { newline: "\n".into(), ..Default::default() },
+            "if (a) {\n    b = 1;\n    c();\n} else {\n    d = 2;\n}\nswitch (x) {\n    case 1:\n        y = 2;\n        \
+             break;\n    default:\n        exit;\n}\n",
+        );
+        assert_format(
+            "if a b=1 else {c=2}",
+            &Options { indent: "\t".into(), brace_style: BraceStyle::NextLine, newline: "\n".into() },
+            "if (a)\n\tb = 1;\nelse\n{\n\tc = 2;\n}\n",
+        );
+    }
+
+    #[test]
+    fn comments_kept() {
+        let source = "// first\na = 1; // a\nb = 2; c = 3; /* c */\nif x { /* inside */\n    y()\n} // end\n/* last */";
+        assert_format(
+            source,
+            &Options { newline: "\n".into(), ..Default::default() },
+            "// first\na = 1; // a\nb = 2;\nc = 3; /* c */\nif (x) {\n    /* inside */\n    y();\n}\n// end\n/* last */\n",
+        );
+        let output = format_expression(b"a+b // sum", &Options::default(), &mut ()).unwrap();
+        assert_eq!(output, b"a + b // sum\r\n");
+    }
+
+    #[test]
+    fn rewrite() {
+        struct Upper;
+        impl Rewrite for Upper {
+            fn expression(&mut self, expr: &Expr, place: Place) -> Option<Vec<u8>> {
+                match (&expr.kind, place) {
+                    (ExprKind::LiteralIdentifier(name), Place::Field) => Some(name.to_ascii_uppercase()),
+                    (ExprKind::Binary(binary), Place::Value) if binary.op == Operator::Add => Some(b"3".to_vec()),
+                    _ => None,
+                }
+            }
+
+            fn function(&mut self, name: &[u8]) -> Option<Vec<u8>> {
+                Some([b"my_", name].concat())
+            }
+        }
+        let output = format(b"a.b = f(1 + 2) * (3 - 4)", &Options::default(), &mut Upper).unwrap();
+        assert_eq!(output, b"a.B = my_f(3) * (3 - 4);\r\n");
+    }
+}